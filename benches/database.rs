@@ -0,0 +1,95 @@
+use std::path::Path;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::SqlitePool;
+use tokio::runtime::Runtime;
+use tokio::task::JoinSet;
+
+use challenge_instancer::database::{ChallengeInstanceInsertionResult, Database};
+use challenge_instancer::models::{ChallengeInstance, ChallengeInstanceState, EndReason, TimeSinceEpoch, User, UserRole};
+
+/// Opens a fresh database holding `users` users, set up the way the instancer sets up its own.
+async fn open_database(path: &Path, users: usize) -> Database {
+    let _ = std::fs::remove_file(path);
+    let pool = SqlitePool::connect_with(SqliteConnectOptions::new()
+        .create_if_missing(true)
+        .filename(path))
+        .await.expect("failed to open the benchmark database");
+    let database = Database::new(pool).await.expect("failed to migrate the benchmark database");
+
+    for i in 0..users {
+        let user = User {
+            id: format!("user-{}", i),
+            username: format!("user-{}", i),
+            display_name: format!("User {}", i),
+            avatar: None,
+            creation_time: TimeSinceEpoch::now(),
+            instance_count: 0,
+            scoreboard_id: None,
+            role: UserRole::Player
+        };
+        database.insert_user(&user).await.expect("failed to insert a user");
+    }
+
+    database
+}
+
+/// Starts an instance for every user at once, the way a burst of players does at the start of an event.
+async fn insert_instances(database: &Database, users: usize) {
+    let mut tasks = JoinSet::new();
+    for i in 0..users {
+        let database = database.clone();
+        tasks.spawn(async move {
+            let instance = ChallengeInstance {
+                user_id: format!("user-{}", i),
+                challenge_id: String::from("challenge"),
+                state: ChallengeInstanceState::QueuedStart,
+                details: None,
+                stop_time: Some(TimeSinceEpoch::from_now(Duration::from_secs(600))),
+                nonce: ChallengeInstance::generate_nonce(),
+                start_time: None,
+                extension_count: 0,
+                progress: None,
+                paused_at: None
+            };
+            database.insert_challenge_instance(&instance, 1, 3).await
+        });
+    }
+
+    while let Some(result) = tasks.join_next().await {
+        let result = result.expect("insertion task panicked").expect("failed to insert an instance");
+        assert!(matches!(result, ChallengeInstanceInsertionResult::Inserted));
+    }
+}
+
+async fn delete_instances(database: &Database, users: usize) {
+    for i in 0..users {
+        database.delete_challenge_instance(&format!("user-{}", i), "challenge", &EndReason::Stopped, &[]).await.expect("failed to delete an instance");
+    }
+}
+
+fn database(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let path = std::env::temp_dir().join(format!("instancer-bench-{}.sqlite", std::process::id()));
+    let mut group = c.benchmark_group("database");
+
+    for users in [1, 8, 32] {
+        let database = runtime.block_on(open_database(&path, users));
+
+        group.bench_with_input(BenchmarkId::new("insert_instance", users), &users, |b, &users| {
+            b.iter_batched(
+                || runtime.block_on(delete_instances(&database, users)),
+                |_| runtime.block_on(insert_instances(&database, users)),
+                BatchSize::PerIteration
+            );
+        });
+    }
+
+    group.finish();
+    let _ = std::fs::remove_file(&path);
+}
+
+criterion_group!(benches, database);
+criterion_main!(benches);
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use challenge_instancer::listing::{populate_listing, ChallengePlayerState, TrackedListing};
+use challenge_instancer::models::{ChallengeInstance, ChallengeInstanceState, ChallengeNotice, InstanceMetadata, TimeSinceEpoch};
+
+fn stopped_listing(challenges: usize) -> BTreeMap<String, ChallengePlayerState> {
+    (0..challenges)
+        .map(|i| {
+            let id = format!("challenge-{}", i);
+            let challenge = ChallengePlayerState {
+                id: id.clone(),
+                name: format!("Challenge {}", i),
+                description: Some(String::from("A challenge with a description of reasonable length.")),
+                state: ChallengeInstanceState::Stopped,
+                stop_time: None,
+                details: None,
+                progress: None,
+                paused: false,
+                flag_submission: true,
+                extendable: true,
+                restartable: true,
+                cancellable: true,
+                notice: None,
+                metadata: BTreeMap::new()
+            };
+            (id, challenge)
+        })
+        .collect()
+}
+
+/// A user running an instance of every fifth challenge, with a notice on every tenth challenge.
+fn user_state(challenges: usize) -> (Vec<ChallengeInstance>, Vec<ChallengeNotice>, Vec<InstanceMetadata>) {
+    let instances = (0..challenges).step_by(5)
+        .map(|i| ChallengeInstance {
+            user_id: String::from("user"),
+            challenge_id: format!("challenge-{}", i),
+            state: ChallengeInstanceState::Running,
+            details: Some(format!("nc challenge-{}.ctf 1337", i)),
+            stop_time: Some(TimeSinceEpoch::from_now(Duration::from_secs(600))),
+            nonce: ChallengeInstance::generate_nonce(),
+            start_time: Some(TimeSinceEpoch::now()),
+            extension_count: 0,
+            progress: None,
+            paused_at: None
+        })
+        .collect::<Vec<_>>();
+    let notices = (0..challenges).step_by(10)
+        .map(|i| ChallengeNotice { challenge_id: format!("challenge-{}", i), contents: Some(String::from("Maintenance in progress")) })
+        .collect();
+    let metadata = instances.iter()
+        .flat_map(|instance| ["host", "port", "password"].map(|key| InstanceMetadata {
+            challenge_id: instance.challenge_id.clone(),
+            key: String::from(key),
+            value: String::from("value")
+        }))
+        .collect();
+    (instances, notices, metadata)
+}
+
+fn listing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("listing");
+
+    for challenges in [50, 500] {
+        group.bench_with_input(BenchmarkId::new("populate", challenges), &challenges, |b, &challenges| {
+            b.iter_batched(
+                || (stopped_listing(challenges), user_state(challenges)),
+                |(mut listing, (instances, notices, metadata))| {
+                    populate_listing(&mut listing, instances, notices, metadata);
+                    listing
+                },
+                BatchSize::SmallInput
+            );
+        });
+
+        group.bench_with_input(BenchmarkId::new("delta", challenges), &challenges, |b, &challenges| {
+            let mut populated = stopped_listing(challenges);
+            let (instances, notices, metadata) = user_state(challenges);
+            populate_listing(&mut populated, instances, notices, metadata);
+
+            b.iter_batched(
+                || (TrackedListing::new(stopped_listing(challenges)), populated.clone()),
+                |(mut tracked, populated)| tracked.update(populated),
+                BatchSize::SmallInput
+            );
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, listing);
+criterion_main!(benches);
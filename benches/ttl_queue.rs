@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use challenge_instancer::models::TimeSinceEpoch;
+use challenge_instancer::ttl_queue::TtlQueue;
+
+fn filled_queue(instances: u64) -> TtlQueue {
+    let mut queue = TtlQueue::new();
+    for i in 0..instances {
+        queue.push(format!("user-{}", i), String::from("challenge"), TimeSinceEpoch::from_now(Duration::from_secs(3600 + i)));
+    }
+    queue
+}
+
+fn ttl_queue(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ttl_queue");
+
+    for instances in [1_000, 10_000] {
+        group.bench_with_input(BenchmarkId::new("extend", instances), &instances, |b, &instances| {
+            let mut queue = filled_queue(instances);
+            let mut i = 0;
+            b.iter(|| {
+                i = (i + 7919) % instances;
+                queue.push(format!("user-{}", i), String::from("challenge"), TimeSinceEpoch::from_now(Duration::from_secs(7200)));
+            });
+        });
+
+        group.bench_with_input(BenchmarkId::new("stop", instances), &instances, |b, &instances| {
+            b.iter_batched(
+                || filled_queue(instances),
+                |mut queue| {
+                    for i in (0..instances).step_by(10) {
+                        queue.remove(&format!("user-{}", i), "challenge");
+                    }
+                    queue
+                },
+                BatchSize::LargeInput
+            );
+        });
+
+        group.bench_with_input(BenchmarkId::new("expire_all", instances), &instances, |b, &instances| {
+            b.iter_batched(
+                || filled_queue(instances),
+                |mut queue| {
+                    while queue.peek().is_some() {
+                        black_box(queue.pop());
+                    }
+                },
+                BatchSize::LargeInput
+            );
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, ttl_queue);
+criterion_main!(benches);
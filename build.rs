@@ -0,0 +1,21 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| String::from("unknown"));
+    let build_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    println!("cargo:rustc-env=INSTANCER_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=INSTANCER_BUILD_TIME={}", build_time);
+
+    for path in [".git/HEAD", ".git/refs", ".sqlx", "src", "templates", "build.rs"] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+}
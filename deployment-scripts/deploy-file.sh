@@ -4,28 +4,52 @@ set -eu
 # Example deployment script that creates a file
 #
 # The arguments are passed as follows
-#   $1 : command (can be start, stop, restart or cleanup)
+#   $1 : command (can be start, stop, restart, cleanup or selftest)
 #   $2 : challenge_id
 #   $3 : user_id
+#   $4 : instance nonce (random, unique per instance and stable until it is stopped)
+#   $5 : "retry" when resuming a start interrupted by an instancer restart, which may have already run
+#
+# INSTANCER_NAMESPACE holds the namespace of the event when one is configured, and should be part of every
+# generated name so that events sharing infrastructure don't collide
+# Each label of the instance is passed as INSTANCER_LABEL_<KEY>, e.g. INSTANCER_LABEL_POOL for the "pool" label
 #
 # Start/Stop/Restart - self-explanatory
 # Cleanup - Stop variant that shouldn't fail, called to fix error scenarios
+# Selftest - Passed no other argument, checks that the dependencies of the script are available. Only called for
+# deployers with selftest set, at startup and from the admin API
 #
-# Deployment details are passed to the instancer by prefixing a line of stdout with '$'
+# Deployment details are passed to the instancer by prefixing a line of stdout with '$'. Those printed by a restart
+# only replace the details of the instance for challenges with restart_details set
+# Structured metadata (host, port, password...) can be passed with lines of the form '@ key=value'
+# Labels (infra pool, region...) can be attached to the instance with lines of the form '& key=value', and are
+# passed back on its later deployments
+# Lines prefixed with '!I ', '!W ' or '!E ' are logged at the info, warn or error level, other lines of stdout are
+# only logged in debug and stderr is logged as warn. With forward_warnings set on the deployer, '!W ' lines of a
+# successful start or restart are also shown to the player
+# Slow starts can report their progress in percent with lines of the form '% 42'
 #
 # To generate a unique identifier, the md5sum of the user_id should be used
+# The instance nonce can be used to find resources left behind by a crashed instance
+
+if [[ "$1" == "selftest" ]]; then
+  [[ -w . ]] || { echo "!E the working directory isn't writable"; exit 1; }
+  exit 0
+fi
 
 uid_hash=$(echo -n "$3" | md5sum | head -c8)
+prefix="${INSTANCER_NAMESPACE:+$INSTANCER_NAMESPACE-}"
 
 create_file() {
-  filename="$1-$2"
+  filename="$prefix$1-$2"
   echo "creating file $filename"
 	touch "$filename"
 	echo "\$ $(pwd)/$filename"
+	echo "@ file=$filename"
 }
 
 remove_file() {
-  filename="$1-$2"
+  filename="$prefix$1-$2"
   echo "removing file $filename"
 	rm "$filename"
 }
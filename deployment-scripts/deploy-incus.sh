@@ -2,20 +2,32 @@
 set -eu
 
 # The arguments are passed as follows
-#   $1 : command (can be start, stop, restart or cleanup)
+#   $1 : command (can be start, stop, restart, cleanup or selftest, the latter passed no other argument)
 #   $2 : challenge_id
 #   $3 : user_id
+#   $4 : instance nonce (random, unique per instance and stable until it is stopped)
+#   $5 : "retry" when resuming a start interrupted by an instancer restart, which may have already run
+#
+# INSTANCER_NAMESPACE holds the namespace of the event when one is configured, and should be part of every
+# generated name so that events sharing infrastructure don't collide
+# Each label of the instance is passed as INSTANCER_LABEL_<KEY>, e.g. INSTANCER_LABEL_POOL for the "pool" label
+
+if [[ "$1" == "selftest" ]]; then
+  incus info > /dev/null
+  exit 0
+fi
 
 uid_hash=$(echo -n "$3" | md5sum | head -c8)
+prefix="${INSTANCER_NAMESPACE:+$INSTANCER_NAMESPACE-}"
 
 create_container() {
   echo "copying container $1-template"
-  incus copy "$1-template" "$1-$2"
-  echo "starting container $1-$2"
-  incus start "$1-$2"
+  incus copy "$1-template" "$prefix$1-$2"
+  echo "starting container $prefix$1-$2"
+  incus start "$prefix$1-$2"
 
   while
-    ctn_ip=$(incus ls -c4 -fcsv "$1-$2" | cut '-d ' -f1)
+    ctn_ip=$(incus ls -c4 -fcsv "$prefix$1-$2" | cut '-d ' -f1)
     [[ -z $ctn_ip ]]
   do true; done
 
@@ -26,11 +38,14 @@ create_container() {
 }
 
 remove_container() {
-  echo "deleting container $1-$2"
-  incus rm -f "$1-$2"
+  echo "deleting container $prefix$1-$2"
+  incus rm -f "$prefix$1-$2"
 }
 
 if [[ "$1" == "start" ]]; then
+  if [[ "${5:-}" == "retry" ]]; then
+    remove_container "$2" "$uid_hash" || true
+  fi
   create_container "$2" "$uid_hash"
 elif [[ "$1" == "stop" ]]; then
   remove_container "$2" "$uid_hash"
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS challenge_history;
\ No newline at end of file
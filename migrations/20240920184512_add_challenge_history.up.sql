@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS challenge_history (
+    user_id          TEXT    NOT NULL,
+    challenge_id     TEXT    NOT NULL,
+    first_start_time INTEGER NOT NULL,
+    PRIMARY KEY (user_id, challenge_id)
+);
\ No newline at end of file
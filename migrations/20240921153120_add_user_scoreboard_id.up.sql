@@ -0,0 +1,2 @@
+ALTER TABLE users
+ADD scoreboard_id TEXT;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE challenge_instances
+DROP nonce;
\ No newline at end of file
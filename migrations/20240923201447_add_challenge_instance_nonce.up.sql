@@ -0,0 +1,4 @@
+ALTER TABLE challenge_instances
+ADD nonce TEXT NOT NULL DEFAULT '';
+
+UPDATE challenge_instances SET nonce = lower(hex(randomblob(8)));
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE challenge_instances
+ADD start_time INTEGER;
\ No newline at end of file
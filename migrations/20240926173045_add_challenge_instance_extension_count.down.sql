@@ -0,0 +1,2 @@
+ALTER TABLE challenge_instances
+DROP extension_count;
\ No newline at end of file
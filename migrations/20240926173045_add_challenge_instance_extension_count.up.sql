@@ -0,0 +1,2 @@
+ALTER TABLE challenge_instances
+ADD extension_count INTEGER NOT NULL DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS challenge_notices;
\ No newline at end of file
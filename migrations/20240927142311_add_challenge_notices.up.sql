@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS challenge_notices (
+    challenge_id TEXT NOT NULL PRIMARY KEY,
+    contents     TEXT NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS instance_metadata;
\ No newline at end of file
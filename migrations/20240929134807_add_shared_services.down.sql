@@ -0,0 +1 @@
+DROP TABLE IF EXISTS shared_services;
\ No newline at end of file
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS shared_services (
+    service_id TEXT NOT NULL PRIMARY KEY,
+    nonce      TEXT NOT NULL
+);
\ No newline at end of file
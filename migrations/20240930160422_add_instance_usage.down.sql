@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS instance_usage;
+
+ALTER TABLE challenge_instances
+DROP accounted_time;
\ No newline at end of file
@@ -0,0 +1,9 @@
+ALTER TABLE challenge_instances
+ADD accounted_time INTEGER;
+
+CREATE TABLE IF NOT EXISTS instance_usage (
+    user_id      TEXT NOT NULL,
+    challenge_id TEXT NOT NULL,
+    runtime      INTEGER NOT NULL,
+    PRIMARY KEY (user_id, challenge_id)
+);
\ No newline at end of file
@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS quota_resets;
+
+ALTER TABLE instance_usage
+DROP quota_runtime;
\ No newline at end of file
@@ -0,0 +1,7 @@
+ALTER TABLE instance_usage
+ADD quota_runtime INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS quota_resets (
+    id         INTEGER PRIMARY KEY CHECK (id = 0),
+    reset_time INTEGER NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS audit_log (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    time         INTEGER NOT NULL,
+    user_id      TEXT    NOT NULL,
+    ip           TEXT    NOT NULL,
+    action       TEXT    NOT NULL,
+    challenge_id TEXT
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_user_id ON audit_log (user_id);
+CREATE INDEX IF NOT EXISTS audit_log_ip ON audit_log (ip);
\ No newline at end of file
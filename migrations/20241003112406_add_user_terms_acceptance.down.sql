@@ -0,0 +1,5 @@
+ALTER TABLE users
+DROP terms_accepted_time;
+
+ALTER TABLE users
+DROP terms_version;
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE users
+ADD terms_version INTEGER;
+
+ALTER TABLE users
+ADD terms_accepted_time INTEGER;
\ No newline at end of file
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS challenge_instances_stop_time;
+DROP INDEX IF EXISTS challenge_instances_state;
+DROP INDEX IF EXISTS challenge_instances_challenge_id;
\ No newline at end of file
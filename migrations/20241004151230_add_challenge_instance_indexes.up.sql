@@ -0,0 +1,3 @@
+CREATE INDEX IF NOT EXISTS challenge_instances_challenge_id ON challenge_instances (challenge_id);
+CREATE INDEX IF NOT EXISTS challenge_instances_state ON challenge_instances (state);
+CREATE INDEX IF NOT EXISTS challenge_instances_stop_time ON challenge_instances (stop_time);
\ No newline at end of file
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id               TEXT    NOT NULL PRIMARY KEY,
+    locale                TEXT    NOT NULL,
+    timezone              TEXT            ,
+    notify_expiry         INTEGER NOT NULL,
+    notify_announcements  INTEGER NOT NULL
+);
\ No newline at end of file
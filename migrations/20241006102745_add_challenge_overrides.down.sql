@@ -0,0 +1 @@
+DROP TABLE IF EXISTS challenge_overrides;
\ No newline at end of file
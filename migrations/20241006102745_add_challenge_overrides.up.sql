@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS challenge_overrides (
+    challenge_id TEXT NOT NULL PRIMARY KEY,
+    name         TEXT   ,
+    description  TEXT   ,
+    ttl          INTEGER,
+    deployer     TEXT
+);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS missed_messages;
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS missed_messages (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    time         INTEGER NOT NULL,
+    user_id      TEXT    NOT NULL,
+    challenge_id TEXT    NOT NULL,
+    message      TEXT    NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS missed_messages_user_id ON missed_messages (user_id);
\ No newline at end of file
@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS challenge_instances_count_insert;
+DROP TRIGGER IF EXISTS challenge_instances_count_delete;
\ No newline at end of file
@@ -0,0 +1,11 @@
+UPDATE users SET instance_count = (SELECT COUNT(*) FROM challenge_instances WHERE challenge_instances.user_id = users.id);
+
+CREATE TRIGGER IF NOT EXISTS challenge_instances_count_insert AFTER INSERT ON challenge_instances
+BEGIN
+    UPDATE users SET instance_count = instance_count + 1 WHERE id = NEW.user_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS challenge_instances_count_delete AFTER DELETE ON challenge_instances
+BEGIN
+    UPDATE users SET instance_count = instance_count - 1 WHERE id = OLD.user_id;
+END;
\ No newline at end of file
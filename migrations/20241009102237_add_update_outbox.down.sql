@@ -0,0 +1 @@
+DROP TABLE IF EXISTS update_outbox;
\ No newline at end of file
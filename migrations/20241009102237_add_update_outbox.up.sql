@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS update_outbox (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    time         INTEGER NOT NULL,
+    user_id      TEXT    NOT NULL,
+    challenge_id TEXT    NOT NULL,
+    payload      TEXT    NOT NULL,
+    delivered_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS update_outbox_delivered_at ON update_outbox (delivered_at);
\ No newline at end of file
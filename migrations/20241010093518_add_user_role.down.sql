@@ -0,0 +1,2 @@
+ALTER TABLE users
+DROP role;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE users
+ADD role TEXT NOT NULL DEFAULT 'player';
\ No newline at end of file
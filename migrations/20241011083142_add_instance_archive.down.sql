@@ -0,0 +1,4 @@
+DROP VIEW IF EXISTS instance_history;
+DROP TABLE IF EXISTS instance_archive;
+ALTER TABLE challenge_instances
+DROP end_reason;
\ No newline at end of file
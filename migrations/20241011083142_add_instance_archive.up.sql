@@ -0,0 +1,21 @@
+ALTER TABLE challenge_instances
+ADD end_reason TEXT;
+
+CREATE TABLE IF NOT EXISTS instance_archive (
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id         TEXT    NOT NULL,
+    challenge_id    TEXT    NOT NULL,
+    nonce           TEXT    NOT NULL,
+    details         TEXT,
+    extension_count INTEGER NOT NULL,
+    started_at      INTEGER,
+    ended_at        INTEGER NOT NULL,
+    end_reason      TEXT    NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS instance_archive_user_challenge ON instance_archive (user_id, challenge_id);
+
+CREATE VIEW IF NOT EXISTS instance_history AS
+SELECT user_id, challenge_id, nonce, details, extension_count, start_time AS started_at, NULL AS ended_at, NULL AS end_reason FROM challenge_instances
+UNION ALL
+SELECT user_id, challenge_id, nonce, details, extension_count, started_at, ended_at, end_reason FROM instance_archive;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS instance_labels;
\ No newline at end of file
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS instance_labels (
+    user_id      TEXT NOT NULL,
+    challenge_id TEXT NOT NULL,
+    key          TEXT NOT NULL,
+    value        TEXT NOT NULL,
+    PRIMARY KEY (user_id, challenge_id, key)
+);
\ No newline at end of file
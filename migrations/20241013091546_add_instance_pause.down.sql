@@ -0,0 +1,4 @@
+ALTER TABLE challenge_instances
+DROP maintenance_pause;
+ALTER TABLE challenge_instances
+DROP paused_at;
\ No newline at end of file
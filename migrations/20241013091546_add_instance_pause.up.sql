@@ -0,0 +1,4 @@
+ALTER TABLE challenge_instances
+ADD paused_at INTEGER;
+ALTER TABLE challenge_instances
+ADD maintenance_pause BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE challenge_instances
+DROP transition_time;
\ No newline at end of file
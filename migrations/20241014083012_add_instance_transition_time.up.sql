@@ -0,0 +1,4 @@
+ALTER TABLE challenge_instances
+ADD transition_time INTEGER;
+UPDATE challenge_instances
+SET transition_time = CAST(strftime('%s', 'now') AS INTEGER) * 1000;
\ No newline at end of file
@@ -0,0 +1,15 @@
+ALTER TABLE challenge_instances
+ADD weight INTEGER NOT NULL DEFAULT 1;
+
+DROP TRIGGER IF EXISTS challenge_instances_count_insert;
+DROP TRIGGER IF EXISTS challenge_instances_count_delete;
+
+CREATE TRIGGER IF NOT EXISTS challenge_instances_count_insert AFTER INSERT ON challenge_instances
+BEGIN
+    UPDATE users SET instance_count = instance_count + NEW.weight WHERE id = NEW.user_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS challenge_instances_count_delete AFTER DELETE ON challenge_instances
+BEGIN
+    UPDATE users SET instance_count = instance_count - OLD.weight WHERE id = OLD.user_id;
+END;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS local_accounts;
\ No newline at end of file
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS local_accounts (
+    user_id       TEXT NOT NULL PRIMARY KEY,
+    email         TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL
+);
\ No newline at end of file
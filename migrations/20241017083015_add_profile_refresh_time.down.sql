@@ -0,0 +1,2 @@
+ALTER TABLE users
+DROP profile_refresh_time;
\ No newline at end of file
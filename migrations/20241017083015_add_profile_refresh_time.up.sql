@@ -0,0 +1,2 @@
+ALTER TABLE users
+ADD profile_refresh_time INTEGER;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE user_preferences
+DROP email;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE user_preferences
+ADD email TEXT;
\ No newline at end of file
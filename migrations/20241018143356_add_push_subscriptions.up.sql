@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS push_subscriptions (
+    endpoint      TEXT    NOT NULL PRIMARY KEY,
+    user_id       TEXT    NOT NULL,
+    p256dh        TEXT    NOT NULL,
+    auth          TEXT    NOT NULL,
+    creation_time INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS push_subscriptions_user_id ON push_subscriptions (user_id);
\ No newline at end of file
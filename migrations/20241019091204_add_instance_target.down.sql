@@ -0,0 +1,2 @@
+ALTER TABLE challenge_instances
+DROP target;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE challenge_instances
+ADD target TEXT;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS target_failovers;
\ No newline at end of file
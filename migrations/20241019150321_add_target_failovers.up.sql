@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS target_failovers (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    time         INTEGER NOT NULL,
+    user_id      TEXT    NOT NULL,
+    challenge_id TEXT    NOT NULL,
+    from_target  TEXT    NOT NULL,
+    to_target    TEXT    NOT NULL
+);
\ No newline at end of file
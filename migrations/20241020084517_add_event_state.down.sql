@@ -0,0 +1 @@
+DROP TABLE IF EXISTS event_state;
\ No newline at end of file
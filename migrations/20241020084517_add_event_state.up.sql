@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS event_state (
+    id       INTEGER PRIMARY KEY CHECK (id = 1),
+    ended_at INTEGER NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1,64 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::AbuseConfig;
+
+pub enum TrackedAction {
+    StartStop,
+    Restart
+}
+
+#[derive(Default)]
+struct UserActivity {
+    start_stops: VecDeque<Instant>,
+    restarts: VecDeque<Instant>,
+    cooldown_until: Option<Instant>
+}
+
+/// Detects users churning through instances faster than legitimate play would,
+/// putting them on a temporary cooldown on top of the per-minute rate limiter.
+pub struct AbuseDetector {
+    config: AbuseConfig,
+    activity: Mutex<HashMap<String, UserActivity>>
+}
+
+impl AbuseDetector {
+    pub fn new(config: AbuseConfig) -> Self {
+        AbuseDetector { config, activity: Mutex::new(HashMap::new()) }
+    }
+
+    /// Returns how long the user remains on cooldown, if they are.
+    pub fn cooldown(&self, user_id: &str) -> Option<Duration> {
+        let activity = self.activity.lock().unwrap();
+        let cooldown_until = activity.get(user_id)?.cooldown_until?;
+        cooldown_until.checked_duration_since(Instant::now())
+    }
+
+    /// Records an action of the user, putting them on cooldown if it trips a heuristic. Returns the reason if it did.
+    pub fn record(&self, user_id: &str, action: TrackedAction) -> Option<String> {
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.window as u64);
+
+        let mut activity = self.activity.lock().unwrap();
+        let user = activity.entry(user_id.to_string()).or_default();
+
+        let (history, limit, kind) = match action {
+            TrackedAction::StartStop => (&mut user.start_stops, self.config.max_start_stops, "starts and stops"),
+            TrackedAction::Restart => (&mut user.restarts, self.config.max_restarts, "restarts")
+        };
+
+        history.push_back(now);
+        while history.front().is_some_and(|time| now.duration_since(*time) > window) {
+            history.pop_front();
+        }
+
+        if history.len() <= limit as usize { return None }
+
+        let reason = format!("{} {} within {}s", history.len(), kind, self.config.window);
+        user.start_stops.clear();
+        user.restarts.clear();
+        user.cooldown_until = Some(now + Duration::from_secs(self.config.cooldown as u64));
+        Some(reason)
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::Response;
+use tower_sessions::Session;
+
+use crate::config::SettingsConfig;
+use crate::deployment_worker::Challenge;
+use crate::error::RouterError;
+use crate::models::{User, UserRole};
+use crate::InstancerState;
+
+/// The role a user registers with. Admins listed in the configuration are also granted theirs at startup.
+pub fn initial_role(settings: &SettingsConfig, uid: &str) -> UserRole {
+    match settings.admins.iter().any(|admin| admin == uid) {
+        true => UserRole::Admin,
+        false => UserRole::Player
+    }
+}
+
+/// Whether a user may look into the instances of a challenge as its author. Admins may for every challenge, authors
+/// only for those that list them as owners.
+pub fn owns_challenge(user: &User, challenge: Option<&Challenge>) -> bool {
+    match user.role {
+        UserRole::Admin => true,
+        UserRole::Author => challenge.is_some_and(|challenge| challenge.owners.contains(&user.id)),
+        UserRole::Demo | UserRole::Player => false
+    }
+}
+
+/// The user of the session, loaded along with their role. Requests without a logged in user are rejected.
+#[derive(Clone)]
+pub struct CurrentUser(pub User);
+
+#[async_trait]
+impl FromRequestParts<Arc<InstancerState>> for CurrentUser {
+    type Rejection = RouterError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<InstancerState>) -> Result<Self, Self::Rejection> {
+        /* the role layers already loaded the user */
+        if let Some(user) = parts.extensions.get::<CurrentUser>() {
+            return Ok(user.clone());
+        }
+
+        let session = Session::from_request_parts(parts, state).await
+            .map_err(|(_, reason)| RouterError::Internal(anyhow::anyhow!(reason)))?;
+        let Some(uid) = session.get::<String>("uid").await? else {
+            return Err(RouterError::Unauthorized);
+        };
+        let Some(user) = state.database.fetch_user(&uid).await? else {
+            return Err(RouterError::Unauthorized);
+        };
+
+        let user = CurrentUser(user);
+        parts.extensions.insert(user.clone());
+        Ok(user)
+    }
+}
+
+impl CurrentUser {
+    pub fn has_role(&self, role: &UserRole) -> bool {
+        &self.0.role >= role
+    }
+}
+
+async fn enforce_role(role: UserRole, user: CurrentUser, request: Request, next: Next) -> Result<Response, RouterError> {
+    if !user.has_role(&role) {
+        return Err(RouterError::Forbidden);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Layer of the routes reserved to admins.
+pub async fn require_admin(user: CurrentUser, request: Request, next: Next) -> Result<Response, RouterError> {
+    enforce_role(UserRole::Admin, user, request, next).await
+}
+
+/// Layer of the routes open to challenge authors, and so to admins.
+pub async fn require_author(user: CurrentUser, request: Request, next: Next) -> Result<Response, RouterError> {
+    enforce_role(UserRole::Author, user, request, next).await
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use axum::body::Bytes;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::header::CONTENT_TYPE;
+
+/// The size requested from the CDN, which is enough for the header and keeps the cache small.
+const AVATAR_SIZE: u32 = 128;
+const MAX_AVATAR_BYTES: usize = 256 * 1024;
+const MAX_CACHED_AVATARS: usize = 2048;
+const AVATAR_TTL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_AVATAR: &str = "https://discordapp.com/assets/a0180771ce23344c2a95.png";
+
+static AVATAR_HASH: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(a_)?[0-9a-f]{32}$").unwrap());
+
+/// Whether a string looks like a Discord avatar hash, so that only avatars go through the proxy.
+pub fn is_valid_hash(hash: &str) -> bool {
+    AVATAR_HASH.is_match(hash)
+}
+
+/// The path the avatar of a user is served under by the instancer.
+pub fn local_url(user_id: &str, avatar: &Option<String>) -> String {
+    match avatar {
+        None => String::from("/avatars/default"),
+        Some(avatar_hash) => format!("/avatars/{}/{}", user_id, avatar_hash)
+    }
+}
+
+#[derive(Clone)]
+pub struct Avatar {
+    pub content_type: String,
+    pub bytes: Bytes
+}
+
+struct CachedAvatar {
+    avatar: Avatar,
+    fetched_at: Instant
+}
+
+/// Avatars fetched from the Discord CDN, so that browsers never load them from Discord themselves.
+pub struct AvatarCache {
+    client: reqwest::Client,
+    avatars: Mutex<HashMap<String, CachedAvatar>>
+}
+
+impl Default for AvatarCache {
+    fn default() -> Self {
+        AvatarCache {
+            client: reqwest::Client::builder().timeout(Duration::from_secs(10)).build().unwrap(),
+            avatars: Mutex::new(HashMap::new())
+        }
+    }
+}
+
+impl AvatarCache {
+    pub async fn user_avatar(&self, user_id: &str, avatar_hash: &str) -> anyhow::Result<Avatar> {
+        self.get(format!("https://cdn.discordapp.com/avatars/{}/{}.png?size={}", user_id, avatar_hash, AVATAR_SIZE)).await
+    }
+
+    pub async fn default_avatar(&self) -> anyhow::Result<Avatar> {
+        self.get(String::from(DEFAULT_AVATAR)).await
+    }
+
+    async fn get(&self, url: String) -> anyhow::Result<Avatar> {
+        if let Some(cached) = self.avatars.lock().unwrap().get(&url).filter(|cached| cached.fetched_at.elapsed() < AVATAR_TTL) {
+            return Ok(cached.avatar.clone());
+        }
+
+        let avatar = self.fetch(&url).await?;
+
+        let mut avatars = self.avatars.lock().unwrap();
+        avatars.retain(|_, cached| cached.fetched_at.elapsed() < AVATAR_TTL);
+        if avatars.len() >= MAX_CACHED_AVATARS {
+            if let Some(oldest) = avatars.iter().min_by_key(|(_, cached)| cached.fetched_at).map(|(url, _)| url.clone()) {
+                avatars.remove(&oldest);
+            }
+        }
+        avatars.insert(url, CachedAvatar { avatar: avatar.clone(), fetched_at: Instant::now() });
+        Ok(avatar)
+    }
+
+    async fn fetch(&self, url: &str) -> anyhow::Result<Avatar> {
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+        let content_type = response.headers().get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .filter(|content_type| content_type.starts_with("image/"))
+            .ok_or_else(|| anyhow!("avatar at {} isn't an image", url))?
+            .to_string();
+        if response.content_length().is_some_and(|length| length as usize > MAX_AVATAR_BYTES) {
+            bail!("avatar at {} is larger than {} bytes", url, MAX_AVATAR_BYTES);
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if bytes.len() + chunk.len() > MAX_AVATAR_BYTES {
+                bail!("avatar at {} is larger than {} bytes", url, MAX_AVATAR_BYTES);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(Avatar { content_type, bytes: Bytes::from(bytes) })
+    }
+}
@@ -0,0 +1,24 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tower_sessions::cookie::time::format_description::well_known::Rfc3339;
+use tower_sessions::cookie::time::OffsetDateTime;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_HASH: &str = env!("INSTANCER_GIT_HASH");
+
+/// Identifies the exact build that is running, embedded at compile time by `build.rs`.
+#[derive(Serialize, Debug)]
+pub struct BuildInfo {
+    version: &'static str,
+    git_hash: &'static str,
+    build_time: String
+}
+
+pub static BUILD_INFO: Lazy<BuildInfo> = Lazy::new(|| {
+    let build_time = env!("INSTANCER_BUILD_TIME").parse::<i64>().ok()
+        .and_then(|timestamp| OffsetDateTime::from_unix_timestamp(timestamp).ok())
+        .and_then(|time| time.format(&Rfc3339).ok())
+        .unwrap_or_else(|| String::from("unknown"));
+
+    BuildInfo { version: VERSION, git_hash: GIT_HASH, build_time }
+});
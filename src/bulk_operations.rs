@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use minijinja::context;
+use serde::{Deserialize, Serialize};
+use tokio::time;
+
+use crate::deployment_worker::{DeploymentUpdate, DeploymentUpdateDetails, MessageSeverity};
+use crate::models::{ChallengeInstance, ChallengeInstanceState, EndReason};
+use crate::InstancerState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkCommand {
+    Stop,
+    Restart,
+    Cleanup
+}
+
+/// Progress of an operation applied to every instance of a challenge.
+#[derive(Debug, Serialize)]
+pub struct BulkOperation {
+    pub challenge_id: String,
+    pub command: BulkCommand,
+    pub total: usize,
+    pub completed: AtomicUsize
+}
+
+/// Applies the command to every running instance of the challenge in the background, replacing any previous
+/// operation's progress report for that challenge.
+pub async fn start(state: Arc<InstancerState>, challenge_id: String, command: BulkCommand) -> anyhow::Result<Arc<BulkOperation>> {
+    let instances: Vec<ChallengeInstance> = state.database.get_challenge_instances().await?.into_iter()
+        .filter(|instance| instance.challenge_id == challenge_id)
+        .filter(|instance| matches!(instance.state, ChallengeInstanceState::Running | ChallengeInstanceState::Expiring))
+        .collect();
+
+    let operation = Arc::new(BulkOperation {
+        challenge_id: challenge_id.clone(),
+        command,
+        total: instances.len(),
+        completed: AtomicUsize::new(0)
+    });
+    state.bulk_operations.lock().unwrap().insert(challenge_id, Arc::clone(&operation));
+
+    let task_operation = Arc::clone(&operation);
+    tokio::spawn(async move {
+        if let Err(err) = run(&state, &task_operation, instances).await {
+            tracing::error!("bulk {:?} of challenge {} failed: {:?}", task_operation.command, task_operation.challenge_id, err);
+        }
+    });
+
+    Ok(operation)
+}
+
+/// Queues the command for the instances, `bulk_concurrency` instances at a time.
+async fn run(state: &InstancerState, operation: &BulkOperation, instances: Vec<ChallengeInstance>) -> anyhow::Result<()> {
+    let Some(challenge) = state.deployer.challenges.get(&operation.challenge_id) else { return Ok(()) };
+
+    for batch in instances.chunks(state.config.settings.bulk_concurrency.max(1)) {
+        let mut pending = Vec::new();
+
+        for instance in batch {
+            let queued = match operation.command {
+                BulkCommand::Stop => state.deployer.queue_stop(&instance.user_id, &instance.challenge_id, EndReason::Admin).await?,
+                BulkCommand::Restart => state.deployer.queue_restart(&instance.user_id, &instance.challenge_id).await?,
+                BulkCommand::Cleanup => state.deployer.queue_cleanup(&instance.user_id, &instance.challenge_id, EndReason::Admin).await?
+            };
+
+            if !queued {
+                operation.completed.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            let template = match operation.command {
+                BulkCommand::Stop | BulkCommand::Cleanup => "bulk_stopped",
+                BulkCommand::Restart => "bulk_restarted"
+            };
+            let contents = state.deployer.messages.render(template, context! { challenge => challenge.name });
+            let message = DeploymentUpdate {
+                user_id: instance.user_id.clone(),
+                challenge_id: instance.challenge_id.clone(),
+                details: DeploymentUpdateDetails::Message { contents, severity: MessageSeverity::Info }
+            };
+            state.deployer.updates.send(message);
+
+            pending.push(instance);
+        }
+
+        /* wait for the batch to be handled before queueing more, so players' requests aren't starved */
+        while !pending.is_empty() {
+            if state.shutdown_token.is_cancelled() { return Ok(()) }
+            time::sleep(Duration::from_secs(1)).await;
+
+            let mut still_pending = Vec::new();
+            for instance in pending {
+                let handled = state.database.get_challenge_instance(&instance.user_id, &instance.challenge_id).await?
+                    .is_none_or(|instance| !instance.state.is_queued());
+                if handled {
+                    operation.completed.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    still_pending.push(instance);
+                }
+            }
+            pending = still_pending;
+        }
+
+        tracing::info!("bulk {:?} of challenge {}: {}/{} instances handled", operation.command, operation.challenge_id, operation.completed.load(Ordering::Relaxed), operation.total);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use tokio::sync::watch;
+
+use crate::deployment_worker::Challenge;
+
+/// The enabled challenges, which admins can redefine while the instancer runs.
+///
+/// Lookups hand out an `Arc` so that a deployment in progress keeps the definition it started with.
+pub struct ChallengeRegistry {
+    challenges: RwLock<HashMap<String, Arc<Challenge>>>,
+    changes: watch::Sender<()>
+}
+
+impl ChallengeRegistry {
+    pub fn new(challenges: HashMap<String, Challenge>) -> Self {
+        let challenges = challenges.into_iter().map(|(id, challenge)| (id, Arc::new(challenge))).collect();
+        ChallengeRegistry { challenges: RwLock::new(challenges), changes: watch::Sender::new(()) }
+    }
+
+    /// Notifies of every change to the challenges, so that dashboards can refresh their listing.
+    pub fn watch(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Challenge>> {
+        self.challenges.read().unwrap().get(id).cloned()
+    }
+
+    pub fn contains_key(&self, id: &str) -> bool {
+        self.challenges.read().unwrap().contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.challenges.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.challenges.read().unwrap().is_empty()
+    }
+
+    /// Returns every challenge as currently defined.
+    pub fn snapshot(&self) -> HashMap<String, Arc<Challenge>> {
+        self.challenges.read().unwrap().clone()
+    }
+
+    pub fn insert(&self, challenge: Challenge) {
+        self.challenges.write().unwrap().insert(challenge.id.clone(), Arc::new(challenge));
+        self.changes.send_replace(());
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.challenges.write().unwrap().remove(id);
+        self.changes.send_replace(());
+    }
+
+    /// Swaps every challenge at once, so that no listing mixes the old and new definitions.
+    pub fn replace(&self, challenges: HashMap<String, Challenge>) {
+        *self.challenges.write().unwrap() = challenges.into_iter().map(|(id, challenge)| (id, Arc::new(challenge))).collect();
+        self.changes.send_replace(());
+    }
+}
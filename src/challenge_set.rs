@@ -0,0 +1,207 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::InstancerConfig;
+use crate::database::{Database, InstanceFilter};
+use crate::deployment_worker::{Challenge, DeploymentWorker};
+use crate::duration::HumanDuration;
+use crate::identifiers;
+use crate::models::ChallengeOverride;
+
+/// A challenge as exported and imported. Only the fields admins can override are included, so that an
+/// export can be imported back unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ChallengeDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub ttl: HumanDuration,
+    pub deployer: String
+}
+
+/// Challenges by id, sorted so that exports diff cleanly.
+pub type ChallengeSet = BTreeMap<String, ChallengeDefinition>;
+
+impl From<&Challenge> for ChallengeDefinition {
+    fn from(challenge: &Challenge) -> Self {
+        ChallengeDefinition {
+            name: challenge.name.clone(),
+            description: challenge.description.clone(),
+            ttl: HumanDuration(challenge.ttl),
+            deployer: challenge.pipeline.first().map(|step| step.name.clone()).unwrap_or_default()
+        }
+    }
+}
+
+impl ChallengeDefinition {
+    /// An override redefining every field, an empty description clearing the configured one.
+    fn to_override(&self, challenge_id: &str) -> ChallengeOverride {
+        ChallengeOverride {
+            challenge_id: challenge_id.to_string(),
+            name: Some(self.name.clone()),
+            description: Some(self.description.clone().unwrap_or_default()),
+            ttl: Some(self.ttl.secs()),
+            deployer: Some(self.deployer.clone())
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChallengeSetFormat {
+    #[default]
+    Json,
+    Yaml
+}
+
+impl ChallengeSetFormat {
+    /// Picks the format from a file's extension, defaulting to JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => ChallengeSetFormat::Yaml,
+            _ => ChallengeSetFormat::Json
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ChallengeSetFormat::Json => "application/json",
+            ChallengeSetFormat::Yaml => "application/yaml"
+        }
+    }
+
+    pub fn serialize(self, set: &ChallengeSet) -> anyhow::Result<String> {
+        Ok(match self {
+            ChallengeSetFormat::Json => serde_json::to_string_pretty(set)?,
+            ChallengeSetFormat::Yaml => serde_yaml::to_string(set)?
+        })
+    }
+
+    pub fn parse(self, contents: &str) -> Result<ChallengeSet, String> {
+        match self {
+            ChallengeSetFormat::Json => serde_json::from_str(contents).map_err(|err| err.to_string()),
+            ChallengeSetFormat::Yaml => serde_yaml::from_str(contents).map_err(|err| err.to_string())
+        }
+    }
+}
+
+/// The challenges an import adds, redefines and removes.
+#[derive(Serialize, Debug)]
+pub struct ChallengeSetDiff {
+    added: Vec<String>,
+    changed: Vec<String>,
+    removed: Vec<String>
+}
+
+impl ChallengeSetDiff {
+    fn new(before: &ChallengeSet, after: &ChallengeSet) -> Self {
+        ChallengeSetDiff {
+            added: after.keys().filter(|id| !before.contains_key(*id)).cloned().collect(),
+            changed: after.iter()
+                .filter(|(id, definition)| before.get(*id).is_some_and(|previous| previous != *definition))
+                .map(|(id, _)| id.clone())
+                .collect(),
+            removed: before.keys().filter(|id| !after.contains_key(*id)).cloned().collect()
+        }
+    }
+}
+
+/// A validated import, ready to be applied.
+pub struct ImportPlan {
+    overrides: Vec<ChallengeOverride>,
+    challenges: HashMap<String, Challenge>,
+    pub diff: ChallengeSetDiff
+}
+
+/// The challenges as currently defined, overrides included.
+pub fn export(deployer: &DeploymentWorker) -> ChallengeSet {
+    deployer.challenges.snapshot().values()
+        .map(|challenge| (challenge.id.clone(), ChallengeDefinition::from(challenge.as_ref())))
+        .collect()
+}
+
+/// Validates an import, which overrides every challenge it lists. Configured challenges it leaves out return to
+/// their configured definition, and challenges only defined by a previous import are removed, which is refused
+/// while they still have instances. Returns the reasons the import was rejected, if any.
+pub async fn plan_import(config: &InstancerConfig, deployer: &DeploymentWorker, imported: ChallengeSet) -> anyhow::Result<Result<ImportPlan, Vec<String>>> {
+    let mut errors = Vec::new();
+    let mut challenges = HashMap::new();
+
+    for (id, cfg) in config.challenges.iter().filter(|(id, _)| !imported.contains_key(*id)) {
+        if let Some(challenge) = deployer.build_challenge(config, id, cfg) {
+            challenges.insert(id.clone(), challenge);
+        }
+    }
+
+    let overrides: Vec<ChallengeOverride> = imported.iter().map(|(id, definition)| definition.to_override(id)).collect();
+    for over in &overrides {
+        if !identifiers::is_valid(&over.challenge_id) {
+            errors.push(format!("{}: invalid challenge id", over.challenge_id));
+        } else if over.ttl == Some(0) {
+            errors.push(format!("{}: the TTL must be positive", over.challenge_id));
+        } else {
+            match deployer.build_challenge_override(config, over) {
+                Some(challenge) => { challenges.insert(over.challenge_id.clone(), challenge); }
+                None => errors.push(format!("{}: unknown deployer, unusable pipeline or unavailable shared service", over.challenge_id))
+            }
+        }
+    }
+
+    let current = export(deployer);
+    for id in current.keys().filter(|id| !imported.contains_key(*id) && !config.challenges.contains_key(*id)) {
+        if has_instances(&deployer.database, id).await? {
+            errors.push(format!("{}: can't be removed while it has instances", id));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Ok(Err(errors));
+    }
+
+    let after = challenges.values().map(|challenge| (challenge.id.clone(), ChallengeDefinition::from(challenge))).collect();
+    let diff = ChallengeSetDiff::new(&current, &after);
+    Ok(Ok(ImportPlan { overrides, challenges, diff }))
+}
+
+/// Stores the overrides of an import, then swaps in its challenges.
+pub async fn apply_import(deployer: &DeploymentWorker, plan: ImportPlan) -> anyhow::Result<ChallengeSetDiff> {
+    deployer.database.replace_challenge_overrides(&plan.overrides).await?;
+    deployer.challenges.replace(plan.challenges);
+    Ok(plan.diff)
+}
+
+async fn has_instances(database: &Database, challenge_id: &str) -> anyhow::Result<bool> {
+    let filter = InstanceFilter { challenge_id: Some(challenge_id), ..Default::default() };
+    let (_, total) = database.search_challenge_instances(&filter, 1, 0).await?;
+    Ok(total > 0)
+}
+
+/// Runs `challenges export <file>` or `challenges import <file> [--dry-run]` against the database, the format
+/// following the file's extension. Imported changes take effect the next time the instancer starts.
+pub async fn run_command(config: &InstancerConfig, deployer: &DeploymentWorker, args: &[String]) -> anyhow::Result<()> {
+    match args {
+        [command, path] if command == "export" => {
+            let path = Path::new(path);
+            std::fs::write(path, ChallengeSetFormat::from_path(path).serialize(&export(deployer))?)?;
+            Ok(())
+        }
+        [command, path, flags @ ..] if command == "import" && flags.iter().all(|flag| flag == "--dry-run") => {
+            let path = Path::new(path);
+            let imported = ChallengeSetFormat::from_path(path).parse(&std::fs::read_to_string(path)?)
+                .map_err(|err| anyhow::anyhow!("couldn't parse {}: {}", path.display(), err))?;
+
+            let plan = match plan_import(config, deployer, imported).await? {
+                Ok(plan) => plan,
+                Err(errors) => anyhow::bail!("import rejected:\n{}", errors.join("\n"))
+            };
+
+            let diff = if flags.is_empty() { apply_import(deployer, plan).await? } else { plan.diff };
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+            Ok(())
+        }
+        _ => anyhow::bail!("usage: challenge-instancer challenges (export <file> | import <file> [--dry-run])")
+    }
+}
@@ -0,0 +1,42 @@
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+
+use crate::InstancerState;
+
+/// The address of the client, taken from `X-Forwarded-For` when the peer is a trusted reverse proxy.
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl FromRequestParts<Arc<InstancerState>> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<InstancerState>) -> Result<Self, Self::Rejection> {
+        let trusted_proxies = &state.config.settings.trusted_proxies;
+        let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>()
+            .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |ConnectInfo(addr)| addr.ip());
+
+        if !trusted_proxies.contains(&peer) {
+            return Ok(ClientIp(peer));
+        }
+
+        // each proxy appends the address it received the request from, so the client is the last untrusted hop
+        let forwarded: Vec<IpAddr> = parts.headers.get_all("x-forwarded-for").iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse().ok())
+            .collect();
+
+        let client = forwarded.iter().rev()
+            .find(|hop| !trusted_proxies.contains(hop))
+            .or(forwarded.first())
+            .copied()
+            .unwrap_or(peer);
+
+        Ok(ClientIp(client))
+    }
+}
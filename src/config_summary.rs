@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::config::{AbuseConfig, AuthProvider, DemoConfig, EntryRulesConfig, FeaturesConfig, HeartbeatConfig, InstancerConfig, LocalAuthConfig, NotificationsConfig};
+use crate::deployment_worker::DeploymentWorker;
+use crate::duration::HumanDuration;
+use crate::models::UserRole;
+
+const REDACTED: &str = "[redacted]";
+
+/// The effective configuration of a running instancer, with every secret redacted.
+#[derive(Serialize, Debug)]
+pub struct ConfigSummary {
+    listen_on: String,
+    namespace: Option<String>,
+    worker_count: u32,
+    worker_pools: BTreeMap<String, u32>,
+    max_concurrent_challenges: u32,
+    role_concurrent_challenges: BTreeMap<UserRole, u32>,
+    max_actions_per_minute: u32,
+    simulate_deployments: bool,
+    queue_capacity: usize,
+    update_capacity: usize,
+    message_replay_window: HumanDuration,
+    deploy_timeout: HumanDuration,
+    deploy_log_dir: Option<PathBuf>,
+    admin_count: usize,
+    staff_count: usize,
+    trusted_proxies: Vec<IpAddr>,
+    session_inactivity: HumanDuration,
+    session_max_age: Option<HumanDuration>,
+    features: FeaturesConfig,
+    heartbeat: HeartbeatConfig,
+    quota_instance_hours: Option<u32>,
+    quota_resets: Vec<String>,
+    abuse: Option<AbuseConfig>,
+    demo: Option<DemoConfig>,
+    database: PathBuf,
+    auth_providers: Vec<AuthProvider>,
+    local_auth: Option<LocalAuthConfig>,
+    #[cfg(feature = "ldap")]
+    ldap: Option<crate::config::LdapConfig>,
+    discord: Option<DiscordSummary>,
+    rctf_url: Option<String>,
+    webhooks: Vec<WebhookSummary>,
+    notifications: NotificationsConfig,
+    #[cfg(feature = "email")]
+    email: Option<EmailSummary>,
+    #[cfg(feature = "web-push")]
+    web_push: Option<crate::config::WebPushConfig>,
+    deployers: BTreeMap<String, DeployerSummary>,
+    services: BTreeMap<String, String>,
+    challenge_count: usize,
+    challenges: BTreeMap<String, ChallengeSummary>
+}
+
+#[derive(Serialize, Debug)]
+struct DiscordSummary {
+    client_id: String,
+    client_secret: &'static str,
+    redirect_url: String,
+    server_id: String,
+    bot_token: Option<&'static str>,
+    entry_rules: EntryRulesConfig,
+    profile_refresh_interval: Option<u32>
+}
+
+#[cfg(feature = "email")]
+#[derive(Serialize, Debug)]
+struct EmailSummary {
+    smtp_url: String,
+    from: String
+}
+
+#[derive(Serialize, Debug)]
+struct WebhookSummary {
+    url: String,
+    secret: Option<&'static str>
+}
+
+#[derive(Serialize, Debug)]
+struct DeployerSummary {
+    path: PathBuf,
+    cwd: Option<PathBuf>,
+    container_image: Option<String>,
+    sandboxed: bool,
+    pinned: bool,
+    forward_warnings: bool,
+    pool: Option<String>,
+    capacity: Option<u32>,
+    labels: BTreeMap<String, String>,
+    selftest: bool
+}
+
+#[derive(Serialize, Debug)]
+struct ChallengeSummary {
+    name: String,
+    deployer: String,
+    pipeline: Vec<String>,
+    targets: Vec<String>,
+    depends_on: Vec<String>,
+    ttl: HumanDuration,
+    enabled: bool,
+    extendable: bool,
+    restart_details: bool,
+    weight: u32,
+    owners: Vec<String>
+}
+
+impl ConfigSummary {
+    pub fn new(config: &InstancerConfig, deployer: &DeploymentWorker) -> Self {
+        let settings = &config.settings;
+
+        ConfigSummary {
+            listen_on: settings.listen_on.clone(),
+            namespace: settings.namespace.clone(),
+            worker_count: settings.worker_count,
+            worker_pools: settings.worker_pools.clone(),
+            max_concurrent_challenges: settings.max_concurrent_challenges,
+            role_concurrent_challenges: settings.role_concurrent_challenges.clone(),
+            max_actions_per_minute: settings.max_actions_per_minute,
+            simulate_deployments: settings.simulate_deployments,
+            queue_capacity: settings.queue_capacity,
+            update_capacity: settings.update_capacity,
+            message_replay_window: HumanDuration(settings.message_replay_window),
+            deploy_timeout: HumanDuration(settings.deploy_timeout),
+            deploy_log_dir: settings.deploy_log_dir.clone(),
+            admin_count: settings.admins.len(),
+            staff_count: settings.staff.len(),
+            trusted_proxies: settings.trusted_proxies.clone(),
+            session_inactivity: HumanDuration(settings.session_inactivity),
+            session_max_age: settings.session_max_age.map(HumanDuration),
+            features: config.features.clone(),
+            heartbeat: config.heartbeat,
+            quota_instance_hours: config.quotas.instance_hours,
+            quota_resets: config.quotas.resets.iter().map(ToString::to_string).collect(),
+            abuse: config.abuse.clone(),
+            demo: config.demo.clone(),
+            database: config.database.file_path.clone(),
+            auth_providers: config.auth.providers.clone(),
+            local_auth: config.auth.enabled(AuthProvider::Local).then(|| config.auth.local.clone()),
+            #[cfg(feature = "ldap")]
+            ldap: config.auth.ldap.clone(),
+            discord: config.discord.as_ref().map(|discord| DiscordSummary {
+                client_id: discord.client_id.clone(),
+                client_secret: REDACTED,
+                redirect_url: discord.redirect_url.clone(),
+                server_id: discord.server_id.clone(),
+                bot_token: discord.bot_token.as_ref().map(|_| REDACTED),
+                entry_rules: discord.entry_rules.clone(),
+                profile_refresh_interval: discord.profile_refresh_interval
+            }),
+            rctf_url: config.rctf.as_ref().map(|rctf| rctf.url.clone()),
+            webhooks: config.webhooks.iter()
+                .map(|webhook| WebhookSummary {
+                    url: redact_url(&webhook.url),
+                    secret: webhook.secret.as_ref().map(|_| REDACTED)
+                })
+                .collect(),
+            notifications: config.notifications.clone(),
+            #[cfg(feature = "email")]
+            email: config.email.as_ref().map(|email| EmailSummary {
+                smtp_url: redact_url(&email.smtp_url),
+                from: email.from.clone()
+            }),
+            #[cfg(feature = "web-push")]
+            web_push: config.web_push.clone(),
+            deployers: config.deployers.iter()
+                .map(|(id, cfg)| (id.clone(), DeployerSummary {
+                    path: cfg.path.clone(),
+                    cwd: cfg.cwd.clone(),
+                    container_image: cfg.container_image.clone(),
+                    sandboxed: cfg.sandbox.is_some(),
+                    pinned: cfg.sha256.is_some(),
+                    forward_warnings: cfg.forward_warnings,
+                    pool: cfg.pool.clone(),
+                    capacity: cfg.capacity,
+                    labels: cfg.labels.clone(),
+                    selftest: cfg.selftest
+                }))
+                .collect(),
+            services: config.services.iter().map(|(id, cfg)| (id.clone(), cfg.deployer.clone())).collect(),
+            challenge_count: deployer.challenges.len(),
+            challenges: config.challenges.iter()
+                .map(|(id, cfg)| (id.clone(), ChallengeSummary {
+                    name: cfg.name.clone(),
+                    deployer: cfg.deployer.clone(),
+                    pipeline: cfg.pipeline.clone(),
+                    targets: cfg.targets.clone(),
+                    depends_on: cfg.depends_on.clone(),
+                    ttl: HumanDuration(cfg.ttl),
+                    enabled: deployer.challenges.contains_key(id),
+                    extendable: cfg.extendable,
+                    restart_details: cfg.restart_details,
+                    weight: cfg.weight,
+                    owners: cfg.owners.clone()
+                }))
+                .collect()
+        }
+    }
+}
+
+/// Strips the query string and credentials from a URL, where tokens usually end up.
+fn redact_url(url: &str) -> String {
+    let Ok(mut url) = reqwest::Url::parse(url) else { return REDACTED.to_string() };
+    if url.query().is_some() { url.set_query(Some(REDACTED)); }
+    if url.password().is_some() { let _ = url.set_password(Some(REDACTED)); }
+    url.to_string()
+}
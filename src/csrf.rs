@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use axum::extract::{Query, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use tower_sessions::Session;
+
+use crate::error::RouterError;
+
+const CSRF_TOKEN_KEY: &str = "csrf_token";
+
+/// Header carrying the token on `fetch` requests. HTML forms pass it as the `csrf_token` query parameter instead.
+const CSRF_TOKEN_HEADER: &str = "x-csrf-token";
+
+/// Returns the CSRF token of the session, issuing one on first use.
+pub async fn session_token(session: &Session) -> Result<String, tower_sessions::session::Error> {
+    if let Some(token) = session.get::<String>(CSRF_TOKEN_KEY).await? {
+        return Ok(token);
+    }
+
+    let token: String = rand::random::<[u8; 32]>().iter().map(|byte| format!("{:02x}", byte)).collect();
+    session.insert(CSRF_TOKEN_KEY, &token).await?;
+    Ok(token)
+}
+
+/// Rejects state-changing requests of logged in users that don't carry the token of their session,
+/// so that another origin can't forge them using the session cookie.
+pub async fn verify(
+    session: Session,
+    Query(params): Query<HashMap<String, String>>,
+    request: Request,
+    next: Next
+) -> Result<Response, RouterError> {
+    if request.method().is_safe() || session.get::<String>("uid").await?.is_none() {
+        return Ok(next.run(request).await);
+    }
+
+    let submitted = request.headers().get(CSRF_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .or(params.get("csrf_token").map(String::as_str));
+
+    match (submitted, session.get::<String>(CSRF_TOKEN_KEY).await?) {
+        (Some(submitted), Some(expected)) if constant_time_eq(submitted.as_bytes(), expected.as_bytes()) => Ok(next.run(request).await),
+        _ => Err(RouterError::Forbidden)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1,9 +1,53 @@
-use crate::models::{ChallengeInstance, ChallengeInstanceState, TimeSinceEpoch, User};
-use sqlx::{Error, SqlitePool};
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(feature = "web-push")]
+use crate::models::PushSubscription;
+use crate::models::{AuditEntry, ChallengeInstance, ChallengeOverride, ChallengeInstanceState, ChallengeNotice, ChallengeOutcomes, EndReason, HourlyStarts, InstanceCountDrift, InstanceHistoryEntry, InstanceLabel, InstanceMetadata, InstanceUsage, LocalAccount, MissedMessage, OutboxEntry, PendingUpdate, TargetFailover, TimeSinceEpoch, User, UserPreferences, UserRole};
+use sqlx::{Error, SqliteConnection, SqlitePool};
+
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::FaultInjector;
 
 #[derive(Clone)]
 pub struct Database {
-    pool: SqlitePool
+    pool: SqlitePool,
+    #[cfg(feature = "fault-injection")]
+    faults: Option<FaultInjector>
+}
+
+/// Adds the runtime of the instances since they were last accounted for to their user's usage of the challenge,
+/// both in total and towards the current quota period. Only the instances of `user_id` on `challenge_id` are
+/// accounted for when given.
+async fn accrue_usage(conn: &mut SqliteConnection, now: &TimeSinceEpoch, user_id: Option<&str>, challenge_id: Option<&str>) -> Result<(), Error> {
+    sqlx::query!("INSERT INTO instance_usage (user_id, challenge_id, runtime, quota_runtime)
+        SELECT user_id, challenge_id, MAX(?1 - COALESCE(accounted_time, start_time), 0), MAX(?1 - COALESCE(accounted_time, start_time), 0) FROM challenge_instances
+        WHERE start_time IS NOT NULL AND (?2 IS NULL OR user_id = ?2) AND (?3 IS NULL OR challenge_id = ?3)
+        ON CONFLICT (user_id, challenge_id) DO UPDATE SET runtime = runtime + excluded.runtime, quota_runtime = quota_runtime + excluded.quota_runtime",
+        now, user_id, challenge_id)
+        .execute(conn).await.map(|_| ())
+}
+
+async fn write_outbox(conn: &mut SqliteConnection, outbox: &[OutboxEntry]) -> Result<(), Error> {
+    let now = TimeSinceEpoch::now();
+    for entry in outbox {
+        sqlx::query!("INSERT INTO update_outbox (time, user_id, challenge_id, payload) VALUES (?, ?, ?, ?)",
+            now, entry.user_id, entry.challenge_id, entry.payload)
+            .execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+/// Criteria of an admin instance search, each ignored when unset.
+#[derive(Default)]
+pub struct InstanceFilter<'a> {
+    pub challenge_id: Option<&'a str>,
+    pub user_id: Option<&'a str>,
+    pub state: Option<&'a ChallengeInstanceState>,
+    pub stop_after: Option<TimeSinceEpoch>,
+    pub stop_before: Option<TimeSinceEpoch>,
+    pub label_key: Option<&'a str>,
+    /// Only matched along with `label_key`.
+    pub label_value: Option<&'a str>
 }
 
 pub enum ChallengeInstanceInsertionResult {
@@ -16,25 +60,65 @@ impl Database {
     pub async fn new(pool: SqlitePool) -> sqlx::Result<Database> {
         sqlx::migrate!().run(&pool).await?;
         Ok(Database {
-            pool
+            pool,
+            #[cfg(feature = "fault-injection")]
+            faults: None
         })
     }
 
+    #[cfg(feature = "fault-injection")]
+    pub fn with_faults(self, faults: FaultInjector) -> Self {
+        Database { faults: Some(faults), ..self }
+    }
+
+    /// The pool every query goes through, after any fault injected into it.
+    async fn pool(&self) -> Result<&SqlitePool, Error> {
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = &self.faults {
+            faults.query().await?;
+        }
+        Ok(&self.pool)
+    }
+
     pub async fn fetch_user(&self, id: &str) -> sqlx::Result<Option<User>> {
-        sqlx::query_as("SELECT * FROM users WHERE id = ?")
-            .bind(id)
-            .fetch_optional(&self.pool).await
+        sqlx::query_as!(User, r#"SELECT id, username, display_name, avatar, creation_time AS "creation_time: TimeSinceEpoch", instance_count, scoreboard_id,
+                role AS "role: UserRole"
+            FROM users WHERE id = ?"#, id)
+            .fetch_optional(self.pool().await?).await
+    }
+
+    pub async fn get_accepted_terms_version(&self, user_id: &str) -> Result<Option<u32>, Error> {
+        sqlx::query_scalar!(r#"SELECT terms_version AS "terms_version: u32" FROM users WHERE id = ?"#, user_id)
+            .fetch_optional(self.pool().await?).await
+            .map(Option::flatten)
+    }
+
+    pub async fn accept_terms(&self, user_id: &str, version: u32) -> Result<(), Error> {
+        let now = TimeSinceEpoch::now();
+        sqlx::query!("UPDATE users SET terms_version = ?, terms_accepted_time = ? WHERE id = ?", version, now, user_id)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    /// Returns the preferences of the user, or the defaults if they never saved any.
+    pub async fn get_user_preferences(&self, user_id: &str) -> Result<UserPreferences, Error> {
+        sqlx::query_as!(UserPreferences, r#"SELECT locale, timezone, notify_expiry AS "notify_expiry: bool", notify_announcements AS "notify_announcements: bool", email
+            FROM user_preferences WHERE user_id = ?"#, user_id)
+            .fetch_optional(self.pool().await?).await
+            .map(Option::unwrap_or_default)
+    }
+
+    pub async fn set_user_preferences(&self, user_id: &str, preferences: &UserPreferences) -> Result<(), Error> {
+        sqlx::query!("INSERT INTO user_preferences (user_id, locale, timezone, notify_expiry, notify_announcements, email) VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (user_id) DO UPDATE SET locale = excluded.locale, timezone = excluded.timezone,
+                notify_expiry = excluded.notify_expiry, notify_announcements = excluded.notify_announcements, email = excluded.email",
+            user_id, preferences.locale, preferences.timezone, preferences.notify_expiry, preferences.notify_announcements, preferences.email)
+            .execute(self.pool().await?).await.map(|_| ())
     }
 
     pub async fn insert_user(&self, user: &User) -> Result<bool, Error> {
-        let result = sqlx::query("INSERT INTO users VALUES (?, ?, ?, ?, ?, ?)")
-            .bind(&user.id)
-            .bind(&user.username)
-            .bind(&user.display_name)
-            .bind(&user.avatar)
-            .bind(&user.creation_time)
-            .bind(&user.instance_count)
-            .execute(&self.pool).await;
+        let result = sqlx::query!("INSERT INTO users (id, username, display_name, avatar, creation_time, instance_count, scoreboard_id, role) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            user.id, user.username, user.display_name, user.avatar, user.creation_time, user.instance_count, user.scoreboard_id, user.role)
+            .execute(self.pool().await?).await;
 
         match result {
             Ok(_) => Ok(true),
@@ -43,101 +127,699 @@ impl Database {
         }
     }
 
-    pub async fn insert_challenge_instance(&self, instance: &ChallengeInstance, max_instance_count: u32) -> Result<ChallengeInstanceInsertionResult, Error> {
-        let mut tx = self.pool.begin().await?;
+    /// Registers a user along with their local account, returns false if the email is already taken.
+    pub async fn insert_local_account(&self, user: &User, email: &str, password_hash: &str) -> Result<bool, Error> {
+        let mut tx = self.pool().await?.begin().await?;
 
-        let result = sqlx::query("UPDATE users SET instance_count = instance_count + 1 WHERE id = ? AND instance_count < ?")
-            .bind(&instance.user_id)
-            .bind(max_instance_count)
+        let result = sqlx::query!("INSERT INTO local_accounts (user_id, email, password_hash) VALUES (?, ?, ?)", user.id, email, password_hash)
+            .execute(&mut *tx).await;
+        match result {
+            Ok(_) => {}
+            Err(Error::Database(err)) if err.is_unique_violation() => return Ok(false),
+            Err(err) => return Err(err)
+        }
+
+        sqlx::query!("INSERT INTO users (id, username, display_name, avatar, creation_time, instance_count, scoreboard_id, role) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            user.id, user.username, user.display_name, user.avatar, user.creation_time, user.instance_count, user.scoreboard_id, user.role)
             .execute(&mut *tx).await?;
 
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn fetch_local_account(&self, email: &str) -> Result<Option<LocalAccount>, Error> {
+        sqlx::query_as!(LocalAccount, "SELECT user_id, password_hash FROM local_accounts WHERE email = ?", email)
+            .fetch_optional(self.pool().await?).await
+    }
+
+    /// Replaces the password of a local account, returns false if the user has none.
+    pub async fn set_local_password(&self, user_id: &str, password_hash: &str) -> Result<bool, Error> {
+        let result = sqlx::query!("UPDATE local_accounts SET password_hash = ? WHERE user_id = ?", password_hash, user_id)
+            .execute(self.pool().await?).await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Grants a role to the given users, leaving out those who don't exist yet.
+    pub async fn set_user_roles(&self, user_ids: &[String], role: &UserRole) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+
+        for user_id in user_ids {
+            sqlx::query!("UPDATE users SET role = ? WHERE id = ?", role, user_id)
+                .execute(&mut *tx).await?;
+        }
+
+        tx.commit().await
+    }
+
+    /// Returns the ids of the users of a role created before the given time.
+    pub async fn get_users_created_before(&self, role: &UserRole, before: &TimeSinceEpoch) -> Result<Vec<String>, Error> {
+        sqlx::query_scalar!("SELECT id FROM users WHERE role = ? AND creation_time < ?", role, before)
+            .fetch_all(self.pool().await?).await
+    }
+
+    /// Deletes a user along with their preferences, history and missed messages unless they still have instances,
+    /// returns whether they were deleted. The archive, usage and audit log keep their records.
+    pub async fn delete_user(&self, user_id: &str) -> Result<bool, Error> {
+        let mut tx = self.pool().await?.begin().await?;
+
+        let result = sqlx::query!("DELETE FROM users WHERE id = ?1 AND NOT EXISTS (SELECT 1 FROM challenge_instances WHERE user_id = ?1)", user_id)
+            .execute(&mut *tx).await?;
         if result.rows_affected() == 0 {
-            return Ok(ChallengeInstanceInsertionResult::LimitReached);
+            return Ok(false);
         }
 
-        let result = sqlx::query("INSERT INTO challenge_instances VALUES (?, ?, ?, ?, ?)")
-            .bind(&instance.user_id)
-            .bind(&instance.challenge_id)
-            .bind(&instance.state)
-            .bind(&instance.details)
-            .bind(&instance.stop_time)
-            .execute(&mut *tx).await;
+        sqlx::query!("DELETE FROM user_preferences WHERE user_id = ?", user_id)
+            .execute(&mut *tx).await?;
+        sqlx::query!("DELETE FROM challenge_history WHERE user_id = ?", user_id)
+            .execute(&mut *tx).await?;
+        sqlx::query!("DELETE FROM missed_messages WHERE user_id = ?", user_id)
+            .execute(&mut *tx).await?;
+        sqlx::query!("DELETE FROM push_subscriptions WHERE user_id = ?", user_id)
+            .execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Registers a browser for push notifications, moving it over if another user had subscribed it.
+    #[cfg(feature = "web-push")]
+    pub async fn insert_push_subscription(&self, user_id: &str, subscription: &PushSubscription) -> Result<(), Error> {
+        let now = TimeSinceEpoch::now();
+        sqlx::query!("INSERT INTO push_subscriptions (endpoint, user_id, p256dh, auth, creation_time) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (endpoint) DO UPDATE SET user_id = excluded.user_id, p256dh = excluded.p256dh, auth = excluded.auth, creation_time = excluded.creation_time",
+            subscription.endpoint, user_id, subscription.p256dh, subscription.auth, now)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    #[cfg(feature = "web-push")]
+    pub async fn delete_push_subscription(&self, user_id: &str, endpoint: &str) -> Result<bool, Error> {
+        let result = sqlx::query!("DELETE FROM push_subscriptions WHERE user_id = ? AND endpoint = ?", user_id, endpoint)
+            .execute(self.pool().await?).await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[cfg(feature = "web-push")]
+    pub async fn get_push_subscriptions(&self, user_id: &str) -> Result<Vec<PushSubscription>, Error> {
+        sqlx::query_as!(PushSubscription, "SELECT endpoint, p256dh, auth FROM push_subscriptions WHERE user_id = ?", user_id)
+            .fetch_all(self.pool().await?).await
+    }
+
+    /// Replaces the Discord profile of a user, marking it as refreshed.
+    pub async fn update_user_profile(&self, user_id: &str, username: &str, display_name: &str, avatar: &Option<String>) -> Result<(), Error> {
+        let now = TimeSinceEpoch::now();
+        sqlx::query!("UPDATE users SET username = ?, display_name = ?, avatar = ?, profile_refresh_time = ? WHERE id = ?",
+            username, display_name, avatar, now, user_id)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    /// Returns the ids of the Discord users whose profile wasn't refreshed since the given time, stalest first.
+    pub async fn get_stale_discord_profiles(&self, before: &TimeSinceEpoch, limit: u32) -> Result<Vec<String>, Error> {
+        sqlx::query_scalar!("SELECT id FROM users WHERE id NOT GLOB '*[^0-9]*' AND COALESCE(profile_refresh_time, creation_time) < ?
+            ORDER BY COALESCE(profile_refresh_time, creation_time) LIMIT ?", before, limit)
+            .fetch_all(self.pool().await?).await
+    }
+
+    pub async fn update_user_scoreboard_id(&self, id: &str, scoreboard_id: &str) -> Result<(), Error> {
+        sqlx::query!("UPDATE users SET scoreboard_id = ? WHERE id = ?", scoreboard_id, id)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    /// Inserts an instance taking `weight` slots unless its user would then fill more than `max_slots`. The limit is
+    /// checked against the weights of the instances themselves in the same statement, and the user's `instance_count`
+    /// follows through triggers.
+    pub async fn insert_challenge_instance(&self, instance: &ChallengeInstance, weight: u32, max_slots: u32) -> Result<ChallengeInstanceInsertionResult, Error> {
+        let now = TimeSinceEpoch::now();
+        let result = sqlx::query!("INSERT INTO challenge_instances (user_id, challenge_id, state, details, stop_time, nonce, start_time, transition_time, weight)
+            SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?9, ?10 FROM users
+            WHERE id = ?1 AND (SELECT COALESCE(SUM(weight), 0) FROM challenge_instances WHERE user_id = ?1) + ?10 <= ?8",
+            instance.user_id, instance.challenge_id, instance.state, instance.details, instance.stop_time, instance.nonce, instance.start_time, max_slots, now, weight)
+            .execute(self.pool().await?).await;
 
         match result {
-            Ok(_) => {
-                tx.commit().await?;
-                Ok(ChallengeInstanceInsertionResult::Inserted)
-            }
+            Ok(result) if result.rows_affected() == 0 => Ok(ChallengeInstanceInsertionResult::LimitReached),
+            Ok(_) => Ok(ChallengeInstanceInsertionResult::Inserted),
             Err(Error::Database(err)) if err.is_unique_violation() => Ok(ChallengeInstanceInsertionResult::Exists),
             Err(err) => Err(err)
         }
     }
 
+    /// Resets the instance count of users whose count drifted from the weights of their actual instances, returning
+    /// the drifts.
+    pub async fn reconcile_instance_counts(&self) -> Result<Vec<InstanceCountDrift>, Error> {
+        let mut tx = self.pool().await?.begin().await?;
+
+        let drifts = sqlx::query_as!(InstanceCountDrift, r#"SELECT user_id AS "user_id!", recorded AS "recorded!", actual AS "actual!: i64" FROM (
+                SELECT id AS user_id, instance_count AS recorded, (SELECT COALESCE(SUM(weight), 0) FROM challenge_instances WHERE user_id = users.id) AS actual FROM users
+            ) WHERE recorded != actual"#)
+            .fetch_all(&mut *tx).await?;
+
+        sqlx::query!("UPDATE users SET instance_count = (SELECT COALESCE(SUM(weight), 0) FROM challenge_instances WHERE user_id = users.id)
+            WHERE instance_count != (SELECT COALESCE(SUM(weight), 0) FROM challenge_instances WHERE user_id = users.id)")
+            .execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(drifts)
+    }
+
     pub async fn transition_challenge_instance_state(&self, user_id: &str, challenge_id: &str, old_state: ChallengeInstanceState, new_state: ChallengeInstanceState) -> Result<bool, Error> {
-        let result = sqlx::query("UPDATE challenge_instances SET state = ? WHERE user_id = ? AND challenge_id = ? AND state = ?")
-            .bind(new_state)
-            .bind(user_id)
-            .bind(challenge_id)
-            .bind(old_state)
-            .execute(&self.pool).await?;
+        let now = TimeSinceEpoch::now();
+        let result = sqlx::query!("UPDATE challenge_instances SET state = ?, transition_time = ? WHERE user_id = ? AND challenge_id = ? AND state = ?", new_state, now, user_id, challenge_id, old_state)
+            .execute(self.pool().await?).await?;
         Ok(result.rows_affected() == 1)
     }
 
-    pub async fn populate_running_challenge_instance(&self, user_id: &str, challenge_id: &str, details: &str, stop_time: Option<TimeSinceEpoch>) -> Result<(), Error> {
+    /// Marks an instance as running, keeping its details if none are given, and writes the updates announcing it to the
+    /// outbox in the same transaction.
+    pub async fn populate_running_challenge_instance(&self, user_id: &str, challenge_id: &str, details: Option<&str>, stop_time: Option<TimeSinceEpoch>, outbox: &[OutboxEntry]) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+        let now = TimeSinceEpoch::now();
+
         match stop_time {
             None => {
-                sqlx::query("UPDATE challenge_instances SET state = ?, details = ? WHERE user_id = ? AND challenge_id = ?")
-                    .bind(ChallengeInstanceState::Running)
-                    .bind(details)
-                    .bind(user_id)
-                    .bind(challenge_id)
-                    .execute(&self.pool).await.map(|_| ())
+                sqlx::query!("UPDATE challenge_instances SET state = ?, details = COALESCE(?, details), transition_time = ? WHERE user_id = ? AND challenge_id = ?",
+                    ChallengeInstanceState::Running, details, now, user_id, challenge_id)
+                    .execute(&mut *tx).await?;
             }
+            /* a new stop time means the instance just started its lifetime */
             Some(stop_time) => {
-                sqlx::query("UPDATE challenge_instances SET state = ?, details = ?, stop_time = ? WHERE user_id = ? AND challenge_id = ?")
-                    .bind(ChallengeInstanceState::Running)
-                    .bind(details)
-                    .bind(stop_time)
-                    .bind(user_id)
-                    .bind(challenge_id)
-                    .execute(&self.pool).await.map(|_| ())
+                sqlx::query!("UPDATE challenge_instances SET state = ?, details = COALESCE(?, details), stop_time = ?, start_time = ?, progress = NULL, transition_time = ? WHERE user_id = ? AND challenge_id = ?",
+                    ChallengeInstanceState::Running, details, stop_time, now, now, user_id, challenge_id)
+                    .execute(&mut *tx).await?;
             }
         }
+
+        write_outbox(&mut tx, outbox).await?;
+        tx.commit().await
     }
 
+    /// Marks a starting instance as deploying with the given progress, returns false if it isn't starting anymore.
+    pub async fn set_challenge_instance_progress(&self, user_id: &str, challenge_id: &str, progress: u8) -> Result<bool, Error> {
+        let now = TimeSinceEpoch::now();
+        let result = sqlx::query!("UPDATE challenge_instances SET state = ?1, progress = ?2, transition_time = CASE WHEN state = ?1 THEN transition_time ELSE ?3 END
+            WHERE state IN (?4, ?1) AND user_id = ?5 AND challenge_id = ?6",
+            ChallengeInstanceState::Deploying, progress, now, ChallengeInstanceState::QueuedStart, user_id, challenge_id)
+            .execute(self.pool().await?).await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Pushes back the stop time of a running instance, rescuing it if it is in its grace period.
     pub async fn extend_challenge_instance(&self, user_id: &str, challenge_id: &str, stop_time: TimeSinceEpoch) -> Result<bool, Error> {
-        let result = sqlx::query("UPDATE challenge_instances SET stop_time = ? WHERE state = ? AND user_id = ? AND challenge_id = ?")
-            .bind(stop_time)
-            .bind(ChallengeInstanceState::Running)
-            .bind(user_id)
-            .bind(challenge_id)
-            .execute(&self.pool).await?;
+        let result = sqlx::query!("UPDATE challenge_instances SET state = ?, stop_time = ? WHERE state IN (?, ?) AND user_id = ? AND challenge_id = ? AND paused_at IS NULL",
+            ChallengeInstanceState::Running, stop_time, ChallengeInstanceState::Running, ChallengeInstanceState::Expiring, user_id, challenge_id)
+            .execute(self.pool().await?).await?;
         Ok(result.rows_affected() == 1)
     }
 
-    pub async fn delete_challenge_instance(&self, user_id: &str, challenge_id: &str) -> Result<(), Error> {
-        let mut tx = self.pool.begin().await?;
+    /// Extends an instance on behalf of its user, counting towards the challenge's extension limit.
+    pub async fn use_challenge_instance_extension(&self, user_id: &str, challenge_id: &str, stop_time: TimeSinceEpoch, max_extensions: Option<u32>) -> Result<bool, Error> {
+        let max_extensions = max_extensions.map_or(i64::MAX, i64::from);
+        let result = sqlx::query!("UPDATE challenge_instances SET state = ?, stop_time = ?, extension_count = extension_count + 1 WHERE state IN (?, ?) AND user_id = ? AND challenge_id = ? AND extension_count < ? AND paused_at IS NULL",
+            ChallengeInstanceState::Running, stop_time, ChallengeInstanceState::Running, ChallengeInstanceState::Expiring, user_id, challenge_id, max_extensions)
+            .execute(self.pool().await?).await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn begin_challenge_instance_grace_period(&self, user_id: &str, challenge_id: &str, stop_time: &TimeSinceEpoch) -> Result<bool, Error> {
+        let result = sqlx::query!("UPDATE challenge_instances SET state = ?, stop_time = ? WHERE state = ? AND user_id = ? AND challenge_id = ? AND paused_at IS NULL",
+            ChallengeInstanceState::Expiring, stop_time, ChallengeInstanceState::Running, user_id, challenge_id)
+            .execute(self.pool().await?).await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Freezes the countdown of a running instance, returns false if it wasn't running or was already paused.
+    pub async fn pause_challenge_instance(&self, user_id: &str, challenge_id: &str, now: &TimeSinceEpoch, maintenance: bool) -> Result<bool, Error> {
+        let result = sqlx::query!("UPDATE challenge_instances SET paused_at = ?, maintenance_pause = ? WHERE state IN (?, ?) AND user_id = ? AND challenge_id = ? AND paused_at IS NULL",
+            now, maintenance, ChallengeInstanceState::Running, ChallengeInstanceState::Expiring, user_id, challenge_id)
+            .execute(self.pool().await?).await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Freezes the countdown of every running instance for maintenance, returning the (user_id, challenge_id) of
+    /// those that weren't already paused.
+    pub async fn pause_all_challenge_instances(&self, now: &TimeSinceEpoch) -> Result<Vec<(String, String)>, Error> {
+        let rows = sqlx::query!("UPDATE challenge_instances SET paused_at = ?, maintenance_pause = TRUE WHERE state IN (?, ?) AND paused_at IS NULL
+            RETURNING user_id, challenge_id", now, ChallengeInstanceState::Running, ChallengeInstanceState::Expiring)
+            .fetch_all(self.pool().await?).await?;
+        Ok(rows.into_iter().map(|row| (row.user_id, row.challenge_id)).collect())
+    }
+
+    /// Restarts the countdown of a paused instance, pushing back its stop time by how long it was paused. Returns the
+    /// new stop time, or None if the instance wasn't paused.
+    pub async fn resume_challenge_instance(&self, user_id: &str, challenge_id: &str, now: &TimeSinceEpoch) -> Result<Option<TimeSinceEpoch>, Error> {
+        sqlx::query_scalar!(r#"UPDATE challenge_instances SET stop_time = stop_time + (? - paused_at), paused_at = NULL, maintenance_pause = FALSE
+            WHERE user_id = ? AND challenge_id = ? AND paused_at IS NOT NULL RETURNING stop_time AS "stop_time!: TimeSinceEpoch""#, now, user_id, challenge_id)
+            .fetch_optional(self.pool().await?).await
+    }
+
+    /// Restarts the countdown of the instances paused for maintenance, returning them along with their new stop time.
+    pub async fn resume_maintenance_paused_instances(&self, now: &TimeSinceEpoch) -> Result<Vec<(String, String, TimeSinceEpoch)>, Error> {
+        let rows = sqlx::query!(r#"UPDATE challenge_instances SET stop_time = stop_time + (? - paused_at), paused_at = NULL, maintenance_pause = FALSE
+            WHERE maintenance_pause RETURNING user_id, challenge_id, stop_time AS "stop_time!: TimeSinceEpoch""#, now)
+            .fetch_all(self.pool().await?).await?;
+        Ok(rows.into_iter().map(|row| (row.user_id, row.challenge_id, row.stop_time)).collect())
+    }
+
+    /// Shifts the stop time of the running instances by `skew_ms` milliseconds, following a step of the wall clock.
+    pub async fn shift_stop_times(&self, skew_ms: i64) -> Result<(), Error> {
+        sqlx::query!("UPDATE challenge_instances SET stop_time = stop_time + ? WHERE state IN (?, ?) AND paused_at IS NULL AND stop_time IS NOT NULL",
+            skew_ms, ChallengeInstanceState::Running, ChallengeInstanceState::Expiring)
+            .execute(self.pool().await?).await?;
+        Ok(())
+    }
 
-        sqlx::query("DELETE FROM challenge_instances WHERE user_id = ? AND challenge_id = ?")
-            .bind(user_id)
-            .bind(challenge_id)
+    /// Whether instances are paused for maintenance, i.e. maintenance was under way when the instancer last stopped.
+    pub async fn has_maintenance_pauses(&self) -> Result<bool, Error> {
+        sqlx::query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM challenge_instances WHERE maintenance_pause) AS "paused!: bool""#)
+            .fetch_one(self.pool().await?).await
+    }
+
+    /// Records that the event ended, so that it stays over across restarts. The first end is the one kept.
+    pub async fn set_event_ended(&self, ended_at: &TimeSinceEpoch) -> Result<(), Error> {
+        sqlx::query!("INSERT OR IGNORE INTO event_state (id, ended_at) VALUES (1, ?)", ended_at)
+            .execute(self.pool().await?).await?;
+        Ok(())
+    }
+
+    pub async fn is_event_ended(&self) -> Result<bool, Error> {
+        sqlx::query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM event_state) AS "ended!: bool""#)
+            .fetch_one(self.pool().await?).await
+    }
+
+    /// Records why an instance queued to end is ending, unless a reason was already recorded.
+    pub async fn set_challenge_instance_end_reason(&self, user_id: &str, challenge_id: &str, reason: &EndReason) -> Result<(), Error> {
+        sqlx::query!("UPDATE challenge_instances SET end_reason = COALESCE(end_reason, ?) WHERE user_id = ? AND challenge_id = ?", reason, user_id, challenge_id)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    /// Records why a deployment of an instance failed, moving it back to QueuedStart if it was deploying, and writes the
+    /// updates announcing it to the outbox in the same transaction.
+    pub async fn fail_challenge_instance(&self, user_id: &str, challenge_id: &str, reason: &EndReason, outbox: &[OutboxEntry]) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+        let now = TimeSinceEpoch::now();
+
+        sqlx::query!("UPDATE challenge_instances SET end_reason = COALESCE(end_reason, ?) WHERE user_id = ? AND challenge_id = ?", reason, user_id, challenge_id)
+            .execute(&mut *tx).await?;
+        sqlx::query!("UPDATE challenge_instances SET state = ?, transition_time = ? WHERE user_id = ? AND challenge_id = ? AND state = ?",
+            ChallengeInstanceState::QueuedStart, now, user_id, challenge_id, ChallengeInstanceState::Deploying)
+            .execute(&mut *tx).await?;
+
+        write_outbox(&mut tx, outbox).await?;
+        tx.commit().await
+    }
+
+    /// Deletes an instance whose start was never queued, without archiving it.
+    pub async fn delete_queued_challenge_instance(&self, user_id: &str, challenge_id: &str) -> Result<(), Error> {
+        sqlx::query!("DELETE FROM challenge_instances WHERE user_id = ? AND challenge_id = ? AND state = ?", user_id, challenge_id, ChallengeInstanceState::QueuedStart)
+            .execute(self.pool().await?).await?;
+        Ok(())
+    }
+
+    /// Moves an instance to the archive, ended for its recorded reason or `reason` if none was, deletes its metadata
+    /// and labels, and writes the updates announcing it to the outbox in the same transaction.
+    pub async fn delete_challenge_instance(&self, user_id: &str, challenge_id: &str, reason: &EndReason, outbox: &[OutboxEntry]) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+        let now = TimeSinceEpoch::now();
+
+        accrue_usage(&mut tx, &now, Some(user_id), Some(challenge_id)).await?;
+
+        sqlx::query!("INSERT INTO instance_archive (user_id, challenge_id, nonce, details, extension_count, started_at, ended_at, end_reason)
+            SELECT user_id, challenge_id, nonce, details, extension_count, start_time, ?, COALESCE(end_reason, ?) FROM challenge_instances
+            WHERE user_id = ? AND challenge_id = ?", now, reason, user_id, challenge_id)
+            .execute(&mut *tx).await?;
+
+        sqlx::query!("DELETE FROM challenge_instances WHERE user_id = ? AND challenge_id = ?", user_id, challenge_id)
+            .execute(&mut *tx).await?;
+
+        sqlx::query!("DELETE FROM instance_metadata WHERE user_id = ? AND challenge_id = ?", user_id, challenge_id)
             .execute(&mut *tx).await?;
 
-        sqlx::query("UPDATE users SET instance_count = instance_count - 1 WHERE id = ?")
-            .bind(user_id)
+        sqlx::query!("DELETE FROM instance_labels WHERE user_id = ? AND challenge_id = ?", user_id, challenge_id)
+            .execute(&mut *tx).await?;
+
+        write_outbox(&mut tx, outbox).await?;
+        tx.commit().await
+    }
+
+    /// Returns the outbox entries that haven't been published yet, in the order they were written.
+    pub async fn get_pending_updates(&self, limit: u32) -> Result<Vec<PendingUpdate>, Error> {
+        let rows = sqlx::query!(r#"SELECT id AS "id!", user_id, challenge_id, payload FROM update_outbox WHERE delivered_at IS NULL ORDER BY id LIMIT ?"#, limit)
+            .fetch_all(self.pool().await?).await?;
+
+        Ok(rows.into_iter()
+            .map(|row| PendingUpdate {
+                id: row.id,
+                entry: OutboxEntry { user_id: row.user_id, challenge_id: row.challenge_id, payload: row.payload }
+            })
+            .collect())
+    }
+
+    /// Marks outbox entries as published, and removes those published before `oldest`.
+    pub async fn mark_updates_delivered(&self, ids: &[i64], oldest: &TimeSinceEpoch) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+        let now = TimeSinceEpoch::now();
+
+        for id in ids {
+            sqlx::query!("UPDATE update_outbox SET delivered_at = ? WHERE id = ?", now, id)
+                .execute(&mut *tx).await?;
+        }
+
+        sqlx::query!("DELETE FROM update_outbox WHERE delivered_at < ?", oldest)
             .execute(&mut *tx).await?;
 
         tx.commit().await
     }
 
+    pub async fn get_challenge_instance(&self, user_id: &str, challenge_id: &str) -> Result<Option<ChallengeInstance>, Error> {
+        sqlx::query_as!(ChallengeInstance, r#"SELECT user_id, challenge_id, state AS "state: ChallengeInstanceState", details, stop_time AS "stop_time: TimeSinceEpoch",
+                nonce, start_time AS "start_time: TimeSinceEpoch", extension_count, progress AS "progress: u8", paused_at AS "paused_at: TimeSinceEpoch"
+            FROM challenge_instances WHERE user_id = ? AND challenge_id = ?"#, user_id, challenge_id)
+            .fetch_optional(self.pool().await?).await
+    }
+
+    /// Returns a page of the instances matching the filter, ordered by user then challenge, along with the total match count.
+    pub async fn search_challenge_instances(&self, filter: &InstanceFilter<'_>, limit: u32, offset: u32) -> Result<(Vec<ChallengeInstance>, i64), Error> {
+        let instances = sqlx::query_as!(ChallengeInstance, r#"SELECT user_id, challenge_id, state AS "state: ChallengeInstanceState", details, stop_time AS "stop_time: TimeSinceEpoch",
+                nonce, start_time AS "start_time: TimeSinceEpoch", extension_count, progress AS "progress: u8", paused_at AS "paused_at: TimeSinceEpoch"
+            FROM challenge_instances
+            WHERE (?1 IS NULL OR challenge_id = ?1) AND (?2 IS NULL OR user_id = ?2) AND (?3 IS NULL OR state = ?3)
+                AND (?4 IS NULL OR stop_time >= ?4) AND (?5 IS NULL OR stop_time < ?5)
+                AND (?6 IS NULL OR EXISTS (SELECT 1 FROM instance_labels AS label
+                    WHERE label.user_id = challenge_instances.user_id AND label.challenge_id = challenge_instances.challenge_id
+                        AND label.key = ?6 AND (?7 IS NULL OR label.value = ?7)))
+            ORDER BY user_id, challenge_id LIMIT ?8 OFFSET ?9"#,
+            filter.challenge_id, filter.user_id, filter.state, filter.stop_after, filter.stop_before, filter.label_key, filter.label_value, limit, offset)
+            .fetch_all(self.pool().await?).await?;
+
+        let total = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!: i64" FROM challenge_instances
+            WHERE (?1 IS NULL OR challenge_id = ?1) AND (?2 IS NULL OR user_id = ?2) AND (?3 IS NULL OR state = ?3)
+                AND (?4 IS NULL OR stop_time >= ?4) AND (?5 IS NULL OR stop_time < ?5)
+                AND (?6 IS NULL OR EXISTS (SELECT 1 FROM instance_labels AS label
+                    WHERE label.user_id = challenge_instances.user_id AND label.challenge_id = challenge_instances.challenge_id
+                        AND label.key = ?6 AND (?7 IS NULL OR label.value = ?7)))"#,
+            filter.challenge_id, filter.user_id, filter.state, filter.stop_after, filter.stop_before, filter.label_key, filter.label_value)
+            .fetch_one(self.pool().await?).await?;
+
+        Ok((instances, total))
+    }
+
     pub async fn get_user_challenge_instances(&self, user_id: &str) -> Result<Vec<ChallengeInstance>, Error> {
-        sqlx::query_as("SELECT * FROM challenge_instances WHERE user_id = ?")
-            .bind(user_id)
-            .fetch_all(&self.pool).await
+        sqlx::query_as!(ChallengeInstance, r#"SELECT user_id, challenge_id, state AS "state: ChallengeInstanceState", details, stop_time AS "stop_time: TimeSinceEpoch",
+                nonce, start_time AS "start_time: TimeSinceEpoch", extension_count, progress AS "progress: u8", paused_at AS "paused_at: TimeSinceEpoch"
+            FROM challenge_instances WHERE user_id = ?"#, user_id)
+            .fetch_all(self.pool().await?).await
+    }
+
+    /// Returns the most recent instances, live ones first then archived ones by end time, optionally only those of a
+    /// user or a challenge.
+    pub async fn get_instance_history(&self, user_id: Option<&str>, challenge_id: Option<&str>, limit: u32) -> Result<Vec<InstanceHistoryEntry>, Error> {
+        sqlx::query_as!(InstanceHistoryEntry, r#"SELECT user_id AS "user_id!", challenge_id AS "challenge_id!", nonce AS "nonce!", details, extension_count AS "extension_count!",
+                started_at AS "started_at: TimeSinceEpoch", ended_at AS "ended_at: TimeSinceEpoch", end_reason AS "end_reason: EndReason"
+            FROM instance_history
+            WHERE (?1 IS NULL OR user_id = ?1) AND (?2 IS NULL OR challenge_id = ?2)
+            ORDER BY ended_at IS NULL DESC, ended_at DESC LIMIT ?3"#, user_id, challenge_id, limit)
+            .fetch_all(self.pool().await?).await
+    }
+
+    pub async fn insert_challenge_history(&self, user_id: &str, challenge_id: &str, first_start_time: &TimeSinceEpoch) -> Result<(), Error> {
+        sqlx::query!("INSERT OR IGNORE INTO challenge_history (user_id, challenge_id, first_start_time) VALUES (?, ?, ?)", user_id, challenge_id, first_start_time)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    pub async fn get_user_challenge_history(&self, user_id: &str) -> Result<Vec<String>, Error> {
+        sqlx::query_scalar!("SELECT challenge_id FROM challenge_history WHERE user_id = ?", user_id)
+            .fetch_all(self.pool().await?).await
+    }
+
+    /// Returns the instances that entered a transitional state (queued or deploying) before `before` and haven't
+    /// left it since.
+    pub async fn get_stale_challenge_instances(&self, before: &TimeSinceEpoch) -> Result<Vec<ChallengeInstance>, Error> {
+        sqlx::query_as!(ChallengeInstance, r#"SELECT user_id, challenge_id, state AS "state: ChallengeInstanceState", details, stop_time AS "stop_time: TimeSinceEpoch",
+                nonce, start_time AS "start_time: TimeSinceEpoch", extension_count, progress AS "progress: u8", paused_at AS "paused_at: TimeSinceEpoch"
+            FROM challenge_instances WHERE state IN (?, ?, ?, ?) AND transition_time < ?"#,
+            ChallengeInstanceState::QueuedStart, ChallengeInstanceState::Deploying, ChallengeInstanceState::QueuedStop, ChallengeInstanceState::QueuedRestart, before)
+            .fetch_all(self.pool().await?).await
+    }
+
+    /// Restarts the clock of an instance still in `state`, returns false if it moved on.
+    pub async fn touch_challenge_instance_transition(&self, user_id: &str, challenge_id: &str, state: &ChallengeInstanceState) -> Result<bool, Error> {
+        let now = TimeSinceEpoch::now();
+        let result = sqlx::query!("UPDATE challenge_instances SET transition_time = ? WHERE user_id = ? AND challenge_id = ? AND state = ?", now, user_id, challenge_id, state)
+            .execute(self.pool().await?).await?;
+        Ok(result.rows_affected() == 1)
     }
 
     pub async fn get_challenge_instances(&self) -> Result<Vec<ChallengeInstance>, Error> {
-        sqlx::query_as("SELECT * FROM challenge_instances")
-            .fetch_all(&self.pool).await
+        sqlx::query_as!(ChallengeInstance, r#"SELECT user_id, challenge_id, state AS "state: ChallengeInstanceState", details, stop_time AS "stop_time: TimeSinceEpoch",
+                nonce, start_time AS "start_time: TimeSinceEpoch", extension_count, progress AS "progress: u8", paused_at AS "paused_at: TimeSinceEpoch"
+            FROM challenge_instances"#)
+            .fetch_all(self.pool().await?).await
+    }
+
+    /// Accounts for the runtime of every running instance up to now.
+    pub async fn accrue_instance_usage(&self) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+        let now = TimeSinceEpoch::now();
+
+        accrue_usage(&mut tx, &now, None, None).await?;
+
+        sqlx::query!("UPDATE challenge_instances SET accounted_time = ? WHERE start_time IS NOT NULL", now)
+            .execute(&mut *tx).await?;
+
+        tx.commit().await
+    }
+
+    pub async fn get_instance_usage(&self) -> Result<Vec<InstanceUsage>, Error> {
+        sqlx::query_as!(InstanceUsage, "SELECT user_id, challenge_id, runtime FROM instance_usage")
+            .fetch_all(self.pool().await?).await
+    }
+
+    pub async fn get_hourly_starts(&self) -> Result<Vec<HourlyStarts>, Error> {
+        sqlx::query_as!(HourlyStarts, r#"SELECT started_at / 3600000 AS "hour!: i64", COUNT(*) AS "count!: i64" FROM instance_history WHERE started_at IS NOT NULL GROUP BY 1 ORDER BY 1"#)
+            .fetch_all(self.pool().await?).await
+    }
+
+    pub async fn get_challenge_outcomes(&self) -> Result<Vec<ChallengeOutcomes>, Error> {
+        sqlx::query_as!(ChallengeOutcomes, r#"SELECT challenge_id, COUNT(*) AS "total!: i64", SUM(end_reason = 'failed') AS "failures!: i64" FROM instance_archive GROUP BY challenge_id ORDER BY 3 DESC, 2 DESC"#)
+            .fetch_all(self.pool().await?).await
+    }
+
+    /// Returns the runtime in milliseconds counted towards the user's quota, including running instances.
+    pub async fn get_user_quota_usage(&self, user_id: &str) -> Result<i64, Error> {
+        let now = TimeSinceEpoch::now();
+        sqlx::query_scalar!(r#"SELECT
+            COALESCE((SELECT SUM(quota_runtime) FROM instance_usage WHERE user_id = ?1), 0)
+            + COALESCE((SELECT SUM(MAX(?2 - COALESCE(accounted_time, start_time), 0)) FROM challenge_instances WHERE user_id = ?1 AND start_time IS NOT NULL), 0) AS "usage!: i64""#,
+            user_id, now)
+            .fetch_one(self.pool().await?).await
+    }
+
+    pub async fn get_last_quota_reset(&self) -> Result<Option<TimeSinceEpoch>, Error> {
+        sqlx::query_scalar!(r#"SELECT reset_time AS "reset_time: TimeSinceEpoch" FROM quota_resets WHERE id = 0"#)
+            .fetch_optional(self.pool().await?).await
+    }
+
+    /// Starts a new quota period, clearing every user's quota usage and the extension counters of running instances.
+    pub async fn reset_quotas(&self) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+        let now = TimeSinceEpoch::now();
+
+        accrue_usage(&mut tx, &now, None, None).await?;
+
+        sqlx::query!("UPDATE challenge_instances SET accounted_time = ?, extension_count = 0 WHERE start_time IS NOT NULL", now)
+            .execute(&mut *tx).await?;
+
+        sqlx::query!("UPDATE instance_usage SET quota_runtime = 0")
+            .execute(&mut *tx).await?;
+
+        sqlx::query!("INSERT INTO quota_resets (id, reset_time) VALUES (0, ?) ON CONFLICT (id) DO UPDATE SET reset_time = excluded.reset_time", now)
+            .execute(&mut *tx).await?;
+
+        tx.commit().await
+    }
+
+    pub async fn insert_audit_entry(&self, entry: &AuditEntry) -> Result<(), Error> {
+        sqlx::query!("INSERT INTO audit_log (time, user_id, ip, action, challenge_id) VALUES (?, ?, ?, ?, ?)",
+            entry.time, entry.user_id, entry.ip, entry.action, entry.challenge_id)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    /// Stores a missed message, dropping those sent before `oldest`.
+    pub async fn insert_missed_message(&self, message: &MissedMessage, oldest: &TimeSinceEpoch) -> Result<(), Error> {
+        sqlx::query!("INSERT INTO missed_messages (time, user_id, challenge_id, message) VALUES (?, ?, ?, ?)",
+            message.time, message.user_id, message.challenge_id, message.message)
+            .execute(self.pool().await?).await?;
+
+        sqlx::query!("DELETE FROM missed_messages WHERE time < ?", oldest)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    /// Removes the missed messages of a user, returning those sent since `oldest` in the order they were sent.
+    pub async fn take_missed_messages(&self, user_id: &str, oldest: &TimeSinceEpoch) -> Result<Vec<MissedMessage>, Error> {
+        let mut tx = self.pool().await?.begin().await?;
+
+        let messages = sqlx::query_as!(MissedMessage, r#"SELECT time AS "time: TimeSinceEpoch", user_id, challenge_id, message
+            FROM missed_messages WHERE user_id = ? AND time >= ? ORDER BY id"#, user_id, oldest)
+            .fetch_all(&mut *tx).await?;
+
+        sqlx::query!("DELETE FROM missed_messages WHERE user_id = ?", user_id)
+            .execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(messages)
+    }
+
+    /// Returns the most recent audit entries, optionally only those of a user or an address.
+    pub async fn get_audit_entries(&self, user_id: Option<&str>, ip: Option<&str>, limit: u32) -> Result<Vec<AuditEntry>, Error> {
+        sqlx::query_as!(AuditEntry, r#"SELECT time AS "time: TimeSinceEpoch", user_id, ip, action, challenge_id FROM audit_log
+            WHERE (?1 IS NULL OR user_id = ?1) AND (?2 IS NULL OR ip = ?2)
+            ORDER BY id DESC LIMIT ?3"#, user_id, ip, limit)
+            .fetch_all(self.pool().await?).await
     }
-}
\ No newline at end of file
+
+    pub async fn get_challenge_overrides(&self) -> Result<Vec<ChallengeOverride>, Error> {
+        sqlx::query_as!(ChallengeOverride, r#"SELECT challenge_id, name, description, ttl AS "ttl: u32", deployer FROM challenge_overrides"#)
+            .fetch_all(self.pool().await?).await
+    }
+
+    pub async fn set_challenge_override(&self, over: &ChallengeOverride) -> Result<(), Error> {
+        sqlx::query!("INSERT INTO challenge_overrides (challenge_id, name, description, ttl, deployer) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (challenge_id) DO UPDATE SET name = excluded.name, description = excluded.description, ttl = excluded.ttl, deployer = excluded.deployer",
+            over.challenge_id, over.name, over.description, over.ttl, over.deployer)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    /// Replaces every stored override at once.
+    pub async fn replace_challenge_overrides(&self, overrides: &[ChallengeOverride]) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+
+        sqlx::query!("DELETE FROM challenge_overrides")
+            .execute(&mut *tx).await?;
+
+        for over in overrides {
+            sqlx::query!("INSERT INTO challenge_overrides (challenge_id, name, description, ttl, deployer) VALUES (?, ?, ?, ?, ?)",
+                over.challenge_id, over.name, over.description, over.ttl, over.deployer)
+                .execute(&mut *tx).await?;
+        }
+
+        tx.commit().await
+    }
+
+    pub async fn delete_challenge_override(&self, challenge_id: &str) -> Result<(), Error> {
+        sqlx::query!("DELETE FROM challenge_overrides WHERE challenge_id = ?", challenge_id)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    pub async fn get_challenge_notices(&self) -> Result<Vec<ChallengeNotice>, Error> {
+        sqlx::query_as!(ChallengeNotice, r#"SELECT challenge_id, contents AS "contents?" FROM challenge_notices"#)
+            .fetch_all(self.pool().await?).await
+    }
+
+    /// Sets the notice of a challenge, or removes it if `contents` is None.
+    pub async fn set_challenge_notice(&self, notice: &ChallengeNotice) -> Result<(), Error> {
+        match &notice.contents {
+            None => {
+                sqlx::query!("DELETE FROM challenge_notices WHERE challenge_id = ?", notice.challenge_id)
+                    .execute(self.pool().await?).await.map(|_| ())
+            }
+            Some(contents) => {
+                sqlx::query!("INSERT OR REPLACE INTO challenge_notices (challenge_id, contents) VALUES (?, ?)", notice.challenge_id, contents)
+                    .execute(self.pool().await?).await.map(|_| ())
+            }
+        }
+    }
+
+    /// Replaces the metadata reported by the deployer for an instance.
+    pub async fn set_instance_metadata(&self, user_id: &str, challenge_id: &str, metadata: &BTreeMap<String, String>) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+
+        sqlx::query!("DELETE FROM instance_metadata WHERE user_id = ? AND challenge_id = ?", user_id, challenge_id)
+            .execute(&mut *tx).await?;
+
+        for (key, value) in metadata {
+            sqlx::query!("INSERT INTO instance_metadata (user_id, challenge_id, key, value) VALUES (?, ?, ?, ?)", user_id, challenge_id, key, value)
+                .execute(&mut *tx).await?;
+        }
+
+        tx.commit().await
+    }
+
+    /// Replaces the labels of an instance, those of its challenge along with those its deployers attached.
+    pub async fn set_instance_labels(&self, user_id: &str, challenge_id: &str, labels: &BTreeMap<String, String>) -> Result<(), Error> {
+        let mut tx = self.pool().await?.begin().await?;
+
+        sqlx::query!("DELETE FROM instance_labels WHERE user_id = ? AND challenge_id = ?", user_id, challenge_id)
+            .execute(&mut *tx).await?;
+
+        for (key, value) in labels {
+            sqlx::query!("INSERT INTO instance_labels (user_id, challenge_id, key, value) VALUES (?, ?, ?, ?)", user_id, challenge_id, key, value)
+                .execute(&mut *tx).await?;
+        }
+
+        tx.commit().await
+    }
+
+    pub async fn get_instance_labels(&self, user_id: &str, challenge_id: &str) -> Result<BTreeMap<String, String>, Error> {
+        let rows = sqlx::query!("SELECT key, value FROM instance_labels WHERE user_id = ? AND challenge_id = ?", user_id, challenge_id)
+            .fetch_all(self.pool().await?).await?;
+        Ok(rows.into_iter().map(|row| (row.key, row.value)).collect())
+    }
+
+    /// Records the target deployer an instance was placed on, which the rest of its lifecycle goes through.
+    pub async fn set_instance_target(&self, user_id: &str, challenge_id: &str, target: &str) -> Result<(), Error> {
+        sqlx::query!("UPDATE challenge_instances SET target = ? WHERE user_id = ? AND challenge_id = ?", target, user_id, challenge_id)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    pub async fn get_instance_target(&self, user_id: &str, challenge_id: &str) -> Result<Option<String>, Error> {
+        let target = sqlx::query_scalar!("SELECT target FROM challenge_instances WHERE user_id = ? AND challenge_id = ?", user_id, challenge_id)
+            .fetch_optional(self.pool().await?).await?;
+        Ok(target.flatten())
+    }
+
+    /// Returns how many instances are placed on each target deployer.
+    pub async fn get_target_loads(&self) -> Result<HashMap<String, i64>, Error> {
+        let rows = sqlx::query!(r#"SELECT target AS "target!", COUNT(*) AS "count!: i64" FROM challenge_instances WHERE target IS NOT NULL GROUP BY target"#)
+            .fetch_all(self.pool().await?).await?;
+        Ok(rows.into_iter().map(|row| (row.target, row.count)).collect())
+    }
+
+    pub async fn insert_target_failover(&self, failover: &TargetFailover) -> Result<(), Error> {
+        sqlx::query!("INSERT INTO target_failovers (time, user_id, challenge_id, from_target, to_target) VALUES (?, ?, ?, ?, ?)",
+            failover.time, failover.user_id, failover.challenge_id, failover.from_target, failover.to_target)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    pub async fn get_target_failovers(&self, challenge_id: Option<&str>, limit: u32) -> Result<Vec<TargetFailover>, Error> {
+        sqlx::query_as!(TargetFailover, r#"SELECT time AS "time: TimeSinceEpoch", user_id, challenge_id, from_target, to_target FROM target_failovers
+            WHERE (?1 IS NULL OR challenge_id = ?1)
+            ORDER BY id DESC LIMIT ?2"#, challenge_id, limit)
+            .fetch_all(self.pool().await?).await
+    }
+
+    /// Returns the labels of every instance, optionally only those of a user or a challenge.
+    pub async fn search_instance_labels(&self, user_id: Option<&str>, challenge_id: Option<&str>) -> Result<Vec<InstanceLabel>, Error> {
+        sqlx::query_as!(InstanceLabel, "SELECT user_id, challenge_id, key, value FROM instance_labels
+            WHERE (?1 IS NULL OR user_id = ?1) AND (?2 IS NULL OR challenge_id = ?2)", user_id, challenge_id)
+            .fetch_all(self.pool().await?).await
+    }
+
+    pub async fn insert_shared_service(&self, service_id: &str, nonce: &str) -> Result<(), Error> {
+        sqlx::query!("INSERT OR REPLACE INTO shared_services (service_id, nonce) VALUES (?, ?)", service_id, nonce)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    pub async fn delete_shared_service(&self, service_id: &str) -> Result<(), Error> {
+        sqlx::query!("DELETE FROM shared_services WHERE service_id = ?", service_id)
+            .execute(self.pool().await?).await.map(|_| ())
+    }
+
+    pub async fn get_shared_service_nonce(&self, service_id: &str) -> Result<Option<String>, Error> {
+        sqlx::query_scalar!("SELECT nonce FROM shared_services WHERE service_id = ?", service_id)
+            .fetch_optional(self.pool().await?).await
+    }
+
+    pub async fn get_user_instance_metadata(&self, user_id: &str) -> Result<Vec<InstanceMetadata>, Error> {
+        sqlx::query_as!(InstanceMetadata, "SELECT challenge_id, key, value FROM instance_metadata WHERE user_id = ?", user_id)
+            .fetch_all(self.pool().await?).await
+    }
+}
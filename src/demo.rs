@@ -0,0 +1,46 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time;
+
+use crate::models::{ChallengeInstanceState, EndReason, TimeSinceEpoch, UserRole};
+use crate::InstancerState;
+
+/// Deletes the demo users past their lifetime at the janitor's interval. Their instances are stopped first, the users
+/// themselves being deleted on a later pass once none is left.
+pub async fn expire_demo_users_periodically(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    let Some(demo) = state.config.demo.clone() else { return Ok(()) };
+    let interval = Duration::from_secs(state.config.settings.janitor_interval as u64);
+    let lifetime = Duration::from_secs(demo.lifetime as u64);
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => break,
+            _ = time::sleep(interval) => {}
+        }
+
+        if let Err(err) = expire_demo_users(&state, lifetime).await {
+            tracing::warn!("couldn't expire demo users: {:?}", err);
+        }
+    }
+
+    Ok(())
+}
+
+async fn expire_demo_users(state: &InstancerState, lifetime: Duration) -> anyhow::Result<()> {
+    let before = TimeSinceEpoch(TimeSinceEpoch::now().0 - lifetime);
+    for user_id in state.database.get_users_created_before(&UserRole::Demo, &before).await? {
+        if state.database.delete_user(&user_id).await? {
+            tracing::info!("deleted expired demo user {}", user_id);
+            continue;
+        }
+
+        for instance in state.database.get_user_challenge_instances(&user_id).await? {
+            if matches!(instance.state, ChallengeInstanceState::Running | ChallengeInstanceState::Expiring) {
+                state.deployer.queue_stop(&user_id, &instance.challenge_id, EndReason::Expired).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast::error::RecvError;
+use tower_sessions::cookie::time::format_description::well_known::Rfc3339;
+use tower_sessions::cookie::time::OffsetDateTime;
+
+use crate::identifiers;
+use crate::live_deployments::{LiveDeployment, LiveDeploymentEvent};
+use crate::InstancerState;
+
+/// Size past which the log of an instance is rotated when its next deployment begins. A single older file is kept.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// The log of a user's instance of a challenge, or None for ids that can't safely be part of a path.
+pub fn log_path(dir: &Path, challenge_id: &str, user_id: &str) -> Option<PathBuf> {
+    (identifiers::is_valid(challenge_id) && identifiers::is_valid(user_id))
+        .then(|| dir.join(challenge_id).join(format!("{}.log", user_id)))
+}
+
+/// The line opening the output of a deployment.
+pub fn header(deployment: &LiveDeployment) -> String {
+    let start_time = OffsetDateTime::from(deployment.start_time.0).format(&Rfc3339).unwrap_or_default();
+    format!("=== {} at {} ===", deployment.command, start_time)
+}
+
+/// The line closing the output of a deployment.
+pub fn footer(success: bool) -> String {
+    format!("=== {} ===", if success { "succeeded" } else { "failed" })
+}
+
+/// Returns the last `count` lines of the log of an instance, none if it never deployed.
+pub async fn tail(path: &Path, count: usize) -> std::io::Result<Vec<String>> {
+    let file = match File::open(path).await {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err)
+    };
+
+    let mut lines = BufReader::new(file).lines();
+    let mut tail = VecDeque::with_capacity(count);
+    while let Some(line) = lines.next_line().await? {
+        if tail.len() == count {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+    Ok(tail.into())
+}
+
+async fn open_log(dir: &Path, deployment: &LiveDeployment) -> std::io::Result<Option<File>> {
+    let Some(path) = log_path(dir, &deployment.challenge_id, &deployment.user_id) else { return Ok(None) };
+    fs::create_dir_all(dir.join(&deployment.challenge_id)).await?;
+
+    if fs::metadata(&path).await.is_ok_and(|metadata| metadata.len() > MAX_LOG_SIZE) {
+        fs::rename(&path, path.with_extension("log.1")).await?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(&path).await?;
+    file.write_all(format!("{}\n", header(deployment)).as_bytes()).await?;
+    Ok(Some(file))
+}
+
+async fn open_logs(dir: &Path, logs: &mut HashMap<u64, File>, deployments: Vec<LiveDeployment>) {
+    for deployment in deployments {
+        if logs.contains_key(&deployment.id) { continue }
+        match open_log(dir, &deployment).await {
+            Ok(Some(file)) => { logs.insert(deployment.id, file); }
+            Ok(None) => {}
+            Err(err) => tracing::warn!("couldn't open the deploy log of challenge {} for user {}: {:?}", deployment.challenge_id, deployment.user_id, err)
+        }
+    }
+}
+
+async fn append(file: &mut File, line: &str) {
+    if let Err(err) = file.write_all(format!("{}\n", line).as_bytes()).await {
+        tracing::warn!("couldn't write to a deploy log: {:?}", err);
+    }
+}
+
+/// Appends the output of every deployment to the log of its instance, `<dir>/<challenge>/<user>.log`.
+pub async fn record_deploy_logs(state: Arc<InstancerState>, dir: PathBuf) -> anyhow::Result<()> {
+    let (deployments, mut event_rx) = state.deployer.live.subscribe();
+    let mut logs = HashMap::new();
+    open_logs(&dir, &mut logs, deployments).await;
+
+    loop {
+        let event = tokio::select! {
+            _ = state.shutdown_token.cancelled() => break,
+            event = event_rx.recv() => event
+        };
+
+        match event {
+            Ok(LiveDeploymentEvent::Started { deployment }) => open_logs(&dir, &mut logs, vec![deployment]).await,
+            Ok(LiveDeploymentEvent::Output { id, line }) => {
+                if let Some(file) = logs.get_mut(&id) { append(file, &line).await; }
+            }
+            Ok(LiveDeploymentEvent::Finished { id, success }) => {
+                if let Some(mut file) = logs.remove(&id) { append(&mut file, &footer(success)).await; }
+            }
+            Err(RecvError::Lagged(missed)) => {
+                /* lines were lost, carry on with the deployments still running */
+                tracing::warn!("deploy logs missed {} deployment events", missed);
+                let (deployments, new_event_rx) = state.deployer.live.subscribe();
+                event_rx = new_event_rx;
+                logs.retain(|id, _| deployments.iter().any(|deployment| deployment.id == *id));
+                open_logs(&dir, &mut logs, deployments).await;
+            }
+            Err(RecvError::Closed) => break
+        }
+    }
+
+    Ok(())
+}
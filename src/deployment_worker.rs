@@ -1,134 +1,661 @@
-use crate::config::InstancerConfig;
+use crate::challenge_registry::ChallengeRegistry;
+use crate::config::{BundleConfig, ChallengeConfig, DeployerConfig, InstancerConfig, SimulationConfig};
 use crate::database::Database;
-use crate::models::{ChallengeInstanceState, TimeSinceEpoch};
-use serde::Serialize;
-use std::cmp::{Ordering, PartialEq, Reverse};
-use std::collections::{BinaryHeap, HashMap};
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::FaultInjector;
+use crate::identifiers::{self, DeployerArg};
+use crate::latency::LatencyTracker;
+use crate::live_deployments::{LiveDeploymentHandle, LiveDeployments};
+use crate::message_templates::MessageTemplates;
+use crate::messages::MessageContents;
+use crate::models::{ChallengeInstanceState, ChallengeOverride, EndReason, OutboxEntry, TargetFailover, TimeSinceEpoch, UserRole};
+use crate::notifications::{Notification, NotificationKind, Notifications};
+use crate::scheduler::FairScheduler;
+use crate::shared_services::SharedServices;
+use crate::ttl_queue::TtlQueue;
+use crate::update_hub::UpdateHub;
+use crate::webhooks::{WebhookEvent, Webhooks};
+use crate::InstancerState;
+use minijinja::context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Not;
-use std::path::PathBuf;
+use std::sync::atomic::{self, AtomicBool, AtomicU64, AtomicUsize};
 use std::process::{Stdio};
+use std::sync::{Arc, PoisonError};
 use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
-use tokio::sync::{broadcast, Mutex};
+use tokio::process::{Child, Command};
+use tokio::sync::{watch, Mutex, Notify};
 use tokio::time;
 use tokio_util::sync::CancellationToken;
 
+/// Environment variable holding the namespace of the event, when one is configured.
+pub const NAMESPACE_VAR: &str = "INSTANCER_NAMESPACE";
+
+/// Prefix of the environment variables holding the labels of an instance, followed by the uppercased key.
+const LABEL_VAR_PREFIX: &str = "INSTANCER_LABEL_";
+
+/// Exit status of deployers whose infrastructure failed, `EX_TEMPFAIL`, which makes starts fail over to another target.
+const INFRASTRUCTURE_EXIT_CODE: i32 = 75;
+
+/// The instance a deployment acts on.
+#[derive(Clone, Copy)]
+pub struct DeploymentTarget<'a> {
+    pub user_id: &'a str,
+    pub nonce: &'a str,
+    pub labels: &'a BTreeMap<String, String>,
+    /// The target deployer the instance was placed on, which replaces the first step of the pipeline.
+    pub deployer: Option<&'a str>
+}
+
 #[derive(Debug)]
 pub struct Challenge {
     pub id: String,
     pub name: String,
     pub description: Option<String>,
     pub ttl: u32,
-    pub deployer_path: PathBuf
+    pub pipeline: Vec<DeploymentStep>,
+    /// Deployers the instances may be placed on instead of the first step of the pipeline, those whose labels
+    /// conflict with the challenge's left out.
+    pub targets: Vec<DeploymentStep>,
+    pub requires: Vec<String>,
+    pub depends_on: Vec<String>,
+    pub scoreboard_id: Option<String>,
+    pub simulation: Option<SimulationConfig>,
+    pub extendable: bool,
+    pub extension: u32,
+    pub max_extensions: Option<u32>,
+    pub deploy_timeout: u32,
+    pub deploy_kill_grace: u32,
+    /// Whether restarts replace the details of instances.
+    pub restart_details: bool,
+    /// How many concurrent instance slots an instance takes.
+    pub weight: u32,
+    pub namespace: Option<String>,
+    /// Labels given to every instance of the challenge, before those its deployers attach.
+    pub labels: BTreeMap<String, String>,
+    /// Ids of the authors of the challenge.
+    pub owners: Vec<String>,
+    pub bundle: Option<BundleConfig>,
+    #[cfg(feature = "fault-injection")]
+    pub faults: Option<FaultInjector>
+}
+
+/// A single deployer invocation of a challenge's pipeline.
+#[derive(Debug)]
+pub struct DeploymentStep {
+    pub name: String,
+    pub deployer: DeployerConfig,
+    deployer_checksum: std::sync::Mutex<Option<String>>
+}
+
+impl DeploymentStep {
+    pub fn new(name: &str, deployer: &DeployerConfig) -> Self {
+        DeploymentStep {
+            name: name.to_string(),
+            deployer: deployer.clone(),
+            deployer_checksum: std::sync::Mutex::new(None)
+        }
+    }
+
+    /// Resolves the deployers of a pipeline, or None if one of them isn't configured.
+    fn pipeline<'a>(config: &InstancerConfig, owner_id: &str, deployer_ids: impl Iterator<Item = &'a String>) -> Option<Vec<DeploymentStep>> {
+        deployer_ids
+            .map(|deployer_id| match config.deployers.get(deployer_id) {
+                Some(deployer) => Some(DeploymentStep::new(deployer_id, deployer)),
+                None => {
+                    tracing::warn!("disabled challenge {}: unknown deployer {}", owner_id, deployer_id);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Builds the deployer command, run from the deployer's working directory as its user, and wrapped in a transient
+    /// systemd scope with resource limits if sandboxed.
+    pub fn command(&self, env: &[(String, String)]) -> Command {
+        let mut command = self.wrapped_command(env);
+        if let Some(cwd) = &self.deployer.cwd {
+            command.current_dir(cwd);
+        }
+        if let (Some(run_as), None, None) = (&self.deployer.run_as, &self.deployer.sandbox, &self.deployer.container_image) {
+            command.uid(run_as.uid).gid(run_as.gid);
+        }
+        command
+    }
+
+    fn wrapped_command(&self, env: &[(String, String)]) -> Command {
+        if let Some(image) = &self.deployer.container_image { return self.container_command(image, env) }
+
+        let Some(sandbox) = &self.deployer.sandbox else { return Command::new(&self.deployer.path) };
+
+        let mut command = Command::new("systemd-run");
+        command.args(["--scope", "--quiet", "--collect"]);
+
+        if let Some(cpu_quota) = &sandbox.cpu_quota {
+            command.arg(format!("--property=CPUQuota={}", cpu_quota));
+        }
+        if let Some(memory_max) = &sandbox.memory_max {
+            command.arg(format!("--property=MemoryMax={}", memory_max));
+        }
+        if let Some(runtime_max) = sandbox.runtime_max {
+            command.arg(format!("--property=RuntimeMaxSec={}", runtime_max));
+        }
+        if let Some(run_as) = &self.deployer.run_as {
+            command.arg(format!("--uid={}", run_as.uid)).arg(format!("--gid={}", run_as.gid));
+        }
+
+        command.arg("--").arg(&self.deployer.path);
+        command
+    }
+
+    /// Builds a command running the deployer inside a throwaway container, with the script mounted at `/deployer`
+    /// and the work directory (the deployer's cwd by default) mounted at `/work`.
+    fn container_command(&self, image: &str, env: &[(String, String)]) -> Command {
+        let container = &self.deployer.container;
+        let script = std::path::absolute(&self.deployer.path).unwrap_or_else(|_| self.deployer.path.clone());
+        let work_dir = container.work_dir.as_ref().or(self.deployer.cwd.as_ref())
+            .and_then(|work_dir| std::path::absolute(work_dir).ok())
+            .or_else(|| std::env::current_dir().ok());
+
+        let mut command = Command::new(&container.runtime);
+        command.args(["run", "--rm", "--init"]);
+        command.arg("--volume").arg(format!("{}:/deployer:ro", script.display()));
+
+        if let Some(work_dir) = work_dir {
+            command.arg("--volume").arg(format!("{}:/work", work_dir.display()));
+            command.args(["--workdir", "/work"]);
+        }
+        if container.docker_socket {
+            command.args(["--volume", "/var/run/docker.sock:/var/run/docker.sock"]);
+        }
+        if let Some(network) = &container.network {
+            command.arg(format!("--network={}", network));
+        }
+        if let Some(run_as) = &self.deployer.run_as {
+            command.arg(format!("--user={}:{}", run_as.uid, run_as.gid));
+        }
+        /* the values are taken from the environment of the runtime, which is the deployer's */
+        for (name, _) in env {
+            command.arg("--env").arg(name);
+        }
+
+        command.arg(image).arg("/deployer");
+        command
+    }
 }
 
 impl Challenge {
-    pub async fn deploy(&self, user_id: &str, action: DeploymentRequestCommand) -> Result<Option<String>, ()> {
+    /// The worker pool deploying the challenge, that of the first of its deployers in a pool.
+    pub fn pool(&self) -> Option<&str> {
+        self.pipeline.iter().find_map(|step| step.deployer.pool.as_deref())
+    }
+
+    /// Runs the challenge's deployers, reporting the overall progress of the pipeline to `progress` if given.
+    pub async fn deploy(&self, live: &LiveDeployments, updates: &UpdateHub, target: &DeploymentTarget<'_>, action: DeploymentRequestCommand, progress: Option<&watch::Sender<u8>>) -> Result<DeploymentOutput, DeploymentError> {
+        let live_id = live.begin(&self.id, target.user_id, action.into());
+        let handle = live.handle(live_id);
+
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = &self.faults {
+            if faults.deployment(&self.id, action.into()).await {
+                live.finish(live_id, false);
+                return Err(DeploymentError::Failed);
+            }
+        }
+
+        let result = match &self.simulation {
+            Some(simulation) => tokio::select! {
+                details = self.simulate(simulation, target.user_id, action) => Ok(DeploymentOutput { details, ..Default::default() }),
+                _ = handle.cancelled() => Err(DeploymentError::Cancelled)
+            },
+            None => self.run_pipeline(handle, updates, target, action, progress).await
+        };
+        let result = result.map_err(|err| if live.is_cancelled(live_id) { DeploymentError::Cancelled } else { err });
+
+        live.finish(live_id, result.is_ok());
+        result
+    }
+
+    /// The steps of the pipeline, the first one running with the target deployer the instance was placed on.
+    fn steps(&self, deployer: Option<&str>) -> Vec<&DeploymentStep> {
+        let mut steps: Vec<&DeploymentStep> = self.pipeline.iter().collect();
+        if let Some(step) = deployer.and_then(|deployer| self.targets.iter().find(|step| step.name == deployer)) {
+            steps[0] = step;
+        }
+        steps
+    }
+
+    /// Runs the pipeline's steps in order to start or restart, and in reverse to stop or clean up. When a step
+    /// fails to start, the steps that completed before it are cleaned up in reverse.
+    async fn run_pipeline(&self, live: LiveDeploymentHandle<'_>, updates: &UpdateHub, target: &DeploymentTarget<'_>, action: DeploymentRequestCommand, progress: Option<&watch::Sender<u8>>) -> Result<DeploymentOutput, DeploymentError> {
+        let forward = matches!(action, DeploymentRequestCommand::Start { .. } | DeploymentRequestCommand::Restart);
+        let pipeline = self.steps(target.deployer);
+        let steps: Vec<(usize, &DeploymentStep)> = if forward {
+            pipeline.iter().copied().enumerate().collect()
+        } else {
+            pipeline.iter().copied().enumerate().rev().collect()
+        };
+
+        let mut output = DeploymentOutput::default();
+        let mut failed = None;
+
+        for (index, step) in steps {
+            if pipeline.len() > 1 {
+                live.output(&format!("--- {} ({}/{}) ---", step.name, index + 1, pipeline.len()));
+
+                let progress = DeploymentUpdate {
+                    user_id: target.user_id.to_string(),
+                    challenge_id: self.id.clone(),
+                    details: DeploymentUpdateDetails::PipelineStep { name: step.name.clone(), index, total: pipeline.len() }
+                };
+                updates.send(progress);
+            }
+
+            let step_progress = progress.map(|tx| StepProgress { tx, index, total: pipeline.len() });
+            match self.run_deployer(step, live, target, action, step_progress).await {
+                Ok(step_output) => output.merge(step_output),
+                Err(err) if matches!(action, DeploymentRequestCommand::Start { .. }) => {
+                    for completed in pipeline[..index].iter().rev() {
+                        tracing::warn!("[{}] rolling back step {} after step {} failed", self.id, completed.name, step.name);
+                        let _ = self.run_deployer(completed, live, target, DeploymentRequestCommand::Cleanup, None).await;
+                    }
+                    return Err(err);
+                }
+                Err(err) if forward => return Err(err),
+                Err(err) => failed = failed.or(Some(err)) /* keep tearing down the remaining steps */
+            }
+        }
+
+        match failed {
+            Some(err) => Err(err),
+            None => Ok(output)
+        }
+    }
+
+    async fn run_deployer(&self, step: &DeploymentStep, live: LiveDeploymentHandle<'_>, target: &DeploymentTarget<'_>, action: DeploymentRequestCommand, progress: Option<StepProgress<'_>>) -> Result<DeploymentOutput, DeploymentError> {
         let action_str = <DeploymentRequestCommand as Into<&str>>::into(action);
 
-        tracing::debug!("[{}] calling script: \"{}\"", self.id, self.deployer_path.display());
-        tracing::debug!("[{}] args: \"{}\" \"{}\" \"{}\"", self.id, action_str, &self.id, user_id);
+        tracing::debug!("[{}] calling script: \"{}\"", self.id, step.deployer.path.display());
+        tracing::debug!("[{}] args: \"{}\" \"{}\" \"{}\" \"{}\"", self.id, action_str, &self.id, target.user_id, target.nonce);
+
+        self.verify_deployer(step).await.map_err(|()| DeploymentError::Failed)?;
 
-        let mut command = Command::new(&self.deployer_path);
+        let args: Vec<DeployerArg> = match [self.id.as_str(), target.user_id, target.nonce].into_iter().map(DeployerArg::try_from).collect() {
+            Ok(args) => args,
+            Err(err) => {
+                tracing::error!("[{}] refusing to call deployer: {}", self.id, err);
+                return Err(DeploymentError::Failed);
+            }
+        };
+
+        let env = self.deployer_env(target);
+        let mut command = step.command(&env);
         command
             .arg(action_str)
-            .arg(&self.id)
-            .arg(user_id)
+            .args(args)
+            .envs(env)
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+            .stderr(Stdio::piped())
+            .process_group(0);
+
+        if let DeploymentRequestCommand::Start { retry: true } = action {
+            command.arg("retry");
+        }
 
         let mut child = match command.spawn() {
             Ok(child) => child,
             Err(err) => {
                 tracing::error!("[{}] couldn't spawn child process: {:?}", self.id, err);
-                return Err(());
+                return Err(DeploymentError::Failed);
             }
         };
 
         let (mut stdout, mut stderr) = match child.stdout.take().zip(child.stderr.take()) {
             None => {
                 tracing::error!("[{}] couldn't take stdout & stderr", self.id);
-                return Err(());
+                return Err(DeploymentError::Failed);
             },
             Some((stdout, stderr)) => (BufReader::new(stdout).lines(), BufReader::new(stderr).lines())
         };
 
         let mut details = String::new();
+        let mut metadata = BTreeMap::new();
+        let mut labels = BTreeMap::new();
+        let mut warnings = Vec::new();
 
-        loop {
-            tokio::select! {
-                Ok(Some(line)) = stdout.next_line() => {
-                    tracing::debug!("[{}] [O] {}", self.id, line);
-                    if line.starts_with("$") {
-                        if details.len() != 0 { details.push('\n'); }
-                        details.push_str(&line[2..]);
+        let run = async {
+            loop {
+                tokio::select! {
+                    Ok(Some(line)) = stdout.next_line() => {
+                        live.output(&line);
+                        match line.strip_prefix("!").and_then(|entry| entry.split_once(' ')) {
+                            Some(("I", message)) => tracing::info!("[{}] {}", self.id, message),
+                            Some(("W", message)) => {
+                                tracing::warn!("[{}] {}", self.id, message);
+                                if step.deployer.forward_warnings { warnings.push(message.to_string()); }
+                            }
+                            Some(("E", message)) => tracing::error!("[{}] {}", self.id, message),
+                            _ => tracing::debug!("[{}] [O] {}", self.id, line)
+                        }
+                        if line.starts_with("$") {
+                            if !details.is_empty() { details.push('\n'); }
+                            details.push_str(&line[2..]);
+                        } else if let Some((key, value)) = line.strip_prefix("@").and_then(|entry| entry.split_once('=')) {
+                            metadata.insert(key.trim().to_string(), value.trim().to_string());
+                        } else if let Some((key, value)) = line.strip_prefix("&").and_then(|entry| entry.split_once('=')) {
+                            match identifiers::is_valid(key.trim()) {
+                                true => { labels.insert(key.trim().to_string(), value.trim().to_string()); }
+                                false => tracing::warn!("[{}] ignoring label with malformed key {:?}", self.id, key.trim())
+                            }
+                        } else if let Some(pct) = line.strip_prefix("%").and_then(|pct| pct.trim().parse::<u8>().ok()) {
+                            if let Some(progress) = &progress {
+                                progress.report(pct);
+                            }
+                        }
                     }
+                    Ok(Some(line)) = stderr.next_line() => {
+                        tracing::warn!("[{}] [E] {}", self.id, line);
+                        live.output(&format!("[E] {}", line));
+                    }
+                    else => break
                 }
-                Ok(Some(line)) = stderr.next_line() => {
-                    tracing::warn!("[{}] [E] {}", self.id, line);
-                }
-                else => break
             }
-        }
+            child.wait().await
+        };
+
+        /* cleanups run to completion, even those rolling back a cancelled start */
+        let cancellable = !matches!(action, DeploymentRequestCommand::Cleanup);
+        let outcome = tokio::select! {
+            outcome = time::timeout(Duration::from_secs(self.deploy_timeout as u64), run) => Some(outcome),
+            _ = live.cancelled(), if cancellable => None
+        };
 
-        let output = child.wait_with_output().await.map_err(|_| ())?;
-        if output.status.success() {
-            Ok(details.is_empty().not().then_some(details))
+        let status = match outcome {
+            Some(Ok(status)) => status.map_err(|_| DeploymentError::Failed)?,
+            Some(Err(_)) => {
+                tracing::error!("[{}] child process timed out after {}s", self.id, self.deploy_timeout);
+                self.kill_deployer(&mut child).await;
+                return Err(DeploymentError::Infrastructure);
+            }
+            None => {
+                tracing::warn!("[{}] deployment cancelled, killing child process", self.id);
+                self.kill_deployer(&mut child).await;
+                return Err(DeploymentError::Failed);
+            }
+        };
+
+        if status.success() {
+            Ok(DeploymentOutput { details: details.is_empty().not().then_some(details), metadata, labels, warnings })
         } else {
-            match output.status.code() {
+            match status.code() {
                 None => tracing::error!("[{}] child process exited with signal", self.id),
                 Some(code) => tracing::error!("[{}] child process exited with status {}", self.id, code)
             }
-            Err(())
+            match status.code() {
+                Some(INFRASTRUCTURE_EXIT_CODE) => Err(DeploymentError::Infrastructure),
+                _ => Err(DeploymentError::Failed)
+            }
+        }
+    }
+
+    async fn simulate(&self, simulation: &SimulationConfig, user_id: &str, action: DeploymentRequestCommand) -> Option<String> {
+        tracing::debug!("[{}] simulating {:?} for user {}", self.id, action, user_id);
+        time::sleep(Duration::from_secs(simulation.delay as u64)).await;
+
+        match action {
+            DeploymentRequestCommand::Start { .. } | DeploymentRequestCommand::Restart | DeploymentRequestCommand::Migrate => Some(simulation.details.clone()),
+            DeploymentRequestCommand::Stop | DeploymentRequestCommand::Cleanup => None
+        }
+    }
+
+    /// Sends SIGTERM to the deployer's process group, then SIGKILL once the grace period is over so that
+    /// children left behind (e.g. docker compose) don't outlive it.
+    async fn kill_deployer(&self, child: &mut Child) {
+        let Some(pid) = child.id() else { return };
+        let process_group = -(pid as libc::pid_t);
+
+        unsafe { libc::kill(process_group, libc::SIGTERM); }
+        if time::timeout(Duration::from_secs(self.deploy_kill_grace as u64), child.wait()).await.is_err() {
+            tracing::warn!("[{}] child process ignored SIGTERM, killing it", self.id);
+        }
+
+        unsafe { libc::kill(process_group, libc::SIGKILL); }
+        let _ = child.wait().await;
+    }
+
+    /// Refuses to run a deployer that doesn't match its pinned checksum, warning whenever the script changed since it last ran.
+    async fn verify_deployer(&self, step: &DeploymentStep) -> Result<(), ()> {
+        let checksum = match tokio::fs::read(&step.deployer.path).await {
+            Ok(contents) => sha256_hex(&contents),
+            Err(err) => {
+                tracing::error!("[{}] couldn't read deployer: {:?}", self.id, err);
+                return Err(());
+            }
+        };
+
+        if let Some(pinned) = step.deployer.sha256.as_ref().filter(|pinned| !pinned.eq_ignore_ascii_case(&checksum)) {
+            tracing::error!("[{}] refusing to run deployer, checksum {} does not match pinned checksum {}", self.id, checksum, pinned);
+            return Err(());
+        }
+
+        let mut recorded_checksum = step.deployer_checksum.lock().unwrap();
+        if recorded_checksum.as_ref() != Some(&checksum) {
+            tracing::warn!("[{}] deployer \"{}\" was modified, sha256 is now {}", self.id, step.deployer.path.display(), checksum);
+            *recorded_checksum = Some(checksum);
+        }
+
+        Ok(())
+    }
+
+    /// The environment passed to deployers: the namespace of the event and the labels of the instance.
+    fn deployer_env(&self, target: &DeploymentTarget<'_>) -> Vec<(String, String)> {
+        let namespace = self.namespace.iter().map(|namespace| (NAMESPACE_VAR.to_string(), namespace.clone()));
+        let labels = target.labels.iter()
+            .map(|(key, value)| (format!("{}{}", LABEL_VAR_PREFIX, key.to_ascii_uppercase().replace('-', "_")), value.clone()));
+        namespace.chain(labels).collect()
+    }
+
+    /// Checks that every deployer of the pipeline exists and matches its pinned checksum, recording the checksums.
+    fn check_pipeline(&self) -> bool {
+        if self.simulation.is_some() { return true; }
+
+        if let Some(step) = self.pipeline.iter().chain(&self.targets).find(|step| !step.deployer.path.exists()) {
+            tracing::warn!("disabled challenge {}: deployer does not exist at \"{}\"", self.id, step.deployer.path.display());
+            return false;
+        }
+
+        if let Some(cwd) = self.pipeline.iter().chain(&self.targets).filter_map(|step| step.deployer.cwd.as_ref()).find(|cwd| !cwd.is_dir()) {
+            tracing::warn!("disabled challenge {}: deployer working directory does not exist at \"{}\"", self.id, cwd.display());
+            return false;
+        }
+
+        self.pipeline.iter().chain(&self.targets).all(|step| {
+            let checksum = match std::fs::read(&step.deployer.path) {
+                Ok(contents) => sha256_hex(&contents),
+                Err(err) => {
+                    tracing::warn!("disabled challenge {}: couldn't read deployer at \"{}\": {:?}", self.id, step.deployer.path.display(), err);
+                    return false;
+                }
+            };
+
+            if let Some(pinned) = step.deployer.sha256.as_ref().filter(|pinned| !pinned.eq_ignore_ascii_case(&checksum)) {
+                tracing::warn!("disabled challenge {}: deployer checksum {} does not match pinned checksum {}", self.id, checksum, pinned);
+                return false;
+            }
+
+            tracing::info!("[{}] deployer \"{}\" has sha256 {}", self.id, step.deployer.path.display(), checksum);
+            *step.deployer_checksum.lock().unwrap() = Some(checksum);
+            true
+        })
+    }
+
+    /// Builds a challenge from its configuration, or None if its deployers aren't usable.
+    pub fn from_config(id: &str, cfg: &ChallengeConfig, config: &InstancerConfig) -> Option<Challenge> {
+        let pipeline = DeploymentStep::pipeline(config, id, std::iter::once(&cfg.deployer).chain(cfg.pipeline.iter()))?;
+        let targets: Vec<DeploymentStep> = match cfg.targets.is_empty() {
+            true => Vec::new(),
+            false => DeploymentStep::pipeline(config, id, std::iter::once(&cfg.deployer).chain(cfg.targets.iter()))?
+                .into_iter()
+                .filter(|step| step.deployer.labels.iter().all(|(key, value)| cfg.labels.get(key).is_none_or(|label| label == value)))
+                .collect()
+        };
+        if !cfg.targets.is_empty() && targets.is_empty() {
+            tracing::warn!("disabled challenge {}: no target matches its labels", id);
+            return None;
         }
+
+        let challenge = Challenge {
+            id: id.to_string(),
+            name: cfg.name.clone(),
+            description: cfg.description.as_deref().map(ammonia::clean),
+            ttl: cfg.ttl,
+            pipeline,
+            targets,
+            requires: cfg.requires.clone(),
+            depends_on: cfg.depends_on.clone(),
+            scoreboard_id: cfg.scoreboard_id.clone(),
+            simulation: config.settings.simulate_deployments.then(|| config.simulation.clone()),
+            extendable: cfg.extendable && config.features.extend_enabled,
+            extension: cfg.extension.unwrap_or(cfg.ttl),
+            max_extensions: cfg.max_extensions,
+            deploy_timeout: cfg.deploy_timeout.unwrap_or(config.settings.deploy_timeout),
+            deploy_kill_grace: config.settings.deploy_kill_grace,
+            restart_details: cfg.restart_details,
+            weight: cfg.weight,
+            namespace: config.settings.namespace.clone(),
+            labels: cfg.labels.clone(),
+            owners: cfg.owners.clone(),
+            bundle: cfg.bundle.clone(),
+            #[cfg(feature = "fault-injection")]
+            faults: config.fault_injection.clone().map(FaultInjector::new)
+        };
+        challenge.check_pipeline().then_some(challenge)
     }
 
     pub fn ttl_duration(&self) -> Duration {
         Duration::from_secs(self.ttl as u64)
     }
+
+    pub fn extension_duration(&self) -> Duration {
+        Duration::from_secs(self.extension as u64)
+    }
 }
 
-#[derive(Debug)]
+pub fn sha256_hex(contents: &[u8]) -> String {
+    Sha256::digest(contents).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeploymentError {
+    Failed,
+    /// The infrastructure behind the deployer failed rather than the deployment itself, which deployers report by
+    /// exiting with `INFRASTRUCTURE_EXIT_CODE`. Deployers timing out count as such.
+    Infrastructure,
+    /// An admin or the instance's owner aborted the deployment.
+    Cancelled
+}
+
+impl From<DeploymentError> for EndReason {
+    fn from(value: DeploymentError) -> Self {
+        match value {
+            DeploymentError::Failed | DeploymentError::Infrastructure => EndReason::Failed,
+            DeploymentError::Cancelled => EndReason::Cancelled
+        }
+    }
+}
+
+/// The share of a pipeline's progress a single step accounts for.
+struct StepProgress<'a> {
+    tx: &'a watch::Sender<u8>,
+    index: usize,
+    total: usize
+}
+
+impl StepProgress<'_> {
+    /// Reports the progress of the step, in percent, as the overall progress of the pipeline.
+    fn report(&self, pct: u8) {
+        let overall = (self.index * 100 + usize::from(pct.min(100))) / self.total;
+        self.tx.send_replace(overall as u8);
+    }
+}
+
+/// What a deployer reported: `$`-prefixed lines are joined into the details, `@ key=value` lines are metadata,
+/// `& key=value` lines are labels and `!W` lines are the warnings forwarded to the player, if its deployer allows it.
+#[derive(Debug, Default)]
+pub struct DeploymentOutput {
+    pub details: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+    pub labels: BTreeMap<String, String>,
+    pub warnings: Vec<String>
+}
+
+impl DeploymentOutput {
+    /// Appends the output of a later pipeline step, whose metadata and labels take precedence.
+    fn merge(&mut self, other: DeploymentOutput) {
+        self.details = match (self.details.take(), other.details) {
+            (Some(details), Some(other_details)) => Some(format!("{}\n{}", details, other_details)),
+            (details, other_details) => details.or(other_details)
+        };
+        self.metadata.extend(other.metadata);
+        self.labels.extend(other.labels);
+        self.warnings.extend(other.warnings);
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct DeploymentRequest {
     pub user_id: String,
     pub challenge_id: String,
-    pub command: DeploymentRequestCommand
+    pub command: DeploymentRequestCommand,
+    pub queued_at: time::Instant
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum DeploymentRequestCommand {
-    Start,
+    /// Starts an instance, `retry` being set when an earlier attempt may have left resources behind.
+    Start { retry: bool },
     Stop,
     Restart,
-    Cleanup
+    Cleanup,
+    /// Moves a running instance to another target, by starting it there before stopping it where it ran. Deployers
+    /// are only ever asked to start and stop.
+    Migrate
 }
 
 impl From<DeploymentRequestCommand> for &str {
     fn from(value: DeploymentRequestCommand) -> Self {
         match value {
-            DeploymentRequestCommand::Start => "start",
+            DeploymentRequestCommand::Start { .. } => "start",
             DeploymentRequestCommand::Stop => "stop",
             DeploymentRequestCommand::Restart => "restart",
-            DeploymentRequestCommand::Cleanup => "cleanup"
+            DeploymentRequestCommand::Cleanup => "cleanup",
+            DeploymentRequestCommand::Migrate => "migrate"
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DeploymentUpdate {
     pub user_id: String,
     pub challenge_id: String,
     pub details: DeploymentUpdateDetails
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum DeploymentUpdateDetails {
     StateChange { state: ChallengeInstanceState, details: Option<String>, stop_time: Option<TimeSinceEpoch> },
-    Message { contents: String, severity: MessageSeverity }
+    Metadata { metadata: BTreeMap<String, String> },
+    PipelineStep { name: String, index: usize, total: usize },
+    Progress { progress: u8 },
+    Paused { paused: bool, stop_time: Option<TimeSinceEpoch> },
+    Message { contents: MessageContents, severity: MessageSeverity }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum MessageSeverity {
     Success,
@@ -137,120 +664,336 @@ pub enum MessageSeverity {
     Error
 }
 
-#[derive(Eq)]
-struct ChallengeInstanceOrdered {
-    pub user_id: String,
-    pub challenge_id: String,
-    pub stop_time: TimeSinceEpoch
-}
-
-impl Ord for ChallengeInstanceOrdered {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.stop_time.cmp(&other.stop_time)
-    }
+/// A request being handled, completed in the scheduler once dropped so that a worker panicking midway doesn't hold
+/// back the other requests of the user.
+struct InFlight<'a> {
+    scheduler: &'a std::sync::Mutex<FairScheduler>,
+    user_id: String
 }
 
-impl PartialOrd for ChallengeInstanceOrdered {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.stop_time.cmp(&other.stop_time))
-    }
-}
-
-impl PartialEq for ChallengeInstanceOrdered {
-    fn eq(&self, other: &Self) -> bool {
-        self.stop_time == other.stop_time
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.scheduler.lock().unwrap_or_else(PoisonError::into_inner).complete(&self.user_id);
     }
 }
 
 pub struct DeploymentWorker {
     request_rx: async_channel::Receiver<DeploymentRequest>,
-    pub request_tx: async_channel::Sender<DeploymentRequest>,
-    pub update_tx: broadcast::Sender<DeploymentUpdate>,
-    pub challenges: HashMap<String, Challenge>,
+    request_tx: async_channel::Sender<DeploymentRequest>,
+    pub updates: Arc<UpdateHub>,
+    pub challenges: ChallengeRegistry,
+    services: SharedServices,
     pub database: Database,
-    ttl_expiries: Mutex<BinaryHeap<Reverse<ChallengeInstanceOrdered>>>,
-    shutdown_token: CancellationToken
+    ttl_expiries: Mutex<TtlQueue>,
+    expiry_warnings: Mutex<TtlQueue>,
+    expiry_warning: u32,
+    expiry_grace_period: u32,
+    auto_extend_max_lifetime: Option<u32>,
+    /// Caps the TTL of the instances of demo users, if the demo is enabled.
+    demo_ttl: Option<u32>,
+    shutdown_token: CancellationToken,
+    rejected_requests: AtomicU64,
+    scheduler: std::sync::Mutex<FairScheduler>,
+    active_workers: AtomicUsize,
+    pub live: LiveDeployments,
+    pub latency: LatencyTracker,
+    pub webhooks: Arc<Webhooks>,
+    pub notifications: Notifications,
+    pub messages: MessageTemplates,
+    /// Wakes the outbox dispatcher once a request has written the updates concluding it.
+    pub outbox: Notify,
+    /// Wakes idle workers once the scheduler may hold requests for them, as requests are moved into it by whichever
+    /// worker receives them.
+    scheduled: Notify,
+    /// Whether TTLs are frozen for maintenance, instances starting in the meantime being paused right away.
+    pub maintenance: AtomicBool,
+    /// Whether the event has ended, starts being dropped without deploying from then on.
+    pub event_ended: AtomicBool
+}
+
+/// Wraps the updates concluding a request for the outbox, so that they're only published once the state they
+/// announce is committed.
+fn outbox_entries(request: &DeploymentRequest, updates: &[DeploymentUpdateDetails]) -> Vec<OutboxEntry> {
+    updates.iter()
+        .map(|details| OutboxEntry {
+            user_id: request.user_id.clone(),
+            challenge_id: request.challenge_id.clone(),
+            payload: serde_json::to_string(details).unwrap()
+        })
+        .collect()
 }
 
 impl DeploymentWorker {
     pub fn new(config: &InstancerConfig, database: Database, shutdown_token: CancellationToken) -> Self {
-        let (request_tx, request_rx) = async_channel::unbounded();
-        let (update_tx, _) = broadcast::channel(16);
+        let (request_tx, request_rx) = async_channel::bounded(config.settings.queue_capacity);
+
+        let simulation = config.settings.simulate_deployments.then(|| config.simulation.clone());
+
+        let services = config.services.iter()
+            .filter_map(|(id, cfg)| {
+                let service = Challenge {
+                    id: id.clone(),
+                    name: id.clone(),
+                    description: None,
+                    ttl: 0,
+                    pipeline: DeploymentStep::pipeline(config, id, std::iter::once(&cfg.deployer))?,
+                    targets: Vec::new(),
+                    requires: Vec::new(),
+                    depends_on: Vec::new(),
+                    scoreboard_id: None,
+                    simulation: simulation.clone(),
+                    extendable: false,
+                    extension: 0,
+                    max_extensions: None,
+                    deploy_timeout: config.settings.deploy_timeout,
+                    deploy_kill_grace: config.settings.deploy_kill_grace,
+                    restart_details: false,
+                    weight: 1,
+                    namespace: config.settings.namespace.clone(),
+                    labels: BTreeMap::new(),
+                    owners: Vec::new(),
+                    bundle: None,
+                    #[cfg(feature = "fault-injection")]
+                    faults: config.fault_injection.clone().map(FaultInjector::new)
+                };
+                service.check_pipeline().then(|| (id.clone(), service))
+            })
+            .collect::<HashMap<String, Challenge>>();
 
         let challenges = config.challenges.iter()
-            .filter_map(|(id, cfg)|
-                config.deployers.get(&cfg.deployer).map(|deployer| {
-                    let challenge = Challenge {
-                        id: id.clone(),
-                        name: cfg.name.clone(),
-                        description: cfg.description.clone(),
-                        ttl: cfg.ttl,
-                        deployer_path: deployer.path.clone(),
-                    };
-                    (id.clone(), challenge)
-                })
-            )
-            .filter(|(_, challenge)| {
-                if challenge.deployer_path.exists() {
-                    true
-                } else {
-                    tracing::warn!("disabled challenge {}: deployer does not exist at \"{}\"", challenge.id, challenge.deployer_path.display());
+            .filter_map(|(id, cfg)| Challenge::from_config(id, cfg, config).map(|challenge| (id.clone(), challenge)))
+            .filter(|(_, challenge)| match challenge.depends_on.iter().find(|service_id| !services.contains_key(*service_id)) {
+                Some(service_id) => {
+                    tracing::warn!("disabled challenge {}: shared service {} is unavailable", challenge.id, service_id);
                     false
                 }
+                None => true
             })
-            .collect();
+            .collect::<HashMap<String, Challenge>>();
+
+        for challenge in challenges.values() {
+            for step in challenge.pipeline.iter().filter(|step| step.deployer.container_image.is_some() && step.deployer.sandbox.is_some()) {
+                tracing::warn!("challenge {} runs deployer {} in a container, its sandbox limits will be ignored", challenge.id, step.name);
+            }
+            for required_id in challenge.requires.iter().filter(|id| !challenges.contains_key(*id)) {
+                tracing::warn!("challenge {} requires unavailable challenge {}, it will never be startable", challenge.id, required_id);
+            }
+        }
+
+        if config.settings.simulate_deployments {
+            tracing::warn!("deployments are simulated, no deployer will be executed");
+        }
+
+        let updates = Arc::new(UpdateHub::new(config.settings.update_capacity, database.clone(), config.settings.message_replay_window));
+        let webhooks = Arc::new(Webhooks::new(config.webhooks.clone()));
+        let notifications = Notifications::new(config, database.clone(), Arc::clone(&updates), Arc::clone(&webhooks));
 
         DeploymentWorker {
             request_rx,
             request_tx,
-            update_tx,
-            challenges,
+            updates,
+            challenges: ChallengeRegistry::new(challenges),
+            services: SharedServices::new(services, database.clone()),
             database,
-            ttl_expiries: Mutex::new(BinaryHeap::new()),
+            ttl_expiries: Mutex::new(TtlQueue::new()),
+            expiry_warnings: Mutex::new(TtlQueue::new()),
+            expiry_warning: config.settings.expiry_warning,
+            expiry_grace_period: config.settings.expiry_grace_period,
+            auto_extend_max_lifetime: config.settings.auto_extend.then_some(config.settings.auto_extend_max_lifetime),
+            demo_ttl: config.demo.as_ref().map(|demo| demo.ttl),
             shutdown_token,
+            rejected_requests: AtomicU64::new(0),
+            scheduler: std::sync::Mutex::new(FairScheduler::new(
+                config.settings.max_in_flight_per_user,
+                config.settings.admins.iter().chain(&config.settings.staff).cloned().collect()
+            )),
+            active_workers: AtomicUsize::new(0),
+            live: LiveDeployments::new(),
+            latency: LatencyTracker::default(),
+            webhooks,
+            notifications,
+            messages: MessageTemplates::load(config.settings.message_templates.as_deref()),
+            outbox: Notify::new(),
+            scheduled: Notify::new(),
+            maintenance: AtomicBool::new(false),
+            event_ended: AtomicBool::new(false)
+        }
+    }
+
+    /// Applies the challenge overrides stored in the database on top of the configuration.
+    pub async fn load_challenge_overrides(&self, config: &InstancerConfig) -> anyhow::Result<()> {
+        for over in self.database.get_challenge_overrides().await? {
+            if !self.apply_challenge_override(config, &over) {
+                tracing::warn!("couldn't apply the override of challenge {}, keeping its configured definition", over.challenge_id);
+            }
         }
+        Ok(())
     }
 
-    pub async fn do_work(&self) -> anyhow::Result<()> {
+    /// Builds a challenge, or None if its deployers or the shared services it depends on are unusable.
+    pub fn build_challenge(&self, config: &InstancerConfig, id: &str, cfg: &ChallengeConfig) -> Option<Challenge> {
+        let challenge = Challenge::from_config(id, cfg, config)?;
+
+        if let Some(service_id) = challenge.depends_on.iter().find(|service_id| !self.services.contains(service_id)) {
+            tracing::warn!("challenge {} depends on unavailable shared service {}", challenge.id, service_id);
+            return None;
+        }
+        Some(challenge)
+    }
+
+    /// Builds a challenge from its configuration overlaid with the override, or None if the result isn't
+    /// a complete challenge.
+    pub fn build_challenge_override(&self, config: &InstancerConfig, over: &ChallengeOverride) -> Option<Challenge> {
+        let cfg = ChallengeConfig::overlay(config.challenges.get(&over.challenge_id), over)?;
+        self.build_challenge(config, &over.challenge_id, &cfg)
+    }
+
+    /// Redefines a challenge with an override. Returns false, leaving the current definition in place,
+    /// if the override can't be built.
+    pub fn apply_challenge_override(&self, config: &InstancerConfig, over: &ChallengeOverride) -> bool {
+        let Some(challenge) = self.build_challenge_override(config, over) else { return false };
+        self.challenges.insert(challenge);
+        true
+    }
+
+    /// Restores the configured definition of a challenge, disabling it if it was only defined by its override.
+    pub fn revert_challenge_override(&self, config: &InstancerConfig, challenge_id: &str) {
+        match config.challenges.get(challenge_id).and_then(|cfg| Challenge::from_config(challenge_id, cfg, config)) {
+            Some(challenge) => self.challenges.insert(challenge),
+            None => self.challenges.remove(challenge_id)
+        }
+    }
+
+    /// Runs `do_work`, restarting it with exponential backoff whenever it fails or panics.
+    pub async fn supervise(state: Arc<InstancerState>, worker_id: u32, pool: Option<String>) {
+        let this = &state.deployer;
+        let mut backoff = Duration::from_secs(1);
+
+        loop {
+            this.active_workers.fetch_add(1, atomic::Ordering::Relaxed);
+            let started_at = time::Instant::now();
+            let attempt = tokio::spawn({
+                let (state, pool) = (Arc::clone(&state), pool.clone());
+                async move { state.deployer.do_work(pool.as_deref()).await }
+            });
+            let result = attempt.await.unwrap_or_else(|err| Err(err.into()));
+            let active_workers = this.active_workers.fetch_sub(1, atomic::Ordering::Relaxed) - 1;
+
+            let Err(err) = result else { break; };
+            tracing::error!("deployment worker {} failed: {:?}", worker_id, err);
+
+            if active_workers == 0 {
+                tracing::error!("all deployment workers are down, requests will not be handled until one restarts");
+            }
+
+            if this.shutdown_token.is_cancelled() { break; }
+
+            /* a worker that ran fine for a while before failing starts over from the shortest backoff */
+            if started_at.elapsed() > Duration::from_secs(300) {
+                backoff = Duration::from_secs(1);
+            }
+
+            tracing::info!("restarting deployment worker {} in {:?}", worker_id, backoff);
+            tokio::select! {
+                _ = this.shutdown_token.cancelled() => break,
+                _ = time::sleep(backoff) => {}
+            }
+            backoff = (backoff * 2).min(Duration::from_secs(60));
+        }
+    }
+
+    /// Handles the requests of the challenges deploying in `pool`, or of those outside of any pool if None.
+    pub async fn do_work(&self, pool: Option<&str>) -> anyhow::Result<()> {
         let request_rx = self.request_rx.clone();
 
-        while !self.shutdown_token.is_cancelled() || request_rx.len() > 0 {
+        while !self.shutdown_token.is_cancelled() || self.queue_len() > 0 {
             let time_until_next_expiry = {
                 let mut ttl_expiries = self.ttl_expiries.lock().await;
+                if let Some(skew) = ttl_expiries.resync() {
+                    tracing::warn!("wall clock jumped by {}ms, shifting the stop times of instances alike", skew);
+                    self.database.shift_stop_times(skew).await?;
+                    ttl_expiries.shift(skew);
+                }
 
                 loop {
-                    let Some(next_expired) = ttl_expiries.peek() else { break Duration::from_secs(60); };
+                    let Some(next_deadline) = ttl_expiries.peek() else { break Duration::from_secs(60); };
 
-                    if next_expired.0.stop_time > TimeSinceEpoch::now() {
-                        break &next_expired.0.stop_time - &TimeSinceEpoch::now();
+                    let now = time::Instant::now();
+                    if next_deadline > now {
+                        break next_deadline - now;
                     };
 
-                    let next_expired = ttl_expiries.pop().unwrap();
+                    let (user_id, challenge_id) = ttl_expiries.pop().unwrap();
 
-                    if self.database.transition_challenge_instance_state(&next_expired.0.user_id, &next_expired.0.challenge_id, ChallengeInstanceState::Running, ChallengeInstanceState::QueuedStop).await? {
-                        let request = DeploymentRequest {
-                            user_id: next_expired.0.user_id.clone(),
-                            challenge_id: next_expired.0.challenge_id.clone(),
-                            command: DeploymentRequestCommand::Stop
-                        };
-                        self.request_tx.send(request).await?;
+                    /* running instances get a grace period to be extended in, expiring ones are stopped */
+                    let expiry = async {
+                        let extendable = self.challenges.get(&challenge_id).is_some_and(|challenge| challenge.extendable);
+                        let grace_stop_time = TimeSinceEpoch::from_now(Duration::from_secs(self.expiry_grace_period as u64));
+                        if extendable && self.database.begin_challenge_instance_grace_period(&user_id, &challenge_id, &grace_stop_time).await? {
+                            ttl_expiries.push(user_id.clone(), challenge_id.clone(), grace_stop_time.clone());
+                            self.notify_expiring(user_id.clone(), challenge_id.clone(), grace_stop_time).await;
+                        } else if self.queue_stop(&user_id, &challenge_id, EndReason::Expired).await? {
+                            self.webhooks.fire(WebhookEvent::Expired, &user_id, &challenge_id, None);
+                        }
+                        anyhow::Ok(())
+                    }.await;
 
-                        let state_change = DeploymentUpdate {
-                            user_id: next_expired.0.user_id.clone(),
-                            challenge_id: next_expired.0.challenge_id.clone(),
-                            details: DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::QueuedStop, details: None, stop_time: None }
-                        };
-                        let _ = self.update_tx.send(state_change);
+                    /* the expiry stays due, for the restarted worker to handle it again */
+                    if let Err(err) = expiry {
+                        ttl_expiries.push(user_id, challenge_id, TimeSinceEpoch::now());
+                        return Err(err);
                     }
                 }
             };
+            let time_until_next_expiry = time_until_next_expiry.min(self.send_due_expiry_warnings().await);
+
+            let scheduled = self.scheduled.notified();
+            let (next_request, received) = {
+                let mut scheduler = self.scheduler.lock().unwrap();
+                let mut received = false;
+                while let Ok(request) = request_rx.try_recv() {
+                    scheduler.push(request);
+                    received = true;
+                }
+                (scheduler.next(|request| self.pool_of(&request.challenge_id).as_deref() == pool), received)
+            };
+            if received {
+                self.scheduled.notify_waiters();
+            }
+
+            if let Some(request) = next_request {
+                self.latency.record_queue_wait(&request.challenge_id, request.queued_at.elapsed());
+                let in_flight = InFlight { scheduler: &self.scheduler, user_id: request.user_id.clone() };
+                let retry = request.clone();
+                let result = self.handle_request(request).await;
+
+                /* the request is handled again by the restarted worker, which may find it went partway through */
+                if result.is_err() {
+                    let command = match retry.command {
+                        DeploymentRequestCommand::Start { .. } => DeploymentRequestCommand::Start { retry: true },
+                        command => command
+                    };
+                    self.enqueue(DeploymentRequest { command, ..retry });
+                }
+                drop(in_flight);
+                /* the next request of the user may be waiting on another pool */
+                self.scheduled.notify_waiters();
+                result?;
+                continue;
+            }
+
+            /* while draining, pending requests may only be waiting on another worker's in-flight request */
+            let shutting_down = self.shutdown_token.is_cancelled();
+            let time_until_wakeup = if shutting_down { time_until_next_expiry.min(Duration::from_millis(100)) } else { time_until_next_expiry };
 
             tokio::select! {
-                _ = self.shutdown_token.cancelled() => {},
-                _ = time::sleep(time_until_next_expiry) => {},
+                _ = self.shutdown_token.cancelled(), if !shutting_down => {},
+                _ = time::sleep(time_until_wakeup) => {},
+                _ = scheduled => {},
                 req = request_rx.recv() => {
                     if let Ok(request) = req {
-                        self.handle_request(request).await?;
+                        self.scheduler.lock().unwrap().push(request);
+                        self.scheduled.notify_waiters();
                     }
                 }
             }
@@ -259,206 +1002,739 @@ impl DeploymentWorker {
         Ok(())
     }
 
+    /// Picks the target with the fewest instances among those with capacity left.
+    async fn choose_target(&self, challenge: &Challenge, excluded: &[String]) -> anyhow::Result<Option<String>> {
+        let loads = self.database.get_target_loads().await?;
+        Ok(challenge.targets.iter()
+            .filter(|step| !excluded.contains(&step.name))
+            .map(|step| (step, loads.get(&step.name).copied().unwrap_or(0)))
+            .filter(|(step, load)| step.deployer.capacity.is_none_or(|capacity| *load < capacity as i64))
+            .min_by_key(|(_, load)| *load)
+            .map(|(step, _)| step.name.clone()))
+    }
+
+    /// Picks a target with `choose_target`, recording it as the instance's.
+    async fn place(&self, challenge: &Challenge, user_id: &str, excluded: &[String]) -> anyhow::Result<Option<String>> {
+        let target = self.choose_target(challenge, excluded).await?;
+        match &target {
+            Some(target) => self.database.set_instance_target(user_id, &challenge.id, target).await?,
+            None => tracing::warn!("couldn't place challenge {} for user {}: no target has capacity left", challenge.id, user_id)
+        }
+        Ok(target)
+    }
+
+    /// Starts the instance on another target for as long as the infrastructure of the current one fails, cleaning up
+    /// after each target it leaves. Failovers are recorded for the admins.
+    async fn fail_over(&self, challenge: &Challenge, target: &DeploymentTarget<'_>, mut output: Result<DeploymentOutput, DeploymentError>, progress: &watch::Sender<u8>) -> Result<DeploymentOutput, DeploymentError> {
+        let Some(mut current) = target.deployer.map(str::to_string) else { return output };
+        let mut tried = vec![current.clone()];
+
+        while output.as_ref().err() == Some(&DeploymentError::Infrastructure) {
+            let failed = DeploymentTarget { deployer: Some(&current), ..*target };
+            let _ = challenge.deploy(&self.live, &self.updates, &failed, DeploymentRequestCommand::Cleanup, None).await;
+
+            let next = match self.place(challenge, target.user_id, &tried).await {
+                Ok(Some(next)) => next,
+                Ok(None) => break,
+                Err(err) => {
+                    tracing::error!("couldn't fail challenge {} over for user {}: {:?}", challenge.id, target.user_id, err);
+                    break;
+                }
+            };
+            tracing::warn!("failing challenge {} over from target {} to {} for user {}", challenge.id, current, next, target.user_id);
+
+            let failover = TargetFailover {
+                time: TimeSinceEpoch::now(),
+                user_id: target.user_id.to_string(),
+                challenge_id: challenge.id.clone(),
+                from_target: current.clone(),
+                to_target: next.clone()
+            };
+            if let Err(err) = self.database.insert_target_failover(&failover).await {
+                tracing::warn!("couldn't record the failover of challenge {} for user {}: {:?}", challenge.id, target.user_id, err);
+            }
+
+            let next_target = DeploymentTarget { deployer: Some(&next), ..*target };
+            output = challenge.deploy(&self.live, &self.updates, &next_target, DeploymentRequestCommand::Start { retry: false }, Some(progress)).await;
+            tried.push(next.clone());
+            current = next;
+        }
+
+        output
+    }
+
+    fn pool_of(&self, challenge_id: &str) -> Option<String> {
+        self.challenges.get(challenge_id).and_then(|challenge| challenge.pool().map(str::to_string))
+    }
+
+    /// Warns users whose instances are about to expire, returning the time until the next warning is due.
+    async fn send_due_expiry_warnings(&self) -> Duration {
+        let mut expiry_warnings = self.expiry_warnings.lock().await;
+        if let Some(skew) = expiry_warnings.resync() { expiry_warnings.shift(skew); }
+
+        loop {
+            let Some(next_warning) = expiry_warnings.peek() else { break Duration::from_secs(60); };
+
+            let now = time::Instant::now();
+            if next_warning > now {
+                break next_warning - now;
+            }
+
+            let (user_id, challenge_id) = expiry_warnings.pop().unwrap();
+            let Some(challenge) = self.challenges.get(&challenge_id) else { continue };
+
+            let minutes = self.expiry_warning.div_ceil(60);
+            self.notifications.send(Notification {
+                kind: NotificationKind::ExpiryWarning,
+                user_id,
+                challenge_id,
+                contents: self.messages.render("expiry_warning", context! { challenge => challenge.name, minutes, extendable => challenge.extendable }),
+                severity: MessageSeverity::Warning
+            }).await;
+        }
+    }
+
+    /// The TTL of a new instance of the challenge for the user, shortened for demo users.
+    async fn instance_ttl(&self, challenge: &Challenge, user_id: &str) -> anyhow::Result<Duration> {
+        let Some(demo_ttl) = self.demo_ttl else { return Ok(challenge.ttl_duration()) };
+
+        let demo = self.database.fetch_user(user_id).await?.is_some_and(|user| user.role == UserRole::Demo);
+        Ok(match demo {
+            true => challenge.ttl_duration().min(Duration::from_secs(demo_ttl as u64)),
+            false => challenge.ttl_duration()
+        })
+    }
+
+    /// Pushes back the stop time of the user's running instances that are past half their TTL, without
+    /// exceeding the max lifetime. Returns the instances that were extended along with their new stop time.
+    pub async fn extend_active_instances(&self, user_id: &str) -> anyhow::Result<Vec<(String, TimeSinceEpoch)>> {
+        let Some(max_lifetime) = self.auto_extend_max_lifetime else { return Ok(Vec::new()) };
+
+        let mut extended = Vec::new();
+        for instance in self.database.get_user_challenge_instances(user_id).await? {
+            let (ChallengeInstanceState::Running | ChallengeInstanceState::Expiring, Some(stop_time), Some(start_time), None) = (&instance.state, &instance.stop_time, &instance.start_time, &instance.paused_at) else { continue };
+            let Some(challenge) = self.challenges.get(&instance.challenge_id).filter(|challenge| challenge.extendable) else { continue };
+
+            let remaining = stop_time.0.duration_since(TimeSinceEpoch::now().0).unwrap_or_default();
+            if remaining > challenge.ttl_duration() / 2 { continue; }
+
+            let max_stop_time = TimeSinceEpoch(start_time.0 + Duration::from_secs(max_lifetime as u64));
+            let new_stop_time = TimeSinceEpoch::from_now(challenge.ttl_duration()).min(max_stop_time);
+            if new_stop_time <= *stop_time { continue; }
+
+            if self.database.extend_challenge_instance(user_id, &instance.challenge_id, new_stop_time.clone()).await? {
+                self.push_ttl(user_id.to_string(), instance.challenge_id.clone(), new_stop_time.clone()).await;
+                extended.push((instance.challenge_id, new_stop_time));
+            }
+        }
+
+        Ok(extended)
+    }
+
+    async fn notify_expiring(&self, user_id: String, challenge_id: String, stop_time: TimeSinceEpoch) {
+        let Some(challenge) = self.challenges.get(&challenge_id) else { return };
+
+        let state_change = DeploymentUpdate {
+            user_id: user_id.clone(),
+            challenge_id: challenge_id.clone(),
+            details: DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Expiring, details: None, stop_time: Some(stop_time) }
+        };
+        self.updates.send(state_change);
+
+        self.notifications.send(Notification {
+            kind: NotificationKind::ExpiryWarning,
+            user_id,
+            challenge_id,
+            contents: self.messages.render("expired", context! { challenge => challenge.name }),
+            severity: MessageSeverity::Warning
+        }).await;
+    }
+
+    /// Number of requests waiting to be handled.
+    pub fn queue_len(&self) -> usize {
+        self.request_tx.len() + self.scheduler.lock().unwrap().len()
+    }
+
+    /// Number of user requests rejected since startup because the queue was full.
+    pub fn rejected_requests(&self) -> u64 {
+        self.rejected_requests.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Enqueues a request made by a user, returning false if the queue is full and it was rejected.
+    pub fn try_enqueue(&self, request: DeploymentRequest) -> bool {
+        let Err(err) = self.request_tx.try_send(request) else { return true };
+
+        let rejected_requests = self.rejected_requests.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+        tracing::warn!("deployment queue is full, rejected request from user {} ({} rejected so far)", err.into_inner().user_id, rejected_requests);
+        false
+    }
+
+    /// Enqueues a request made by the instancer itself, which is never rejected. Once the queue is full it is handed to
+    /// the scheduler right away, workers enqueueing follow-up requests having no one to wait on otherwise.
+    pub fn enqueue(&self, request: DeploymentRequest) {
+        if let Err(err) = self.request_tx.try_send(request) {
+            self.scheduler.lock().unwrap().push(err.into_inner());
+            self.scheduled.notify_waiters();
+        }
+    }
+
+    /// Transitions a running instance to QueuedStop and enqueues its stop request, returns false if it wasn't running.
+    pub async fn queue_stop(&self, user_id: &str, challenge_id: &str, reason: EndReason) -> anyhow::Result<bool> {
+        self.queue_command(user_id, challenge_id, &[ChallengeInstanceState::Running, ChallengeInstanceState::Expiring], ChallengeInstanceState::QueuedStop, DeploymentRequestCommand::Stop, Some(reason)).await
+    }
+
+    /// Transitions a running instance to QueuedRestart and enqueues its restart request, returns false if it wasn't running.
+    pub async fn queue_restart(&self, user_id: &str, challenge_id: &str) -> anyhow::Result<bool> {
+        self.queue_command(user_id, challenge_id, &[ChallengeInstanceState::Running], ChallengeInstanceState::QueuedRestart, DeploymentRequestCommand::Restart, None).await
+    }
+
+    /// Transitions a running instance to QueuedRestart and enqueues its migration to another target, returns false if it
+    /// wasn't running. Should the instancer stop before the migration, the instance is cleaned up as interrupted like
+    /// any queued restart.
+    pub async fn queue_migrate(&self, user_id: &str, challenge_id: &str) -> anyhow::Result<bool> {
+        self.queue_command(user_id, challenge_id, &[ChallengeInstanceState::Running], ChallengeInstanceState::QueuedRestart, DeploymentRequestCommand::Migrate, None).await
+    }
+
+    /// Transitions a running instance to QueuedStop and enqueues its cleanup request, returns false if it wasn't running.
+    pub async fn queue_cleanup(&self, user_id: &str, challenge_id: &str, reason: EndReason) -> anyhow::Result<bool> {
+        self.queue_command(user_id, challenge_id, &[ChallengeInstanceState::Running, ChallengeInstanceState::Expiring], ChallengeInstanceState::QueuedStop, DeploymentRequestCommand::Cleanup, Some(reason)).await
+    }
+
+    /// Transitions an instance to `queued_state` and enqueues its request, recording why it ends if the request ends it.
+    async fn queue_command(&self, user_id: &str, challenge_id: &str, from: &[ChallengeInstanceState], queued_state: ChallengeInstanceState, command: DeploymentRequestCommand, end_reason: Option<EndReason>) -> anyhow::Result<bool> {
+        let mut queued = false;
+        for state in from {
+            if self.database.transition_challenge_instance_state(user_id, challenge_id, state.clone(), queued_state.clone()).await? {
+                queued = true;
+                break;
+            }
+        }
+        if !queued {
+            return Ok(false);
+        }
+
+        if let Some(end_reason) = end_reason {
+            self.database.set_challenge_instance_end_reason(user_id, challenge_id, &end_reason).await?;
+        }
+
+        let request = DeploymentRequest {
+            user_id: user_id.to_string(),
+            challenge_id: challenge_id.to_string(),
+            command,
+            queued_at: time::Instant::now()
+        };
+        self.enqueue(request);
+
+        let state_change = DeploymentUpdate {
+            user_id: user_id.to_string(),
+            challenge_id: challenge_id.to_string(),
+            details: DeploymentUpdateDetails::StateChange { state: queued_state, details: None, stop_time: None }
+        };
+        self.updates.send(state_change);
+
+        Ok(true)
+    }
+
     async fn handle_request(&self, request: DeploymentRequest) -> anyhow::Result<()> {
         let Some(challenge) = self.challenges.get(&request.challenge_id) else { return Ok(()) };
+        let Some(instance) = self.database.get_challenge_instance(&request.user_id, &request.challenge_id).await? else { return Ok(()) };
 
-        let (state_change, message) = match &request.command {
-            DeploymentRequestCommand::Start => {
-                match challenge.deploy(&request.user_id, DeploymentRequestCommand::Start).await {
-                    Ok(Some(details)) => {
-                        tracing::info!("started challenge {} for user {}", challenge.id, request.user_id);
+        /* labels attached by the deployers on a previous deployment take precedence over the configured ones */
+        let mut labels = challenge.labels.clone();
+        labels.extend(self.database.get_instance_labels(&request.user_id, &request.challenge_id).await?);
 
-                        let stop_time = TimeSinceEpoch::from_now(challenge.ttl_duration());
+        /* starts are placed on a target, which every later request of the instance goes through */
+        let mut placement = self.database.get_instance_target(&request.user_id, &request.challenge_id).await?;
+        if placement.is_none() && !challenge.targets.is_empty() && matches!(request.command, DeploymentRequestCommand::Start { .. }) && instance.state.is_starting() {
+            placement = self.place(&challenge, &request.user_id, &[]).await?;
+        }
+        let placed = placement.is_some() || challenge.targets.is_empty();
+        let target = DeploymentTarget { user_id: &request.user_id, nonce: &instance.nonce, labels: &labels, deployer: placement.as_deref() };
 
-                        self.push_ttl(request.user_id.clone(), request.challenge_id.clone(), stop_time.clone()).await;
-                        self.database.populate_running_challenge_instance(&request.user_id, &request.challenge_id, &details, Some(stop_time.clone())).await?;
+        match &request.command {
+            /* a start can be queued again when the instancer restarts, so there's nothing to do if it already went through */
+            DeploymentRequestCommand::Start { .. } if !instance.state.is_starting() => return Ok(()),
+            /* starts still queued when the event ends are cleaned up without deploying */
+            DeploymentRequestCommand::Start { .. } if self.event_ended.load(atomic::Ordering::Relaxed) => {
+                self.database.fail_challenge_instance(&request.user_id, &request.challenge_id, &EndReason::EventEnded, &outbox_entries(&request, &[
+                    DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::QueuedStart, details: None, stop_time: None },
+                    DeploymentUpdateDetails::Message {
+                        contents: self.messages.render("event_ended", context! {}),
+                        severity: MessageSeverity::Warning
+                    }
+                ])).await?;
 
-                        (
-                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Running, details: Some(details), stop_time: Some(stop_time) },
+                let cleanup_request = DeploymentRequest {
+                    user_id: request.user_id.clone(),
+                    challenge_id: request.challenge_id.clone(),
+                    command: DeploymentRequestCommand::Cleanup,
+                    queued_at: time::Instant::now()
+                };
+                self.enqueue(cleanup_request);
+            }
+            DeploymentRequestCommand::Start { retry } => {
+                let acquired = placed && self.services.acquire(&self.live, &self.updates, &challenge.depends_on).await.is_ok();
+                let output = if acquired {
+                    let (progress_tx, progress_rx) = watch::channel(0);
+                    let deploy = async {
+                        let started_at = time::Instant::now();
+                        let output = challenge.deploy(&self.live, &self.updates, &target, DeploymentRequestCommand::Start { retry: *retry }, Some(&progress_tx)).await;
+                        let output = self.fail_over(&challenge, &target, output, &progress_tx).await;
+                        if output.is_ok() {
+                            self.latency.record_deploy_duration(&challenge.id, started_at.elapsed());
+                        }
+                        drop(progress_tx);
+                        output
+                    };
+                    let (output, ()) = tokio::join!(deploy, self.report_progress(&request.user_id, &request.challenge_id, progress_rx));
+
+                    output.and_then(|output| match output.details.is_some() || !output.metadata.is_empty() {
+                        true => Ok(output),
+                        false => Err(DeploymentError::Failed)
+                    })
+                } else {
+                    Err(DeploymentError::Failed)
+                };
+
+                match output {
+                    Ok(DeploymentOutput { details, metadata, labels: reported, warnings }) => {
+                        tracing::info!("started challenge {} for user {}", challenge.id, request.user_id);
+
+                        let stop_time = TimeSinceEpoch::from_now(self.instance_ttl(&challenge, &request.user_id).await?);
+                        let mut updates = vec![
+                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Running, details: details.clone(), stop_time: Some(stop_time.clone()) },
+                            DeploymentUpdateDetails::Metadata { metadata: metadata.clone() },
                             DeploymentUpdateDetails::Message {
-                                contents: format!("Le défi <strong>{}</strong> a été démarré!", challenge.name),
+                                contents: self.messages.render("started", context! { challenge => challenge.name }),
                                 severity: MessageSeverity::Success
                             }
-                        )
+                        ];
+                        updates.extend(self.warning_messages(&challenge, &warnings));
+
+                        /* instances started during maintenance wait for it to end before their TTL runs */
+                        let maintenance = self.maintenance.load(atomic::Ordering::Relaxed);
+                        if maintenance {
+                            updates.push(DeploymentUpdateDetails::Paused { paused: true, stop_time: None });
+                        } else {
+                            self.push_ttl(request.user_id.clone(), request.challenge_id.clone(), stop_time.clone()).await;
+                        }
+                        let outbox = outbox_entries(&request, &updates);
+
+                        self.database.set_instance_metadata(&request.user_id, &request.challenge_id, &metadata).await?;
+                        labels.extend(reported);
+                        self.database.set_instance_labels(&request.user_id, &request.challenge_id, &labels).await?;
+                        self.database.populate_running_challenge_instance(&request.user_id, &request.challenge_id, Some(details.as_deref().unwrap_or_default()), Some(stop_time), &outbox).await?;
+                        if maintenance {
+                            self.database.pause_challenge_instance(&request.user_id, &request.challenge_id, &TimeSinceEpoch::now(), true).await?;
+                        }
+                        self.database.insert_challenge_history(&request.user_id, &request.challenge_id, &TimeSinceEpoch::now()).await?;
+                        self.webhooks.fire(WebhookEvent::Started, &request.user_id, &request.challenge_id, details.as_deref());
+                        self.notify_state_change(&request, "started", &challenge).await;
                     }
-                    Err(_) | Ok(None) => {
+                    Err(err) => {
                         tracing::error!("couldn't start challenge {} for user {}", challenge.id, request.user_id);
+                        self.database.fail_challenge_instance(&request.user_id, &request.challenge_id, &EndReason::from(err), &outbox_entries(&request, &[
+                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::QueuedStart, details: None, stop_time: None }
+                        ])).await?;
+                        if acquired {
+                            self.services.release(&self.live, &self.updates, &challenge.depends_on).await;
+                        }
+                        self.webhooks.fire(WebhookEvent::Failed, &request.user_id, &request.challenge_id, None);
+                        self.notify_failure(&request, err, "start_failed", &challenge).await;
 
                         let cleanup_request = DeploymentRequest {
                             user_id: request.user_id.clone(),
                             challenge_id: request.challenge_id.clone(),
                             command: DeploymentRequestCommand::Cleanup,
+                            queued_at: time::Instant::now()
                         };
-                        self.request_tx.send(cleanup_request).await?;
-
-                        (
-                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::QueuedStart, details: None, stop_time: None },
-                            DeploymentUpdateDetails::Message {
-                                contents: format!("Le défi <strong>{}</strong> n'a pas pu être démarré.<br>Contactez un administrateur si l'erreur persiste.", challenge.name),
-                                severity: MessageSeverity::Error
-                            }
-                        )
+                        self.enqueue(cleanup_request);
                     }
                 }
             }
             DeploymentRequestCommand::Stop => {
-                match challenge.deploy(&request.user_id, DeploymentRequestCommand::Stop).await {
+                match challenge.deploy(&self.live, &self.updates, &target, DeploymentRequestCommand::Stop, None).await {
                     Ok(_) => {
                         tracing::info!("stopped challenge {} for user {}", challenge.id, request.user_id);
+                        self.webhooks.fire(WebhookEvent::Stopped, &request.user_id, &request.challenge_id, None);
 
-                        self.pop_ttl(&request.user_id, &request.challenge_id).await;
-                        self.database.delete_challenge_instance(&request.user_id, &request.challenge_id).await?;
-
-                        (
+                        let outbox = outbox_entries(&request, &[
                             DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Stopped, details: None, stop_time: None },
                             DeploymentUpdateDetails::Message {
-                                contents: format!("Le défi <strong>{}</strong> a été arrêté.", challenge.name),
+                                contents: self.messages.render("stopped", context! { challenge => challenge.name }),
                                 severity: MessageSeverity::Success
                             }
-                        )
+                        ]);
+
+                        self.pop_ttl(&request.user_id, &request.challenge_id).await;
+                        self.database.delete_challenge_instance(&request.user_id, &request.challenge_id, &EndReason::Stopped, &outbox).await?;
+                        self.services.release(&self.live, &self.updates, &challenge.depends_on).await;
+                        self.notify_state_change(&request, "stopped", &challenge).await;
                     }
-                    Err(_) => {
+                    Err(err) => {
                         tracing::error!("couldn't stop challenge {} for user {}", challenge.id, request.user_id);
+                        self.database.fail_challenge_instance(&request.user_id, &request.challenge_id, &EndReason::from(err), &outbox_entries(&request, &[
+                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::QueuedStop, details: None, stop_time: None }
+                        ])).await?;
+                        self.webhooks.fire(WebhookEvent::Failed, &request.user_id, &request.challenge_id, None);
+                        self.notify_failure(&request, err, "stop_failed", &challenge).await;
 
                         let cleanup_request = DeploymentRequest {
                             user_id: request.user_id.clone(),
                             challenge_id: request.challenge_id.clone(),
                             command: DeploymentRequestCommand::Cleanup,
+                            queued_at: time::Instant::now()
                         };
-                        self.request_tx.send(cleanup_request).await?;
-
-                        (
-                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::QueuedStop, details: None, stop_time: None },
-                            DeploymentUpdateDetails::Message {
-                                contents: format!("Le défi <strong>{}</strong> n'a pas pu être arrêté.<br>Contactez un administrateur si l'erreur persiste.", challenge.name),
-                                severity: MessageSeverity::Error
-                            }
-                        )
+                        self.enqueue(cleanup_request);
                     }
                 }
             }
             DeploymentRequestCommand::Restart => {
-                match challenge.deploy(&request.user_id, DeploymentRequestCommand::Restart).await {
-                    Ok(details) => {
+                match challenge.deploy(&self.live, &self.updates, &target, DeploymentRequestCommand::Restart, None).await {
+                    Ok(DeploymentOutput { details, metadata, labels: reported, warnings }) => {
                         tracing::info!("restarted challenge {} for user {}", challenge.id, request.user_id);
 
-                        match &details {
-                            None => { self.database.transition_challenge_instance_state(&request.user_id, &request.challenge_id, ChallengeInstanceState::QueuedRestart, ChallengeInstanceState::Running).await?; },
-                            Some(details) => { self.database.populate_running_challenge_instance(&request.user_id, &request.challenge_id, &details, None).await?; }
+                        let details = match challenge.restart_details {
+                            true => details,
+                            false => {
+                                if details.is_some() {
+                                    tracing::debug!("discarded the details printed by the restart of challenge {} for user {}", challenge.id, request.user_id);
+                                }
+                                None
+                            }
+                        };
+                        self.webhooks.fire(WebhookEvent::Started, &request.user_id, &request.challenge_id, details.as_deref().or(instance.details.as_deref()));
+
+                        let mut updates = vec![DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Running, details: details.clone(), stop_time: None }];
+                        if !metadata.is_empty() {
+                            self.database.set_instance_metadata(&request.user_id, &request.challenge_id, &metadata).await?;
+                            updates.push(DeploymentUpdateDetails::Metadata { metadata });
+                        }
+                        if !reported.is_empty() {
+                            labels.extend(reported);
+                            self.database.set_instance_labels(&request.user_id, &request.challenge_id, &labels).await?;
                         }
+                        updates.push(DeploymentUpdateDetails::Message {
+                            contents: self.messages.render("restarted", context! { challenge => challenge.name }),
+                            severity: MessageSeverity::Success
+                        });
+                        updates.extend(self.warning_messages(&challenge, &warnings));
 
-                        (
-                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Running, details, stop_time: None },
-                            DeploymentUpdateDetails::Message {
-                                contents: format!("Le défi <strong>{}</strong> a été redémarré!", challenge.name),
-                                severity: MessageSeverity::Success
-                            }
-                        )
+                        self.database.populate_running_challenge_instance(&request.user_id, &request.challenge_id, details.as_deref(), None, &outbox_entries(&request, &updates)).await?;
+                        self.notify_state_change(&request, "restarted", &challenge).await;
                     }
-                    Err(_) => {
+                    Err(err) => {
                         tracing::error!("couldn't restart challenge {} for user {}", challenge.id, request.user_id);
+                        self.database.fail_challenge_instance(&request.user_id, &request.challenge_id, &EndReason::from(err), &outbox_entries(&request, &[
+                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::QueuedRestart, details: None, stop_time: None }
+                        ])).await?;
+                        self.webhooks.fire(WebhookEvent::Failed, &request.user_id, &request.challenge_id, None);
+                        self.notify_failure(&request, err, "restart_failed", &challenge).await;
 
                         let cleanup_request = DeploymentRequest {
                             user_id: request.user_id.clone(),
                             challenge_id: request.challenge_id.clone(),
                             command: DeploymentRequestCommand::Cleanup,
+                            queued_at: time::Instant::now()
                         };
-                        self.request_tx.send(cleanup_request).await?;
+                        self.enqueue(cleanup_request);
+                    }
+                }
+            }
+            DeploymentRequestCommand::Migrate => {
+                let destination = self.choose_target(&challenge, placement.as_slice()).await?;
+                let output = match &destination {
+                    Some(destination) => {
+                        let destination_target = DeploymentTarget { deployer: Some(destination), ..target };
+                        challenge.deploy(&self.live, &self.updates, &destination_target, DeploymentRequestCommand::Start { retry: false }, None).await
+                    }
+                    None => {
+                        tracing::warn!("couldn't migrate challenge {} for user {}: no other target has capacity left", challenge.id, request.user_id);
+                        Err(DeploymentError::Failed)
+                    }
+                };
 
-                        (
-                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::QueuedRestart, details: None, stop_time: None },
-                            DeploymentUpdateDetails::Message {
-                                contents: format!("Le défi <strong>{}</strong> n'a pas pu être redémarré.<br>Contactez un administrateur si l'erreur persiste.", challenge.name),
-                                severity: MessageSeverity::Error
-                            }
-                        )
+                match (output, destination) {
+                    (Ok(DeploymentOutput { details, metadata, labels: reported, warnings }), Some(destination)) => {
+                        tracing::info!("migrated challenge {} for user {} from target {:?} to {}", challenge.id, request.user_id, placement, destination);
+                        self.database.set_instance_target(&request.user_id, &request.challenge_id, &destination).await?;
+
+                        /* the target the instance left is likely unhealthy, so tearing it down there is best effort */
+                        if challenge.deploy(&self.live, &self.updates, &target, DeploymentRequestCommand::Stop, None).await.is_err()
+                            && challenge.deploy(&self.live, &self.updates, &target, DeploymentRequestCommand::Cleanup, None).await.is_err() {
+                            tracing::error!("couldn't tear down challenge {} for user {} on target {:?} after migrating it", challenge.id, request.user_id, placement);
+                        }
+
+                        /* details not printed again by the new target are kept */
+                        let mut updates = vec![DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Running, details: details.clone(), stop_time: None }];
+                        if !metadata.is_empty() {
+                            self.database.set_instance_metadata(&request.user_id, &request.challenge_id, &metadata).await?;
+                            updates.push(DeploymentUpdateDetails::Metadata { metadata });
+                        }
+                        if !reported.is_empty() {
+                            labels.extend(reported);
+                            self.database.set_instance_labels(&request.user_id, &request.challenge_id, &labels).await?;
+                        }
+                        updates.push(DeploymentUpdateDetails::Message {
+                            contents: self.messages.render("migrated", context! { challenge => challenge.name }),
+                            severity: MessageSeverity::Info
+                        });
+                        updates.extend(self.warning_messages(&challenge, &warnings));
+
+                        self.database.populate_running_challenge_instance(&request.user_id, &request.challenge_id, details.as_deref(), None, &outbox_entries(&request, &updates)).await?;
+                        self.webhooks.fire(WebhookEvent::Started, &request.user_id, &request.challenge_id, details.as_deref().or(instance.details.as_deref()));
+                        self.notify_state_change(&request, "migrated", &challenge).await;
+                    }
+                    (output, destination) => {
+                        tracing::error!("couldn't migrate challenge {} for user {}, it keeps running on target {:?}", challenge.id, request.user_id, placement);
+                        if let (Err(_), Some(destination)) = (output, destination) {
+                            let destination_target = DeploymentTarget { deployer: Some(&destination), ..target };
+                            let _ = challenge.deploy(&self.live, &self.updates, &destination_target, DeploymentRequestCommand::Cleanup, None).await;
+                        }
+
+                        let outbox = outbox_entries(&request, &[
+                            DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Running, details: None, stop_time: None }
+                        ]);
+                        self.database.populate_running_challenge_instance(&request.user_id, &request.challenge_id, None, None, &outbox).await?;
                     }
                 }
             }
             DeploymentRequestCommand::Cleanup => {
-                match challenge.deploy(&request.user_id, DeploymentRequestCommand::Cleanup).await {
+                match challenge.deploy(&self.live, &self.updates, &target, DeploymentRequestCommand::Cleanup, None).await {
                     Ok(_) => {
                         tracing::info!("cleaned up challenge {} for user {}", challenge.id, request.user_id);
 
-                        self.pop_ttl(&request.user_id, &request.challenge_id).await;
-                        self.database.delete_challenge_instance(&request.user_id, &request.challenge_id).await?;
-
-                        (
+                        let outbox = outbox_entries(&request, &[
                             DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Stopped, details: None, stop_time: None },
                             DeploymentUpdateDetails::Message {
-                                contents: format!("Le défi <strong>{}</strong> a été réinitialisé.", challenge.name),
+                                contents: self.messages.render("reset", context! { challenge => challenge.name }),
                                 severity: MessageSeverity::Info
                             }
-                        )
+                        ]);
+
+                        self.pop_ttl(&request.user_id, &request.challenge_id).await;
+                        self.database.delete_challenge_instance(&request.user_id, &request.challenge_id, &EndReason::Failed, &outbox).await?;
+
+                        /* instances that never finished starting don't hold their shared services */
+                        if !instance.state.is_starting() {
+                            self.services.release(&self.live, &self.updates, &challenge.depends_on).await;
+                        }
                     }
-                    Err(_) => panic!("failed to clean up challenge {} for user {}", challenge.id, request.user_id)
+                    /* the instance stays queued, for the janitor to repair or escalate */
+                    Err(err) => tracing::error!("couldn't clean up challenge {} for user {}: {:?}", challenge.id, request.user_id, err)
                 }
             }
+        }
+
+        self.outbox.notify_one();
+        Ok(())
+    }
+
+    /// Tells a user that their deployment failed with the `failed` message, or that it was cancelled.
+    async fn notify_failure(&self, request: &DeploymentRequest, err: DeploymentError, failed: &str, challenge: &Challenge) {
+        let (contents, severity) = match err {
+            DeploymentError::Failed | DeploymentError::Infrastructure => (self.messages.render(failed, context! { challenge => challenge.name }), MessageSeverity::Error),
+            DeploymentError::Cancelled => (self.messages.render("cancelled", context! { challenge => challenge.name }), MessageSeverity::Warning)
         };
+        self.notifications.send(Notification {
+            kind: NotificationKind::DeployFailure,
+            user_id: request.user_id.clone(),
+            challenge_id: request.challenge_id.clone(),
+            contents,
+            severity
+        }).await;
+    }
 
-        let state_change = DeploymentUpdate {
+    /// Relays the outcome of a deployment with the `message` message to the channels state changes are routed to, the
+    /// dashboard getting it through the outbox.
+    async fn notify_state_change(&self, request: &DeploymentRequest, message: &str, challenge: &Challenge) {
+        self.notifications.send(Notification {
+            kind: NotificationKind::StateChange,
             user_id: request.user_id.clone(),
             challenge_id: request.challenge_id.clone(),
-            details: state_change,
-        };
-        let _ = self.update_tx.send(state_change);
+            contents: self.messages.render(message, context! { challenge => challenge.name }),
+            severity: MessageSeverity::Success
+        }).await;
+    }
 
-        let message = DeploymentUpdate {
-            user_id: request.user_id,
-            challenge_id: request.challenge_id,
-            details: message
-        };
-        let _ = self.update_tx.send(message);
+    /// Relays the warnings a deployer forwarded to the player.
+    fn warning_messages<'a>(&'a self, challenge: &'a Challenge, warnings: &'a [String]) -> impl Iterator<Item = DeploymentUpdateDetails> + 'a {
+        warnings.iter().map(|warning| DeploymentUpdateDetails::Message {
+            contents: self.messages.render("deployer_warning", context! { challenge => challenge.name, warning }),
+            severity: MessageSeverity::Warning
+        })
+    }
 
-        Ok(())
+    /// Persists and broadcasts the progress reported while an instance starts, until its deployers are done. The
+    /// instance becomes Deploying with its first report.
+    async fn report_progress(&self, user_id: &str, challenge_id: &str, mut progress_rx: watch::Receiver<u8>) {
+        let mut deploying = false;
+
+        while progress_rx.changed().await.is_ok() {
+            let progress = *progress_rx.borrow_and_update();
+            match self.database.set_challenge_instance_progress(user_id, challenge_id, progress).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) => {
+                    tracing::warn!("couldn't store the progress of challenge {} for user {}: {:?}", challenge_id, user_id, err);
+                    continue;
+                }
+            }
+
+            if !deploying {
+                deploying = true;
+                self.updates.send(DeploymentUpdate {
+                    user_id: user_id.to_string(),
+                    challenge_id: challenge_id.to_string(),
+                    details: DeploymentUpdateDetails::StateChange { state: ChallengeInstanceState::Deploying, details: None, stop_time: None }
+                });
+            }
+
+            self.updates.send(DeploymentUpdate {
+                user_id: user_id.to_string(),
+                challenge_id: challenge_id.to_string(),
+                details: DeploymentUpdateDetails::Progress { progress }
+            });
+        }
     }
 
     pub async fn prepare(&self) -> anyhow::Result<()> {
         let challenge_instances = self.database.get_challenge_instances().await?;
 
-        for instance in challenge_instances.iter().filter(|instance| instance.state.is_queued()) {
+        for instance in challenge_instances.iter().filter(|instance| !instance.state.is_starting()) {
+            if let Some(challenge) = self.challenges.get(&instance.challenge_id) {
+                self.services.restore(&challenge.depends_on).await?;
+            }
+        }
+
+        /* starts interrupted by the restart are resumed, the deployer being told that it may have run before */
+        for instance in challenge_instances.iter().filter(|instance| instance.state.is_starting()) {
+            self.database.transition_challenge_instance_state(&instance.user_id, &instance.challenge_id, ChallengeInstanceState::Deploying, ChallengeInstanceState::QueuedStart).await?;
+            let start_request = DeploymentRequest {
+                user_id: instance.user_id.clone(),
+                challenge_id: instance.challenge_id.clone(),
+                command: DeploymentRequestCommand::Start { retry: true },
+                queued_at: time::Instant::now()
+            };
+            self.enqueue(start_request);
+        }
+
+        for instance in challenge_instances.iter().filter(|instance| instance.state.is_queued() && !instance.state.is_starting()) {
+            self.database.set_challenge_instance_end_reason(&instance.user_id, &instance.challenge_id, &EndReason::Interrupted).await?;
             let cleanup_request = DeploymentRequest {
                 user_id: instance.user_id.clone(),
                 challenge_id: instance.challenge_id.clone(),
                 command: DeploymentRequestCommand::Cleanup,
+                queued_at: time::Instant::now()
             };
-            self.request_tx.send(cleanup_request).await?;
+            self.enqueue(cleanup_request);
         }
 
-        let mut ttl_expiries = self.ttl_expiries.lock().await;
-        for instance in challenge_instances.into_iter().filter(|instance| instance.state == ChallengeInstanceState::Running) {
-            ttl_expiries.push(Reverse(ChallengeInstanceOrdered {
-                user_id: instance.user_id,
-                challenge_id: instance.challenge_id,
-                stop_time: instance.stop_time.unwrap()
-            }));
+        for instance in challenge_instances.into_iter().filter(|instance| matches!(instance.state, ChallengeInstanceState::Running | ChallengeInstanceState::Expiring) && instance.paused_at.is_none()) {
+            self.push_ttl(instance.user_id, instance.challenge_id, instance.stop_time.unwrap()).await;
+        }
+
+        if self.database.has_maintenance_pauses().await? {
+            tracing::warn!("instances are still paused for maintenance, their TTLs will resume once it ends");
+            self.maintenance.store(true, atomic::Ordering::Relaxed);
+        }
+        if self.database.is_event_ended().await? {
+            self.event_ended.store(true, atomic::Ordering::Relaxed);
         }
 
         Ok(())
     }
 
-    pub async fn push_ttl(&self, user_id: String, challenge_id: String, stop_time: TimeSinceEpoch) {
-        self.pop_ttl(&user_id, &challenge_id).await;
+    /// Freezes the TTL of a running instance, returns false if it wasn't running or was already paused.
+    pub async fn pause_instance(&self, user_id: &str, challenge_id: &str) -> anyhow::Result<bool> {
+        /* holding the queue keeps the instance from expiring between the pause and its removal from the queue */
+        let mut ttl_expiries = self.ttl_expiries.lock().await;
+        if !self.database.pause_challenge_instance(user_id, challenge_id, &TimeSinceEpoch::now(), false).await? {
+            return Ok(false);
+        }
+        ttl_expiries.remove(user_id, challenge_id);
+        self.expiry_warnings.lock().await.remove(user_id, challenge_id);
+        drop(ttl_expiries);
+
+        self.notify_paused(user_id, challenge_id, None, false);
+        Ok(true)
+    }
+
+    /// Restarts the TTL of a paused instance, shifting its stop time by the length of the pause. Returns the new
+    /// stop time, or None if the instance wasn't paused.
+    pub async fn resume_instance(&self, user_id: &str, challenge_id: &str) -> anyhow::Result<Option<TimeSinceEpoch>> {
+        let Some(stop_time) = self.database.resume_challenge_instance(user_id, challenge_id, &TimeSinceEpoch::now()).await? else { return Ok(None) };
+        self.push_ttl(user_id.to_string(), challenge_id.to_string(), stop_time.clone()).await;
+
+        self.notify_paused(user_id, challenge_id, Some(stop_time.clone()), false);
+        Ok(Some(stop_time))
+    }
+
+    /// Freezes the TTL of every running instance until maintenance ends. Returns the number of instances paused,
+    /// or None if maintenance was already under way.
+    pub async fn begin_maintenance(&self) -> anyhow::Result<Option<usize>> {
+        if self.maintenance.swap(true, atomic::Ordering::Relaxed) { return Ok(None) };
 
         let mut ttl_expiries = self.ttl_expiries.lock().await;
-        ttl_expiries.push(Reverse(ChallengeInstanceOrdered {
-            user_id,
-            challenge_id,
-            stop_time
-        }));
+        let mut expiry_warnings = self.expiry_warnings.lock().await;
+        let paused = self.database.pause_all_challenge_instances(&TimeSinceEpoch::now()).await?;
+        for (user_id, challenge_id) in &paused {
+            ttl_expiries.remove(user_id, challenge_id);
+            expiry_warnings.remove(user_id, challenge_id);
+        }
+        drop((ttl_expiries, expiry_warnings));
+
+        for (user_id, challenge_id) in &paused {
+            self.notify_paused(user_id, challenge_id, None, true);
+        }
+        Ok(Some(paused.len()))
     }
 
-    pub async fn pop_ttl(&self, user_id: &str, challenge_id: &str) {
-        let mut heap = self.ttl_expiries.lock().await;
-        let mut buffer = Vec::with_capacity(heap.len());
+    /// Restarts the TTL of the instances paused for maintenance. Returns the number of instances resumed, or None
+    /// if there was no maintenance under way.
+    pub async fn end_maintenance(&self) -> anyhow::Result<Option<usize>> {
+        if !self.maintenance.swap(false, atomic::Ordering::Relaxed) { return Ok(None) };
 
-        while let Some(val) = heap.pop() {
-            if val.0.user_id == user_id && val.0.challenge_id == challenge_id { continue; }
-            buffer.push(val);
+        let resumed = self.database.resume_maintenance_paused_instances(&TimeSinceEpoch::now()).await?;
+        for (user_id, challenge_id, stop_time) in &resumed {
+            self.push_ttl(user_id.clone(), challenge_id.clone(), stop_time.clone()).await;
+            self.notify_paused(user_id, challenge_id, Some(stop_time.clone()), true);
         }
+        Ok(Some(resumed.len()))
+    }
 
-        for val in buffer.into_iter() {
-            heap.push(val);
+    /// Tells a user that the TTL of their instance was paused, or resumed if it comes with its new stop time.
+    fn notify_paused(&self, user_id: &str, challenge_id: &str, stop_time: Option<TimeSinceEpoch>, maintenance: bool) {
+        let Some(challenge) = self.challenges.get(challenge_id) else { return };
+        let paused = stop_time.is_none();
+
+        self.updates.send(DeploymentUpdate {
+            user_id: user_id.to_string(),
+            challenge_id: challenge_id.to_string(),
+            details: DeploymentUpdateDetails::Paused { paused, stop_time }
+        });
+        self.updates.send(DeploymentUpdate {
+            user_id: user_id.to_string(),
+            challenge_id: challenge_id.to_string(),
+            details: DeploymentUpdateDetails::Message {
+                contents: self.messages.render(if paused { "paused" } else { "resumed" }, context! { challenge => challenge.name, maintenance }),
+                severity: MessageSeverity::Info
+            }
+        });
+    }
+
+    pub async fn push_ttl(&self, user_id: String, challenge_id: String, stop_time: TimeSinceEpoch) {
+        let warning_time = stop_time.0.checked_sub(Duration::from_secs(self.expiry_warning as u64)).map(TimeSinceEpoch);
+        match warning_time {
+            Some(warning_time) if warning_time > TimeSinceEpoch::now() => self.expiry_warnings.lock().await.push(user_id.clone(), challenge_id.clone(), warning_time),
+            _ => self.expiry_warnings.lock().await.remove(&user_id, &challenge_id)
         }
+
+        self.ttl_expiries.lock().await.push(user_id, challenge_id, stop_time);
+    }
+
+    pub async fn pop_ttl(&self, user_id: &str, challenge_id: &str) {
+        self.expiry_warnings.lock().await.remove(user_id, challenge_id);
+        self.ttl_expiries.lock().await.remove(user_id, challenge_id);
     }
 }
\ No newline at end of file
@@ -1,9 +1,26 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
 use const_format::concatcp;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
 use serde::Deserialize;
+use tower_sessions::cookie::time::format_description::well_known::Rfc3339;
+use tower_sessions::cookie::time::OffsetDateTime;
+
+use crate::config::EntryRulesConfig;
+use crate::deployment_worker::sha256_hex;
 
-const HOST: &'static str = "https://discord.com/api/v10";
+const HOST: &str = "https://discord.com/api/v10";
 
-pub const SCOPES: [&'static str; 2] = ["identify", "guilds"];
+pub const SCOPES: [&str; 2] = ["identify", "guilds"];
+
+const MAX_ATTEMPTS: u32 = 4;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Rate limits lasting longer than this fail the request, rather than keeping the user waiting on the login.
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(10);
+const MEMBERSHIP_CACHE_TTL: Duration = Duration::from_secs(60);
 
 pub struct Discord {
     access_token: String,
@@ -18,11 +35,159 @@ pub struct User {
     pub avatar: Option<String>
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Guild {
     pub id: String
 }
 
+#[derive(Deserialize, Debug)]
+struct Channel {
+    id: String
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GuildMember {
+    pub roles: Vec<String>,
+    pub joined_at: String
+}
+
+impl GuildMember {
+    /// Why the member doesn't pass the entry rules, shown as is on the login page, if they don't.
+    pub fn check_entry_rules(&self, rules: &EntryRulesConfig) -> Result<(), &'static str> {
+        if let Some(min_membership) = rules.min_membership {
+            let joined_at = OffsetDateTime::parse(&self.joined_at, &Rfc3339).map_err(|_| "Votre date d'arrivée sur le serveur Discord n'a pas pu être vérifiée.")?;
+            let member_for = Duration::try_from(OffsetDateTime::now_utc() - joined_at).unwrap_or_default();
+            if member_for < Duration::from_secs(min_membership as u64) {
+                return Err("Vous êtes arrivé trop récemment sur le serveur Discord pour utiliser cette plateforme, réessayez plus tard.");
+            }
+        }
+
+        if !rules.required_roles.is_empty() && !self.roles.iter().any(|role| rules.required_roles.contains(role)) {
+            return Err("Il vous manque un rôle sur le serveur Discord pour utiliser cette plateforme.");
+        }
+
+        Ok(())
+    }
+}
+
+struct Cached<T> {
+    value: T,
+    fetched_at: Instant
+}
+
+/// Cached values by key, dropped once older than `MEMBERSHIP_CACHE_TTL`.
+type CacheEntries<K, T> = Mutex<HashMap<K, Cached<T>>>;
+
+/// Recent guild membership lookups, so that users retrying their login during a rush don't hit the API again.
+#[derive(Default)]
+pub struct MembershipCache {
+    /// The guilds of users, by digest of their access token.
+    guilds: CacheEntries<String, Vec<Guild>>,
+    /// The members of guilds, by guild and user id.
+    members: CacheEntries<(String, String), Option<GuildMember>>
+}
+
+impl MembershipCache {
+    fn get<K: Eq + std::hash::Hash, T: Clone>(entries: &CacheEntries<K, T>, key: &K) -> Option<T> {
+        let mut entries = entries.lock().unwrap();
+        entries.retain(|_, cached| cached.fetched_at.elapsed() < MEMBERSHIP_CACHE_TTL);
+        entries.get(key).map(|cached| cached.value.clone())
+    }
+
+    fn put<K: Eq + std::hash::Hash, T>(entries: &CacheEntries<K, T>, key: K, value: T) {
+        entries.lock().unwrap().insert(key, Cached { value, fetched_at: Instant::now() });
+    }
+}
+
+/// How long Discord asks to wait before retrying a rate limited request.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    ["X-RateLimit-Reset-After", "Retry-After"].iter()
+        .filter_map(|name| headers.get(*name)?.to_str().ok()?.parse::<f64>().ok())
+        .find_map(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+/// Sends a request, retrying it with backoff when Discord rate limits it or fails on its end.
+async fn send(request: RequestBuilder) -> anyhow::Result<Response> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let result = request.try_clone().expect("Discord requests have no streamed body").send().await;
+        let wait = match &result {
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => retry_after(response.headers()).unwrap_or(backoff),
+            Ok(response) if response.status().is_server_error() => backoff,
+            Err(err) if err.is_timeout() || err.is_connect() => backoff,
+            _ => return Ok(result?)
+        };
+        if attempt == MAX_ATTEMPTS || wait > MAX_RETRY_WAIT {
+            return Ok(result?);
+        }
+
+        tracing::warn!("Discord API request failed (attempt {}/{}), retrying in {:?}", attempt, MAX_ATTEMPTS, wait);
+        tokio::time::sleep(wait).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// Calls the API as a bot, for what users don't grant through OAuth.
+#[derive(Clone)]
+pub struct DiscordBot {
+    token: String,
+    client: reqwest::Client
+}
+
+impl DiscordBot {
+    pub fn new(token: String) -> Self {
+        DiscordBot {
+            token,
+            client: reqwest::Client::new()
+        }
+    }
+
+    /// Looks up a member of a server the bot is in, None if the user isn't a member.
+    pub async fn guild_member(&self, cache: &MembershipCache, guild_id: &str, user_id: &str) -> anyhow::Result<Option<GuildMember>> {
+        let key = (guild_id.to_string(), user_id.to_string());
+        if let Some(member) = MembershipCache::get(&cache.members, &key) {
+            return Ok(member);
+        }
+
+        let response = send(self.client.get(format!("{}/guilds/{}/members/{}", HOST, guild_id, user_id))
+            .header("Authorization", format!("Bot {}", self.token))).await?;
+        let member = match response.status() {
+            StatusCode::NOT_FOUND => None,
+            _ => Some(response.error_for_status()?.json::<GuildMember>().await?)
+        };
+
+        MembershipCache::put(&cache.members, key, member.clone());
+        Ok(member)
+    }
+
+    /// Sends a direct message to a user, which fails if they don't share a server with the bot or refuse DMs.
+    pub async fn send_direct_message(&self, user_id: &str, content: &str) -> anyhow::Result<()> {
+        let channel: Channel = send(self.client.post(concatcp!(HOST, "/users/@me/channels"))
+            .header("Authorization", format!("Bot {}", self.token))
+            .json(&serde_json::json!({ "recipient_id": user_id }))).await?
+            .error_for_status()?
+            .json().await?;
+
+        send(self.client.post(format!("{}/channels/{}/messages", HOST, channel.id))
+            .header("Authorization", format!("Bot {}", self.token))
+            .json(&serde_json::json!({ "content": content }))).await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Looks up any user by id, None if they don't exist anymore.
+    pub async fn user(&self, user_id: &str) -> anyhow::Result<Option<User>> {
+        let response = send(self.client.get(format!("{}/users/{}", HOST, user_id))
+            .header("Authorization", format!("Bot {}", self.token))).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            _ => Ok(Some(response.error_for_status()?.json().await?))
+        }
+    }
+}
+
 impl Discord {
     pub fn new(access_token: String) -> Self {
         Discord {
@@ -32,23 +197,24 @@ impl Discord {
     }
 
     pub async fn current_user(&self) -> anyhow::Result<User> {
-        Ok(self.client.get(concatcp!(HOST, "/users/@me"))
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .send().await?
+        Ok(send(self.client.get(concatcp!(HOST, "/users/@me"))
+            .header("Authorization", format!("Bearer {}", self.access_token))).await?
+            .error_for_status()?
             .json().await?)
     }
 
-    pub async fn current_guilds(&self) -> anyhow::Result<Vec<Guild>> {
-        Ok(self.client.get(concatcp!(HOST, "/users/@me/guilds"))
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .send().await?
-            .json().await?)
-    }
-
-    pub fn avatar_url(id: &str, avatar: &Option<String>) -> String {
-        match avatar {
-            None => String::from("https://discordapp.com/assets/a0180771ce23344c2a95.png"),
-            Some(avatar_hash) => format!("https://cdn.discordapp.com/avatars/{}/{}.png", id, avatar_hash)
+    pub async fn current_guilds(&self, cache: &MembershipCache) -> anyhow::Result<Vec<Guild>> {
+        let key = sha256_hex(self.access_token.as_bytes());
+        if let Some(guilds) = MembershipCache::get(&cache.guilds, &key) {
+            return Ok(guilds);
         }
+
+        let guilds: Vec<Guild> = send(self.client.get(concatcp!(HOST, "/users/@me/guilds"))
+            .header("Authorization", format!("Bearer {}", self.access_token))).await?
+            .error_for_status()?
+            .json().await?;
+
+        MembershipCache::put(&cache.guilds, key, guilds.clone());
+        Ok(guilds)
     }
 }
\ No newline at end of file
@@ -0,0 +1,199 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::de::{Error, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const UNITS: [(char, u32); 4] = [('d', 24 * 60 * 60), ('h', 60 * 60), ('m', 60), ('s', 1)];
+
+/// A positive whole number of seconds, written in the configuration as a sum of amounts of days, hours, minutes and
+/// seconds from largest to smallest unit, e.g. `90s`, `1h30m` or `2d12h`. Bare integers, quoted or not, are read as
+/// seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HumanDuration(pub u32);
+
+impl HumanDuration {
+    pub fn secs(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<HumanDuration> for Duration {
+    fn from(duration: HumanDuration) -> Self {
+        Duration::from_secs(duration.0.into())
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_secs(s)? {
+            0 => Err(format!("duration \"{}\" must be positive", s)),
+            total => Ok(HumanDuration(total))
+        }
+    }
+}
+
+/// Reads a duration into its number of seconds, zero included.
+fn parse_secs(s: &str) -> Result<u32, String> {
+    let malformed = || format!("malformed duration \"{}\", expected e.g. \"90s\", \"15m\" or \"1h30m\"", s);
+    let too_long = || format!("duration \"{}\" is too long", s);
+
+    if s.is_empty() {
+        return Err(malformed());
+    }
+    if s.bytes().all(|c| c.is_ascii_digit()) {
+        return s.parse().map_err(|_| too_long());
+    }
+
+    let mut total: u32 = 0;
+    let mut units = UNITS.iter();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(malformed)?;
+        let amount: u32 = rest[..digits].parse().map_err(|_| malformed())?;
+        let unit = rest[digits..].chars().next().ok_or_else(malformed)?;
+        let (_, multiplier) = units.find(|(symbol, _)| *symbol == unit).ok_or_else(malformed)?;
+        total = amount.checked_mul(*multiplier)
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or_else(too_long)?;
+        rest = &rest[digits + unit.len_utf8()..];
+    }
+    Ok(total)
+}
+
+impl Display for HumanDuration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0 == 0 {
+            return f.write_str("0s");
+        }
+
+        let mut rest = self.0;
+        for (symbol, multiplier) in UNITS {
+            if rest >= multiplier {
+                write!(f, "{}{}", rest / multiplier, symbol)?;
+                rest %= multiplier;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for HumanDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Reads a duration into its number of seconds, rejecting zero unless `allow_zero` is set.
+struct SecsVisitor {
+    allow_zero: bool
+}
+
+impl Visitor<'_> for SecsVisitor {
+    type Value = u32;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("a duration such as \"1h30m\", or a number of seconds")
+    }
+
+    fn visit_u64<E: Error>(self, seconds: u64) -> Result<Self::Value, E> {
+        match u32::try_from(seconds) {
+            Ok(0) if !self.allow_zero => Err(E::custom("durations must be positive")),
+            Ok(seconds) => Ok(seconds),
+            Err(_) => Err(E::custom(format!("duration of {} seconds is too long", seconds)))
+        }
+    }
+
+    fn visit_i64<E: Error>(self, seconds: i64) -> Result<Self::Value, E> {
+        u64::try_from(seconds).map_err(|_| E::custom("durations must be positive")).and_then(|seconds| self.visit_u64(seconds))
+    }
+
+    fn visit_str<E: Error>(self, s: &str) -> Result<Self::Value, E> {
+        match parse_secs(s) {
+            Ok(0) if !self.allow_zero => Err(E::custom(format!("duration \"{}\" must be positive", s))),
+            parsed => parsed.map_err(E::custom)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SecsVisitor { allow_zero: false }).map(HumanDuration)
+    }
+}
+
+/// Reads a duration into its number of seconds, for `#[serde(deserialize_with)]`.
+pub fn deserialize_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    HumanDuration::deserialize(deserializer).map(HumanDuration::secs)
+}
+
+/// Reads a duration into its number of seconds like [`deserialize_secs`], for settings that zero disables.
+pub fn deserialize_secs_or_zero<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    deserializer.deserialize_any(SecsVisitor { allow_zero: true })
+}
+
+pub fn deserialize_optional_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    Option::<HumanDuration>::deserialize(deserializer).map(|duration| duration.map(HumanDuration::secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::value::{Error as ValueError, StrDeserializer, U64Deserializer};
+    use serde::de::IntoDeserializer;
+
+    use super::*;
+
+    fn from_str(s: &str) -> StrDeserializer<'_, ValueError> {
+        s.into_deserializer()
+    }
+
+    fn from_u64(seconds: u64) -> U64Deserializer<ValueError> {
+        seconds.into_deserializer()
+    }
+
+    #[test]
+    fn parses_compound_durations() {
+        assert_eq!("90s".parse(), Ok(HumanDuration(90)));
+        assert_eq!("1h30m".parse(), Ok(HumanDuration(90 * 60)));
+        assert_eq!("2d12h".parse(), Ok(HumanDuration(60 * 60 * 60)));
+        assert_eq!("1d2h3m4s".parse(), Ok(HumanDuration(24 * 60 * 60 + 2 * 60 * 60 + 3 * 60 + 4)));
+    }
+
+    #[test]
+    fn parses_bare_numbers_as_seconds() {
+        assert_eq!("90".parse(), Ok(HumanDuration(90)));
+        assert_eq!(HumanDuration::deserialize(from_str("90")), Ok(HumanDuration(90)));
+        assert_eq!(HumanDuration::deserialize(from_u64(90)), Ok(HumanDuration(90)));
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for malformed in ["", "h", "1h30", "30m1h", "1h1h", "1w", "1.5h", "-5s", " 5s"] {
+            assert!(malformed.parse::<HumanDuration>().is_err(), "accepted \"{}\"", malformed);
+        }
+        assert!("5000000000".parse::<HumanDuration>().is_err());
+        assert!("50000d".parse::<HumanDuration>().is_err());
+    }
+
+    #[test]
+    fn zero_is_only_accepted_where_it_disables() {
+        assert!("0".parse::<HumanDuration>().is_err());
+        assert!("0s".parse::<HumanDuration>().is_err());
+        assert!(deserialize_secs(from_u64(0)).is_err());
+
+        assert_eq!(deserialize_secs_or_zero(from_str("0")), Ok(0));
+        assert_eq!(deserialize_secs_or_zero(from_str("0m")), Ok(0));
+        assert_eq!(deserialize_secs_or_zero(from_u64(0)), Ok(0));
+        assert_eq!(deserialize_secs_or_zero(from_str("10m")), Ok(600));
+    }
+
+    #[test]
+    fn displays_from_largest_unit() {
+        assert_eq!(HumanDuration(0).to_string(), "0s");
+        assert_eq!(HumanDuration(90).to_string(), "1m30s");
+        assert_eq!(HumanDuration(2 * 24 * 60 * 60 + 12 * 60 * 60).to_string(), "2d12h");
+    }
+}
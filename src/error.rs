@@ -0,0 +1,152 @@
+use askama::Template;
+use axum::extract::Request;
+use axum::http::header::ACCEPT;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+use crate::templating::HtmlTemplate;
+
+/// Errors surfaced by the HTTP handlers, each rendered as its own page, or as JSON where [`negotiate`] applies.
+pub enum RouterError {
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    RateLimited,
+    #[cfg(feature = "geoip")]
+    GeoBlocked,
+    Upstream(anyhow::Error),
+    Database(anyhow::Error),
+    Internal(anyhow::Error)
+}
+
+#[derive(Template)]
+#[template(path = "unauthorized.html")]
+struct UnauthorizedTemplate;
+
+#[derive(Template)]
+#[template(path = "forbidden.html")]
+struct ForbiddenTemplate;
+
+#[derive(Template)]
+#[template(path = "not_found.html")]
+struct NotFoundTemplate;
+
+#[derive(Template)]
+#[template(path = "rate_limited.html")]
+struct RateLimitedTemplate;
+
+#[cfg(feature = "geoip")]
+#[derive(Template)]
+#[template(path = "geo_blocked.html")]
+struct GeoBlockedTemplate;
+
+#[derive(Template)]
+#[template(path = "upstream_error.html")]
+struct UpstreamErrorTemplate {
+    incident_id: String
+}
+
+#[derive(Template)]
+#[template(path = "database_error.html")]
+struct DatabaseErrorTemplate {
+    incident_id: String
+}
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorTemplate {
+    incident_id: String
+}
+
+/// Logs the error under a short random identifier the user can quote to the admins.
+fn report_incident(err: &anyhow::Error) -> String {
+    let incident_id = format!("{:08x}", rand::random::<u32>());
+    tracing::error!("[incident {}] {:?}", incident_id, err);
+    incident_id
+}
+
+/// The error as told to JSON clients, attached to the error pages so that [`negotiate`] can swap it in.
+#[derive(Serialize, Clone)]
+struct ErrorBody {
+    error: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    incident_id: Option<String>
+}
+
+impl ErrorBody {
+    fn new(error: &'static str) -> Self {
+        ErrorBody { error, incident_id: None }
+    }
+
+    fn incident(error: &'static str, incident_id: &str) -> Self {
+        ErrorBody { error, incident_id: Some(incident_id.to_string()) }
+    }
+}
+
+impl IntoResponse for RouterError {
+    fn into_response(self) -> Response {
+        let (mut response, body) = match self {
+            RouterError::Unauthorized => ((StatusCode::UNAUTHORIZED, HtmlTemplate(UnauthorizedTemplate)).into_response(), ErrorBody::new("unauthorized")),
+            RouterError::Forbidden => ((StatusCode::FORBIDDEN, HtmlTemplate(ForbiddenTemplate)).into_response(), ErrorBody::new("forbidden")),
+            RouterError::NotFound => ((StatusCode::NOT_FOUND, HtmlTemplate(NotFoundTemplate)).into_response(), ErrorBody::new("not_found")),
+            RouterError::RateLimited => ((StatusCode::TOO_MANY_REQUESTS, HtmlTemplate(RateLimitedTemplate)).into_response(), ErrorBody::new("rate_limited")),
+            #[cfg(feature = "geoip")]
+            RouterError::GeoBlocked => ((StatusCode::FORBIDDEN, HtmlTemplate(GeoBlockedTemplate)).into_response(), ErrorBody::new("geo_blocked")),
+            RouterError::Upstream(err) => {
+                let incident_id = report_incident(&err);
+                let body = ErrorBody::incident("upstream", &incident_id);
+                ((StatusCode::BAD_GATEWAY, HtmlTemplate(UpstreamErrorTemplate { incident_id })).into_response(), body)
+            }
+            RouterError::Database(err) => {
+                let incident_id = report_incident(&err);
+                let body = ErrorBody::incident("database", &incident_id);
+                ((StatusCode::SERVICE_UNAVAILABLE, HtmlTemplate(DatabaseErrorTemplate { incident_id })).into_response(), body)
+            }
+            RouterError::Internal(err) => {
+                let incident_id = report_incident(&err);
+                let body = ErrorBody::incident("internal", &incident_id);
+                ((StatusCode::INTERNAL_SERVER_ERROR, HtmlTemplate(ErrorTemplate { incident_id })).into_response(), body)
+            }
+        };
+        response.extensions_mut().insert(body);
+        response
+    }
+}
+
+/// Answers errors with their JSON body rather than their page on the `/api/*` routes, and to clients accepting JSON.
+pub async fn negotiate(request: Request, next: Next) -> Response {
+    let wants_json = request.uri().path().starts_with("/api/") || request.headers().get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+
+    let response = next.run(request).await;
+    match response.extensions().get::<ErrorBody>() {
+        Some(body) if wants_json => (response.status(), Json(body.clone())).into_response(),
+        _ => response
+    }
+}
+
+impl<E> From<E> for RouterError
+where
+    E: Into<anyhow::Error>
+{
+    fn from(err: E) -> Self {
+        let err = err.into();
+
+        if let Some(upstream_err) = err.downcast_ref::<reqwest::Error>() {
+            if upstream_err.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) {
+                return RouterError::RateLimited;
+            }
+            return RouterError::Upstream(err);
+        }
+
+        if err.is::<sqlx::Error>() || err.is::<tower_sessions::session::Error>() {
+            return RouterError::Database(err);
+        }
+
+        RouterError::Internal(err)
+    }
+}
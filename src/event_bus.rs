@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::EventBusConfig;
+use crate::InstancerState;
+
+/// Publishes every deployment update to NATS under `<subject>.<challenge_id>`.
+pub async fn publish_updates(state: Arc<InstancerState>, config: EventBusConfig) -> anyhow::Result<()> {
+    let client = async_nats::connect(&config.url).await?;
+    let mut update_rx = state.deployer.updates.subscribe_all();
+
+    tracing::info!("publishing deployment updates to {} under subject {}", config.url, config.subject);
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => break,
+            update = update_rx.recv() => match update {
+                Ok(update) => {
+                    let subject = format!("{}.{}", config.subject, update.challenge_id);
+                    if let Err(err) = client.publish(subject, serde_json::to_vec(&update)?.into()).await {
+                        tracing::warn!("couldn't publish deployment update to event bus: {:?}", err);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => tracing::warn!("event bus publisher lagged behind, {} updates were dropped", skipped),
+                Err(RecvError::Closed) => break
+            }
+        }
+    }
+
+    client.flush().await?;
+    Ok(())
+}
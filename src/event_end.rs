@@ -0,0 +1,109 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use minijinja::context;
+use tokio::time;
+
+use crate::deployment_worker::{DeploymentUpdate, DeploymentUpdateDetails, MessageSeverity};
+use crate::models::{ChallengeInstanceState, EndReason, TimeSinceEpoch};
+use crate::InstancerState;
+
+/// How long to wait between passes over the instances left, and before retrying a failed end.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Ends the event at the configured time, if any.
+pub async fn end_event_on_schedule(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    /* an event that ended before a restart still has its remaining instances to stop */
+    if state.deployer.event_ended.load(Ordering::Relaxed) {
+        stop_instances(&state).await;
+        return Ok(());
+    }
+    let Some(event_end) = state.config.settings.event_end.clone() else { return Ok(()) };
+
+    let until_end = event_end.0.duration_since(TimeSinceEpoch::now().0).unwrap_or_default();
+    tokio::select! {
+        _ = state.shutdown_token.cancelled() => return Ok(()),
+        _ = time::sleep(until_end) => {}
+    }
+
+    while let Err(err) = end_event(Arc::clone(&state)).await {
+        tracing::error!("couldn't end event, retrying: {:?}", err);
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => return Ok(()),
+            _ = time::sleep(RETRY_INTERVAL) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Disables new starts, persisting that the event ended so that it survives restarts, tells every connected user and
+/// stops every instance. Fails without ending anything if the end can't be persisted, so that it can be retried.
+pub async fn end_event(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    if state.deployer.event_ended.load(Ordering::Relaxed) { return Ok(()) }
+    state.database.set_event_ended(&TimeSinceEpoch::now()).await?;
+    if state.deployer.event_ended.swap(true, Ordering::Relaxed) { return Ok(()) }
+
+    tracing::info!("event has ended, stopping all instances");
+    state.deployer.updates.broadcast("", DeploymentUpdateDetails::Message {
+        contents: state.deployer.messages.render("event_ended", context! {}),
+        severity: MessageSeverity::Warning
+    });
+
+    stop_instances(&state).await;
+    Ok(())
+}
+
+/// Stops running instances and cancels starts in progress until none are left, logging progress along the way. Starts
+/// that were still queued are dropped by the workers, and those completing before being cancelled are stopped on the
+/// next pass, as are the instances a failed pass left behind.
+async fn stop_instances(state: &InstancerState) {
+    let mut total = None;
+    loop {
+        match stop_pass(state).await {
+            Ok(0) => {
+                tracing::info!("event end: all instances are stopped");
+                return;
+            }
+            Ok(remaining) => {
+                let total = *total.get_or_insert(remaining);
+                tracing::info!("event end: {}/{} instances stopped", total.saturating_sub(remaining), total);
+            }
+            Err(err) => tracing::error!("event end: couldn't stop the remaining instances, retrying: {:?}", err)
+        }
+
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => return,
+            _ = time::sleep(RETRY_INTERVAL) => {}
+        }
+    }
+}
+
+/// Queues the stop of every running instance and cancels starts in progress, returning how many instances are left.
+async fn stop_pass(state: &InstancerState) -> anyhow::Result<usize> {
+    let instances = state.database.get_challenge_instances().await?;
+    for instance in &instances {
+        let Some(challenge) = state.deployer.challenges.get(&instance.challenge_id) else { continue };
+        match instance.state {
+            ChallengeInstanceState::Running | ChallengeInstanceState::Expiring => {
+                if !state.deployer.queue_stop(&instance.user_id, &instance.challenge_id, EndReason::EventEnded).await? { continue }
+
+                state.deployer.updates.send(DeploymentUpdate {
+                    user_id: instance.user_id.clone(),
+                    challenge_id: instance.challenge_id.clone(),
+                    details: DeploymentUpdateDetails::Message {
+                        contents: state.deployer.messages.render("event_over", context! { challenge => challenge.name }),
+                        severity: MessageSeverity::Info
+                    }
+                });
+            }
+            _ if instance.state.is_starting() => {
+                let _ = state.deployer.live.find(&instance.challenge_id, &instance.user_id)
+                    .filter(|deployment| matches!(deployment.command, "start" | "restart"))
+                    .and_then(|deployment| state.deployer.live.cancel(deployment.id));
+            }
+            _ => {}
+        }
+    }
+    Ok(instances.len())
+}
@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time;
+
+use crate::config::FaultInjectionConfig;
+
+/// Faults are only injected once the instancer has started, so that startup itself goes through.
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+pub fn arm() {
+    ARMED.store(true, Ordering::Relaxed);
+}
+
+/// Makes deployments and database queries fail or slow down at random, to exercise the failure paths of the
+/// instancer before an event does.
+#[derive(Debug, Clone)]
+pub struct FaultInjector {
+    config: FaultInjectionConfig
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultInjectionConfig) -> Self {
+        FaultInjector { config }
+    }
+
+    /// Delays a deployment, returning whether it should fail without running its deployers.
+    pub async fn deployment(&self, challenge_id: &str, action: &str) -> bool {
+        if !ARMED.load(Ordering::Relaxed) { return false; }
+        self.delay().await;
+
+        let failed = rand::thread_rng().gen_bool(self.config.deploy_failure_rate);
+        if failed {
+            tracing::warn!("[{}] injecting a failure into {}", challenge_id, action);
+        }
+        failed
+    }
+
+    /// Delays a query, failing it as if the database had been closed under it.
+    pub async fn query(&self) -> Result<(), sqlx::Error> {
+        if !ARMED.load(Ordering::Relaxed) { return Ok(()); }
+        self.delay().await;
+
+        match rand::thread_rng().gen_bool(self.config.database_error_rate) {
+            true => Err(sqlx::Error::PoolClosed),
+            false => Ok(())
+        }
+    }
+
+    async fn delay(&self) {
+        let delay = {
+            let mut rng = rand::thread_rng();
+            rng.gen_bool(self.config.latency_rate)
+                .then(|| Duration::from_secs(self.config.latency.into()).mul_f64(rng.gen()))
+        };
+
+        if let Some(delay) = delay {
+            time::sleep(delay).await;
+        }
+    }
+}
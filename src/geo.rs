@@ -0,0 +1,46 @@
+use std::net::IpAddr;
+
+use maxminddb::{geoip2, Reader};
+
+use crate::config::GeoConfig;
+
+/// Restricts access by the country or autonomous system of the client, looked up in MaxMind databases.
+pub struct GeoFilter {
+    countries: Option<Reader<Vec<u8>>>,
+    asns: Option<Reader<Vec<u8>>>,
+    config: GeoConfig
+}
+
+impl GeoFilter {
+    pub fn open(config: GeoConfig) -> anyhow::Result<Self> {
+        let countries = config.country_db.as_ref().map(Reader::open_readfile).transpose()?;
+        let asns = config.asn_db.as_ref().map(Reader::open_readfile).transpose()?;
+        Ok(GeoFilter { countries, asns, config })
+    }
+
+    /// Whether the address passes every configured list. Addresses missing from a database, like private ones,
+    /// fail its allow list unless `allow_unknown` is set, but never match its deny list.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        let country = self.countries.as_ref()
+            .and_then(|reader| reader.lookup::<geoip2::Country>(ip).ok())
+            .and_then(|record| record.country?.iso_code.map(str::to_string));
+        let asn = self.asns.as_ref()
+            .and_then(|reader| reader.lookup::<geoip2::Asn>(ip).ok())
+            .and_then(|record| record.autonomous_system_number);
+
+        let allowed = check(country.as_ref(), &self.config.allowed_countries, &self.config.denied_countries, self.config.allow_unknown)
+            && check(asn.as_ref(), &self.config.allowed_asns, &self.config.denied_asns, self.config.allow_unknown);
+
+        if !allowed {
+            tracing::info!("rejected client {} (country {:?}, asn {:?})", ip, country, asn);
+        }
+        allowed
+    }
+}
+
+fn check<T: PartialEq>(value: Option<&T>, allowed: &[T], denied: &[T], allow_unknown: bool) -> bool {
+    match value {
+        Some(value) => (allowed.is_empty() || allowed.contains(value)) && !denied.contains(value),
+        None => allowed.is_empty() || allow_unknown
+    }
+}
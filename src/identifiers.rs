@@ -0,0 +1,51 @@
+use std::ffi::OsStr;
+use std::fmt::{Display, Formatter};
+
+/// Longest identifier accepted. Discord snowflakes and rCTF UUIDs are well under it.
+pub const MAX_LENGTH: usize = 64;
+
+/// Whether an identifier is safe to hand to a deployer: 1 to 64 ASCII letters, digits, `-` or `_`,
+/// not starting with `-` so that it can't be mistaken for an option.
+pub fn is_valid(id: &str) -> bool {
+    (1..=MAX_LENGTH).contains(&id.len())
+        && !id.starts_with('-')
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Whether an identifier can be used as part of a DNS label or a container name: 1 to 32 lowercase ASCII letters,
+/// digits or `-`, neither starting nor ending with `-`.
+pub fn is_dns_label(id: &str) -> bool {
+    (1..=32).contains(&id.len())
+        && !id.starts_with('-')
+        && !id.ends_with('-')
+        && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+#[derive(Debug)]
+pub struct InvalidIdentifier(String);
+
+impl Display for InvalidIdentifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "malformed identifier {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidIdentifier {}
+
+/// An identifier that passed [`is_valid`], the only form in which identifiers reach a deployer's arguments.
+#[derive(Debug, Clone, Copy)]
+pub struct DeployerArg<'a>(&'a str);
+
+impl<'a> TryFrom<&'a str> for DeployerArg<'a> {
+    type Error = InvalidIdentifier;
+
+    fn try_from(id: &'a str) -> Result<Self, Self::Error> {
+        if is_valid(id) { Ok(DeployerArg(id)) } else { Err(InvalidIdentifier(id.to_string())) }
+    }
+}
+
+impl AsRef<OsStr> for DeployerArg<'_> {
+    fn as_ref(&self) -> &OsStr {
+        OsStr::new(self.0)
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time;
+
+use crate::deployment_worker::{DeploymentRequest, DeploymentRequestCommand};
+use crate::models::{ChallengeInstance, ChallengeInstanceState, TimeSinceEpoch};
+use crate::InstancerState;
+
+/// Times a stuck instance is enqueued again before the janitor leaves it to the admins.
+const MAX_REPAIRS: u32 = 3;
+
+/// What the janitor did since the instancer started, along with the instances it gave up on.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct JanitorReport {
+    pub sweeps: u64,
+    pub repaired: u64,
+    pub escalated: u64,
+    pub last_sweep: Option<TimeSinceEpoch>,
+    pub stuck: Vec<StuckInstance>
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StuckInstance {
+    pub user_id: String,
+    pub challenge_id: String,
+    pub state: ChallengeInstanceState
+}
+
+/// Looks for instances stuck queued or deploying, which happens when the worker handling them failed, and enqueues
+/// their request again. Instances that stay stuck after [`MAX_REPAIRS`] attempts are logged and listed in the report.
+pub async fn sweep_periodically(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    let interval = Duration::from_secs(state.config.settings.janitor_interval as u64);
+    let threshold = Duration::from_secs(state.config.settings.stale_instance_threshold as u64);
+    let mut repairs = HashMap::new();
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => break,
+            _ = time::sleep(interval) => {}
+        }
+
+        if let Err(err) = sweep(&state, threshold, &mut repairs).await {
+            tracing::warn!("couldn't sweep stale instances: {:?}", err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Repairs of the instances that were stuck, by (user_id, challenge_id), along with the state they were stuck in.
+type Repairs = HashMap<(String, String), (ChallengeInstanceState, u32)>;
+
+async fn sweep(state: &InstancerState, threshold: Duration, repairs: &mut Repairs) -> anyhow::Result<()> {
+    /* instances that left the state they were stuck in start over with a clean slate */
+    let instances = state.database.get_challenge_instances().await?;
+    repairs.retain(|(user_id, challenge_id), (stuck_state, _)| instances.iter()
+        .any(|instance| instance.user_id == *user_id && instance.challenge_id == *challenge_id && instance.state == *stuck_state));
+
+    let before = TimeSinceEpoch(TimeSinceEpoch::now().0 - threshold);
+    let (mut repaired, mut escalated, mut stuck) = (0, 0, Vec::new());
+    for instance in state.database.get_stale_challenge_instances(&before).await? {
+        /* a deployment that is still running will either finish or hit its timeout */
+        if state.deployer.live.find(&instance.challenge_id, &instance.user_id).is_some() { continue; }
+
+        let (_, attempts) = repairs.entry((instance.user_id.clone(), instance.challenge_id.clone())).or_insert((instance.state.clone(), 0));
+        if *attempts >= MAX_REPAIRS {
+            /* escalated once, then only listed */
+            if *attempts == MAX_REPAIRS {
+                tracing::error!("challenge {} for user {} is still {:?} after {} repairs, it needs to be looked into", instance.challenge_id, instance.user_id, instance.state, MAX_REPAIRS);
+                *attempts += 1;
+                escalated += 1;
+            }
+            stuck.push(StuckInstance { user_id: instance.user_id, challenge_id: instance.challenge_id, state: instance.state });
+            continue;
+        }
+
+        if repair(state, &instance).await? {
+            tracing::warn!("challenge {} for user {} was stuck {:?}, enqueued its request again", instance.challenge_id, instance.user_id, instance.state);
+            *attempts += 1;
+            repaired += 1;
+        }
+    }
+
+    let mut report = state.janitor.lock().unwrap();
+    report.sweeps += 1;
+    report.repaired += repaired;
+    report.escalated += escalated;
+    report.last_sweep = Some(TimeSinceEpoch::now());
+    report.stuck = stuck;
+    Ok(())
+}
+
+/// Enqueues the request an instance is waiting on, returns false if it left its state in the meantime.
+async fn repair(state: &InstancerState, instance: &ChallengeInstance) -> anyhow::Result<bool> {
+    let command = match instance.state {
+        /* the deployer may have been interrupted midway */
+        ChallengeInstanceState::QueuedStart | ChallengeInstanceState::Deploying => DeploymentRequestCommand::Start { retry: true },
+        ChallengeInstanceState::QueuedStop => DeploymentRequestCommand::Stop,
+        ChallengeInstanceState::QueuedRestart => DeploymentRequestCommand::Restart,
+        _ => return Ok(false)
+    };
+
+    if !state.database.touch_challenge_instance_transition(&instance.user_id, &instance.challenge_id, &instance.state).await? {
+        return Ok(false);
+    }
+
+    let request = DeploymentRequest {
+        user_id: instance.user_id.clone(),
+        challenge_id: instance.challenge_id.clone(),
+        command,
+        queued_at: time::Instant::now()
+    };
+    state.deployer.enqueue(request);
+    Ok(true)
+}
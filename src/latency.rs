@@ -0,0 +1,115 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// The most recent samples kept per challenge, so that percentiles follow the current load rather than the whole event.
+const WINDOW: usize = 200;
+
+#[derive(Default)]
+struct Samples(VecDeque<Duration>);
+
+impl Samples {
+    fn push(&mut self, sample: Duration) {
+        if self.0.len() == WINDOW {
+            self.0.pop_front();
+        }
+        self.0.push_back(sample);
+    }
+
+    fn summary(&self) -> Option<LatencySummary> {
+        if self.0.is_empty() { return None; }
+
+        let mut sorted: Vec<_> = self.0.iter().copied().collect();
+        sorted.sort();
+        Some(LatencySummary {
+            samples: sorted.len(),
+            p50_ms: percentile(&sorted, 0.5).as_millis() as u64,
+            p95_ms: percentile(&sorted, 0.95).as_millis() as u64
+        })
+    }
+}
+
+fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+    let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+    sorted[index]
+}
+
+#[derive(Default)]
+struct ChallengeSamples {
+    queue_wait: Samples,
+    deploy_duration: Samples
+}
+
+#[derive(Serialize, Debug)]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub p50_ms: u64,
+    pub p95_ms: u64
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChallengeLatency {
+    /// How long requests waited for a worker after being queued.
+    pub queue_wait: Option<LatencySummary>,
+    /// How long the deployers took to start an instance.
+    pub deploy_duration: Option<LatencySummary>
+}
+
+/// A summary exported to Prometheus, read from the latency of each challenge.
+struct Metric {
+    name: &'static str,
+    help: &'static str,
+    summary: fn(&ChallengeLatency) -> &Option<LatencySummary>
+}
+
+const METRICS: [Metric; 2] = [
+    Metric { name: "instancer_queue_wait_seconds", help: "Time deployment requests waited for a worker.", summary: |latency| &latency.queue_wait },
+    Metric { name: "instancer_deploy_duration_seconds", help: "Time taken to start an instance.", summary: |latency| &latency.deploy_duration }
+];
+
+/// Rolling percentiles of the time requests spend queued and deploying, per challenge.
+#[derive(Default)]
+pub struct LatencyTracker {
+    challenges: Mutex<HashMap<String, ChallengeSamples>>
+}
+
+impl LatencyTracker {
+    pub fn record_queue_wait(&self, challenge_id: &str, wait: Duration) {
+        self.challenges.lock().unwrap().entry(challenge_id.to_string()).or_default().queue_wait.push(wait);
+    }
+
+    pub fn record_deploy_duration(&self, challenge_id: &str, duration: Duration) {
+        self.challenges.lock().unwrap().entry(challenge_id.to_string()).or_default().deploy_duration.push(duration);
+    }
+
+    pub fn report(&self) -> BTreeMap<String, ChallengeLatency> {
+        self.challenges.lock().unwrap().iter()
+            .map(|(challenge_id, samples)| (challenge_id.clone(), ChallengeLatency {
+                queue_wait: samples.queue_wait.summary(),
+                deploy_duration: samples.deploy_duration.summary()
+            }))
+            .collect()
+    }
+
+    /// Renders the percentiles in the Prometheus text format, as summaries in seconds.
+    pub fn prometheus(&self) -> String {
+        let report = self.report();
+        let mut output = String::new();
+
+        for Metric { name, help, summary } in METRICS {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} summary", name);
+            for (challenge_id, latency) in &report {
+                let Some(summary) = summary(latency) else { continue };
+                let challenge_id = challenge_id.replace('\\', "\\\\").replace('"', "\\\"");
+                let _ = writeln!(output, "{}{{challenge=\"{}\",quantile=\"0.5\"}} {}", name, challenge_id, summary.p50_ms as f64 / 1000.0);
+                let _ = writeln!(output, "{}{{challenge=\"{}\",quantile=\"0.95\"}} {}", name, challenge_id, summary.p95_ms as f64 / 1000.0);
+            }
+        }
+
+        output
+    }
+}
@@ -0,0 +1,68 @@
+use ldap3::{dn_escape, ldap_escape, LdapConnAsync, Scope, SearchEntry};
+
+use crate::config::LdapConfig;
+use crate::models::UserRole;
+
+/// A directory user whose password was checked.
+pub struct LdapUser {
+    pub username: String,
+    pub display_name: String,
+    /// DNs of the groups the user is a member of.
+    pub groups: Vec<String>
+}
+
+/// Binds as the user to check their password, then reads their display name and groups. Returns None if the
+/// credentials are refused.
+pub async fn authenticate(config: &LdapConfig, username: &str, password: &str) -> anyhow::Result<Option<LdapUser>> {
+    /* an empty password makes an unauthenticated bind, which servers accept */
+    if username.is_empty() || password.is_empty() {
+        return Ok(None);
+    }
+
+    let (conn, mut ldap) = LdapConnAsync::new(&config.url).await?;
+    ldap3::drive!(conn);
+
+    /* the username is escaped as a DN value to bind, and as a filter value to search */
+    let bind_dn = config.bind_dn.replace("{username}", &dn_escape(username));
+    if ldap.simple_bind(&bind_dn, password).await?.success().is_err() {
+        let _ = ldap.unbind().await;
+        return Ok(None);
+    }
+
+    let filter = config.user_filter.replace("{username}", &ldap_escape(username));
+    let (entries, _) = ldap.search(&config.search_base, Scope::Subtree, &filter, vec![config.display_name_attribute.as_str(), "memberOf"])
+        .await?.success()?;
+    let _ = ldap.unbind().await;
+
+    let Some(entry) = entries.into_iter().next().map(SearchEntry::construct) else {
+        tracing::warn!("LDAP user {} could bind but matches no entry under {}", username, config.search_base);
+        return Ok(None);
+    };
+
+    let display_name = entry.attrs.get(&config.display_name_attribute)
+        .and_then(|values| values.first().cloned())
+        .unwrap_or_else(|| username.to_string());
+    let groups = entry.attrs.get("memberOf").cloned().unwrap_or_default();
+
+    Ok(Some(LdapUser { username: username.to_string(), display_name, groups }))
+}
+
+impl LdapUser {
+    /// Whether the user is in one of the allowed groups, if any are configured. Group DNs are compared
+    /// case-insensitively, as directories do.
+    pub fn is_allowed(&self, config: &LdapConfig) -> bool {
+        config.allowed_groups.is_empty() || config.allowed_groups.iter().any(|allowed| self.in_group(allowed))
+    }
+
+    /// The highest role mapped to the groups of the user, if any is.
+    pub fn role(&self, config: &LdapConfig) -> Option<UserRole> {
+        config.group_roles.iter()
+            .filter(|(group, _)| self.in_group(group))
+            .map(|(_, role)| role.clone())
+            .max()
+    }
+
+    fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|member_of| member_of.eq_ignore_ascii_case(group))
+    }
+}
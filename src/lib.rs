@@ -0,0 +1,58 @@
+use crate::state::InstancerState;
+
+pub mod abuse;
+pub mod auth;
+pub mod avatars;
+pub mod router;
+pub mod build_info;
+pub mod bulk_operations;
+pub mod challenge_registry;
+pub mod challenge_set;
+pub mod templating;
+pub mod client_ip;
+pub mod config;
+pub mod config_summary;
+pub mod csrf;
+pub mod state;
+pub mod discord;
+pub mod database;
+pub mod duration;
+pub mod error;
+pub mod event_end;
+pub mod identifiers;
+pub mod janitor;
+pub mod latency;
+pub mod listing;
+pub mod live_deployments;
+pub mod local_auth;
+pub mod message_templates;
+pub mod messages;
+pub mod models;
+pub mod notifications;
+pub mod deployment_worker;
+pub mod demo;
+pub mod deploy_logs;
+pub mod outbox;
+pub mod profiles;
+pub mod quotas;
+pub mod rctf;
+pub mod reconciliation;
+pub mod scheduler;
+pub mod selftest;
+pub mod session_policy;
+pub mod shared_services;
+pub mod stats;
+pub mod ttl_queue;
+pub mod update_hub;
+pub mod usage;
+pub mod webhooks;
+#[cfg(feature = "load-test")]
+pub mod load_test;
+#[cfg(feature = "fault-injection")]
+pub mod fault_injection;
+#[cfg(feature = "event-bus")]
+pub mod event_bus;
+#[cfg(feature = "geoip")]
+pub mod geo;
+#[cfg(feature = "ldap")]
+pub mod ldap;
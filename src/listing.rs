@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use serde::Serialize;
+
+use crate::models::{ChallengeInstance, ChallengeInstanceState, ChallengeNotice, InstanceMetadata, TimeSinceEpoch};
+
+/// How long the listing of a closed socket is kept for the client to resume from when it reconnects.
+const RESUME_WINDOW: Duration = Duration::from_secs(600);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ChallengePlayerState {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub state: ChallengeInstanceState,
+    pub stop_time: Option<TimeSinceEpoch>,
+    pub details: Option<String>,
+    pub progress: Option<u8>,
+    pub paused: bool,
+    pub flag_submission: bool,
+    pub extendable: bool,
+    pub restartable: bool,
+    pub cancellable: bool,
+    pub notice: Option<String>,
+    pub metadata: BTreeMap<String, String>
+}
+
+/// How many instances a user runs, out of how many they may run at once.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct InstanceSlots {
+    pub used: i64,
+    pub max: u32
+}
+
+/// Fills in a listing of stopped challenges with the instances of its user, the notices of the challenges and the
+/// metadata of the instances.
+pub fn populate_listing(challenges: &mut BTreeMap<String, ChallengePlayerState>, instances: Vec<ChallengeInstance>, notices: Vec<ChallengeNotice>, metadata: Vec<InstanceMetadata>) {
+    for instance in instances {
+        if let Some(challenge) = challenges.get_mut(&instance.challenge_id) {
+            challenge.state = instance.state;
+            challenge.stop_time = instance.stop_time;
+            challenge.details = instance.details;
+            challenge.progress = instance.progress;
+            challenge.paused = instance.paused_at.is_some();
+        }
+    }
+
+    for notice in notices {
+        if let Some(challenge) = challenges.get_mut(&notice.challenge_id) {
+            challenge.notice = notice.contents;
+        }
+    }
+
+    for entry in metadata {
+        if let Some(challenge) = challenges.get_mut(&entry.challenge_id) {
+            challenge.metadata.insert(entry.key, entry.value);
+        }
+    }
+}
+
+/// The entries of a listing that differ from what a client displays.
+#[derive(Serialize, Debug, Default)]
+pub struct ListingDelta {
+    pub changed: BTreeMap<String, ChallengePlayerState>,
+    pub removed: Vec<String>
+}
+
+impl ListingDelta {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The listing as a client displays it, following every update sent over its socket the way the dashboard applies
+/// them, so that a later listing can be sent as the entries that changed.
+pub struct TrackedListing {
+    pub resume_token: String,
+    challenges: BTreeMap<String, ChallengePlayerState>
+}
+
+impl TrackedListing {
+    pub fn new(challenges: BTreeMap<String, ChallengePlayerState>) -> Self {
+        let resume_token = rand::thread_rng().gen::<[u8; 16]>().iter().map(|byte| format!("{:02x}", byte)).collect();
+        TrackedListing { resume_token, challenges }
+    }
+
+    pub fn challenges(&self) -> &BTreeMap<String, ChallengePlayerState> {
+        &self.challenges
+    }
+
+    /// Replaces the tracked listing, returning the entries the client is missing.
+    pub fn update(&mut self, challenges: BTreeMap<String, ChallengePlayerState>) -> ListingDelta {
+        let delta = ListingDelta {
+            changed: challenges.iter()
+                .filter(|(id, challenge)| self.challenges.get(*id) != Some(*challenge))
+                .map(|(id, challenge)| (id.clone(), challenge.clone()))
+                .collect(),
+            removed: self.challenges.keys().filter(|id| !challenges.contains_key(*id)).cloned().collect()
+        };
+
+        self.challenges = challenges;
+        delta
+    }
+
+    /// Mirrors a state change, which only replaces the details and stop time it carries. A stopped instance is
+    /// no longer paused.
+    pub fn apply_state_change(&mut self, id: &str, state: &ChallengeInstanceState, details: Option<&String>, stop_time: Option<&TimeSinceEpoch>) {
+        if let Some(challenge) = self.challenges.get_mut(id) {
+            challenge.state = state.clone();
+            if *state == ChallengeInstanceState::Stopped {
+                challenge.paused = false;
+            }
+            if let Some(details) = details.filter(|details| !details.is_empty()) {
+                challenge.details = Some(details.clone());
+            }
+            if let Some(stop_time) = stop_time {
+                challenge.stop_time = Some(stop_time.clone());
+            }
+        }
+    }
+
+    pub fn apply_progress(&mut self, id: &str, progress: u8) {
+        if let Some(challenge) = self.challenges.get_mut(id) {
+            challenge.progress = Some(progress);
+        }
+    }
+
+    pub fn apply_paused(&mut self, id: &str, paused: bool, stop_time: Option<&TimeSinceEpoch>) {
+        if let Some(challenge) = self.challenges.get_mut(id) {
+            challenge.paused = paused;
+            if let Some(stop_time) = stop_time {
+                challenge.stop_time = Some(stop_time.clone());
+            }
+        }
+    }
+
+    pub fn apply_metadata(&mut self, id: &str, metadata: &BTreeMap<String, String>) {
+        if let Some(challenge) = self.challenges.get_mut(id) {
+            challenge.metadata = metadata.clone();
+        }
+    }
+
+    pub fn apply_notice(&mut self, id: &str, notice: Option<&String>) {
+        if let Some(challenge) = self.challenges.get_mut(id) {
+            challenge.notice = notice.cloned();
+        }
+    }
+}
+
+struct StoredListing {
+    user_id: String,
+    listing: TrackedListing,
+    stored_at: Instant
+}
+
+/// The listings of recently closed sockets, by resume token.
+#[derive(Default)]
+pub struct ListingCache {
+    listings: Mutex<HashMap<String, StoredListing>>
+}
+
+impl ListingCache {
+    pub fn store(&self, user_id: &str, listing: TrackedListing) {
+        let mut listings = self.listings.lock().unwrap();
+        listings.retain(|_, stored| stored.stored_at.elapsed() < RESUME_WINDOW);
+        listings.insert(listing.resume_token.clone(), StoredListing { user_id: user_id.to_string(), listing, stored_at: Instant::now() });
+    }
+
+    /// Takes back the listing stored under a token, provided it belongs to the user and hasn't expired.
+    pub fn resume(&self, user_id: &str, resume_token: &str) -> Option<TrackedListing> {
+        let stored = self.listings.lock().unwrap().remove(resume_token)?;
+        (stored.user_id == user_id && stored.stored_at.elapsed() < RESUME_WINDOW).then_some(stored.listing)
+    }
+}
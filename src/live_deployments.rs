@@ -0,0 +1,142 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::models::TimeSinceEpoch;
+
+const RECENT_OUTPUT_LINES: usize = 20;
+
+/// Deploy operations currently executing, along with their most recent output.
+pub struct LiveDeployments {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, LiveDeployment>>,
+    event_tx: broadcast::Sender<LiveDeploymentEvent>
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveDeployment {
+    pub id: u64,
+    pub challenge_id: String,
+    pub user_id: String,
+    pub command: &'static str,
+    pub start_time: TimeSinceEpoch,
+    pub output: VecDeque<String>,
+    #[serde(skip)]
+    cancel: CancellationToken
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveDeploymentEvent {
+    Started { deployment: LiveDeployment },
+    Output { id: u64, line: String },
+    Finished { id: u64, success: bool }
+}
+
+#[derive(Clone, Copy)]
+pub struct LiveDeploymentHandle<'a> {
+    live: &'a LiveDeployments,
+    id: u64
+}
+
+impl LiveDeploymentHandle<'_> {
+    pub fn output(&self, line: &str) {
+        self.live.output(self.id, line);
+    }
+
+    /// Completes once the deployment is cancelled.
+    pub async fn cancelled(&self) {
+        let cancel = self.live.active.lock().unwrap().get(&self.id).map(|deployment| deployment.cancel.clone());
+        match cancel {
+            Some(cancel) => cancel.cancelled().await,
+            None => std::future::pending().await
+        }
+    }
+}
+
+impl Default for LiveDeployments {
+    fn default() -> Self {
+        let (event_tx, _) = broadcast::channel(256);
+
+        LiveDeployments {
+            next_id: AtomicU64::new(0),
+            active: Mutex::new(HashMap::new()),
+            event_tx
+        }
+    }
+}
+
+impl LiveDeployments {
+    pub fn new() -> Self {
+        LiveDeployments::default()
+    }
+
+    pub fn begin(&self, challenge_id: &str, user_id: &str, command: &'static str) -> u64 {
+        let deployment = LiveDeployment {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            challenge_id: challenge_id.to_string(),
+            user_id: user_id.to_string(),
+            command,
+            start_time: TimeSinceEpoch::now(),
+            output: VecDeque::new(),
+            cancel: CancellationToken::new()
+        };
+
+        let id = deployment.id;
+        self.active.lock().unwrap().insert(id, deployment.clone());
+        let _ = self.event_tx.send(LiveDeploymentEvent::Started { deployment });
+        id
+    }
+
+    pub fn output(&self, id: u64, line: &str) {
+        if let Some(deployment) = self.active.lock().unwrap().get_mut(&id) {
+            if deployment.output.len() == RECENT_OUTPUT_LINES {
+                deployment.output.pop_front();
+            }
+            deployment.output.push_back(line.to_string());
+        }
+        let _ = self.event_tx.send(LiveDeploymentEvent::Output { id, line: line.to_string() });
+    }
+
+    /// Returns the deployment in progress for a user's instance of a challenge, if any.
+    pub fn find(&self, challenge_id: &str, user_id: &str) -> Option<LiveDeployment> {
+        self.active.lock().unwrap().values()
+            .find(|deployment| deployment.challenge_id == challenge_id && deployment.user_id == user_id)
+            .cloned()
+    }
+
+    /// Aborts a deployment in progress, returning it unless it already finished. Cleanups can't be cancelled since
+    /// they're what puts instances back in order, including cancelled ones.
+    pub fn cancel(&self, id: u64) -> Option<LiveDeployment> {
+        let active = self.active.lock().unwrap();
+        let deployment = active.get(&id).filter(|deployment| deployment.command != "cleanup")?;
+        deployment.cancel.cancel();
+        Some(deployment.clone())
+    }
+
+    pub fn is_cancelled(&self, id: u64) -> bool {
+        self.active.lock().unwrap().get(&id).is_some_and(|deployment| deployment.cancel.is_cancelled())
+    }
+
+    /// Refers to a deployment in progress, so that its output can be appended without passing its id around.
+    pub fn handle(&self, id: u64) -> LiveDeploymentHandle<'_> {
+        LiveDeploymentHandle { live: self, id }
+    }
+
+    pub fn finish(&self, id: u64, success: bool) {
+        self.active.lock().unwrap().remove(&id);
+        let _ = self.event_tx.send(LiveDeploymentEvent::Finished { id, success });
+    }
+
+    /// Returns the deployments in progress along with a receiver for everything that happens after them.
+    pub fn subscribe(&self) -> (Vec<LiveDeployment>, broadcast::Receiver<LiveDeploymentEvent>) {
+        let active = self.active.lock().unwrap();
+        let mut deployments: Vec<LiveDeployment> = active.values().cloned().collect();
+        deployments.sort_by_key(|deployment| deployment.id);
+        (deployments, self.event_tx.subscribe())
+    }
+}
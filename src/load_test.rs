@@ -0,0 +1,156 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures_util::{SinkExt, StreamExt};
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tokio::time::{self, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use tower_sessions::cookie::time::{Duration as CookieDuration, OffsetDateTime};
+use tower_sessions::session::{Id, Record};
+use tower_sessions::SessionStore;
+
+use crate::auth;
+use crate::config::LoadTestConfig;
+use crate::models::{TimeSinceEpoch, User};
+use crate::session_policy::LOGIN_TIME_KEY;
+use crate::InstancerState;
+
+#[derive(Default)]
+struct LoadTestMetrics {
+    acknowledgement_latencies: Vec<Duration>,
+    completion_latencies: Vec<Duration>,
+    rejected_actions: u32,
+    failed_users: u32,
+    max_queue_len: usize
+}
+
+/// Simulates users connecting over WS and starting/stopping random challenges, then reports latencies.
+pub async fn run(state: Arc<InstancerState>, load_test: LoadTestConfig) {
+    if !state.config.settings.simulate_deployments {
+        tracing::warn!("load test running against real deployers, set settings.simulate_deployments to use the mock deployer");
+    }
+
+    tracing::info!("starting load test with {} synthetic users", load_test.users);
+
+    let metrics = Arc::new(Mutex::new(LoadTestMetrics::default()));
+    let mut users = JoinSet::new();
+    for index in 0..load_test.users {
+        let state = Arc::clone(&state);
+        let metrics = Arc::clone(&metrics);
+        let load_test = load_test.clone();
+        users.spawn(async move {
+            if let Err(err) = simulate_user(&state, &load_test, index, &metrics).await {
+                tracing::warn!("synthetic user {} failed: {:?}", index, err);
+                metrics.lock().await.failed_users += 1;
+            }
+        });
+    }
+    users.join_all().await;
+
+    let mut metrics = metrics.lock().await;
+    metrics.acknowledgement_latencies.sort();
+    metrics.completion_latencies.sort();
+
+    tracing::info!("load test finished: {} actions completed, {} rejected, {} users failed, max queue length {}",
+        metrics.completion_latencies.len(), metrics.rejected_actions, metrics.failed_users, metrics.max_queue_len);
+    tracing::info!("acknowledgement latency: p50 {:?}, p95 {:?}, max {:?}",
+        percentile(&metrics.acknowledgement_latencies, 0.5), percentile(&metrics.acknowledgement_latencies, 0.95), metrics.acknowledgement_latencies.last());
+    tracing::info!("completion latency: p50 {:?}, p95 {:?}, max {:?}",
+        percentile(&metrics.completion_latencies, 0.5), percentile(&metrics.completion_latencies, 0.95), metrics.completion_latencies.last());
+}
+
+async fn simulate_user(state: &InstancerState, load_test: &LoadTestConfig, index: u32, metrics: &Mutex<LoadTestMetrics>) -> anyhow::Result<()> {
+    let uid = format!("load-test-{}", index);
+
+    let user = User {
+        id: uid.clone(),
+        username: uid.clone(),
+        display_name: uid.clone(),
+        avatar: None,
+        creation_time: TimeSinceEpoch::now(),
+        instance_count: 0,
+        scoreboard_id: None,
+        role: auth::initial_role(&state.config.settings, &uid)
+    };
+    state.database.insert_user(&user).await?;
+
+    let mut record = Record {
+        id: Id::default(),
+        data: HashMap::from([
+            (String::from("uid"), Value::String(uid.clone())),
+            (String::from(LOGIN_TIME_KEY), Value::from(i64::from(&TimeSinceEpoch::now())))
+        ]),
+        expiry_date: OffsetDateTime::now_utc() + CookieDuration::seconds(load_test.duration as i64 + 3600)
+    };
+    state.session_store.create(&mut record).await?;
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws?sid={}", state.config.settings.listen_on, record.id)).await?;
+
+    let mut running = HashSet::new();
+    let deadline = Instant::now() + Duration::from_secs(load_test.duration as u64);
+
+    while Instant::now() < deadline && !state.shutdown_token.is_cancelled() {
+        let jitter = rand::thread_rng().gen_range(0.5..1.5);
+        time::sleep(Duration::from_secs(load_test.action_interval as u64).mul_f64(jitter)).await;
+
+        let Some(cid) = state.deployer.challenges.snapshot().keys().choose(&mut rand::thread_rng()).cloned() else {
+            return Err(anyhow!("no challenges are available"));
+        };
+        let action = if running.contains(&cid) { "stop" } else { "start" };
+
+        let sent_at = Instant::now();
+        socket.send(Message::Text(json!({ "type": "challenge_action", "id": cid, "action": action }).to_string())).await?;
+
+        let wait = async {
+            while let Some(message) = socket.next().await {
+                let Message::Text(text) = message? else { continue };
+                let message: Value = serde_json::from_str(&text)?;
+                if message["id"].as_str() != Some(&cid) { continue; }
+
+                match (message["type"].as_str(), message["state"].as_str(), message["severity"].as_str()) {
+                    (Some("challenge_state_change"), Some("running"), _) => {
+                        running.insert(cid.clone());
+                        return Ok(true);
+                    }
+                    (Some("challenge_state_change"), Some("stopped"), _) => {
+                        running.remove(&cid);
+                        return Ok(true);
+                    }
+                    (Some("challenge_state_change"), _, _) => metrics.lock().await.acknowledgement_latencies.push(sent_at.elapsed()),
+                    (Some("message"), _, Some("warning" | "error")) => return Ok(false),
+                    _ => {}
+                }
+            }
+            Err(anyhow!("socket closed"))
+        };
+
+        let completed = time::timeout(Duration::from_secs(600), wait).await??;
+
+        let mut metrics = metrics.lock().await;
+        if completed {
+            metrics.completion_latencies.push(sent_at.elapsed());
+        } else {
+            metrics.rejected_actions += 1;
+        }
+        metrics.max_queue_len = metrics.max_queue_len.max(state.deployer.queue_len());
+    }
+
+    for cid in running {
+        socket.send(Message::Text(json!({ "type": "challenge_action", "id": cid, "action": "stop" }).to_string())).await?;
+    }
+    socket.close(None).await?;
+
+    Ok(())
+}
+
+fn percentile(sorted: &[Duration], percentile: f64) -> Option<Duration> {
+    if sorted.is_empty() { return None; }
+    let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+    Some(sorted[index])
+}
@@ -0,0 +1,44 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+use crate::config::LocalAuthConfig;
+
+/// Password attempts an IP may make per minute against local accounts.
+pub const LOGIN_ATTEMPTS_PER_MINUTE: u32 = 10;
+
+/// Hashes a password with argon2 and a random salt, off the async workers since it is meant to be slow.
+pub async fn hash_password(password: String) -> anyhow::Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default().hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|err| anyhow::anyhow!("couldn't hash password: {}", err))
+    }).await?
+}
+
+/// Whether the password matches the hash, false if the hash is malformed.
+pub async fn verify_password(password: String, hash: String) -> anyhow::Result<bool> {
+    Ok(tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash).is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+    }).await?)
+}
+
+/// Emails are compared case-insensitively and without surrounding whitespace.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Only checks that the email looks like one, it is never sent anything.
+pub fn is_valid_email(email: &str) -> bool {
+    email.len() <= 254 && email.split_once('@').is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.') && !email.contains(char::is_whitespace))
+}
+
+/// Why a password is refused, shown as is to the user.
+pub fn check_password(config: &LocalAuthConfig, password: &str) -> Result<(), String> {
+    match password.chars().count() {
+        length if length < config.min_password_length => Err(format!("Le mot de passe doit contenir au moins {} caractères.", config.min_password_length)),
+        length if length > 256 => Err(String::from("Le mot de passe ne peut pas dépasser 256 caractères.")),
+        _ => Ok(())
+    }
+}
@@ -1,14 +1,19 @@
 extern crate alloc;
 extern crate core;
 
+use std::net::SocketAddr;
 use std::sync::Arc;
 
-use crate::config::InstancerConfig;
-use crate::database::Database;
-use crate::deployment_worker::DeploymentWorker;
-use crate::state::InstancerState;
-use axum::routing::get;
-use axum::Router;
+use challenge_instancer::config::InstancerConfig;
+use challenge_instancer::config_summary::ConfigSummary;
+use challenge_instancer::database::Database;
+use challenge_instancer::deployment_worker::DeploymentWorker;
+use challenge_instancer::models::UserRole;
+use challenge_instancer::state::InstancerState;
+use anyhow::Context;
+use axum::handler::HandlerWithoutStateExt;
+use axum::routing::{get, post};
+use axum::{middleware, Router};
 use ::config::{Config, File};
 use sd_notify::NotifyState;
 use sqlx::sqlite::SqliteConnectOptions;
@@ -24,14 +29,15 @@ use tower_sessions::{Expiry, SessionManagerLayer};
 use tower_sessions_sqlx_store::{sqlx::SqlitePool, SqliteStore};
 use tracing::log::LevelFilter;
 
-mod router;
-mod templating;
-mod config;
-mod state;
-mod discord;
-mod database;
-mod models;
-mod deployment_worker;
+use challenge_instancer::{auth, build_info, challenge_set, csrf, demo, deploy_logs, error, event_end, janitor, outbox, profiles, quotas, rctf, reconciliation, router, selftest, session_policy, usage};
+#[cfg(feature = "load-test")]
+use challenge_instancer::load_test;
+#[cfg(feature = "fault-injection")]
+use challenge_instancer::fault_injection;
+#[cfg(feature = "event-bus")]
+use challenge_instancer::event_bus;
+#[cfg(feature = "geoip")]
+use challenge_instancer::geo;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -43,10 +49,11 @@ async fn main() -> anyhow::Result<()> {
         )
         .init();
 
-    let config: InstancerConfig = Config::builder()
+    let config: InstancerConfig = serde_path_to_error::deserialize(Config::builder()
         .add_source(File::with_name("config.toml"))
-        .build()?
-        .try_deserialize()?;
+        .build()?)
+        .map_err(|err| anyhow::anyhow!("invalid config.toml, at {}: {}", err.path(), err.inner()))?;
+    config.validate().context("invalid config.toml")?;
 
     let sqlite_pool = SqlitePool::connect_with(SqliteConnectOptions::new()
         .create_if_missing(true)
@@ -54,44 +61,167 @@ async fn main() -> anyhow::Result<()> {
         .filename(config.database.file_path.clone()))
         .await.expect("failed to setup sqlite pool for session store");
     let database = Database::new(sqlite_pool.clone()).await?;
+    database.set_user_roles(&config.settings.admins, &UserRole::Admin).await?;
+    #[cfg(feature = "fault-injection")]
+    let database = match config.fault_injection.clone() {
+        Some(faults) => {
+            tracing::warn!("fault injection enabled: deployments fail {}% of the time, queries {}%, {}% of both are delayed by up to {}s",
+                faults.deploy_failure_rate * 100.0, faults.database_error_rate * 100.0, faults.latency_rate * 100.0, faults.latency);
+            database.with_faults(fault_injection::FaultInjector::new(faults))
+        }
+        None => database
+    };
 
     let shutdown_token = CancellationToken::new();
     let deployer = DeploymentWorker::new(&config, database.clone(), shutdown_token.clone());
 
+    deployer.load_challenge_overrides(&config).await?;
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some((command, args)) = args.split_first() {
+        anyhow::ensure!(command == "challenges", "unknown command {}", command);
+        return challenge_set::run_command(&config, &deployer, args).await;
+    }
+
     deployer.prepare().await?;
+    if !config.settings.simulate_deployments {
+        selftest::run(&config, &deployer).await.log();
+    }
 
     let session_store = SqliteStore::new(sqlite_pool);
     session_store.migrate().await.expect("failed to migrate session store");
 
     let session_layer = SessionManagerLayer::new(session_store.clone())
         .with_same_site(SameSite::Lax)
-        .with_expiry(Expiry::OnInactivity(Duration::days(3)))
+        .with_expiry(Expiry::OnInactivity(Duration::seconds(config.settings.session_inactivity.into())))
         .with_http_only(false)
         .with_secure(false);
 
-    let state = Arc::new(InstancerState::new(config, database, deployer, session_store, shutdown_token.clone()));
+    let summary = ConfigSummary::new(&config, &deployer);
+    tracing::info!("effective configuration: {}", serde_json::to_string(&summary)?);
+
+    #[allow(unused_mut)]
+    let mut state = InstancerState::new(config, database, deployer, session_store, shutdown_token.clone());
+
+    #[cfg(feature = "geoip")]
+    if let Some(geo) = state.config.geo.clone() {
+        state.geo = Some(geo::GeoFilter::open(geo)?);
+    }
+
+    let state = Arc::new(state);
 
     let mut workers = JoinSet::new();
-    for _ in 1..=state.config.settings.worker_count {
+    let pools = std::iter::repeat_n(None, state.config.settings.worker_count as usize)
+        .chain(state.config.settings.worker_pools.iter().flat_map(|(pool, count)| std::iter::repeat_n(Some(pool.clone()), *count as usize)));
+    for (worker_id, pool) in (1..).zip(pools) {
         let state = Arc::clone(&state);
-        workers.spawn(async move { state.deployer.do_work().await });
+        workers.spawn(async move { DeploymentWorker::supervise(state, worker_id, pool).await; Ok(()) });
+    }
+    workers.spawn(outbox::dispatch_updates(Arc::clone(&state)));
+    workers.spawn(rctf::stop_solved_instances(Arc::clone(&state)));
+    workers.spawn(event_end::end_event_on_schedule(Arc::clone(&state)));
+    workers.spawn(usage::accrue_periodically(Arc::clone(&state)));
+    workers.spawn(reconciliation::reconcile_periodically(Arc::clone(&state)));
+    workers.spawn(janitor::sweep_periodically(Arc::clone(&state)));
+    workers.spawn(demo::expire_demo_users_periodically(Arc::clone(&state)));
+    workers.spawn(profiles::refresh_profiles_periodically(Arc::clone(&state)));
+    workers.spawn(quotas::reset_on_schedule(Arc::clone(&state)));
+
+    if let Some(dir) = state.config.settings.deploy_log_dir.clone() {
+        workers.spawn(deploy_logs::record_deploy_logs(Arc::clone(&state), dir));
     }
 
+    #[cfg(feature = "event-bus")]
+    if let Some(event_bus) = state.config.event_bus.clone() {
+        workers.spawn(event_bus::publish_updates(Arc::clone(&state), event_bus));
+    }
+
+    let admin_routes = Router::new()
+        .route("/admin/deployments/:id/cancel", post(router::admin_cancel_deployment))
+        .route("/admin/config", get(router::admin_config))
+        .route("/admin/usage", get(router::admin_usage))
+        .route("/admin/stats", get(router::admin_stats))
+        .route("/admin/latency", get(router::admin_latency))
+        .route("/admin/metrics", get(router::admin_metrics))
+        .route("/admin/instances", get(router::admin_instances))
+        .route("/admin/instances/history", get(router::admin_instance_history))
+        .route("/admin/failovers", get(router::admin_failovers))
+        .route("/admin/selftest", post(router::admin_selftest))
+        .route("/admin/instances/:challenge/:user/pause", post(router::admin_pause_instance))
+        .route("/admin/instances/:challenge/:user/resume", post(router::admin_resume_instance))
+        .route("/admin/instances/:challenge/:user/migrate", post(router::admin_migrate_instance))
+        .route("/admin/maintenance", get(router::admin_maintenance_status).post(router::admin_set_maintenance))
+        .route("/admin/audit", get(router::admin_audit))
+        .route("/admin/accounts", post(router::admin_create_local_account))
+        .route("/admin/accounts/:id/password", post(router::admin_reset_local_password))
+        .route("/admin/janitor", get(router::admin_janitor))
+        .route("/admin/event", get(router::admin_event_status))
+        .route("/admin/event/end", post(router::admin_end_event))
+        .route("/admin/challenge-set", get(router::admin_export_challenges).post(router::admin_import_challenges))
+        .route("/admin/challenges/:id", get(router::admin_challenge_override).put(router::admin_set_challenge_override).delete(router::admin_delete_challenge_override))
+        .route("/admin/challenges/:id/bulk", get(router::admin_bulk_status).post(router::admin_bulk_operation))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&state), auth::require_admin));
+
+    let author_routes = Router::new()
+        .route("/admin/challenges/:id/notice", post(router::admin_set_notice))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&state), auth::require_author));
+
+    let ldap_routes: Router<Arc<InstancerState>> = Router::new();
+    #[cfg(feature = "ldap")]
+    let ldap_routes = ldap_routes.route("/login/ldap", post(router::login_ldap));
+
+    let push_routes: Router<Arc<InstancerState>> = Router::new();
+    #[cfg(feature = "web-push")]
+    let push_routes = push_routes.route("/api/push/subscriptions", post(router::subscribe_push).delete(router::unsubscribe_push));
+
     let app = Router::new()
         .route("/", get(router::dashboard))
         .route("/help", get(router::help))
+        .route("/terms", get(router::terms).post(router::accept_terms))
+        .route("/csrf", get(router::csrf_token))
+        .route("/preferences", get(router::preferences).post(router::update_preferences))
+        .route("/version", get(router::version))
+        .route("/avatars/default", get(router::default_avatar))
+        .route("/avatars/:user_id/:avatar_hash", get(router::avatar))
         .route("/login", get(router::login))
+        .route("/login/rctf", post(router::login_rctf))
+        .route("/login/demo", post(router::login_demo))
+        .route("/login/local", post(router::login_local))
+        .route("/register", get(router::register).post(router::submit_registration))
         .route("/logout", get(router::logout))
         .route("/ws", get(router::dashboard_ws_handler))
-        .fallback_service(ServeDir::new("static"))
+        .route("/admin/ws/deployments", get(router::admin_deployments_ws_handler))
+        .route("/admin/ws/instances/:challenge/:user/logs", get(router::admin_instance_logs_ws_handler))
+        .route("/api/challenges", get(router::api_challenges))
+        .route("/api/preferences", get(router::api_preferences))
+        .route("/api/instances/:challenge_id", get(router::api_instance))
+        .route("/api/instances/:challenge_id/bundle", get(router::api_instance_bundle))
+        .route("/api/submit", post(router::submit_flag))
+        .merge(admin_routes)
+        .merge(author_routes)
+        .merge(ldap_routes)
+        .merge(push_routes)
+        .fallback_service(ServeDir::new("static").not_found_service(router::not_found.into_service()))
+        .layer(middleware::from_fn(csrf::verify))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), session_policy::enforce_max_age))
+        .layer(middleware::from_fn(error::negotiate))
         .with_state(Arc::clone(&state))
         .layer(session_layer);
 
-    tracing::info!("started instancer on {}", state.config.settings.listen_on);
+    tracing::info!("started instancer {} ({}) on {}", build_info::VERSION, build_info::GIT_HASH, state.config.settings.listen_on);
 
     let listener = TcpListener::bind(&state.config.settings.listen_on).await?;
+
+    #[cfg(feature = "load-test")]
+    if let Some(load_test) = state.config.load_test.clone() {
+        tokio::spawn(load_test::run(Arc::clone(&state), load_test));
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fault_injection::arm();
+
     let _ = sd_notify::notify(true, &[NotifyState::Ready]);
-    axum::serve(listener, app).with_graceful_shutdown(shutdown_signal()).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).with_graceful_shutdown(shutdown_signal()).await?;
 
     tracing::info!("shutdown requested, draining pending deployment requests...");
 
@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use minijinja::{AutoEscape, Environment, UndefinedBehavior, Value};
+
+use crate::messages::MessageContents;
+
+/// The built-in wording of every message, each replaceable by a `<name>.html` file in the templates directory.
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    ("terms_required", "Vous devez accepter les <a href=\"/terms\">conditions d'utilisation</a> avant d'utiliser les défis."),
+    ("rate_limited", "Veuillez attendre {{ seconds }} seconde{% if seconds != 1 %}s{% endif %} avant votre prochaine action."),
+    ("overloaded", "La plateforme est surchargée, réessayez dans quelques minutes."),
+    ("abuse_cooldown", "En raison d'une activité inhabituelle, vous ne pouvez pas démarrer de défi pendant encore {{ minutes }} minute{% if minutes != 1 %}s{% endif %}."),
+    ("throttled", "Activité inhabituelle détectée, vous ne pourrez pas démarrer de défi pendant {{ minutes }} minute{% if minutes != 1 %}s{% endif %}."),
+    ("event_ended", "L'événement est terminé, aucun défi ne peut être démarré."),
+    ("challenge_required", "Vous devez d'abord démarrer le défi <strong>{{ challenge }}</strong>."),
+    ("quota_exhausted", "Vous avez épuisé votre quota de {{ hours }} heure{% if hours != 1 %}s{% endif %} d'instances."),
+    ("concurrent_limit", "Vous avez atteint la limite de {{ limit }} défis concurrents.{% if weight > 1 %} Ce défi compte pour {{ weight }} défis.{% endif %}"),
+    ("restart_disabled", "Le redémarrage des défis est désactivé."),
+    ("not_extendable", "Le défi <strong>{{ challenge }}</strong> ne peut pas être étendu."),
+    ("extended", "Le défi <strong>{{ challenge }}</strong> a été étendu."),
+    ("extension_limit", "Le défi <strong>{{ challenge }}</strong> a atteint la limite de {{ max_extensions }} extension{% if max_extensions != 1 %}s{% endif %}."),
+    ("expiry_warning", "Le défi <strong>{{ challenge }}</strong> sera arrêté dans {{ minutes }} minute{% if minutes != 1 %}s{% endif %}{% if extendable %}, cliquez sur <strong>Étendre</strong> pour le garder actif{% endif %}."),
+    ("expired", "Le défi <strong>{{ challenge }}</strong> a expiré et sera bientôt arrêté, cliquez sur <strong>Étendre</strong> pour le conserver."),
+    ("started", "Le défi <strong>{{ challenge }}</strong> a été démarré!"),
+    ("start_failed", "Le défi <strong>{{ challenge }}</strong> n'a pas pu être démarré.<br>Contactez un administrateur si l'erreur persiste."),
+    ("stopped", "Le défi <strong>{{ challenge }}</strong> a été arrêté."),
+    ("stop_failed", "Le défi <strong>{{ challenge }}</strong> n'a pas pu être arrêté.<br>Contactez un administrateur si l'erreur persiste."),
+    ("restarted", "Le défi <strong>{{ challenge }}</strong> a été redémarré!"),
+    ("restart_failed", "Le défi <strong>{{ challenge }}</strong> n'a pas pu être redémarré.<br>Contactez un administrateur si l'erreur persiste."),
+    ("migrated", "Le défi <strong>{{ challenge }}</strong> a été déplacé sur une autre infrastructure, ses informations de connexion ont pu changer."),
+    ("cancelled", "Le déploiement du défi <strong>{{ challenge }}</strong> a été annulé."),
+    ("cancel_disabled", "L'annulation des déploiements est désactivée."),
+    ("not_cancellable", "Aucun démarrage du défi <strong>{{ challenge }}</strong> n'est en cours."),
+    ("announcement", "Annonce pour le défi <strong>{{ challenge }}</strong> : {{ notice }}"),
+    ("deployer_warning", "Avertissement du défi <strong>{{ challenge }}</strong> : {{ warning }}"),
+    ("reset", "Le défi <strong>{{ challenge }}</strong> a été réinitialisé."),
+    ("bulk_stopped", "Un administrateur a arrêté les instances du défi <strong>{{ challenge }}</strong>."),
+    ("bulk_restarted", "Un administrateur a redémarré les instances du défi <strong>{{ challenge }}</strong>."),
+    ("paused", "Le temps restant du défi <strong>{{ challenge }}</strong> est suspendu{% if maintenance %} pendant la maintenance{% endif %}."),
+    ("resumed", "Le temps restant du défi <strong>{{ challenge }}</strong> s'écoule à nouveau, la pause ne l'a pas entamé."),
+    ("event_over", "L'événement est terminé, l'instance du défi <strong>{{ challenge }}</strong> sera arrêtée. Merci d'avoir participé!"),
+    ("solved", "Bravo! Le défi <strong>{{ challenge }}</strong> a été résolu, son instance sera arrêtée.")
+];
+
+/// The wording of the messages sent to users, rendered with minijinja so that it can change without a rebuild.
+///
+/// Templates may use `<strong>`, `<br>` and `<a href="/...">`, and interpolated values are escaped before the
+/// output is split into [`MessageContents`].
+pub struct MessageTemplates {
+    env: Environment<'static>
+}
+
+impl MessageTemplates {
+    /// Loads the built-in templates, replaced by those of `dir` that compile.
+    pub fn load(dir: Option<&Path>) -> Self {
+        let mut env = Environment::new();
+        env.set_auto_escape_callback(|_| AutoEscape::Html);
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+
+        for (name, source) in DEFAULT_TEMPLATES {
+            env.add_template(name, source).expect("built-in message templates compile");
+        }
+
+        if let Some(dir) = dir {
+            for (name, _) in DEFAULT_TEMPLATES {
+                let path = dir.join(format!("{}.html", name));
+                let source = match std::fs::read_to_string(&path) {
+                    Ok(source) => source,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(err) => {
+                        tracing::warn!("couldn't read message template {}: {:?}", path.display(), err);
+                        continue;
+                    }
+                };
+
+                match env.add_template_owned(*name, source.trim_end().to_string()) {
+                    Ok(()) => tracing::info!("using message template {}", path.display()),
+                    Err(err) => tracing::warn!("ignoring message template {}: {}", path.display(), err)
+                }
+            }
+        }
+
+        MessageTemplates { env }
+    }
+
+    /// Renders a message, falling back to its built-in wording if the template doesn't render with `ctx`.
+    pub fn render(&self, name: &str, ctx: Value) -> MessageContents {
+        let rendered = self.env.get_template(name).and_then(|template| template.render(&ctx));
+        let markup = rendered.or_else(|err| {
+            tracing::warn!("couldn't render message template {}: {}", name, err);
+            let (_, source) = DEFAULT_TEMPLATES.iter().find(|(default, _)| *default == name).ok_or(err)?;
+            self.env.render_str(source, &ctx)
+        });
+
+        match markup {
+            Ok(markup) => MessageContents::from_markup(&markup),
+            Err(err) => {
+                tracing::error!("couldn't render built-in message template {}: {}", name, err);
+                MessageContents::default()
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// The tags message templates may use. Links only go to pages of the instancer, so `//` isn't accepted.
+static MARKUP_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<strong>|</strong>|<br\s*/?>|<a href="(/(?:[^/"][^"]*)?)">|</a>"#).unwrap());
+
+/// A notification shown to users, sent as styled spans rather than HTML. The dashboard renders every span as text,
+/// so challenge names and other interpolated values can't inject markup.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MessageContents(Vec<MessageSpan>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "style", rename_all = "snake_case")]
+enum MessageSpan {
+    Text { text: String },
+    Strong { text: String },
+    Link { text: String, href: String },
+    LineBreak
+}
+
+#[derive(Clone)]
+enum Style {
+    Text,
+    Strong,
+    Link(String)
+}
+
+impl MessageContents {
+    /// Parses the output of a message template: HTML-escaped text with `<strong>`, `<br>` and `<a href="/...">` tags.
+    /// Any other tag is kept as text.
+    pub fn from_markup(markup: &str) -> Self {
+        let mut contents = MessageContents::default();
+        let mut style = Style::Text;
+        let mut position = 0;
+
+        for tag in MARKUP_TAG.captures_iter(markup) {
+            let whole = tag.get(0).unwrap();
+            contents.push(&style, &markup[position..whole.start()]);
+            position = whole.end();
+
+            style = match (whole.as_str(), tag.get(1)) {
+                ("<strong>", _) => Style::Strong,
+                (_, Some(href)) => Style::Link(unescape(href.as_str())),
+                (tag, _) if tag.starts_with("<br") => {
+                    contents.0.push(MessageSpan::LineBreak);
+                    style
+                }
+                _ => Style::Text
+            };
+        }
+
+        contents.push(&style, &markup[position..]);
+        contents
+    }
+
+    /// The text of the message without its styling, for the channels that can't render it.
+    pub fn to_plain_text(&self) -> String {
+        self.0.iter()
+            .map(|span| match span {
+                MessageSpan::Text { text } | MessageSpan::Strong { text } | MessageSpan::Link { text, .. } => text.as_str(),
+                MessageSpan::LineBreak => "\n"
+            })
+            .collect()
+    }
+
+    fn push(&mut self, style: &Style, text: &str) {
+        if text.is_empty() { return; }
+
+        let text = unescape(text);
+        self.0.push(match style {
+            Style::Text => MessageSpan::Text { text },
+            Style::Strong => MessageSpan::Strong { text },
+            Style::Link(href) => MessageSpan::Link { text, href: href.clone() }
+        });
+    }
+}
+
+/// Reverses the HTML escaping of interpolated values.
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&#x2f;", "/")
+        .replace("&amp;", "&")
+}
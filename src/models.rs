@@ -1,32 +1,320 @@
 use std::ops::{Add, Sub};
 use std::time::{Duration, SystemTime};
 
-use serde::{Serialize, Serializer};
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sqlx::encode::IsNull;
 use sqlx::error::BoxDynError;
 use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
 use sqlx::{Decode, Encode, Sqlite};
 
-#[derive(sqlx::FromRow)]
+#[derive(Debug, Clone)]
 pub struct User {
     pub id: String,
     pub username: String,
     pub display_name: String,
     pub avatar: Option<String>,
     pub creation_time: TimeSinceEpoch,
-    pub instance_count: i64
+    /// How many instance slots the instances of the user take, each counting as its challenge's weight.
+    pub instance_count: i64,
+    pub scoreboard_id: Option<String>,
+    pub role: UserRole
 }
 
-#[derive(sqlx::FromRow)]
+/// What a user is allowed to do, each role allowing what the roles before it do.
+#[derive(Debug, Serialize, Deserialize, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+    /// Throwaway user of a public demo, created without an account and deleted after a while.
+    Demo,
+    Player,
+    /// Maintains challenges, such as posting notices about them.
+    Author,
+    Admin
+}
+
+impl From<&str> for UserRole {
+    fn from(value: &str) -> Self {
+        match value {
+            "demo" => UserRole::Demo,
+            "player" => UserRole::Player,
+            "author" => UserRole::Author,
+            "admin" => UserRole::Admin,
+            v => panic!("unknown user role: {}", v)
+        }
+    }
+}
+
+impl From<&UserRole> for &str {
+    fn from(value: &UserRole) -> Self {
+        match value {
+            UserRole::Demo => "demo",
+            UserRole::Player => "player",
+            UserRole::Author => "author",
+            UserRole::Admin => "admin"
+        }
+    }
+}
+
+impl sqlx::Type<Sqlite> for UserRole {
+    fn type_info() -> SqliteTypeInfo {
+        <&str as sqlx::Type<Sqlite>>::type_info()
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for UserRole {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let value = <&str as Decode<Sqlite>>::decode(value)?;
+        Ok(value.into())
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for UserRole {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> Result<IsNull, BoxDynError> {
+        let value: &str = self.into();
+        <&str as Encode<Sqlite>>::encode(value, buf)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChallengeNotice {
+    pub challenge_id: String,
+    pub contents: Option<String>
+}
+
+/// Fields of a challenge redefined by an admin, overlaid on its configuration.
+/// Challenges missing from the configuration are defined entirely by their override.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChallengeOverride {
+    pub challenge_id: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub ttl: Option<u32>,
+    pub deployer: Option<String>
+}
+
+pub struct InstanceMetadata {
+    pub challenge_id: String,
+    pub key: String,
+    pub value: String
+}
+
+pub struct InstanceLabel {
+    pub user_id: String,
+    pub challenge_id: String,
+    pub key: String,
+    pub value: String
+}
+
+/// Locales the interface can be displayed in, the first being the default.
+pub const SUPPORTED_LOCALES: [&str; 2] = ["fr", "en"];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UserPreferences {
+    pub locale: String,
+    pub timezone: Option<String>,
+    pub notify_expiry: bool,
+    pub notify_announcements: bool,
+    /// Where notifications are emailed, if the user opted in.
+    pub email: Option<String>
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        UserPreferences {
+            locale: SUPPORTED_LOCALES[0].to_string(),
+            timezone: None,
+            notify_expiry: true,
+            notify_announcements: true,
+            email: None
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub time: TimeSinceEpoch,
+    pub user_id: String,
+    pub ip: String,
+    pub action: String,
+    pub challenge_id: Option<String>
+}
+
+/// A start moved to another target deployer after the infrastructure of the first one failed.
+#[derive(Serialize)]
+pub struct TargetFailover {
+    pub time: TimeSinceEpoch,
+    pub user_id: String,
+    pub challenge_id: String,
+    pub from_target: String,
+    pub to_target: String
+}
+
+/// A message sent while none of its user's dashboards were open, stored as JSON until they reconnect.
+pub struct MissedMessage {
+    pub time: TimeSinceEpoch,
+    pub user_id: String,
+    pub challenge_id: String,
+    pub message: String
+}
+
+/// A deployment update written to the outbox along with the state it announces, as JSON.
+pub struct OutboxEntry {
+    pub user_id: String,
+    pub challenge_id: String,
+    pub payload: String
+}
+
+/// An outbox entry that hasn't been published yet.
+pub struct PendingUpdate {
+    pub id: i64,
+    pub entry: OutboxEntry
+}
+
+/// A browser a user subscribed to push notifications with.
+#[cfg(feature = "web-push")]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub p256dh: String,
+    pub auth: String
+}
+
+/// The credentials of a user logging in with an email and a password.
+pub struct LocalAccount {
+    pub user_id: String,
+    pub password_hash: String
+}
+
+/// A user whose recorded instance count didn't match their instances.
+#[derive(Serialize, Debug)]
+pub struct InstanceCountDrift {
+    pub user_id: String,
+    pub recorded: i64,
+    pub actual: i64
+}
+
+pub struct InstanceUsage {
+    pub user_id: String,
+    pub challenge_id: String,
+    pub runtime: i64
+}
+
+/// How many instances were started during an hour, counted in hours since the epoch.
+pub struct HourlyStarts {
+    pub hour: i64,
+    pub count: i64
+}
+
+/// How many instances of a challenge ended, and how many of those because they failed.
+pub struct ChallengeOutcomes {
+    pub challenge_id: String,
+    pub total: i64,
+    pub failures: i64
+}
+
+/// An instance as kept in the history, which has no end yet while the instance is alive.
+#[derive(Serialize, Debug)]
+pub struct InstanceHistoryEntry {
+    pub user_id: String,
+    pub challenge_id: String,
+    pub nonce: String,
+    pub details: Option<String>,
+    pub extension_count: i64,
+    pub started_at: Option<TimeSinceEpoch>,
+    pub ended_at: Option<TimeSinceEpoch>,
+    pub end_reason: Option<EndReason>
+}
+
+/// Why an instance ended, recorded when it is queued to end. The first reason recorded is kept, so that an instance
+/// cleaned up after its stop failed still ends for the reason it was stopped.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    Stopped,
+    Expired,
+    Solved,
+    EventEnded,
+    Admin,
+    Failed,
+    Cancelled,
+    Interrupted
+}
+
+impl From<&str> for EndReason {
+    fn from(value: &str) -> Self {
+        match value {
+            "stopped" => EndReason::Stopped,
+            "expired" => EndReason::Expired,
+            "solved" => EndReason::Solved,
+            "event_ended" => EndReason::EventEnded,
+            "admin" => EndReason::Admin,
+            "failed" => EndReason::Failed,
+            "cancelled" => EndReason::Cancelled,
+            "interrupted" => EndReason::Interrupted,
+            v => panic!("unknown end reason: {}", v)
+        }
+    }
+}
+
+impl From<&EndReason> for &str {
+    fn from(value: &EndReason) -> Self {
+        match value {
+            EndReason::Stopped => "stopped",
+            EndReason::Expired => "expired",
+            EndReason::Solved => "solved",
+            EndReason::EventEnded => "event_ended",
+            EndReason::Admin => "admin",
+            EndReason::Failed => "failed",
+            EndReason::Cancelled => "cancelled",
+            EndReason::Interrupted => "interrupted"
+        }
+    }
+}
+
+impl sqlx::Type<Sqlite> for EndReason {
+    fn type_info() -> SqliteTypeInfo {
+        <&str as sqlx::Type<Sqlite>>::type_info()
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for EndReason {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let value = <&str as Decode<Sqlite>>::decode(value)?;
+        Ok(value.into())
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for EndReason {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> Result<IsNull, BoxDynError> {
+        let value: &str = self.into();
+        <&str as Encode<Sqlite>>::encode(value, buf)
+    }
+}
+
+#[derive(Serialize)]
 pub struct ChallengeInstance {
     pub user_id: String,
     pub challenge_id: String,
     pub state: ChallengeInstanceState,
     pub details: Option<String>,
-    pub stop_time: Option<TimeSinceEpoch>
+    pub stop_time: Option<TimeSinceEpoch>,
+    pub nonce: String,
+    pub start_time: Option<TimeSinceEpoch>,
+    pub extension_count: i64,
+    /// How far along its deployment is, in percent, while the instance is deploying.
+    pub progress: Option<u8>,
+    /// When the countdown of the instance was frozen, if it is.
+    pub paused_at: Option<TimeSinceEpoch>
+}
+
+impl ChallengeInstance {
+    /// Generates a random identifier unique to a single instance's lifetime.
+    pub fn generate_nonce() -> String {
+        rand::thread_rng().gen::<[u8; 8]>().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
 }
 
-#[derive(Debug, Serialize, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Ord, PartialOrd, Eq, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum ChallengeInstanceState {
     Stopped,
@@ -34,14 +322,18 @@ pub enum ChallengeInstanceState {
     QueuedStart,
     QueuedRestart,
     QueuedStop,
+    Deploying,
+    Expiring
 }
 
 impl ChallengeInstanceState {
     pub fn is_queued(&self) -> bool {
-        match self {
-            ChallengeInstanceState::QueuedStop | ChallengeInstanceState::QueuedStart | ChallengeInstanceState::QueuedRestart => true,
-            _ => false
-        }
+        matches!(self, ChallengeInstanceState::QueuedStop | ChallengeInstanceState::QueuedStart | ChallengeInstanceState::QueuedRestart | ChallengeInstanceState::Deploying)
+    }
+
+    /// Whether the instance hasn't finished starting yet, and so doesn't hold its shared services.
+    pub fn is_starting(&self) -> bool {
+        matches!(self, ChallengeInstanceState::QueuedStart | ChallengeInstanceState::Deploying)
     }
 }
 
@@ -59,6 +351,8 @@ impl From<&str> for ChallengeInstanceState {
             "queued_start" => ChallengeInstanceState::QueuedStart,
             "queued_restart" => ChallengeInstanceState::QueuedRestart,
             "queued_stop" => ChallengeInstanceState::QueuedStop,
+            "deploying" => ChallengeInstanceState::Deploying,
+            "expiring" => ChallengeInstanceState::Expiring,
             v => panic!("unknown challenge instance state: {}", v)
         }
     }
@@ -71,7 +365,9 @@ impl From<&ChallengeInstanceState> for &str {
             ChallengeInstanceState::Running => "running",
             ChallengeInstanceState::QueuedStart => "queued_start",
             ChallengeInstanceState::QueuedStop => "queued_stop",
-            ChallengeInstanceState::QueuedRestart => "queued_restart"
+            ChallengeInstanceState::QueuedRestart => "queued_restart",
+            ChallengeInstanceState::Deploying => "deploying",
+            ChallengeInstanceState::Expiring => "expiring"
         }
     }
 }
@@ -110,20 +406,21 @@ impl TimeSinceEpoch {
 impl Sub for &TimeSinceEpoch {
     type Output = Duration;
 
+    /// Saturates to zero when `rhs` is later, which a clock step can make happen between two reads of the clock.
     fn sub(self, rhs: Self) -> Self::Output {
-        self.0.duration_since(rhs.0).unwrap()
+        self.0.duration_since(rhs.0).unwrap_or_default()
     }
 }
 
 impl From<i64> for TimeSinceEpoch {
     fn from(value: i64) -> Self {
-        TimeSinceEpoch(SystemTime::UNIX_EPOCH.add(Duration::from_millis(value as u64)))
+        TimeSinceEpoch(SystemTime::UNIX_EPOCH.add(Duration::from_millis(value.max(0) as u64)))
     }
 }
 
 impl From<&TimeSinceEpoch> for i64 {
     fn from(value: &TimeSinceEpoch) -> Self {
-        value.0.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as i64
+        value.0.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as i64)
     }
 }
 
@@ -147,6 +444,15 @@ impl<'q> Encode<'q, Sqlite> for TimeSinceEpoch {
     }
 }
 
+impl<'de> Deserialize<'de> for TimeSinceEpoch {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        i64::deserialize(deserializer).map(TimeSinceEpoch::from)
+    }
+}
+
 impl Serialize for TimeSinceEpoch {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{InstancerConfig, NotificationsConfig};
+use crate::database::Database;
+use crate::deployment_worker::{DeploymentUpdate, DeploymentUpdateDetails, MessageSeverity};
+use crate::discord::DiscordBot;
+use crate::messages::MessageContents;
+use crate::update_hub::UpdateHub;
+use crate::webhooks::{WebhookEvent, Webhooks};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    ExpiryWarning,
+    DeployFailure,
+    Announcement,
+    /// An instance was started, stopped or restarted.
+    StateChange
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 4] = [NotificationKind::ExpiryWarning, NotificationKind::DeployFailure, NotificationKind::Announcement, NotificationKind::StateChange];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    /// The dashboard, along with the messages missed while it was closed.
+    Websocket,
+    DiscordDm,
+    Webhook,
+    /// Emailed to the users who gave an address in their preferences.
+    #[cfg(feature = "email")]
+    Email,
+    /// Pushed to the browsers the user subscribed, even with the dashboard closed.
+    #[cfg(feature = "web-push")]
+    WebPush
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub user_id: String,
+    pub challenge_id: String,
+    pub contents: MessageContents,
+    pub severity: MessageSeverity
+}
+
+/// Delivers notifications through a single channel.
+pub trait Notifier: Send + Sync {
+    /// Hands the notification over, in the background when it involves a remote service so that deployments aren't
+    /// held up by it.
+    fn notify(&self, notification: &Notification);
+}
+
+pub struct WebSocketNotifier {
+    updates: Arc<UpdateHub>
+}
+
+impl Notifier for WebSocketNotifier {
+    fn notify(&self, notification: &Notification) {
+        self.updates.send(DeploymentUpdate {
+            user_id: notification.user_id.clone(),
+            challenge_id: notification.challenge_id.clone(),
+            details: DeploymentUpdateDetails::Message { contents: notification.contents.clone(), severity: notification.severity.clone() }
+        });
+    }
+}
+
+/// Sends notifications as DMs from the bot, to the users who logged in with Discord.
+pub struct DiscordDmNotifier {
+    bot: DiscordBot
+}
+
+impl Notifier for DiscordDmNotifier {
+    fn notify(&self, notification: &Notification) {
+        if !is_discord_user(&notification.user_id) { return; }
+
+        let bot = self.bot.clone();
+        let user_id = notification.user_id.clone();
+        let content = notification.contents.to_plain_text();
+        tokio::spawn(async move {
+            if let Err(err) = bot.send_direct_message(&user_id, &content).await {
+                tracing::warn!("couldn't send a Discord DM to user {}: {:?}", user_id, err);
+            }
+        });
+    }
+}
+
+pub struct WebhookNotifier {
+    webhooks: Arc<Webhooks>
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, notification: &Notification) {
+        let text = notification.contents.to_plain_text();
+        self.webhooks.fire(WebhookEvent::Notification, &notification.user_id, &notification.challenge_id, Some(&text));
+    }
+}
+
+#[cfg(feature = "email")]
+pub struct EmailNotifier {
+    transport: lettre::AsyncSmtpTransport<lettre::Tokio1Executor>,
+    from: lettre::message::Mailbox,
+    database: Database
+}
+
+#[cfg(feature = "email")]
+impl EmailNotifier {
+    pub fn new(config: &crate::config::EmailConfig, database: Database) -> Self {
+        EmailNotifier {
+            transport: lettre::AsyncSmtpTransport::<lettre::Tokio1Executor>::from_url(&config.smtp_url)
+                .expect("the smtp url was validated")
+                .build(),
+            from: config.from.parse().expect("the email sender was validated"),
+            database
+        }
+    }
+
+    async fn send(transport: lettre::AsyncSmtpTransport<lettre::Tokio1Executor>, from: lettre::message::Mailbox, database: Database, notification: Notification) -> anyhow::Result<()> {
+        use lettre::AsyncTransport;
+
+        let Some(to) = database.get_user_preferences(&notification.user_id).await?.email else { return Ok(()) };
+        let subject = match notification.kind {
+            NotificationKind::ExpiryWarning => "Votre instance va bientôt expirer",
+            NotificationKind::DeployFailure => "Le déploiement de votre instance a échoué",
+            NotificationKind::Announcement => "Annonce concernant un défi",
+            NotificationKind::StateChange => "Mise à jour de votre instance"
+        };
+
+        let email = lettre::Message::builder()
+            .from(from)
+            .to(to.parse()?)
+            .subject(subject)
+            .body(notification.contents.to_plain_text())?;
+        transport.send(email).await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "email")]
+impl Notifier for EmailNotifier {
+    fn notify(&self, notification: &Notification) {
+        let (transport, from, database) = (self.transport.clone(), self.from.clone(), self.database.clone());
+        let notification = notification.clone();
+        tokio::spawn(async move {
+            let user_id = notification.user_id.clone();
+            if let Err(err) = EmailNotifier::send(transport, from, database, notification).await {
+                tracing::warn!("couldn't email a notification to user {}: {:?}", user_id, err);
+            }
+        });
+    }
+}
+
+#[cfg(feature = "web-push")]
+pub struct WebPushNotifier {
+    client: Arc<web_push::IsahcWebPushClient>,
+    private_key: Arc<Vec<u8>>,
+    subject: String,
+    database: Database
+}
+
+#[cfg(feature = "web-push")]
+#[derive(Serialize)]
+struct PushPayload<'a> {
+    title: &'a str,
+    body: String
+}
+
+#[cfg(feature = "web-push")]
+impl WebPushNotifier {
+    pub fn new(config: &crate::config::WebPushConfig, database: Database) -> anyhow::Result<Self> {
+        Ok(WebPushNotifier {
+            client: Arc::new(web_push::IsahcWebPushClient::new()?),
+            private_key: Arc::new(std::fs::read(&config.private_key_file)?),
+            subject: config.subject.clone(),
+            database
+        })
+    }
+
+    /// Pushes the notification to every browser of the user, forgetting the subscriptions that push services reject.
+    async fn send(client: &web_push::IsahcWebPushClient, private_key: &[u8], subject: &str, database: &Database, notification: &Notification) -> anyhow::Result<()> {
+        use web_push::{ContentEncoding, SubscriptionInfo, VapidSignatureBuilder, WebPushClient, WebPushError, WebPushMessageBuilder};
+
+        let payload = serde_json::to_vec(&PushPayload { title: "UnitedCTF", body: notification.contents.to_plain_text() })?;
+        for subscription in database.get_push_subscriptions(&notification.user_id).await? {
+            let info = SubscriptionInfo::new(&subscription.endpoint, &subscription.p256dh, &subscription.auth);
+            let mut signature = VapidSignatureBuilder::from_pem(private_key, &info)?;
+            signature.add_claim("sub", subject);
+
+            let mut message = WebPushMessageBuilder::new(&info);
+            message.set_payload(ContentEncoding::Aes128Gcm, &payload);
+            message.set_vapid_signature(signature.build()?);
+
+            match client.send(message.build()?).await {
+                Ok(()) => {}
+                Err(WebPushError::EndpointNotValid | WebPushError::EndpointNotFound) => {
+                    database.delete_push_subscription(&notification.user_id, &subscription.endpoint).await?;
+                }
+                Err(err) => tracing::warn!("couldn't push a notification to user {}: {:?}", notification.user_id, err)
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "web-push")]
+impl Notifier for WebPushNotifier {
+    fn notify(&self, notification: &Notification) {
+        let (client, private_key, subject, database) = (Arc::clone(&self.client), Arc::clone(&self.private_key), self.subject.clone(), self.database.clone());
+        let notification = notification.clone();
+        tokio::spawn(async move {
+            if let Err(err) = WebPushNotifier::send(&client, &private_key, &subject, &database, &notification).await {
+                tracing::warn!("couldn't push a notification to user {}: {:?}", notification.user_id, err);
+            }
+        });
+    }
+}
+
+/// Discord ids are the only ones made up of digits alone, the other providers prefixing theirs.
+fn is_discord_user(user_id: &str) -> bool {
+    !user_id.is_empty() && user_id.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Routes each kind of notification to the channels configured for it.
+///
+/// The dashboard always gets what's routed to it, the other channels honor the preferences of the user.
+pub struct Notifications {
+    routes: NotificationsConfig,
+    notifiers: HashMap<NotificationChannel, Box<dyn Notifier>>,
+    /// The public VAPID key browsers subscribe with, if web push is enabled.
+    web_push_key: Option<String>,
+    database: Database
+}
+
+impl Notifications {
+    pub fn new(config: &InstancerConfig, database: Database, updates: Arc<UpdateHub>, webhooks: Arc<Webhooks>) -> Self {
+        let mut notifiers: HashMap<NotificationChannel, Box<dyn Notifier>> = HashMap::new();
+        notifiers.insert(NotificationChannel::Websocket, Box::new(WebSocketNotifier { updates }));
+        notifiers.insert(NotificationChannel::Webhook, Box::new(WebhookNotifier { webhooks }));
+        if let Some(token) = config.discord.as_ref().and_then(|discord| discord.bot_token.clone()) {
+            notifiers.insert(NotificationChannel::DiscordDm, Box::new(DiscordDmNotifier { bot: DiscordBot::new(token) }));
+        }
+        #[cfg(feature = "email")]
+        if let Some(email) = &config.email {
+            notifiers.insert(NotificationChannel::Email, Box::new(EmailNotifier::new(email, database.clone())));
+        }
+        #[allow(unused_mut)]
+        let mut web_push_key = None;
+        #[cfg(feature = "web-push")]
+        if let Some(web_push) = &config.web_push {
+            match WebPushNotifier::new(web_push, database.clone()) {
+                Ok(notifier) => {
+                    notifiers.insert(NotificationChannel::WebPush, Box::new(notifier));
+                    web_push_key = Some(web_push.public_key.clone());
+                }
+                Err(err) => tracing::error!("web push notifications are disabled, couldn't set them up: {:?}", err)
+            }
+        }
+
+        Notifications {
+            routes: config.notifications.clone(),
+            notifiers,
+            web_push_key,
+            database
+        }
+    }
+
+    /// Whether users may give an address to be emailed at.
+    pub fn emails_enabled(&self) -> bool {
+        #[cfg(feature = "email")]
+        return NotificationKind::ALL.into_iter().any(|kind| self.routes.channels(kind).contains(&NotificationChannel::Email));
+        #[cfg(not(feature = "email"))]
+        false
+    }
+
+    pub fn web_push_key(&self) -> Option<&str> {
+        self.web_push_key.as_deref()
+    }
+
+    pub async fn send(&self, notification: Notification) {
+        let channels = self.routes.channels(notification.kind);
+        let remote = channels.iter().any(|channel| *channel != NotificationChannel::Websocket);
+        let opted_in = remote && self.opted_in(&notification).await;
+
+        for channel in channels {
+            if *channel != NotificationChannel::Websocket && !opted_in { continue; }
+            if let Some(notifier) = self.notifiers.get(channel) {
+                notifier.notify(&notification);
+            }
+        }
+    }
+
+    /// Whether the user wants this kind of notification outside of the dashboard. Only expiry warnings and announcements
+    /// can be opted out of.
+    async fn opted_in(&self, notification: &Notification) -> bool {
+        if matches!(notification.kind, NotificationKind::DeployFailure | NotificationKind::StateChange) { return true; }
+
+        match self.database.get_user_preferences(&notification.user_id).await {
+            Ok(preferences) => match notification.kind {
+                NotificationKind::ExpiryWarning => preferences.notify_expiry,
+                _ => preferences.notify_announcements
+            },
+            Err(err) => {
+                tracing::warn!("couldn't read the preferences of user {}, only notifying the dashboard: {:?}", notification.user_id, err);
+                false
+            }
+        }
+    }
+}
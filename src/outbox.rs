@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time;
+
+use crate::deployment_worker::{DeploymentUpdate, DeploymentUpdateDetails};
+use crate::models::TimeSinceEpoch;
+use crate::InstancerState;
+
+/// How many outbox entries are published per database round trip.
+const BATCH_SIZE: u32 = 100;
+
+/// How often the outbox is checked without being woken up, which retries the entries a failed pass left behind.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long published entries are kept around before being removed.
+const RETENTION: Duration = Duration::from_secs(3600);
+
+/// Publishes the updates that deployments write to the outbox along with the state they announce, then marks them
+/// delivered. Entries written before a crash are published at startup, so an update is sent at least once.
+pub async fn dispatch_updates(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    loop {
+        if let Err(err) = dispatch_pending(&state).await {
+            tracing::warn!("couldn't dispatch the update outbox: {:?}", err);
+        }
+
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => break,
+            _ = state.deployer.outbox.notified() => {},
+            _ = time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+
+    Ok(())
+}
+
+async fn dispatch_pending(state: &InstancerState) -> Result<(), sqlx::Error> {
+    loop {
+        let pending = state.database.get_pending_updates(BATCH_SIZE).await?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let ids = pending.iter().map(|update| update.id).collect::<Vec<_>>();
+        for update in pending {
+            match serde_json::from_str::<DeploymentUpdateDetails>(&update.entry.payload) {
+                Ok(details) => state.deployer.updates.send(DeploymentUpdate {
+                    user_id: update.entry.user_id,
+                    challenge_id: update.entry.challenge_id,
+                    details
+                }),
+                Err(err) => tracing::warn!("dropping unreadable outbox entry {}: {:?}", update.id, err)
+            }
+        }
+
+        let oldest = TimeSinceEpoch(std::time::SystemTime::now() - RETENTION);
+        state.database.mark_updates_delivered(&ids, &oldest).await?;
+    }
+}
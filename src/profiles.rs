@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time;
+
+use crate::discord::DiscordBot;
+use crate::models::TimeSinceEpoch;
+use crate::InstancerState;
+
+/// How many profiles are refreshed per pass, spreading the lookups out so they stay clear of Discord's rate limits.
+const PROFILES_PER_PASS: u32 = 50;
+
+/// Refreshes the stalest Discord profiles at the janitor's interval, so that renamed users and new avatars show up
+/// even for users who stay logged in.
+pub async fn refresh_profiles_periodically(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    let Some(refresh_interval) = state.config.discord.as_ref().and_then(|discord| discord.profile_refresh_interval) else { return Ok(()) };
+    let Some(bot) = &state.discord_bot else { return Ok(()) };
+    let interval = Duration::from_secs(state.config.settings.janitor_interval as u64);
+    let refresh_interval = Duration::from_secs(refresh_interval as u64);
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => break,
+            _ = time::sleep(interval) => {}
+        }
+
+        let before = TimeSinceEpoch(TimeSinceEpoch::now().0 - refresh_interval);
+        let user_ids = match state.database.get_stale_discord_profiles(&before, PROFILES_PER_PASS).await {
+            Ok(user_ids) => user_ids,
+            Err(err) => {
+                tracing::warn!("couldn't list stale profiles: {:?}", err);
+                continue;
+            }
+        };
+
+        for user_id in user_ids {
+            if let Err(err) = refresh_profile(&state, bot, &user_id).await {
+                tracing::warn!("couldn't refresh the profile of user {}: {:?}", user_id, err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn refresh_profile(state: &InstancerState, bot: &DiscordBot, user_id: &str) -> anyhow::Result<()> {
+    let Some(user) = state.database.fetch_user(user_id).await? else { return Ok(()) };
+
+    // Deleted accounts keep their last known profile, which is still marked as refreshed so they don't hog the passes.
+    let (username, display_name, avatar) = match bot.user(user_id).await? {
+        Some(discord_user) => {
+            let display_name = discord_user.global_name.unwrap_or_else(|| discord_user.username.clone());
+            (discord_user.username, display_name, discord_user.avatar)
+        }
+        None => (user.username, user.display_name, user.avatar)
+    };
+
+    state.database.update_user_profile(user_id, &username, &display_name, &avatar).await?;
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time;
+
+use crate::models::TimeSinceEpoch;
+use crate::InstancerState;
+
+/// How long to wait before trying again when the database fails.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Resets user quotas at every configured schedule, catching up on a reset missed while the instancer was down.
+pub async fn reset_on_schedule(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    let schedules = &state.config.quotas.resets;
+    if schedules.is_empty() { return Ok(()) }
+
+    loop {
+        let last_reset = match state.database.get_last_quota_reset().await {
+            Ok(last_reset) => last_reset.unwrap_or_else(TimeSinceEpoch::now),
+            Err(err) => {
+                tracing::warn!("couldn't read when quotas were last reset, retrying: {:?}", err);
+                tokio::select! {
+                    _ = state.shutdown_token.cancelled() => return Ok(()),
+                    _ = time::sleep(RETRY_INTERVAL) => continue
+                }
+            }
+        };
+        let Some(next_reset) = schedules.iter().map(|schedule| schedule.next_after(last_reset.0)).min() else { return Ok(()) };
+
+        let until_reset = next_reset.duration_since(TimeSinceEpoch::now().0).unwrap_or_default();
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => return Ok(()),
+            _ = time::sleep(until_reset) => {}
+        }
+
+        while let Err(err) = state.database.reset_quotas().await {
+            tracing::warn!("couldn't reset user quotas, retrying: {:?}", err);
+            tokio::select! {
+                _ = state.shutdown_token.cancelled() => return Ok(()),
+                _ = time::sleep(RETRY_INTERVAL) => {}
+            }
+        }
+        tracing::info!("user quotas and extension counters were reset");
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use minijinja::context;
+use serde::{Deserialize, Serialize};
+use tokio::time;
+
+use crate::deployment_worker::{DeploymentUpdate, DeploymentUpdateDetails, MessageSeverity};
+use crate::models::{ChallengeInstanceState, EndReason};
+use crate::InstancerState;
+
+pub struct Rctf {
+    url: String,
+    client: reqwest::Client
+}
+
+#[derive(Deserialize, Debug)]
+struct Response<T> {
+    kind: String,
+    data: Option<T>
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginRequest<'a> {
+    team_token: &'a str
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LoginData {
+    auth_token: String
+}
+
+#[derive(Deserialize, Debug)]
+pub struct User {
+    pub id: String,
+    pub name: String
+}
+
+#[derive(Deserialize, Debug)]
+struct PublicUser {
+    solves: Vec<Solve>
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Solve {
+    pub id: String
+}
+
+#[derive(Serialize)]
+struct SubmitRequest<'a> {
+    flag: &'a str
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmissionResult {
+    Correct,
+    Incorrect,
+    AlreadySolved,
+    RateLimited
+}
+
+impl Rctf {
+    pub fn new(url: String) -> Self {
+        Rctf {
+            url: url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new()
+        }
+    }
+
+    /// Exchanges a team token for an auth token, returns None if the team token is invalid.
+    pub async fn login(&self, team_token: &str) -> anyhow::Result<Option<String>> {
+        let response: Response<LoginData> = self.client.post(format!("{}/api/v1/auth/login", self.url))
+            .json(&LoginRequest { team_token })
+            .send().await?
+            .json().await?;
+
+        Ok(match response.kind.as_str() {
+            "goodLogin" => response.data.map(|data| data.auth_token),
+            _ => None
+        })
+    }
+
+    pub async fn current_user(&self, auth_token: &str) -> anyhow::Result<User> {
+        let response: Response<User> = self.client.get(format!("{}/api/v1/users/me", self.url))
+            .header("Authorization", format!("Bearer {}", auth_token))
+            .send().await?
+            .json().await?;
+
+        response.data.ok_or(anyhow!("unexpected rCTF response kind: {}", response.kind))
+    }
+
+    pub async fn user_solves(&self, id: &str) -> anyhow::Result<Vec<Solve>> {
+        let response: Response<PublicUser> = self.client.get(format!("{}/api/v1/users/{}", self.url, id))
+            .send().await?
+            .json().await?;
+
+        response.data
+            .map(|user| user.solves)
+            .ok_or(anyhow!("unexpected rCTF response kind: {}", response.kind))
+    }
+
+    pub async fn submit_flag(&self, auth_token: &str, challenge_id: &str, flag: &str) -> anyhow::Result<SubmissionResult> {
+        let response: Response<serde_json::Value> = self.client.post(format!("{}/api/v1/challs/{}/submit", self.url, challenge_id))
+            .header("Authorization", format!("Bearer {}", auth_token))
+            .json(&SubmitRequest { flag })
+            .send().await?
+            .json().await?;
+
+        match response.kind.as_str() {
+            "goodFlag" => Ok(SubmissionResult::Correct),
+            "badFlag" => Ok(SubmissionResult::Incorrect),
+            "badAlreadySolvedChallenge" => Ok(SubmissionResult::AlreadySolved),
+            "badRateLimit" => Ok(SubmissionResult::RateLimited),
+            kind => Err(anyhow!("unexpected rCTF response kind: {}", kind))
+        }
+    }
+}
+
+/// Periodically pulls the solves of users with running instances and stops the instances of solved challenges.
+pub async fn stop_solved_instances(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    let (Some(rctf), Some(rctf_config)) = (&state.rctf, &state.config.rctf) else { return Ok(()) };
+    if !rctf_config.stop_solved_instances { return Ok(()) };
+
+    while !state.shutdown_token.is_cancelled() {
+        let instances = state.database.get_challenge_instances().await.unwrap_or_else(|err| {
+            tracing::error!("couldn't list instances to stop the solved ones: {:?}", err);
+            Vec::new()
+        });
+        let mut solves_by_user: HashMap<String, Vec<Solve>> = HashMap::new();
+
+        for instance in instances.iter().filter(|instance| instance.state == ChallengeInstanceState::Running) {
+            let Some(challenge) = state.deployer.challenges.get(&instance.challenge_id) else { continue };
+            let Some(scoreboard_id) = &challenge.scoreboard_id else { continue };
+
+            if !solves_by_user.contains_key(&instance.user_id) {
+                let user = match state.database.fetch_user(&instance.user_id).await {
+                    Ok(user) => user,
+                    Err(err) => {
+                        tracing::error!("couldn't fetch user {} to check their rCTF solves: {:?}", instance.user_id, err);
+                        continue;
+                    }
+                };
+                let Some(user_scoreboard_id) = user.and_then(|user| user.scoreboard_id) else { continue };
+                match rctf.user_solves(&user_scoreboard_id).await {
+                    Ok(solves) => { solves_by_user.insert(instance.user_id.clone(), solves); }
+                    Err(err) => {
+                        tracing::warn!("couldn't fetch rCTF solves for user {}: {:?}", instance.user_id, err);
+                        continue;
+                    }
+                }
+            }
+
+            if !solves_by_user[&instance.user_id].iter().any(|solve| &solve.id == scoreboard_id) { continue }
+
+            let queued = state.deployer.queue_stop(&instance.user_id, &instance.challenge_id, EndReason::Solved).await
+                .unwrap_or_else(|err| {
+                    tracing::error!("couldn't stop solved challenge {} for user {}: {:?}", challenge.id, instance.user_id, err);
+                    false
+                });
+            if queued {
+                tracing::info!("stopping solved challenge {} for user {}", challenge.id, instance.user_id);
+
+                let message = DeploymentUpdate {
+                    user_id: instance.user_id.clone(),
+                    challenge_id: instance.challenge_id.clone(),
+                    details: DeploymentUpdateDetails::Message {
+                        contents: state.deployer.messages.render("solved", context! { challenge => challenge.name }),
+                        severity: MessageSeverity::Success
+                    }
+                };
+                state.deployer.updates.send(message);
+            }
+        }
+
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => {},
+            _ = time::sleep(Duration::from_secs(rctf_config.solve_poll_interval as u64)) => {}
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time;
+
+use crate::InstancerState;
+
+/// Repairs the instance counts of users at startup and then at a regular interval. Triggers keep the counts in sync
+/// with the instances, so any drift means rows were edited by hand and is reported.
+pub async fn reconcile_periodically(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    let interval = Duration::from_secs(state.config.settings.reconciliation_interval as u64);
+
+    loop {
+        match state.database.reconcile_instance_counts().await {
+            Ok(drifts) => for drift in drifts {
+                tracing::warn!("repaired the instance count of user {}, which was {} instead of {}", drift.user_id, drift.recorded, drift.actual);
+            },
+            Err(err) => tracing::warn!("couldn't reconcile instance counts: {:?}", err)
+        }
+
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => break,
+            _ = time::sleep(interval) => {}
+        }
+    }
+
+    Ok(())
+}
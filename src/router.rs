@@ -1,62 +1,79 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use anyhow::anyhow;
 use askama::Template;
-use axum::extract::ws::{Message, WebSocket};
-use axum::extract::{Query, State, WebSocketUpgrade};
-use axum::http::StatusCode;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use axum::extract::{Path, Query, State, WebSocketUpgrade};
+use axum::{Form, Json};
+use axum::http::header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Redirect, Response};
 use governor::clock::{Clock, QuantaClock};
+use minijinja::context;
+use once_cell::sync::Lazy;
 use oauth2::reqwest::async_http_client;
 use oauth2::{AuthorizationCode, CsrfToken, Scope, TokenResponse};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{self, Instant};
 use tower_sessions::session::Id;
-use tower_sessions::{Session, SessionStore};
+use tower_sessions::cookie::time::{Duration as CookieDuration, OffsetDateTime};
+use tower_sessions::{Expiry, Session, SessionStore};
 
-use crate::deployment_worker::{DeploymentRequest, DeploymentRequestCommand, DeploymentUpdateDetails, MessageSeverity};
+use crate::abuse::TrackedAction;
+use crate::build_info::{BuildInfo, BUILD_INFO};
+use crate::client_ip::ClientIp;
+use crate::challenge_set::ChallengeSetFormat;
+use crate::config::{AuthProvider, HeartbeatConfig};
+use crate::config_summary::ConfigSummary;
+use crate::deployment_worker::{sha256_hex, DeploymentRequest, DeploymentRequestCommand, DeploymentUpdateDetails, MessageSeverity};
 use crate::discord::Discord;
-use crate::models::{ChallengeInstance, ChallengeInstanceState, TimeSinceEpoch, User};
+use crate::duration::HumanDuration;
+use crate::models::{AuditEntry, ChallengeInstance, ChallengeInstanceState, ChallengeNotice, ChallengeOverride, EndReason, TimeSinceEpoch, User, UserPreferences, UserRole, SUPPORTED_LOCALES};
 use crate::templating::HtmlTemplate;
-use crate::{discord, InstancerState};
-use crate::database::ChallengeInstanceInsertionResult;
-
-#[derive(Template)]
-#[template(path = "error.html")]
-struct ErrorTemplate;
-
-pub struct InternalError(anyhow::Error);
-
-impl IntoResponse for InternalError {
-    fn into_response(self) -> Response {
-        tracing::error!("{:?}", self.0);
-        let error = ErrorTemplate;
-        HtmlTemplate(error).into_response()
-    }
-}
-
-impl<E> From<E> for InternalError
-where
-    E: Into<anyhow::Error>
-{
-    fn from(err: E) -> Self {
-        Self(err.into())
-    }
-}
+use crate::{avatars, bulk_operations, challenge_set, csrf, deploy_logs, discord, event_end, identifiers, local_auth, selftest, InstancerState};
+use crate::bulk_operations::BulkCommand;
+use crate::database::{ChallengeInstanceInsertionResult, InstanceFilter};
+use crate::error::RouterError;
+use crate::auth::{self, CurrentUser};
+use crate::listing::{populate_listing, ChallengePlayerState, InstanceSlots, ListingDelta, TrackedListing};
+use crate::live_deployments::{LiveDeployment, LiveDeploymentEvent};
+use crate::messages::MessageContents;
+use crate::notifications::{Notification, NotificationKind};
+use crate::rctf::SubmissionResult;
+use crate::session_policy::{is_record_past_max_age, LOGIN_TIME_KEY};
+use crate::stats::StatsReport;
+use crate::usage::UsageReport;
+use crate::webhooks::WebhookEvent;
+#[cfg(feature = "ldap")]
+use crate::ldap;
+#[cfg(feature = "web-push")]
+use crate::models::PushSubscription;
 
 #[derive(Template)]
 #[template(path = "dashboard.html")]
 struct DashboardTemplate {
-    avatar_url: String
+    avatar_url: String,
+    csrf_token: String
 }
 
 pub async fn dashboard(
     session: Session,
-    State(_state): State<Arc<InstancerState>>
-) -> Result<Response, InternalError> {
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
     if let Some(uid) = session.get::<String>("uid").await? {
+        if !terms_accepted(&state, &uid).await? {
+            return Ok(Redirect::to("/terms").into_response());
+        }
+
         let dashboard = DashboardTemplate {
-            avatar_url: Discord::avatar_url(&uid, &session.get::<Option<String>>("avatar").await?.unwrap())
+            avatar_url: avatar_url(&state, &uid).await?,
+            csrf_token: csrf::session_token(&session).await?
         };
         Ok(HtmlTemplate(dashboard).into_response())
     } else {
@@ -64,6 +81,244 @@ pub async fn dashboard(
     }
 }
 
+/// The avatar of the user as currently stored, so that refreshed profiles show up in open sessions.
+async fn avatar_url(state: &InstancerState, uid: &str) -> Result<String, RouterError> {
+    let avatar = state.database.fetch_user(uid).await?.and_then(|user| user.avatar);
+    Ok(avatars::local_url(uid, &avatar))
+}
+
+/// Whether the user accepted the current terms, if any are configured.
+async fn terms_accepted(state: &InstancerState, uid: &str) -> Result<bool, sqlx::Error> {
+    let Some(terms) = &state.config.terms else { return Ok(true) };
+    let accepted = state.database.get_accepted_terms_version(uid).await?;
+    Ok(accepted.is_some_and(|version| version >= terms.version))
+}
+
+#[derive(Template)]
+#[template(path = "terms.html")]
+struct TermsTemplate {
+    avatar_url: String,
+    terms: String,
+    version: u32,
+    reaccept: bool,
+    csrf_token: String
+}
+
+pub async fn terms(
+    session: Session,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let Some(terms) = &state.config.terms else {
+        return Ok(Redirect::to("/").into_response());
+    };
+
+    let template = TermsTemplate {
+        avatar_url: avatar_url(&state, &uid).await?,
+        terms: tokio::fs::read_to_string(&terms.file).await?,
+        version: terms.version,
+        reaccept: state.database.get_accepted_terms_version(&uid).await?.is_some(),
+        csrf_token: csrf::session_token(&session).await?
+    };
+    Ok(HtmlTemplate(template).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct TermsAcceptance {
+    version: u32
+}
+
+/// Accepts the terms, unless they were bumped while the user was reading them.
+pub async fn accept_terms(
+    session: Session,
+    ClientIp(ip): ClientIp,
+    State(state): State<Arc<InstancerState>>,
+    Form(acceptance): Form<TermsAcceptance>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let Some(terms) = &state.config.terms else {
+        return Ok(Redirect::to("/").into_response());
+    };
+
+    if acceptance.version != terms.version {
+        return Ok(Redirect::to("/terms").into_response());
+    }
+
+    state.database.accept_terms(&uid, terms.version).await?;
+    audit(&state, &uid, ip, "accept_terms", None).await;
+    Ok(Redirect::to("/").into_response())
+}
+
+#[derive(Serialize)]
+struct CsrfTokenResponse {
+    token: String
+}
+
+/// Hands out the CSRF token of the session, for clients performing POSTs outside of the pages.
+pub async fn csrf_token(
+    session: Session
+) -> Result<Response, RouterError> {
+    if session.get::<String>("uid").await?.is_none() {
+        return Err(RouterError::Unauthorized);
+    }
+
+    Ok(Json(CsrfTokenResponse { token: csrf::session_token(&session).await? }).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "preferences.html")]
+struct PreferencesTemplate {
+    avatar_url: String,
+    csrf_token: String,
+    locales: Vec<(&'static str, bool)>,
+    preferences: UserPreferences,
+    email_notifications: bool,
+    web_push_key: Option<String>,
+    error: Option<&'static str>
+}
+
+pub async fn preferences(
+    session: Session,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let preferences = state.database.get_user_preferences(&uid).await?;
+    preferences_page(&state, &session, &uid, preferences, None).await
+}
+
+async fn preferences_page(state: &InstancerState, session: &Session, uid: &str, preferences: UserPreferences, error: Option<&'static str>) -> Result<Response, RouterError> {
+    let template = PreferencesTemplate {
+        avatar_url: avatar_url(state, uid).await?,
+        csrf_token: csrf::session_token(session).await?,
+        locales: SUPPORTED_LOCALES.iter().map(|&locale| (locale, locale == preferences.locale)).collect(),
+        preferences,
+        email_notifications: state.deployer.notifications.emails_enabled(),
+        web_push_key: state.deployer.notifications.web_push_key().map(str::to_string),
+        error
+    };
+    Ok(HtmlTemplate(template).into_response())
+}
+
+/// Unchecked checkboxes are left out of the form, hence the defaults.
+#[derive(Deserialize)]
+pub struct PreferencesForm {
+    locale: String,
+    timezone: String,
+    #[serde(default)]
+    notify_expiry: bool,
+    #[serde(default)]
+    notify_announcements: bool,
+    #[serde(default)]
+    email: String
+}
+
+pub async fn update_preferences(
+    session: Session,
+    State(state): State<Arc<InstancerState>>,
+    Form(form): Form<PreferencesForm>
+) -> Result<Response, RouterError> {
+    static TIMEZONE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z_]+(/[A-Za-z0-9_+-]+)*$").unwrap());
+
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let timezone = form.timezone.trim();
+    let email = local_auth::normalize_email(&form.email);
+    let preferences = UserPreferences {
+        locale: form.locale,
+        timezone: (!timezone.is_empty()).then(|| timezone.to_string()),
+        notify_expiry: form.notify_expiry,
+        notify_announcements: form.notify_announcements,
+        email: (!email.is_empty()).then_some(email)
+    };
+
+    if !SUPPORTED_LOCALES.contains(&preferences.locale.as_str()) {
+        return preferences_page(&state, &session, &uid, UserPreferences { locale: SUPPORTED_LOCALES[0].to_string(), ..preferences }, Some("La langue est invalide. / The language is invalid.")).await;
+    }
+    if preferences.timezone.as_ref().is_some_and(|timezone| timezone.len() > 64 || !TIMEZONE_RE.is_match(timezone)) {
+        return preferences_page(&state, &session, &uid, preferences, Some("Le fuseau horaire est invalide. / The time zone is invalid.")).await;
+    }
+    if preferences.email.as_ref().is_some_and(|email| !local_auth::is_valid_email(email)) {
+        return preferences_page(&state, &session, &uid, preferences, Some("L'adresse courriel est invalide. / The email address is invalid.")).await;
+    }
+
+    state.database.set_user_preferences(&uid, &preferences).await?;
+    Ok(Redirect::to("/preferences").into_response())
+}
+
+/// Exposes the preferences of the user to API clients.
+pub async fn api_preferences(
+    session: Session,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Err(RouterError::Unauthorized);
+    };
+
+    Ok(Json(state.database.get_user_preferences(&uid).await?).into_response())
+}
+
+/// A push subscription as serialized by browsers.
+#[cfg(feature = "web-push")]
+#[derive(Deserialize)]
+pub struct PushSubscriptionRequest {
+    endpoint: String,
+    keys: Option<PushSubscriptionKeys>
+}
+
+#[cfg(feature = "web-push")]
+#[derive(Deserialize)]
+pub struct PushSubscriptionKeys {
+    p256dh: String,
+    auth: String
+}
+
+/// Subscribes a browser of the user to push notifications.
+#[cfg(feature = "web-push")]
+pub async fn subscribe_push(
+    session: Session,
+    State(state): State<Arc<InstancerState>>,
+    Json(request): Json<PushSubscriptionRequest>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Err(RouterError::Unauthorized);
+    };
+    if state.deployer.notifications.web_push_key().is_none() {
+        return Err(RouterError::NotFound);
+    }
+
+    let Some(keys) = request.keys else { return Ok(StatusCode::BAD_REQUEST.into_response()) };
+    if !request.endpoint.starts_with("https://") || request.endpoint.len() > 1024 || keys.p256dh.len() > 128 || keys.auth.len() > 64 {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let subscription = PushSubscription { endpoint: request.endpoint, p256dh: keys.p256dh, auth: keys.auth };
+    state.database.insert_push_subscription(&uid, &subscription).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(feature = "web-push")]
+pub async fn unsubscribe_push(
+    session: Session,
+    State(state): State<Arc<InstancerState>>,
+    Json(request): Json<PushSubscriptionRequest>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Err(RouterError::Unauthorized);
+    };
+
+    state.database.delete_push_subscription(&uid, &request.endpoint).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
 #[derive(Template)]
 #[template(path = "help.html")]
 struct HelpTemplate {
@@ -72,11 +327,11 @@ struct HelpTemplate {
 
 pub async fn help(
     session: Session,
-    State(_state): State<Arc<InstancerState>>
-) -> Result<Response, InternalError> {
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
     if let Some(uid) = session.get::<String>("uid").await? {
         let help = HelpTemplate {
-            avatar_url: Discord::avatar_url(&uid, &session.get::<Option<String>>("avatar").await?.unwrap())
+            avatar_url: avatar_url(&state, &uid).await?
         };
         Ok(HtmlTemplate(help).into_response())
     } else {
@@ -84,14 +339,44 @@ pub async fn help(
     }
 }
 
-#[derive(Serialize, Debug)]
-pub struct ChallengePlayerState {
-    pub id: String,
-    pub name: String,
-    pub description: Option<String>,
-    pub state: ChallengeInstanceState,
-    pub stop_time: Option<TimeSinceEpoch>,
-    pub details: Option<String>
+pub async fn version() -> Json<&'static BuildInfo> {
+    Json(&BUILD_INFO)
+}
+
+/// Serves the Discord avatar of a user from the local cache, to logged in users only so that it isn't an open proxy.
+pub async fn avatar(
+    session: Session,
+    State(state): State<Arc<InstancerState>>,
+    Path((user_id, avatar_hash)): Path<(String, String)>
+) -> Result<Response, RouterError> {
+    if session.get::<String>("uid").await?.is_none() {
+        return Err(RouterError::Unauthorized);
+    }
+    if !identifiers::is_valid(&user_id) || !avatars::is_valid_hash(&avatar_hash) {
+        return Err(RouterError::NotFound);
+    }
+
+    let avatar = state.avatars.user_avatar(&user_id, &avatar_hash).await.map_err(RouterError::Upstream)?;
+    Ok(avatar_response(avatar))
+}
+
+pub async fn default_avatar(
+    session: Session,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    if session.get::<String>("uid").await?.is_none() {
+        return Err(RouterError::Unauthorized);
+    }
+
+    let avatar = state.avatars.default_avatar().await.map_err(RouterError::Upstream)?;
+    Ok(avatar_response(avatar))
+}
+
+fn avatar_response(avatar: avatars::Avatar) -> Response {
+    ([
+        (CONTENT_TYPE, avatar.content_type),
+        (CACHE_CONTROL, String::from("private, max-age=3600"))
+    ], avatar.bytes).into_response()
 }
 
 #[derive(Debug, Deserialize)]
@@ -107,15 +392,35 @@ enum ChallengeActionCommand {
     Start,
     Stop,
     Restart,
-    Extend
+    Extend,
+    Cancel
+}
+
+impl From<&ChallengeActionCommand> for &str {
+    fn from(value: &ChallengeActionCommand) -> Self {
+        match value {
+            ChallengeActionCommand::Start => "start",
+            ChallengeActionCommand::Stop => "stop",
+            ChallengeActionCommand::Restart => "restart",
+            ChallengeActionCommand::Extend => "extend",
+            ChallengeActionCommand::Cancel => "cancel"
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum ClientBoundMessage {
-    ChallengeListing { challenges: HashMap<String, ChallengePlayerState> },
-    ChallengeStateChange { id: String, state: ChallengeInstanceState, details: Option<String>, stop_time: Option<TimeSinceEpoch> },
-    Message { id: String, contents: String, severity: MessageSeverity },
+    ChallengeListing { challenges: BTreeMap<String, ChallengePlayerState>, slots: InstanceSlots, heartbeat: HeartbeatConfig, resume_token: String },
+    ChallengeListingDelta { changed: BTreeMap<String, ChallengePlayerState>, removed: Vec<String>, slots: InstanceSlots, heartbeat: HeartbeatConfig, resume_token: String },
+    ChallengeStateChange { id: String, state: ChallengeInstanceState, details: Option<String>, stop_time: Option<TimeSinceEpoch>, slots: InstanceSlots },
+    ChallengeNotice { id: String, notice: Option<String> },
+    ChallengeMetadata { id: String, metadata: BTreeMap<String, String> },
+    ChallengePipelineStep { id: String, name: String, index: usize, total: usize },
+    ChallengeProgress { id: String, progress: u8 },
+    ChallengePaused { id: String, paused: bool, stop_time: Option<TimeSinceEpoch> },
+    Message { id: String, contents: MessageContents, severity: MessageSeverity },
+    SessionExpired,
     Heartbeat
 }
 
@@ -138,65 +443,222 @@ impl TryFrom<Message> for ServerBoundMessage {
 
 pub async fn dashboard_ws_handler(
     ws: WebSocketUpgrade,
+    ClientIp(ip): ClientIp,
     Query(params): Query<HashMap<String, String>>,
     State(state): State<Arc<InstancerState>>
-) -> Result<Response, InternalError> {
-    let Some(session_id) = params.get("sid").and_then(|sid: &String| Id::from_str(sid.as_str()).ok()) else {
-        return Ok(StatusCode::UNAUTHORIZED.into_response());
-    };
+) -> Result<Response, RouterError> {
+    #[cfg(feature = "geoip")]
+    check_geo(&state, ip)?;
 
-    let Some(session) = state.session_store.load(&session_id).await? else {
-        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    let Some((session_id, uid)) = ws_session_uid(&state, &params).await? else {
+        return Err(RouterError::Unauthorized);
     };
 
-    let Some(uid): Option<String> = session.data.get("uid").and_then(|val| val.as_str()).map(|s| s.to_string()) else {
-        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    let resume_token = params.get("resume").cloned();
+    Ok(ws.on_upgrade(move |socket| dashboard_handle_ws_guarded(Arc::clone(&state), socket, session_id, uid, ip, resume_token)))
+}
+
+/// Resolves the user of the session passed as the `sid` query parameter of a WS upgrade.
+async fn ws_session_uid(state: &InstancerState, params: &HashMap<String, String>) -> Result<Option<(Id, String)>, RouterError> {
+    let Some(session_id) = params.get("sid").and_then(|sid: &String| Id::from_str(sid.as_str()).ok()) else {
+        return Ok(None);
     };
 
-    Ok(ws.on_upgrade(move |socket| dashboard_handle_ws_unwrap(Arc::clone(&state), socket, uid)))
+    Ok(session_uid(state, &session_id).await?.map(|uid| (session_id, uid)))
 }
 
-pub async fn dashboard_handle_ws_unwrap(state: Arc<InstancerState>, socket: WebSocket, uid: String) {
-    dashboard_handle_ws(state, socket, uid).await.unwrap()
+/// Resolves the user of a session, unless it expired or was invalidated.
+async fn session_uid(state: &InstancerState, session_id: &Id) -> Result<Option<String>, tower_sessions::session_store::Error> {
+    let Some(session) = state.session_store.load(session_id).await? else {
+        return Ok(None);
+    };
+
+    if is_record_past_max_age(&state.config.settings, &session.data) {
+        return Ok(None);
+    }
+
+    Ok(session.data.get("uid").and_then(|val| val.as_str()).map(|s| s.to_string()))
 }
 
-pub async fn dashboard_handle_ws(state: Arc<InstancerState>, mut socket: WebSocket, uid: String) -> anyhow::Result<()> {
-    let request_tx = state.deployer.request_tx.clone();
-    let mut update_rx = state.deployer.update_tx.subscribe();
+/// Runs the WS session, closing the socket with an internal error frame if it fails. The listing the client was left
+/// with is kept for it to resume from when it reconnects.
+pub async fn dashboard_handle_ws_guarded(state: Arc<InstancerState>, mut socket: WebSocket, session_id: Id, uid: String, ip: IpAddr, resume_token: Option<String>) {
+    let mut listing = None;
+    let result = dashboard_handle_ws(Arc::clone(&state), &mut socket, session_id, uid.clone(), ip, resume_token, &mut listing).await;
+    if let Some(listing) = listing {
+        state.listings.store(&uid, listing);
+    }
 
-    let challenge_instances = state.database.get_user_challenge_instances(&uid).await?;
-    let challenges: HashMap<String, ChallengePlayerState> = state.deployer.challenges.iter()
-        .map(|(id, challenge)| {
-            let (state, stop_time, details) = match challenge_instances.iter().filter(|instance| &instance.challenge_id == id).next() {
-                None => (ChallengeInstanceState::Stopped, None, None),
-                Some(instance) => (instance.state.clone(), instance.stop_time.clone(), instance.details.clone())
-            };
+    if let Err(err) = result {
+        tracing::error!("websocket session of user {} failed: {:?}", uid, err);
+
+        let close_frame = CloseFrame {
+            code: close_code::ERROR,
+            reason: "erreur interne".into()
+        };
+        let _ = socket.send(Message::Close(Some(close_frame))).await;
+    }
+}
 
+/// Builds the state of every challenge as seen by the user.
+async fn challenge_listing(state: &InstancerState, uid: &str) -> Result<BTreeMap<String, ChallengePlayerState>, sqlx::Error> {
+    let challenge_instances = state.database.get_user_challenge_instances(uid).await?;
+    let notices = state.database.get_challenge_notices().await?;
+    let metadata = state.database.get_user_instance_metadata(uid).await?;
+    let flag_submission = state.config.features.api_enabled && state.config.rctf.as_ref().is_some_and(|rctf| rctf.flag_submission);
+    let restartable = state.config.features.restart_enabled;
+    let cancellable = state.config.features.cancel_enabled;
+    let mut challenges = state.deployer.challenges.snapshot().iter()
+        .map(|(id, challenge)| {
             let challenge = ChallengePlayerState {
                 id: challenge.id.clone(),
                 name: challenge.name.clone(),
                 description: challenge.description.clone(),
-                stop_time,
-                state,
-                details
+                state: ChallengeInstanceState::Stopped,
+                stop_time: None,
+                details: None,
+                progress: None,
+                paused: false,
+                flag_submission: flag_submission && challenge.scoreboard_id.is_some(),
+                extendable: challenge.extendable,
+                restartable,
+                cancellable,
+                notice: None,
+                metadata: BTreeMap::new()
             };
 
             (id.clone(), challenge)
         })
         .collect();
 
-    let challenge_listing = ClientBoundMessage::ChallengeListing { challenges };
-    let _ = socket.send(challenge_listing.into()).await;
+    populate_listing(&mut challenges, challenge_instances, notices, metadata);
+    Ok(challenges)
+}
+
+/// How many instance slots the user fills out of the number their role may fill at once, for the dashboard to show.
+async fn instance_slots(state: &InstancerState, uid: &str) -> Result<InstanceSlots, sqlx::Error> {
+    let settings = &state.config.settings;
+    Ok(match state.database.fetch_user(uid).await? {
+        Some(user) => {
+            let max = match (&user.role, &state.config.demo) {
+                (UserRole::Demo, Some(demo)) => demo.max_concurrent_challenges,
+                (role, _) => settings.concurrent_challenge_limit(role)
+            };
+            InstanceSlots { used: user.instance_count, max }
+        }
+        None => InstanceSlots { used: 0, max: settings.max_concurrent_challenges }
+    })
+}
+
+/// Sends the state of the dashboard: only what changed if the client resumes a listing it was left with, the full
+/// listing otherwise.
+async fn open_challenge_listing(state: &InstancerState, socket: &mut WebSocket, uid: &str, resume_token: Option<String>) -> Result<TrackedListing, sqlx::Error> {
+    let challenges = challenge_listing(state, uid).await?;
+    let slots = instance_slots(state, uid).await?;
+
+    match resume_token.and_then(|token| state.listings.resume(uid, &token)) {
+        Some(mut listing) => {
+            let delta = listing.update(challenges);
+            send_listing_delta(state, socket, &listing, delta, slots).await;
+            Ok(listing)
+        }
+        None => {
+            let listing = TrackedListing::new(challenges);
+            let message = ClientBoundMessage::ChallengeListing {
+                challenges: listing.challenges().clone(),
+                slots,
+                heartbeat: state.config.heartbeat,
+                resume_token: listing.resume_token.clone()
+            };
+            let _ = socket.send(message.into()).await;
+            Ok(listing)
+        }
+    }
+}
+
+/// Brings the dashboard up to date, sending the entries that changed since the listing the client displays.
+async fn refresh_challenge_listing(state: &InstancerState, socket: &mut WebSocket, uid: &str, listing: &mut TrackedListing) -> Result<(), sqlx::Error> {
+    let delta = listing.update(challenge_listing(state, uid).await?);
+    if !delta.is_empty() {
+        send_listing_delta(state, socket, listing, delta, instance_slots(state, uid).await?).await;
+    }
+    Ok(())
+}
+
+async fn send_listing_delta(state: &InstancerState, socket: &mut WebSocket, listing: &TrackedListing, delta: ListingDelta, slots: InstanceSlots) {
+    let message = ClientBoundMessage::ChallengeListingDelta {
+        changed: delta.changed,
+        removed: delta.removed,
+        slots,
+        heartbeat: state.config.heartbeat,
+        resume_token: listing.resume_token.clone()
+    };
+    let _ = socket.send(message.into()).await;
+}
+
+/// Sends an update to one of the challenges, applying it to the listing the client displays.
+async fn send_tracked(socket: &mut WebSocket, listing: &mut TrackedListing, message: ClientBoundMessage) {
+    match &message {
+        ClientBoundMessage::ChallengeStateChange { id, state, details, stop_time, .. } => listing.apply_state_change(id, state, details.as_ref(), stop_time.as_ref()),
+        ClientBoundMessage::ChallengeMetadata { id, metadata } => listing.apply_metadata(id, metadata),
+        ClientBoundMessage::ChallengeNotice { id, notice } => listing.apply_notice(id, notice.as_ref()),
+        ClientBoundMessage::ChallengeProgress { id, progress } => listing.apply_progress(id, *progress),
+        ClientBoundMessage::ChallengePaused { id, paused, stop_time } => listing.apply_paused(id, *paused, stop_time.as_ref()),
+        _ => {}
+    }
+    let _ = socket.send(message.into()).await;
+}
+
+pub async fn dashboard_handle_ws(state: Arc<InstancerState>, socket: &mut WebSocket, session_id: Id, uid: String, ip: IpAddr, resume_token: Option<String>, listing: &mut Option<TrackedListing>) -> anyhow::Result<()> {
+    let mut update_rx = state.deployer.updates.subscribe(&uid);
+    let mut notice_rx = state.notice_tx.subscribe();
+    let mut challenges_rx = state.deployer.challenges.watch();
+
+    let listing = listing.insert(open_challenge_listing(&state, socket, &uid, resume_token).await?);
+
+    for missed in state.deployer.updates.take_missed(&uid).await? {
+        if let DeploymentUpdateDetails::Message { contents, severity } = missed.details {
+            let message = ClientBoundMessage::Message { id: missed.challenge_id, contents, severity };
+            let _ = socket.send(message.into()).await;
+        }
+    }
+
+    let accepted_terms = terms_accepted(&state, &uid).await?;
+    let demo = state.database.fetch_user(&uid).await?.is_some_and(|user| user.role == UserRole::Demo);
+
+    let liveness_timeout = state.config.heartbeat.liveness_timeout();
+    let mut liveness_deadline = Instant::now() + liveness_timeout;
 
     loop {
         tokio::select! {
             Some(res) = socket.recv() => {
+                liveness_deadline = Instant::now() + liveness_timeout;
                 if state.shutdown_token.is_cancelled() { continue; }
 
                 match res.ok().and_then(|m| ServerBoundMessage::try_from(m).ok()) {
                     Some(msg) => match msg {
+                        ServerBoundMessage::ChallengeAction { .. } if session_uid(&state, &session_id).await?.as_ref() != Some(&uid) => {
+                            let _ = socket.send(ClientBoundMessage::SessionExpired.into()).await;
+
+                            let close_frame = CloseFrame {
+                                code: close_code::POLICY,
+                                reason: "session expirée".into()
+                            };
+                            let _ = socket.send(Message::Close(Some(close_frame))).await;
+                            return Ok(());
+                        }
                         ServerBoundMessage::ChallengeAction { id: cid, action } => match state.deployer.challenges.get(&cid) {
                             Some(challenge) => {
+                                if !accepted_terms {
+                                    let message = ClientBoundMessage::Message {
+                                        id: cid,
+                                        severity: MessageSeverity::Warning,
+                                        contents: state.deployer.messages.render("terms_required", context! {}),
+                                    };
+                                    let _ = socket.send(message.into()).await;
+                                    continue;
+                                }
+
                                 if let Err(not_until) = state.rate_limiter.check_key(&uid) {
                                     let clock = QuantaClock::default();
                                     let duration_until = not_until.wait_time_from(clock.now());
@@ -206,39 +668,119 @@ pub async fn dashboard_handle_ws(state: Arc<InstancerState>, mut socket: WebSock
                                     let message = ClientBoundMessage::Message {
                                         id: challenge.id.clone(),
                                         severity: MessageSeverity::Warning,
-                                        contents: format!("Veuillez attendre {} seconde{} avant votre prochaine action.", seconds_until, if seconds_until == 1.0 { "" } else { "s" }),
+                                        contents: state.deployer.messages.render("rate_limited", context! { seconds => seconds_until as u64 }),
                                     };
                                     let _ = socket.send(message.into()).await;
                                     continue;
                                 }
 
+                                audit(&state, &uid, ip, (&action).into(), Some(&cid)).await;
+
+                                if matches!(action, ChallengeActionCommand::Start | ChallengeActionCommand::Restart) {
+                                    if let Some(cooldown) = state.abuse.as_ref().and_then(|abuse| abuse.cooldown(&uid)) {
+                                        let minutes = cooldown.as_secs().div_ceil(60);
+                                        let message = ClientBoundMessage::Message {
+                                            id: cid,
+                                            severity: MessageSeverity::Warning,
+                                            contents: state.deployer.messages.render("abuse_cooldown", context! { minutes }),
+                                        };
+                                        let _ = socket.send(message.into()).await;
+                                        continue;
+                                    }
+                                }
+
                                 match action {
                                     ChallengeActionCommand::Start => {
+                                        if state.deployer.event_ended.load(Ordering::Relaxed) {
+                                            let message = ClientBoundMessage::Message {
+                                                id: cid,
+                                                severity: MessageSeverity::Warning,
+                                                contents: state.deployer.messages.render("event_ended", context! {}),
+                                            };
+                                            let _ = socket.send(message.into()).await;
+                                            continue;
+                                        }
+
+                                        if !challenge.requires.is_empty() {
+                                            let mut completed = state.database.get_user_challenge_history(&uid).await?;
+                                            if let (Some(rctf), Some(scoreboard_id)) = (&state.rctf, state.database.fetch_user(&uid).await?.and_then(|user| user.scoreboard_id)) {
+                                                match rctf.user_solves(&scoreboard_id).await {
+                                                    Ok(solves) => completed.extend(state.deployer.challenges.snapshot().values()
+                                                        .filter(|c| c.scoreboard_id.as_ref().is_some_and(|id| solves.iter().any(|solve| &solve.id == id)))
+                                                        .map(|c| c.id.clone())),
+                                                    Err(err) => tracing::warn!("couldn't fetch rCTF solves for user {}: {:?}", uid, err)
+                                                }
+                                            }
+
+                                            if let Some(required_id) = challenge.requires.iter().find(|id| !completed.contains(id)) {
+                                                let required_name = state.deployer.challenges.get(required_id).map_or(required_id.clone(), |required| required.name.clone());
+                                                let message = ClientBoundMessage::Message {
+                                                    id: cid,
+                                                    severity: MessageSeverity::Warning,
+                                                    contents: state.deployer.messages.render("challenge_required", context! { challenge => required_name }),
+                                                };
+                                                let _ = socket.send(message.into()).await;
+                                                continue;
+                                            }
+                                        }
+
+                                        if let Some(instance_hours) = state.config.quotas.instance_hours {
+                                            if state.database.get_user_quota_usage(&uid).await? >= i64::from(instance_hours) * 3_600_000 {
+                                                let message = ClientBoundMessage::Message {
+                                                    id: cid,
+                                                    severity: MessageSeverity::Warning,
+                                                    contents: state.deployer.messages.render("quota_exhausted", context! { hours => instance_hours }),
+                                                };
+                                                let _ = socket.send(message.into()).await;
+                                                continue;
+                                            }
+                                        }
+
                                         let instance = ChallengeInstance {
                                             user_id: uid.clone(),
                                             challenge_id: cid.clone(),
                                             state: ChallengeInstanceState::QueuedStart,
                                             stop_time: None,
-                                            details: None
+                                            details: None,
+                                            nonce: ChallengeInstance::generate_nonce(),
+                                            start_time: None,
+                                            extension_count: 0,
+                                            progress: None,
+                                            paused_at: None
                                         };
 
-                                        match state.database.insert_challenge_instance(&instance, state.config.settings.max_concurrent_challenges).await? {
+                                        let max_slots = instance_slots(&state, &uid).await?.max;
+                                        match state.database.insert_challenge_instance(&instance, challenge.weight, max_slots).await? {
                                             ChallengeInstanceInsertionResult::Inserted => {
                                                 let request = DeploymentRequest {
                                                     user_id: uid.clone(),
                                                     challenge_id: cid.clone(),
-                                                    command: DeploymentRequestCommand::Start
+                                                    command: DeploymentRequestCommand::Start { retry: false },
+                                                    queued_at: Instant::now()
                                                 };
-                                                request_tx.send(request).await?;
+                                                if !state.deployer.try_enqueue(request) {
+                                                    state.database.delete_queued_challenge_instance(&uid, &cid).await?;
+                                                    let message = ClientBoundMessage::Message {
+                                                        id: cid,
+                                                        severity: MessageSeverity::Warning,
+                                                        contents: state.deployer.messages.render("overloaded", context! {}),
+                                                    };
+                                                    let _ = socket.send(message.into()).await;
+                                                    continue;
+                                                }
+                                                let throttled = track_abuse(&state, &uid, &cid, TrackedAction::StartStop);
 
-                                                let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: cid, state: ChallengeInstanceState::QueuedStart, details: None, stop_time: None};
-                                                let _ = socket.send(challenge_state_change.into()).await;
+                                                let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: cid, state: ChallengeInstanceState::QueuedStart, details: None, stop_time: None, slots: instance_slots(&state, &uid).await? };
+                                                send_tracked(socket, listing, challenge_state_change).await;
+                                                if let Some(message) = throttled {
+                                                    let _ = socket.send(message.into()).await;
+                                                }
                                             }
                                             ChallengeInstanceInsertionResult::LimitReached => {
                                                 let message = ClientBoundMessage::Message {
                                                     id: cid,
                                                     severity: MessageSeverity::Warning,
-                                                    contents: format!("Vous avez atteint la limite de {} défis concurrents.", state.config.settings.max_concurrent_challenges),
+                                                    contents: state.deployer.messages.render("concurrent_limit", context! { limit => max_slots, weight => challenge.weight }),
                                                 };
                                                 let _ = socket.send(message.into()).await;
                                             }
@@ -246,44 +788,137 @@ pub async fn dashboard_handle_ws(state: Arc<InstancerState>, mut socket: WebSock
                                         }
                                     }
                                     ChallengeActionCommand::Stop => {
-                                        if state.database.transition_challenge_instance_state(&uid, &cid, ChallengeInstanceState::Running, ChallengeInstanceState::QueuedStop).await? {
+                                        let mut stopped_from = None;
+                                        for from in [ChallengeInstanceState::Running, ChallengeInstanceState::Expiring] {
+                                            if state.database.transition_challenge_instance_state(&uid, &cid, from.clone(), ChallengeInstanceState::QueuedStop).await? {
+                                                stopped_from = Some(from);
+                                                break;
+                                            }
+                                        }
+
+                                        if let Some(from) = stopped_from {
                                             let request = DeploymentRequest {
                                                 user_id: uid.clone(),
                                                 challenge_id: cid.clone(),
-                                                command: DeploymentRequestCommand::Stop
+                                                command: DeploymentRequestCommand::Stop,
+                                                queued_at: Instant::now()
                                             };
-                                            request_tx.send(request).await?;
+                                            if !state.deployer.try_enqueue(request) {
+                                                state.database.transition_challenge_instance_state(&uid, &cid, ChallengeInstanceState::QueuedStop, from).await?;
+                                                let message = ClientBoundMessage::Message {
+                                                    id: cid,
+                                                    severity: MessageSeverity::Warning,
+                                                    contents: state.deployer.messages.render("overloaded", context! {}),
+                                                };
+                                                let _ = socket.send(message.into()).await;
+                                                continue;
+                                            }
+                                            let throttled = track_abuse(&state, &uid, &cid, TrackedAction::StartStop);
 
-                                            let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: cid, state: ChallengeInstanceState::QueuedStop, details: None, stop_time: None};
-                                            let _ = socket.send(challenge_state_change.into()).await;
+                                            let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: cid, state: ChallengeInstanceState::QueuedStop, details: None, stop_time: None, slots: instance_slots(&state, &uid).await? };
+                                            send_tracked(socket, listing, challenge_state_change).await;
+                                            if let Some(message) = throttled {
+                                                let _ = socket.send(message.into()).await;
+                                            }
                                         }
                                     }
                                     ChallengeActionCommand::Restart => {
+                                        if !state.config.features.restart_enabled {
+                                            let message = ClientBoundMessage::Message {
+                                                id: cid,
+                                                severity: MessageSeverity::Warning,
+                                                contents: state.deployer.messages.render("restart_disabled", context! {}),
+                                            };
+                                            let _ = socket.send(message.into()).await;
+                                            continue;
+                                        }
+
                                         if state.database.transition_challenge_instance_state(&uid, &cid, ChallengeInstanceState::Running, ChallengeInstanceState::QueuedRestart).await? {
                                             let request = DeploymentRequest {
                                                 user_id: uid.clone(),
                                                 challenge_id: cid.clone(),
-                                                command: DeploymentRequestCommand::Restart
+                                                command: DeploymentRequestCommand::Restart,
+                                                queued_at: Instant::now()
                                             };
-                                            request_tx.send(request).await?;
+                                            if !state.deployer.try_enqueue(request) {
+                                                state.database.transition_challenge_instance_state(&uid, &cid, ChallengeInstanceState::QueuedRestart, ChallengeInstanceState::Running).await?;
+                                                let message = ClientBoundMessage::Message {
+                                                    id: cid,
+                                                    severity: MessageSeverity::Warning,
+                                                    contents: state.deployer.messages.render("overloaded", context! {}),
+                                                };
+                                                let _ = socket.send(message.into()).await;
+                                                continue;
+                                            }
+                                            let throttled = track_abuse(&state, &uid, &cid, TrackedAction::Restart);
 
-                                            let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: cid, state: ChallengeInstanceState::QueuedRestart, details: None, stop_time: None};
-                                            let _ = socket.send(challenge_state_change.into()).await;
+                                            let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: cid, state: ChallengeInstanceState::QueuedRestart, details: None, stop_time: None, slots: instance_slots(&state, &uid).await? };
+                                            send_tracked(socket, listing, challenge_state_change).await;
+                                            if let Some(message) = throttled {
+                                                let _ = socket.send(message.into()).await;
+                                            }
                                         }
                                     }
                                     ChallengeActionCommand::Extend => {
-                                        let stop_time = TimeSinceEpoch::from_now(challenge.ttl_duration());
+                                        if !challenge.extendable || demo {
+                                            let message = ClientBoundMessage::Message {
+                                                id: cid,
+                                                severity: MessageSeverity::Warning,
+                                                contents: state.deployer.messages.render("not_extendable", context! { challenge => challenge.name }),
+                                            };
+                                            let _ = socket.send(message.into()).await;
+                                            continue;
+                                        }
 
-                                        if state.database.extend_challenge_instance(&uid, &cid, stop_time.clone()).await? {
+                                        let stop_time = TimeSinceEpoch::from_now(challenge.extension_duration());
+
+                                        if state.database.use_challenge_instance_extension(&uid, &cid, stop_time.clone(), challenge.max_extensions).await? {
                                             state.deployer.push_ttl(uid.clone(), cid.clone(), stop_time.clone()).await;
 
-                                            let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: cid.clone(), state: ChallengeInstanceState::Running, details: None, stop_time: Some(stop_time) };
-                                            let _ = socket.send(challenge_state_change.into()).await;
+                                            let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: cid.clone(), state: ChallengeInstanceState::Running, details: None, stop_time: Some(stop_time), slots: instance_slots(&state, &uid).await? };
+                                            send_tracked(socket, listing, challenge_state_change).await;
 
                                             let message = ClientBoundMessage::Message {
                                                 id: cid,
                                                 severity: MessageSeverity::Success,
-                                                contents: format!("Le défi <strong>{}</strong> a été étendu.", challenge.name),
+                                                contents: state.deployer.messages.render("extended", context! { challenge => challenge.name }),
+                                            };
+                                            let _ = socket.send(message.into()).await;
+                                        } else if let Some(max_extensions) = challenge.max_extensions {
+                                            let limit_reached = state.database.get_challenge_instance(&uid, &cid).await?
+                                                .is_some_and(|instance| instance.extension_count >= max_extensions as i64);
+
+                                            if limit_reached {
+                                                let message = ClientBoundMessage::Message {
+                                                    id: cid,
+                                                    severity: MessageSeverity::Warning,
+                                                    contents: state.deployer.messages.render("extension_limit", context! { challenge => challenge.name, max_extensions }),
+                                                };
+                                                let _ = socket.send(message.into()).await;
+                                            }
+                                        }
+                                    }
+                                    ChallengeActionCommand::Cancel => {
+                                        if !state.config.features.cancel_enabled {
+                                            let message = ClientBoundMessage::Message {
+                                                id: cid,
+                                                severity: MessageSeverity::Warning,
+                                                contents: state.deployer.messages.render("cancel_disabled", context! {}),
+                                            };
+                                            let _ = socket.send(message.into()).await;
+                                            continue;
+                                        }
+
+                                        /* the worker reports the cancellation once the deployer is killed */
+                                        let cancelled = state.deployer.live.find(&cid, &uid)
+                                            .filter(|deployment| matches!(deployment.command, "start" | "restart"))
+                                            .and_then(|deployment| state.deployer.live.cancel(deployment.id));
+
+                                        if cancelled.is_none() {
+                                            let message = ClientBoundMessage::Message {
+                                                id: cid,
+                                                severity: MessageSeverity::Warning,
+                                                contents: state.deployer.messages.render("not_cancellable", context! { challenge => challenge.name }),
                                             };
                                             let _ = socket.send(message.into()).await;
                                         }
@@ -294,30 +929,956 @@ pub async fn dashboard_handle_ws(state: Arc<InstancerState>, mut socket: WebSock
                         },
                         ServerBoundMessage::Heartbeat => {
                             let _ = socket.send(ClientBoundMessage::Heartbeat.into()).await;
+
+                            if demo { continue; }
+                            for (cid, stop_time) in state.deployer.extend_active_instances(&uid).await? {
+                                let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: cid, state: ChallengeInstanceState::Running, details: None, stop_time: Some(stop_time), slots: instance_slots(&state, &uid).await? };
+                                send_tracked(socket, listing, challenge_state_change).await;
+                            }
                         }
                     },
                     None => return Ok(()) /* received invalid message, close connection */
                 }
             }
-            Ok(update) = update_rx.recv() => {
-                if update.user_id != uid { continue; }
+            update = update_rx.recv() => {
+                let update = match update {
+                    Ok(update) => update,
+                    Err(RecvError::Lagged(skipped)) => {
+                        /* updates were missed, resync the client with what changed since */
+                        tracing::warn!("websocket of user {} lagged behind by {} updates, refreshing the listing", uid, skipped);
+                        refresh_challenge_listing(&state, socket, &uid, listing).await?;
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return Ok(()) /* the deployment worker is down */
+                };
 
                 match update.details {
-                    DeploymentUpdateDetails::StateChange { state, details, stop_time } => {
-                        let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: update.challenge_id, state, details, stop_time };
-                        let _ = socket.send(challenge_state_change.into()).await;
+                    DeploymentUpdateDetails::StateChange { state: instance_state, details, stop_time } => {
+                        let slots = instance_slots(&state, &uid).await?;
+                        let challenge_state_change = ClientBoundMessage::ChallengeStateChange { id: update.challenge_id, state: instance_state, details, stop_time, slots };
+                        send_tracked(socket, listing, challenge_state_change).await;
                     }
-                    DeploymentUpdateDetails::Message { contents, severity } => {
-                        let message = ClientBoundMessage::Message { id: update.challenge_id, contents, severity };
-                        let _ = socket.send(message.into()).await;
+                    DeploymentUpdateDetails::Metadata { metadata } => {
+                        let challenge_metadata = ClientBoundMessage::ChallengeMetadata { id: update.challenge_id, metadata };
+                        send_tracked(socket, listing, challenge_metadata).await;
+                    }
+                    DeploymentUpdateDetails::PipelineStep { name, index, total } => {
+                        let pipeline_step = ClientBoundMessage::ChallengePipelineStep { id: update.challenge_id, name, index, total };
+                        let _ = socket.send(pipeline_step.into()).await;
+                    }
+                    DeploymentUpdateDetails::Progress { progress } => {
+                        let challenge_progress = ClientBoundMessage::ChallengeProgress { id: update.challenge_id, progress };
+                        send_tracked(socket, listing, challenge_progress).await;
+                    }
+                    DeploymentUpdateDetails::Paused { paused, stop_time } => {
+                        let challenge_paused = ClientBoundMessage::ChallengePaused { id: update.challenge_id, paused, stop_time };
+                        send_tracked(socket, listing, challenge_paused).await;
+                    }
+                    DeploymentUpdateDetails::Message { contents, severity } => {
+                        let message = ClientBoundMessage::Message { id: update.challenge_id, contents, severity };
+                        let _ = socket.send(message.into()).await;
                     }
                 }
             },
+            notice = notice_rx.recv() => {
+                let notice = match notice {
+                    Ok(notice) => notice,
+                    Err(RecvError::Lagged(_)) => {
+                        refresh_challenge_listing(&state, socket, &uid, listing).await?;
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return Ok(())
+                };
+                let challenge_notice = ClientBoundMessage::ChallengeNotice { id: notice.challenge_id, notice: notice.contents };
+                send_tracked(socket, listing, challenge_notice).await;
+            },
+            Ok(()) = challenges_rx.changed() => {
+                /* challenges were redefined */
+                refresh_challenge_listing(&state, socket, &uid, listing).await?;
+            },
+            _ = time::sleep_until(liveness_deadline) => {
+                tracing::debug!("closing silent websocket of user {}", uid);
+                let close_frame = CloseFrame {
+                    code: close_code::AWAY,
+                    reason: "aucun signe de vie".into()
+                };
+                let _ = socket.send(Message::Close(Some(close_frame))).await;
+                return Ok(());
+            },
             else => return Ok(()) /* socket has closed or update sender has closed, indicating that the deployment worker is down */
         }
     }
 }
 
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum LiveDeploymentsMessage {
+    Snapshot { deployments: Vec<LiveDeployment> }
+}
+
+/// Streams the deploy operations currently executing to admins.
+pub async fn admin_deployments_ws_handler(
+    ws: WebSocketUpgrade,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some((_, uid)) = ws_session_uid(&state, &params).await? else {
+        return Err(RouterError::Unauthorized);
+    };
+
+    match state.database.fetch_user(&uid).await? {
+        Some(user) if user.role == UserRole::Admin => {}
+        Some(_) => return Err(RouterError::Forbidden),
+        None => return Err(RouterError::Unauthorized)
+    }
+
+    Ok(ws.on_upgrade(move |socket| admin_handle_deployments_ws(state, socket)))
+}
+
+async fn admin_handle_deployments_ws(state: Arc<InstancerState>, mut socket: WebSocket) {
+    let (deployments, mut event_rx) = state.deployer.live.subscribe();
+    let snapshot = LiveDeploymentsMessage::Snapshot { deployments };
+    if socket.send(Message::Text(serde_json::to_string(&snapshot).unwrap())).await.is_err() { return; }
+
+    loop {
+        tokio::select! {
+            msg = socket.recv() => match msg {
+                Some(Ok(_)) => continue,
+                _ => return
+            },
+            event = event_rx.recv() => {
+                let msg = match event {
+                    Ok(event) => serde_json::to_string(&event).unwrap(),
+                    Err(RecvError::Lagged(_)) => {
+                        /* events were missed, start over from a fresh snapshot */
+                        let (deployments, new_event_rx) = state.deployer.live.subscribe();
+                        event_rx = new_event_rx;
+                        serde_json::to_string(&LiveDeploymentsMessage::Snapshot { deployments }).unwrap()
+                    }
+                    Err(RecvError::Closed) => return
+                };
+                if socket.send(Message::Text(msg)).await.is_err() { return; }
+            }
+        }
+    }
+}
+
+/// Streams the deploy log of an instance to the authors of its challenge: its last `lines` lines (100 by default), then the
+/// output of its deployments as they run.
+pub async fn admin_instance_logs_ws_handler(
+    ws: WebSocketUpgrade,
+    Path((challenge_id, user_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some((_, uid)) = ws_session_uid(&state, &params).await? else {
+        return Err(RouterError::Unauthorized);
+    };
+
+    let Some(user) = state.database.fetch_user(&uid).await? else {
+        return Err(RouterError::Unauthorized);
+    };
+    if !auth::owns_challenge(&user, state.deployer.challenges.get(&challenge_id).as_deref()) {
+        return Err(RouterError::Forbidden);
+    }
+
+    let Some(dir) = &state.config.settings.deploy_log_dir else { return Err(RouterError::NotFound) };
+    let path = deploy_logs::log_path(dir, &challenge_id, &user_id).ok_or(RouterError::NotFound)?;
+    let lines = params.get("lines").and_then(|lines| lines.parse().ok()).unwrap_or(100).clamp(1, 1000);
+
+    Ok(ws.on_upgrade(move |socket| admin_handle_instance_logs_ws(state, socket, challenge_id, user_id, path, lines)))
+}
+
+async fn admin_handle_instance_logs_ws(state: Arc<InstancerState>, mut socket: WebSocket, challenge_id: String, user_id: String, path: PathBuf, lines: usize) {
+    let follow = |deployments: Vec<LiveDeployment>| deployments.into_iter()
+        .filter(|deployment| deployment.challenge_id == challenge_id && deployment.user_id == user_id)
+        .map(|deployment| deployment.id)
+        .collect::<HashSet<u64>>();
+
+    /* subscribing before reading the log may repeat a few lines, but doesn't skip any */
+    let (deployments, mut event_rx) = state.deployer.live.subscribe();
+    let mut followed = follow(deployments);
+
+    let tail = deploy_logs::tail(&path, lines).await.unwrap_or_else(|err| {
+        tracing::warn!("couldn't read deploy log {}: {:?}", path.display(), err);
+        Vec::new()
+    });
+    for line in tail {
+        if socket.send(Message::Text(line)).await.is_err() { return; }
+    }
+
+    loop {
+        tokio::select! {
+            msg = socket.recv() => match msg {
+                Some(Ok(_)) => continue,
+                _ => return
+            },
+            event = event_rx.recv() => {
+                let line = match event {
+                    Ok(LiveDeploymentEvent::Started { deployment }) if deployment.challenge_id == challenge_id && deployment.user_id == user_id => {
+                        followed.insert(deployment.id);
+                        deploy_logs::header(&deployment)
+                    }
+                    Ok(LiveDeploymentEvent::Output { id, line }) if followed.contains(&id) => line,
+                    Ok(LiveDeploymentEvent::Finished { id, success }) if followed.remove(&id) => deploy_logs::footer(success),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(_)) => {
+                        let (deployments, new_event_rx) = state.deployer.live.subscribe();
+                        event_rx = new_event_rx;
+                        followed = follow(deployments);
+                        String::from("=== output missed ===")
+                    }
+                    Err(RecvError::Closed) => return
+                };
+                if socket.send(Message::Text(line)).await.is_err() { return; }
+            }
+        }
+    }
+}
+
+/// Summarizes the configuration this instance was launched with, secrets excluded.
+pub async fn admin_config(
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    Ok(Json(ConfigSummary::new(&state.config, &state.deployer)).into_response())
+}
+
+/// Lists the most recent audit entries, filtered by the `user` and `ip` query parameters.
+pub async fn admin_audit(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let entries = state.database.get_audit_entries(params.get("user").map(String::as_str), params.get("ip").map(String::as_str), 500).await?;
+    Ok(Json(entries).into_response())
+}
+
+/// Lists the most recent starts moved to another target after an infrastructure failure, filtered by the `challenge`
+/// query parameter.
+pub async fn admin_failovers(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let failovers = state.database.get_target_failovers(params.get("challenge").map(String::as_str), 500).await?;
+    Ok(Json(failovers).into_response())
+}
+
+/// Self-tests the deployers again, e.g. once the infrastructure they rely on was fixed, reporting which challenges are
+/// ready to be deployed.
+pub async fn admin_selftest(
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let report = selftest::run(&state.config, &state.deployer).await;
+    report.log();
+    Ok(Json(report).into_response())
+}
+
+/// Lists the most recent instances, live and ended, filtered by the `user` and `challenge` query parameters.
+pub async fn admin_instance_history(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let history = state.database.get_instance_history(params.get("user").map(String::as_str), params.get("challenge").map(String::as_str), 500).await?;
+    Ok(Json(history).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InstanceSearch {
+    challenge: Option<String>,
+    user: Option<String>,
+    state: Option<ChallengeInstanceState>,
+    stop_after: Option<i64>,
+    stop_before: Option<i64>,
+    /// Either `key`, matching any value, or `key=value`.
+    label: Option<String>,
+    #[serde(default)]
+    page: u32,
+    per_page: Option<u32>
+}
+
+#[derive(Serialize)]
+struct LabeledInstance {
+    #[serde(flatten)]
+    instance: ChallengeInstance,
+    labels: BTreeMap<String, String>
+}
+
+#[derive(Serialize)]
+struct InstancePage {
+    instances: Vec<LabeledInstance>,
+    total: i64,
+    page: u32,
+    per_page: u32
+}
+
+/// Searches instances by challenge, user, state, stop time (milliseconds since the epoch) and label, one page at a time.
+pub async fn admin_instances(
+    Query(search): Query<InstanceSearch>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let per_page = search.per_page.unwrap_or(100).clamp(1, 1000);
+    let (label_key, label_value) = match search.label.as_deref().map(|label| label.split_once('=')) {
+        Some(Some((key, value))) => (Some(key), Some(value)),
+        Some(None) => (search.label.as_deref(), None),
+        None => (None, None)
+    };
+    let filter = InstanceFilter {
+        challenge_id: search.challenge.as_deref(),
+        user_id: search.user.as_deref(),
+        state: search.state.as_ref(),
+        stop_after: search.stop_after.map(TimeSinceEpoch::from),
+        stop_before: search.stop_before.map(TimeSinceEpoch::from),
+        label_key,
+        label_value
+    };
+
+    let (instances, total) = state.database.search_challenge_instances(&filter, per_page, search.page.saturating_mul(per_page)).await?;
+
+    let mut labels: HashMap<(String, String), BTreeMap<String, String>> = HashMap::new();
+    for label in state.database.search_instance_labels(filter.user_id, filter.challenge_id).await? {
+        labels.entry((label.user_id, label.challenge_id)).or_default().insert(label.key, label.value);
+    }
+    let instances = instances.into_iter()
+        .map(|instance| LabeledInstance {
+            labels: labels.remove(&(instance.user_id.clone(), instance.challenge_id.clone())).unwrap_or_default(),
+            instance
+        })
+        .collect();
+
+    Ok(Json(InstancePage { instances, total, page: search.page, per_page }).into_response())
+}
+
+/// Reports the instance-minutes used so far, per challenge and per user.
+pub async fn admin_usage(
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    state.database.accrue_instance_usage().await?;
+    let usage = state.database.get_instance_usage().await?;
+    Ok(Json(UsageReport::new(&usage)).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "admin_stats.html")]
+struct AdminStatsTemplate {
+    avatar_url: String,
+    stats: StatsReport
+}
+
+/// Charts instances over time, failures per challenge, the busiest hours and the users running the most instances.
+pub async fn admin_stats(
+    session: Session,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Err(RouterError::Unauthorized);
+    };
+
+    state.database.accrue_instance_usage().await?;
+    let starts = state.database.get_hourly_starts().await?;
+    let outcomes = state.database.get_challenge_outcomes().await?;
+    let usage = state.database.get_instance_usage().await?;
+
+    let page = AdminStatsTemplate {
+        avatar_url: avatar_url(&state, &uid).await?,
+        stats: StatsReport::new(&starts, &outcomes, &usage)
+    };
+    Ok(HtmlTemplate(page).into_response())
+}
+
+/// Rolling p50/p95 of the time requests wait for a worker and of the time starts take, per challenge.
+pub async fn admin_latency(
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    Ok(Json(state.deployer.latency.report()).into_response())
+}
+
+/// The deployment percentiles and queue length, in the Prometheus text format.
+pub async fn admin_metrics(
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let mut metrics = state.deployer.latency.prometheus();
+    metrics.push_str("# HELP instancer_queue_length Deployment requests waiting for a worker.\n");
+    metrics.push_str("# TYPE instancer_queue_length gauge\n");
+    metrics.push_str(&format!("instancer_queue_length {}\n", state.deployer.queue_len()));
+    metrics.push_str("# HELP instancer_rejected_requests_total User requests rejected because the deployment queue was full.\n");
+    metrics.push_str("# TYPE instancer_rejected_requests_total counter\n");
+    metrics.push_str(&format!("instancer_rejected_requests_total {}\n", state.deployer.rejected_requests()));
+    Ok(([(CONTENT_TYPE, "text/plain; version=0.0.4")], metrics).into_response())
+}
+
+/// Rejects clients excluded by the geo restrictions.
+#[cfg(feature = "geoip")]
+fn check_geo(state: &InstancerState, ip: IpAddr) -> Result<(), RouterError> {
+    match &state.geo {
+        Some(geo) if !geo.allows(ip) => Err(RouterError::GeoBlocked),
+        _ => Ok(())
+    }
+}
+
+/// Reports how many stuck instances the janitor repaired, and the ones it gave up on.
+pub async fn admin_janitor(
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let report = state.janitor.lock().unwrap().clone();
+    Ok(Json(report).into_response())
+}
+
+/// Records an action in the audit log. Failures are only logged so that auditing never blocks players.
+async fn audit(state: &InstancerState, uid: &str, ip: IpAddr, action: &str, cid: Option<&str>) {
+    let entry = AuditEntry {
+        time: TimeSinceEpoch::now(),
+        user_id: uid.to_string(),
+        ip: ip.to_string(),
+        action: action.to_string(),
+        challenge_id: cid.map(|cid| cid.to_string())
+    };
+
+    if let Err(err) = state.database.insert_audit_entry(&entry).await {
+        tracing::warn!("couldn't record {} of user {} in the audit log: {:?}", action, uid, err);
+    }
+}
+
+/// Records a queued action towards the abuse heuristics, alerting admins and returning a notice for the user if it got them throttled.
+fn track_abuse(state: &InstancerState, uid: &str, cid: &str, action: TrackedAction) -> Option<ClientBoundMessage> {
+    let abuse = state.abuse.as_ref()?;
+    let reason = abuse.record(uid, action)?;
+
+    tracing::warn!("throttling user {} for suspicious activity: {}", uid, reason);
+    state.deployer.webhooks.fire(WebhookEvent::Throttled, uid, cid, Some(&reason));
+
+    let minutes = abuse.cooldown(uid).unwrap_or_default().as_secs().div_ceil(60);
+    Some(ClientBoundMessage::Message {
+        id: cid.to_string(),
+        severity: MessageSeverity::Warning,
+        contents: state.deployer.messages.render("throttled", context! { minutes }),
+    })
+}
+
+#[derive(Serialize, Debug)]
+struct EventStatus {
+    ended: bool,
+    remaining_instances: usize
+}
+
+pub async fn admin_event_status(
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let status = EventStatus {
+        ended: state.deployer.event_ended.load(Ordering::Relaxed),
+        remaining_instances: state.database.get_challenge_instances().await?.len()
+    };
+    Ok(Json(status).into_response())
+}
+
+/// Ends the event immediately: new starts are refused and every instance is stopped in the background.
+pub async fn admin_end_event(
+    CurrentUser(admin): CurrentUser,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    tracing::info!("event end requested by admin {}", admin.id);
+
+    tokio::spawn(async move {
+        if let Err(err) = event_end::end_event(Arc::clone(&state)).await {
+            tracing::error!("couldn't end event: {:?}", err);
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkOperationRequest {
+    command: BulkCommand
+}
+
+/// Stops, restarts or cleans up every instance of a challenge, e.g. after it was patched.
+pub async fn admin_bulk_operation(
+    CurrentUser(admin): CurrentUser,
+    Path(challenge_id): Path<String>,
+    State(state): State<Arc<InstancerState>>,
+    Json(request): Json<BulkOperationRequest>
+) -> Result<Response, RouterError> {
+    if !state.deployer.challenges.contains_key(&challenge_id) {
+        return Err(RouterError::NotFound);
+    }
+
+    tracing::info!("bulk {:?} of challenge {} requested by admin {}", request.command, challenge_id, admin.id);
+    let operation = bulk_operations::start(Arc::clone(&state), challenge_id, request.command).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(operation)).into_response())
+}
+
+/// Aborts a deployment in progress, whose instance is then cleaned up.
+pub async fn admin_cancel_deployment(
+    CurrentUser(admin): CurrentUser,
+    Path(deployment_id): Path<u64>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let deployment = state.deployer.live.cancel(deployment_id).ok_or(RouterError::NotFound)?;
+    tracing::info!("{} of challenge {} for user {} cancelled by admin {}", deployment.command, deployment.challenge_id, deployment.user_id, admin.id);
+
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+/// Moves a running instance to another of the targets of its challenge, e.g. to drain a failing host. The player is
+/// told once it is back up, along with its new connection details.
+pub async fn admin_migrate_instance(
+    CurrentUser(admin): CurrentUser,
+    Path((challenge_id, user_id)): Path<(String, String)>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    if state.deployer.challenges.get(&challenge_id).is_none_or(|challenge| challenge.targets.len() <= 1) {
+        return Err(RouterError::NotFound);
+    }
+    if !state.deployer.queue_migrate(&user_id, &challenge_id).await? {
+        return Err(RouterError::NotFound);
+    }
+    tracing::info!("migration of challenge {} for user {} queued by admin {}", challenge_id, user_id, admin.id);
+
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+/// Freezes the TTL of an instance until it is resumed, e.g. while its infrastructure is being looked into.
+pub async fn admin_pause_instance(
+    CurrentUser(admin): CurrentUser,
+    Path((challenge_id, user_id)): Path<(String, String)>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    if !state.deployer.pause_instance(&user_id, &challenge_id).await? {
+        return Err(RouterError::NotFound);
+    }
+    tracing::info!("TTL of challenge {} for user {} paused by admin {}", challenge_id, user_id, admin.id);
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Serialize, Debug)]
+struct ResumedInstance {
+    stop_time: TimeSinceEpoch
+}
+
+/// Restarts the TTL of a paused instance, its stop time pushed back by as long as it was paused.
+pub async fn admin_resume_instance(
+    CurrentUser(admin): CurrentUser,
+    Path((challenge_id, user_id)): Path<(String, String)>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some(stop_time) = state.deployer.resume_instance(&user_id, &challenge_id).await? else {
+        return Err(RouterError::NotFound);
+    };
+    tracing::info!("TTL of challenge {} for user {} resumed by admin {}", challenge_id, user_id, admin.id);
+
+    Ok(Json(ResumedInstance { stop_time }).into_response())
+}
+
+#[derive(Serialize, Debug)]
+struct MaintenanceStatus {
+    enabled: bool,
+    paused_instances: usize
+}
+
+async fn maintenance_status(state: &InstancerState) -> Result<MaintenanceStatus, RouterError> {
+    Ok(MaintenanceStatus {
+        enabled: state.deployer.maintenance.load(Ordering::Relaxed),
+        paused_instances: state.database.get_challenge_instances().await?.iter().filter(|instance| instance.paused_at.is_some()).count()
+    })
+}
+
+pub async fn admin_maintenance_status(
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    Ok(Json(maintenance_status(&state).await?).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MaintenanceRequest {
+    enabled: bool
+}
+
+/// Starts or ends maintenance. TTLs are frozen for its whole length, so that players don't lose the time their
+/// instances were unusable.
+pub async fn admin_set_maintenance(
+    CurrentUser(admin): CurrentUser,
+    State(state): State<Arc<InstancerState>>,
+    Json(request): Json<MaintenanceRequest>
+) -> Result<Response, RouterError> {
+    if request.enabled {
+        if let Some(paused) = state.deployer.begin_maintenance().await? {
+            tracing::warn!("maintenance started by admin {}, paused {} instances", admin.id, paused);
+        }
+    } else if let Some(resumed) = state.deployer.end_maintenance().await? {
+        tracing::warn!("maintenance ended by admin {}, resumed {} instances", admin.id, resumed);
+    }
+
+    Ok(Json(maintenance_status(&state).await?).into_response())
+}
+
+pub async fn admin_bulk_status(
+    Path(challenge_id): Path<String>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some(operation) = state.bulk_operations.lock().unwrap().get(&challenge_id).cloned() else {
+        return Err(RouterError::NotFound);
+    };
+    Ok(Json(operation).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NoticeRequest {
+    notice: Option<String>
+}
+
+/// Sets or clears the status note shown on a challenge's card on every dashboard. Open to the authors of the challenge.
+pub async fn admin_set_notice(
+    CurrentUser(user): CurrentUser,
+    Path(challenge_id): Path<String>,
+    State(state): State<Arc<InstancerState>>,
+    Json(request): Json<NoticeRequest>
+) -> Result<Response, RouterError> {
+    let Some(challenge) = state.deployer.challenges.get(&challenge_id) else {
+        return Err(RouterError::NotFound);
+    };
+    if !auth::owns_challenge(&user, Some(&challenge)) {
+        return Err(RouterError::Forbidden);
+    }
+
+    let notice = ChallengeNotice {
+        challenge_id,
+        contents: request.notice.filter(|notice| !notice.trim().is_empty())
+    };
+    state.database.set_challenge_notice(&notice).await?;
+    tracing::info!("notice of challenge {} set to {:?} by {:?} {}", notice.challenge_id, notice.contents, user.role, user.id);
+
+    if let Some(contents) = &notice.contents {
+        let filter = InstanceFilter { challenge_id: Some(&notice.challenge_id), ..Default::default() };
+        let (instances, _) = state.database.search_challenge_instances(&filter, u32::MAX, 0).await?;
+        for instance in instances {
+            state.deployer.notifications.send(Notification {
+                kind: NotificationKind::Announcement,
+                user_id: instance.user_id,
+                challenge_id: instance.challenge_id,
+                contents: state.deployer.messages.render("announcement", context! { challenge => challenge.name, notice => contents }),
+                severity: MessageSeverity::Info
+            }).await;
+        }
+    }
+
+    let _ = state.notice_tx.send(notice);
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChallengeOverrideRequest {
+    name: Option<String>,
+    description: Option<String>,
+    ttl: Option<HumanDuration>,
+    deployer: Option<String>
+}
+
+pub async fn admin_challenge_override(
+    Path(challenge_id): Path<String>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let overrides = state.database.get_challenge_overrides().await?;
+    let Some(over) = overrides.into_iter().find(|over| over.challenge_id == challenge_id) else {
+        return Err(RouterError::NotFound);
+    };
+    Ok(Json(over).into_response())
+}
+
+/// Redefines a challenge without a restart. Omitted fields keep their configured value, so a challenge
+/// absent from the configuration needs a name, a TTL and a deployer.
+pub async fn admin_set_challenge_override(
+    CurrentUser(admin): CurrentUser,
+    Path(challenge_id): Path<String>,
+    State(state): State<Arc<InstancerState>>,
+    Json(request): Json<ChallengeOverrideRequest>
+) -> Result<Response, RouterError> {
+    if !identifiers::is_valid(&challenge_id) {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let over = ChallengeOverride {
+        challenge_id,
+        name: request.name,
+        description: request.description,
+        ttl: request.ttl.map(HumanDuration::secs),
+        deployer: request.deployer
+    };
+    if !state.deployer.apply_challenge_override(&state.config, &over) {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    if let Err(err) = state.database.set_challenge_override(&over).await {
+        state.deployer.revert_challenge_override(&state.config, &over.challenge_id);
+        return Err(err.into());
+    }
+    tracing::info!("challenge {} overridden by admin {}: {:?}", over.challenge_id, admin.id, over);
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Serialize, Debug)]
+struct ChallengeOverrideConflict {
+    error: &'static str,
+    instances: i64
+}
+
+/// Drops the override of a challenge, restoring its configured definition. A challenge only defined by
+/// its override can't be removed while it still has instances.
+pub async fn admin_delete_challenge_override(
+    CurrentUser(admin): CurrentUser,
+    Path(challenge_id): Path<String>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    if !state.config.challenges.contains_key(&challenge_id) {
+        let filter = InstanceFilter { challenge_id: Some(&challenge_id), ..Default::default() };
+        let (_, total) = state.database.search_challenge_instances(&filter, 1, 0).await?;
+        if total > 0 {
+            let conflict = ChallengeOverrideConflict { error: "challenge_has_instances", instances: total };
+            return Ok((StatusCode::CONFLICT, Json(conflict)).into_response());
+        }
+    }
+
+    state.database.delete_challenge_override(&challenge_id).await?;
+    state.deployer.revert_challenge_override(&state.config, &challenge_id);
+    tracing::info!("override of challenge {} removed by admin {}", challenge_id, admin.id);
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChallengeSetQuery {
+    #[serde(default)]
+    format: ChallengeSetFormat,
+    #[serde(default)]
+    dry_run: bool
+}
+
+#[derive(Serialize, Debug)]
+struct ChallengeSetRejection {
+    errors: Vec<String>
+}
+
+/// Exports the challenges as currently defined, as JSON or YAML.
+pub async fn admin_export_challenges(
+    Query(query): Query<ChallengeSetQuery>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let body = query.format.serialize(&challenge_set::export(&state.deployer))?;
+    Ok(([(CONTENT_TYPE, query.format.content_type())], body).into_response())
+}
+
+/// Imports a challenge set atomically, answering with what changed. With `dry_run`, only the changes are computed.
+pub async fn admin_import_challenges(
+    CurrentUser(admin): CurrentUser,
+    Query(query): Query<ChallengeSetQuery>,
+    State(state): State<Arc<InstancerState>>,
+    body: String
+) -> Result<Response, RouterError> {
+    let imported = match query.format.parse(&body) {
+        Ok(imported) => imported,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, Json(ChallengeSetRejection { errors: vec![err] })).into_response())
+    };
+
+    let plan = match challenge_set::plan_import(&state.config, &state.deployer, imported).await? {
+        Ok(plan) => plan,
+        Err(errors) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(ChallengeSetRejection { errors })).into_response())
+    };
+
+    if query.dry_run {
+        return Ok(Json(plan.diff).into_response());
+    }
+
+    let diff = challenge_set::apply_import(&state.deployer, plan).await?;
+    tracing::info!("challenge set imported by admin {}: {:?}", admin.id, diff);
+    Ok(Json(diff).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FlagSubmission {
+    challenge_id: String,
+    flag: String
+}
+
+#[derive(Serialize, Debug)]
+struct FlagSubmissionResponse {
+    result: SubmissionResult
+}
+
+/// Lists the challenges of the user, answering 304 Not Modified when the listing matches the `If-None-Match` header.
+pub async fn api_challenges(
+    session: Session,
+    headers: HeaderMap,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Err(RouterError::Unauthorized);
+    };
+
+    if !state.config.features.api_enabled {
+        return Err(RouterError::NotFound);
+    }
+
+    let body = serde_json::to_vec(&challenge_listing(&state, &uid).await?)?;
+    let etag = format!("\"{}\"", sha256_hex(&body));
+
+    let not_modified = headers.get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag));
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response());
+    }
+
+    Ok(([(CONTENT_TYPE, String::from("application/json")), (ETAG, etag)], body).into_response())
+}
+
+/// Metadata keys holding credentials, grouped apart from the rest of the metadata of an instance.
+const CREDENTIAL_KEYS: &[&str] = &["username", "password", "token"];
+
+#[derive(Serialize)]
+struct InstanceConnection {
+    challenge_id: String,
+    state: ChallengeInstanceState,
+    details: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    credentials: BTreeMap<String, String>,
+    stop_time: Option<TimeSinceEpoch>,
+    /// Whether the TTL is frozen, the stop time then being pushed back once it resumes.
+    paused: bool,
+    metadata: BTreeMap<String, String>,
+    /// Whether a connection bundle can be downloaded for the instance.
+    bundle: bool
+}
+
+/// Returns how to connect to the user's instance of a challenge, from the metadata reported by its deployers.
+pub async fn api_instance(
+    session: Session,
+    Path(challenge_id): Path<String>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Err(RouterError::Unauthorized);
+    };
+
+    if !state.config.features.api_enabled {
+        return Err(RouterError::NotFound);
+    }
+
+    let Some(instance) = state.database.get_challenge_instance(&uid, &challenge_id).await? else {
+        return Err(RouterError::NotFound);
+    };
+
+    let metadata: BTreeMap<String, String> = state.database.get_user_instance_metadata(&uid).await?.into_iter()
+        .filter(|entry| entry.challenge_id == challenge_id)
+        .map(|entry| (entry.key, entry.value))
+        .collect();
+    let credentials = metadata.iter()
+        .filter(|(key, _)| CREDENTIAL_KEYS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+
+    Ok(Json(InstanceConnection {
+        challenge_id: instance.challenge_id,
+        state: instance.state,
+        details: instance.details,
+        host: metadata.get("host").cloned(),
+        port: metadata.get("port").and_then(|port| port.parse().ok()),
+        credentials,
+        stop_time: instance.stop_time,
+        paused: instance.paused_at.is_some(),
+        metadata,
+        bundle: state.deployer.challenges.get(&challenge_id).is_some_and(|challenge| challenge.bundle.is_some())
+    }).into_response())
+}
+
+/// Serves the connection bundle of the user's running instance of a challenge, rendered from its template.
+pub async fn api_instance_bundle(
+    session: Session,
+    Path(challenge_id): Path<String>,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Err(RouterError::Unauthorized);
+    };
+
+    if !state.config.features.api_enabled {
+        return Err(RouterError::NotFound);
+    }
+
+    let Some(bundle) = state.deployer.challenges.get(&challenge_id).and_then(|challenge| challenge.bundle.clone()) else {
+        return Err(RouterError::NotFound);
+    };
+    let Some(instance) = state.database.get_challenge_instance(&uid, &challenge_id).await?.filter(|instance| instance.state == ChallengeInstanceState::Running) else {
+        return Err(RouterError::NotFound);
+    };
+
+    let metadata: BTreeMap<String, String> = state.database.get_user_instance_metadata(&uid).await?.into_iter()
+        .filter(|entry| entry.challenge_id == challenge_id)
+        .map(|entry| (entry.key, entry.value))
+        .collect();
+
+    let template = tokio::fs::read_to_string(&bundle.template).await
+        .map_err(|err| anyhow!("couldn't read bundle template {}: {}", bundle.template.display(), err))?;
+    let mut env = minijinja::Environment::new();
+    env.set_undefined_behavior(minijinja::UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    let contents = env.render_str(&template, context! {
+        challenge => challenge_id,
+        details => instance.details,
+        metadata => metadata,
+        stop_time => instance.stop_time.as_ref().map(i64::from)
+    }).map_err(|err| anyhow!("couldn't render bundle template {}: {}", bundle.template.display(), err))?;
+
+    Ok(([
+        (CONTENT_TYPE, String::from("application/octet-stream")),
+        (CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", bundle.filename)),
+        (CACHE_CONTROL, String::from("no-store"))
+    ], contents).into_response())
+}
+
+/// Forwards a flag to the scoreboard on behalf of the user, stopping their instance once solved.
+pub async fn submit_flag(
+    session: Session,
+    State(state): State<Arc<InstancerState>>,
+    Json(submission): Json<FlagSubmission>
+) -> Result<Response, RouterError> {
+    let Some(uid) = session.get::<String>("uid").await? else {
+        return Err(RouterError::Unauthorized);
+    };
+
+    if !state.config.features.api_enabled {
+        return Err(RouterError::NotFound);
+    }
+
+    let (Some(rctf), Some(true)) = (&state.rctf, state.config.rctf.as_ref().map(|rctf| rctf.flag_submission)) else {
+        return Err(RouterError::NotFound);
+    };
+
+    let Some(scoreboard_id) = state.deployer.challenges.get(&submission.challenge_id).and_then(|challenge| challenge.scoreboard_id.clone()) else {
+        return Err(RouterError::NotFound);
+    };
+
+    let Some(auth_token) = session.get::<String>("rctf_token").await? else {
+        return Err(RouterError::Forbidden);
+    };
+
+    if state.rate_limiter.check_key(&uid).is_err() {
+        return Ok(Json(FlagSubmissionResponse { result: SubmissionResult::RateLimited }).into_response());
+    }
+
+    let result = rctf.submit_flag(&auth_token, &scoreboard_id, &submission.flag).await?;
+    if result == SubmissionResult::Correct || result == SubmissionResult::AlreadySolved {
+        state.deployer.queue_stop(&uid, &submission.challenge_id, EndReason::Solved).await?;
+    }
+
+    Ok(Json(FlagSubmissionResponse { result }).into_response())
+}
+
+pub async fn not_found() -> RouterError {
+    RouterError::NotFound
+}
+
 pub async fn logout(
     session: Session
 ) -> impl IntoResponse {
@@ -328,72 +1889,711 @@ pub async fn logout(
 #[derive(Template)]
 #[template(path = "login.html")]
 struct LoginTemplate {
-    oauth2_url: String,
+    oauth2_url: Option<String>,
+    local_login: bool,
+    local_registration: bool,
+    ldap_login: bool,
+    rctf_login: bool,
+    demo_login: bool,
     error: Option<&'static str>
 }
 
+const REGISTRATION_CLOSED: &str = "Les inscriptions sont fermées, seuls les comptes existants peuvent se connecter.";
+const MALFORMED_USER_ID: &str = "Votre identifiant d'utilisateur n'est pas pris en charge.";
+const NOT_IN_SERVER: &str = "Vous devez faire partie du serveur Discord du UnitedCTF pour utiliser cette plateforme.";
+
+fn login_page(state: &InstancerState, error: Option<&'static str>) -> Response {
+    let auth_url = state.oauth2.as_ref().map(|oauth2| oauth2
+        .authorize_url(CsrfToken::new_random)
+        .add_scopes(discord::SCOPES.iter().map(|scope| Scope::new(scope.to_string())))
+        .add_extra_param("prompt", "none")
+        .url().0);
+
+    let login = LoginTemplate {
+        oauth2_url: auth_url.map(|url| url.to_string()),
+        local_login: state.config.auth.enabled(AuthProvider::Local),
+        local_registration: local_registration_open(state),
+        #[cfg(feature = "ldap")]
+        ldap_login: state.config.auth.enabled(AuthProvider::Ldap),
+        #[cfg(not(feature = "ldap"))]
+        ldap_login: false,
+        rctf_login: state.config.rctf.as_ref().is_some_and(|rctf| rctf.login_enabled),
+        demo_login: state.config.demo.is_some(),
+        error
+    };
+    HtmlTemplate(login).into_response()
+}
+
 pub async fn login(
     session: Session,
+    ClientIp(ip): ClientIp,
     Query(params): Query<HashMap<String, String>>,
     State(state): State<Arc<InstancerState>>
-) -> Result<impl IntoResponse, InternalError> {
-    let (auth_url, _) = state.oauth2
-        .authorize_url(CsrfToken::new_random)
-        .add_scopes(discord::SCOPES.iter().map(|scope| Scope::new(scope.to_string())))
-        .add_extra_param("prompt", "none")
-        .url();
+) -> Result<impl IntoResponse, RouterError> {
+    #[cfg(feature = "geoip")]
+    check_geo(&state, ip)?;
 
-    if let Some(code) = params.get("code") {
-        match state.oauth2.exchange_code(AuthorizationCode::new(code.clone()))
+    if let (Some(code), Some(oauth2), Some(discord_config)) = (params.get("code"), &state.oauth2, &state.config.discord) {
+        match oauth2.exchange_code(AuthorizationCode::new(code.clone()))
                 .request_async(async_http_client).await {
             Ok(token) => {
                 let scopes = token.scopes().ok_or(anyhow::Error::msg("scopes are undefined"))?;
                 let scopes: Vec<&str> = scopes.iter().map(|scope| scope.as_str()).collect();
 
                 if !discord::SCOPES.iter().all(|sc1| scopes.iter().any(|sc2| sc1 == sc2)) {
-                    let login = LoginTemplate { oauth2_url: auth_url.to_string(), error: Some("Certains des scopes OAuth requis n'ont pas été autorisés.") };
-                    return Ok(HtmlTemplate(login).into_response());
+                    return Ok(login_page(&state, Some("Certains des scopes OAuth requis n'ont pas été autorisés.")));
                 }
 
                 let discord = Discord::new(token.access_token().secret().clone());
                 let discord_user = discord.current_user().await?;
+                if !identifiers::is_valid(&discord_user.id) {
+                    tracing::warn!("refused login of Discord user with malformed id {:?}", discord_user.id);
+                    return Ok(login_page(&state, Some(MALFORMED_USER_ID)));
+                }
+
+                let gated = !discord_config.entry_rules.is_empty() && auth::initial_role(&state.config.settings, &discord_user.id) != UserRole::Admin;
+                if let Some(bot) = state.discord_bot.as_ref().filter(|_| gated) {
+                    let verdict = match bot.guild_member(&state.discord_membership, &discord_config.server_id, &discord_user.id).await? {
+                        Some(member) => member.check_entry_rules(&discord_config.entry_rules),
+                        None => Err(NOT_IN_SERVER)
+                    };
+                    if let Err(reason) = verdict {
+                        tracing::info!("refused login of Discord user {}, who doesn't pass the entry rules", discord_user.id);
+                        return Ok(login_page(&state, Some(reason)));
+                    }
+                }
 
                 let user = match state.database.fetch_user(&discord_user.id).await? {
                     None => {
-                        let guilds = discord.current_guilds().await?;
-                        if !guilds.iter().any(|guild| guild.id == state.config.discord.server_id) {
-                            let login = LoginTemplate { oauth2_url: auth_url.to_string(), error: Some("Vous devez faire partie du serveur Discord du UnitedCTF pour utiliser cette plateforme.") };
-                            return Ok(HtmlTemplate(login).into_response())
+                        if !state.config.features.registration_open {
+                            return Ok(login_page(&state, Some(REGISTRATION_CLOSED)));
+                        }
+
+                        let guilds = discord.current_guilds(&state.discord_membership).await?;
+                        if !guilds.iter().any(|guild| guild.id == discord_config.server_id) {
+                            return Ok(login_page(&state, Some(NOT_IN_SERVER)));
                         }
 
+                        let role = auth::initial_role(&state.config.settings, &discord_user.id);
                         let new_user = User {
                             id: discord_user.id,
                             username: discord_user.username.clone(),
                             display_name: discord_user.global_name.unwrap_or(discord_user.username),
                             avatar: discord_user.avatar,
                             creation_time: TimeSinceEpoch::now(),
-                            instance_count: 0
+                            instance_count: 0,
+                            scoreboard_id: None,
+                            role
                         };
 
                         state.database.insert_user(&new_user).await?;
 
                         new_user
                     }
-                    Some(user) => user
+                    Some(mut user) => {
+                        let display_name = discord_user.global_name.unwrap_or_else(|| discord_user.username.clone());
+                        if user.username != discord_user.username || user.display_name != display_name || user.avatar != discord_user.avatar {
+                            state.database.update_user_profile(&user.id, &discord_user.username, &display_name, &discord_user.avatar).await?;
+                            user.username = discord_user.username;
+                            user.display_name = display_name;
+                            user.avatar = discord_user.avatar;
+                        }
+                        user
+                    }
                 };
 
+                audit(&state, &user.id, ip, "login", None).await;
+                session.insert(LOGIN_TIME_KEY, i64::from(&TimeSinceEpoch::now())).await?;
                 session.insert("uid", user.id).await?;
-                session.insert("avatar", user.avatar).await?;
-
+            
                 Ok(Redirect::to("/").into_response())
             },
-            Err(_) => {
-                let login = LoginTemplate { oauth2_url: auth_url.to_string(), error: Some("Un code OAuth invalide a été reçu de la part de Discord.") };
-                Ok(HtmlTemplate(login).into_response())
-            }
+            Err(_) => Ok(login_page(&state, Some("Un code OAuth invalide a été reçu de la part de Discord.")))
         }
     } else {
-        let login = LoginTemplate { oauth2_url: auth_url.to_string(), error: None };
-        Ok(HtmlTemplate(login).into_response())
+        Ok(login_page(&state, None))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RctfLoginForm {
+    token: String
+}
+
+/// Logs in with an rCTF team token, or links the team to the current user if already logged in. The token is only
+/// accepted in the body of a POST, which needs the CSRF token of logged in sessions, so that it stays out of logs and
+/// a link can't attach an account to another team.
+pub async fn login_rctf(
+    session: Session,
+    ClientIp(ip): ClientIp,
+    State(state): State<Arc<InstancerState>>,
+    Form(form): Form<RctfLoginForm>
+) -> Result<Response, RouterError> {
+    #[cfg(feature = "geoip")]
+    check_geo(&state, ip)?;
+
+    let (Some(rctf), Some(rctf_config)) = (&state.rctf, &state.config.rctf) else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let Some(auth_token) = rctf.login(&form.token).await? else {
+        return Ok(login_page(&state, Some("Le jeton d'équipe rCTF est invalide.")));
+    };
+    let rctf_user = rctf.current_user(&auth_token).await?;
+    if !identifiers::is_valid(&rctf_user.id) {
+        tracing::warn!("refused login of rCTF team with malformed id {:?}", rctf_user.id);
+        return Ok(login_page(&state, Some(MALFORMED_USER_ID)));
+    }
+    session.insert("rctf_token", auth_token).await?;
+
+    if let Some(uid) = session.get::<String>("uid").await? {
+        state.database.update_user_scoreboard_id(&uid, &rctf_user.id).await?;
+        audit(&state, &uid, ip, "link_rctf", None).await;
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    if !rctf_config.login_enabled {
+        return Ok(login_page(&state, Some("La connexion via rCTF n'est pas activée.")));
+    }
+
+    let user = match state.database.fetch_user(&rctf_user.id).await? {
+        None => {
+            if !state.config.features.registration_open {
+                return Ok(login_page(&state, Some(REGISTRATION_CLOSED)));
+            }
+
+            let role = auth::initial_role(&state.config.settings, &rctf_user.id);
+            let new_user = User {
+                id: rctf_user.id.clone(),
+                username: rctf_user.name.clone(),
+                display_name: rctf_user.name,
+                avatar: None,
+                creation_time: TimeSinceEpoch::now(),
+                instance_count: 0,
+                scoreboard_id: Some(rctf_user.id),
+                role
+            };
+
+            state.database.insert_user(&new_user).await?;
+
+            new_user
+        }
+        Some(user) => user
+    };
+
+    audit(&state, &user.id, ip, "login_rctf", None).await;
+    session.insert(LOGIN_TIME_KEY, i64::from(&TimeSinceEpoch::now())).await?;
+    session.insert("uid", user.id).await?;
+
+    Ok(Redirect::to("/").into_response())
+}
+
+const INVALID_CREDENTIALS: &str = "L'adresse courriel ou le mot de passe est invalide.";
+
+/// Whether visitors may create local accounts themselves.
+fn local_registration_open(state: &InstancerState) -> bool {
+    state.config.auth.enabled(AuthProvider::Local) && state.config.auth.local.registration_open && state.config.features.registration_open
+}
+
+/// A new user of a local account. Its id is random rather than derived from the email, which can't be trusted to
+/// be a valid identifier.
+fn local_user(state: &InstancerState, email: &str, display_name: &str) -> User {
+    let id = format!("local-{}", rand::random::<[u8; 8]>().iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+    User {
+        role: auth::initial_role(&state.config.settings, &id),
+        id,
+        username: email.to_string(),
+        display_name: display_name.to_string(),
+        avatar: None,
+        creation_time: TimeSinceEpoch::now(),
+        instance_count: 0,
+        scoreboard_id: None
+    }
+}
+
+/// Checks the fields of a new local account, returning the reason they are refused for.
+fn check_local_account(state: &InstancerState, email: &str, display_name: &str, password: &str) -> Result<(), String> {
+    if !local_auth::is_valid_email(email) {
+        return Err(String::from("L'adresse courriel est invalide."));
+    }
+    if display_name.is_empty() || display_name.chars().count() > 32 {
+        return Err(String::from("Le nom affiché doit contenir entre 1 et 32 caractères."));
+    }
+    local_auth::check_password(&state.config.auth.local, password)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LocalLoginForm {
+    email: String,
+    password: String
+}
+
+/// Logs in with the email and password of a local account. Each IP may only make a few attempts per minute.
+pub async fn login_local(
+    session: Session,
+    ClientIp(ip): ClientIp,
+    State(state): State<Arc<InstancerState>>,
+    Form(form): Form<LocalLoginForm>
+) -> Result<Response, RouterError> {
+    #[cfg(feature = "geoip")]
+    check_geo(&state, ip)?;
+
+    let (true, Some(limiter)) = (state.config.auth.enabled(AuthProvider::Local), &state.password_login_limiter) else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    if limiter.check_key(&ip).is_err() {
+        return Err(RouterError::RateLimited);
+    }
+
+    let account = state.database.fetch_local_account(&local_auth::normalize_email(&form.email)).await?;
+    let verified = match &account {
+        Some(account) => local_auth::verify_password(form.password, account.password_hash.clone()).await?,
+        None => false
+    };
+    let (true, Some(account)) = (verified, account) else {
+        return Ok(login_page(&state, Some(INVALID_CREDENTIALS)));
+    };
+    let Some(user) = state.database.fetch_user(&account.user_id).await? else {
+        return Ok(login_page(&state, Some(INVALID_CREDENTIALS)));
+    };
+
+    audit(&state, &user.id, ip, "login_local", None).await;
+    session.insert(LOGIN_TIME_KEY, i64::from(&TimeSinceEpoch::now())).await?;
+    session.insert("uid", user.id).await?;
+
+    Ok(Redirect::to("/").into_response())
+}
+
+#[derive(Template)]
+#[template(path = "register.html")]
+struct RegisterTemplate {
+    min_password_length: usize,
+    error: Option<String>
+}
+
+fn register_page(state: &InstancerState, error: Option<String>) -> Response {
+    let register = RegisterTemplate {
+        min_password_length: state.config.auth.local.min_password_length,
+        error
+    };
+    HtmlTemplate(register).into_response()
+}
+
+pub async fn register(
+    State(state): State<Arc<InstancerState>>
+) -> Response {
+    if !local_registration_open(&state) {
+        return Redirect::to("/login").into_response();
+    }
+
+    register_page(&state, None)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RegistrationForm {
+    email: String,
+    display_name: String,
+    password: String
+}
+
+/// Creates a local account and logs in with it, sharing the password attempts limit of the IP.
+pub async fn submit_registration(
+    session: Session,
+    ClientIp(ip): ClientIp,
+    State(state): State<Arc<InstancerState>>,
+    Form(form): Form<RegistrationForm>
+) -> Result<Response, RouterError> {
+    #[cfg(feature = "geoip")]
+    check_geo(&state, ip)?;
+
+    let (true, Some(limiter)) = (local_registration_open(&state), &state.password_login_limiter) else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    if limiter.check_key(&ip).is_err() {
+        return Err(RouterError::RateLimited);
+    }
+
+    let email = local_auth::normalize_email(&form.email);
+    let display_name = form.display_name.trim();
+    if let Err(reason) = check_local_account(&state, &email, display_name, &form.password) {
+        return Ok(register_page(&state, Some(reason)));
+    }
+
+    let user = local_user(&state, &email, display_name);
+    let password_hash = local_auth::hash_password(form.password).await?;
+    if !state.database.insert_local_account(&user, &email, &password_hash).await? {
+        return Ok(register_page(&state, Some(String::from("Cette adresse courriel est déjà utilisée."))));
+    }
+
+    audit(&state, &user.id, ip, "register_local", None).await;
+    session.insert(LOGIN_TIME_KEY, i64::from(&TimeSinceEpoch::now())).await?;
+    session.insert("uid", user.id).await?;
+
+    Ok(Redirect::to("/").into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LocalAccountRequest {
+    email: String,
+    display_name: String,
+    password: String
+}
+
+#[derive(Serialize, Debug)]
+struct CreatedLocalAccount {
+    user_id: String
+}
+
+/// Creates a local account for someone, whether or not registration is open.
+pub async fn admin_create_local_account(
+    CurrentUser(admin): CurrentUser,
+    State(state): State<Arc<InstancerState>>,
+    Json(request): Json<LocalAccountRequest>
+) -> Result<Response, RouterError> {
+    if !state.config.auth.enabled(AuthProvider::Local) {
+        return Err(RouterError::NotFound);
+    }
+
+    let email = local_auth::normalize_email(&request.email);
+    let display_name = request.display_name.trim();
+    if check_local_account(&state, &email, display_name, &request.password).is_err() {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let user = local_user(&state, &email, display_name);
+    let password_hash = local_auth::hash_password(request.password).await?;
+    if !state.database.insert_local_account(&user, &email, &password_hash).await? {
+        return Ok(StatusCode::CONFLICT.into_response());
+    }
+    tracing::info!("local account {} created for {} by admin {}", user.id, email, admin.id);
+
+    Ok((StatusCode::CREATED, Json(CreatedLocalAccount { user_id: user.id })).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PasswordResetRequest {
+    password: String
+}
+
+/// Replaces the password of a local account, for users who lost theirs.
+pub async fn admin_reset_local_password(
+    CurrentUser(admin): CurrentUser,
+    Path(user_id): Path<String>,
+    State(state): State<Arc<InstancerState>>,
+    Json(request): Json<PasswordResetRequest>
+) -> Result<Response, RouterError> {
+    if local_auth::check_password(&state.config.auth.local, &request.password).is_err() {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let password_hash = local_auth::hash_password(request.password).await?;
+    if !state.database.set_local_password(&user_id, &password_hash).await? {
+        return Err(RouterError::NotFound);
+    }
+    tracing::info!("password of local account {} reset by admin {}", user_id, admin.id);
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(feature = "ldap")]
+#[derive(Deserialize, Debug)]
+pub struct LdapLoginForm {
+    username: String,
+    password: String
+}
+
+/// Logs in by binding to the directory as the user, their role following the groups they are in at every login.
+/// Each IP may only make a few attempts per minute.
+#[cfg(feature = "ldap")]
+pub async fn login_ldap(
+    session: Session,
+    ClientIp(ip): ClientIp,
+    State(state): State<Arc<InstancerState>>,
+    Form(form): Form<LdapLoginForm>
+) -> Result<Response, RouterError> {
+    #[cfg(feature = "geoip")]
+    check_geo(&state, ip)?;
+
+    let (true, Some(ldap_config), Some(limiter)) = (state.config.auth.enabled(AuthProvider::Ldap), &state.config.auth.ldap, &state.password_login_limiter) else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    if limiter.check_key(&ip).is_err() {
+        return Err(RouterError::RateLimited);
+    }
+
+    let ldap_user = match ldap::authenticate(ldap_config, form.username.trim(), &form.password).await {
+        Ok(Some(ldap_user)) => ldap_user,
+        Ok(None) => return Ok(login_page(&state, Some("Le nom d'utilisateur ou le mot de passe est invalide."))),
+        Err(err) => return Err(RouterError::Upstream(err))
+    };
+    if !ldap_user.is_allowed(ldap_config) {
+        return Ok(login_page(&state, Some("Vous ne faites partie d'aucun groupe autorisé à utiliser cette plateforme.")));
+    }
+
+    /* logins may contain characters identifiers can't, and directories compare them case-insensitively */
+    let id = format!("ldap-{}", &sha256_hex(ldap_user.username.to_lowercase().as_bytes())[..16]);
+    let role = ldap_user.role(ldap_config).into_iter().chain([auth::initial_role(&state.config.settings, &id)]).max().unwrap();
+
+    let user = match state.database.fetch_user(&id).await? {
+        None => {
+            if !state.config.features.registration_open {
+                return Ok(login_page(&state, Some(REGISTRATION_CLOSED)));
+            }
+
+            let new_user = User {
+                id,
+                username: ldap_user.username,
+                display_name: ldap_user.display_name,
+                avatar: None,
+                creation_time: TimeSinceEpoch::now(),
+                instance_count: 0,
+                scoreboard_id: None,
+                role
+            };
+
+            state.database.insert_user(&new_user).await?;
+
+            new_user
+        }
+        Some(user) => {
+            if user.role != role {
+                tracing::info!("role of LDAP user {} changed from {:?} to {:?} following their groups", user.id, user.role, role);
+                state.database.set_user_roles(std::slice::from_ref(&user.id), &role).await?;
+            }
+            user
+        }
+    };
+
+    audit(&state, &user.id, ip, "login_ldap", None).await;
+    session.insert(LOGIN_TIME_KEY, i64::from(&TimeSinceEpoch::now())).await?;
+    session.insert("uid", user.id).await?;
+
+    Ok(Redirect::to("/").into_response())
+}
+
+/// Logs in as a new throwaway user of the demo, which ends along with its session after the configured lifetime.
+/// Each IP may only create a few of them per hour.
+pub async fn login_demo(
+    session: Session,
+    ClientIp(ip): ClientIp,
+    State(state): State<Arc<InstancerState>>
+) -> Result<Response, RouterError> {
+    #[cfg(feature = "geoip")]
+    check_geo(&state, ip)?;
+
+    let (Some(demo), Some(demo_limiter)) = (&state.config.demo, &state.demo_limiter) else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    if demo_limiter.check_key(&ip).is_err() {
+        tracing::warn!("refused demo login from {}, which created too many demo users", ip);
+        return Err(RouterError::RateLimited);
+    }
+
+    let suffix: String = rand::random::<[u8; 8]>().iter().map(|byte| format!("{:02x}", byte)).collect();
+    let user = User {
+        id: format!("demo-{}", suffix),
+        username: format!("demo-{}", suffix),
+        display_name: String::from("Visiteur"),
+        avatar: None,
+        creation_time: TimeSinceEpoch::now(),
+        instance_count: 0,
+        scoreboard_id: None,
+        role: UserRole::Demo
+    };
+    state.database.insert_user(&user).await?;
+
+    audit(&state, &user.id, ip, "login_demo", None).await;
+    session.set_expiry(Some(Expiry::AtDateTime(OffsetDateTime::now_utc() + CookieDuration::seconds(demo.lifetime.into()))));
+    session.insert(LOGIN_TIME_KEY, i64::from(&TimeSinceEpoch::now())).await?;
+    session.insert("uid", user.id).await?;
+
+    Ok(Redirect::to("/").into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use proptest::strategy::LazyJust;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const ACTIONS: &[&str] = &["start", "stop", "restart", "extend", "cancel"];
+
+    /// The message types the dashboard handles, the server's heartbeats only refreshing its liveness check.
+    const HANDLED_TYPES: &[&str] = &[
+        "challenge_listing", "challenge_listing_delta", "challenge_state_change", "challenge_metadata", "challenge_progress",
+        "challenge_paused", "challenge_pipeline_step", "challenge_notice", "message", "session_expired", "heartbeat"
+    ];
+
+    fn json_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            ".*".prop_map(Value::from),
+            prop::sample::select(ACTIONS).prop_map(Value::from)
+        ];
+        leaf.prop_recursive(3, 32, 6, |inner| prop_oneof![
+            prop::collection::vec(inner.clone(), 0..6).prop_map(Value::from),
+            prop::collection::btree_map("type|id|action|[a-z_]{1,8}", inner, 0..6)
+                .prop_map(|fields| Value::Object(fields.into_iter().collect()))
+        ])
+    }
+
+    fn instance_state() -> impl Strategy<Value = ChallengeInstanceState> {
+        prop::sample::select(vec![
+            ChallengeInstanceState::Stopped, ChallengeInstanceState::Running, ChallengeInstanceState::QueuedStart,
+            ChallengeInstanceState::QueuedRestart, ChallengeInstanceState::QueuedStop, ChallengeInstanceState::Deploying,
+            ChallengeInstanceState::Expiring
+        ])
+    }
+
+    /// Timestamps are sent in milliseconds, so only those are generated for them to compare equal once parsed.
+    fn timestamp() -> impl Strategy<Value = TimeSinceEpoch> {
+        (0i64..1 << 45).prop_map(TimeSinceEpoch::from)
+    }
+
+    fn player_state() -> impl Strategy<Value = ChallengePlayerState> {
+        (
+            (any::<String>(), any::<String>(), any::<Option<String>>(), instance_state(), prop::option::of(timestamp())),
+            (any::<Option<String>>(), any::<Option<u8>>(), any::<[bool; 5]>(), any::<Option<String>>()),
+            prop::collection::btree_map(any::<String>(), any::<String>(), 0..4)
+        ).prop_map(|((id, name, description, state, stop_time), (details, progress, flags, notice), metadata)| ChallengePlayerState {
+            id, name, description, state, stop_time, details, progress, paused: flags[4],
+            flag_submission: flags[0], extendable: flags[1], restartable: flags[2], cancellable: flags[3],
+            notice, metadata
+        })
+    }
+
+    fn instance_slots() -> impl Strategy<Value = InstanceSlots> {
+        (0i64..16, any::<u32>()).prop_map(|(used, max)| InstanceSlots { used, max })
+    }
+
+    fn client_bound_message() -> impl Strategy<Value = ClientBoundMessage> {
+        let heartbeat = || (1u32.., any::<u32>()).prop_map(|(interval, allowed_misses)| HeartbeatConfig { interval, allowed_misses });
+        let listing = || prop::collection::btree_map(any::<String>(), player_state(), 0..4);
+        let severity = prop::sample::select(vec![MessageSeverity::Success, MessageSeverity::Info, MessageSeverity::Warning, MessageSeverity::Error]);
+
+        prop_oneof![
+            (listing(), instance_slots(), heartbeat(), any::<String>())
+                .prop_map(|(challenges, slots, heartbeat, resume_token)| ClientBoundMessage::ChallengeListing { challenges, slots, heartbeat, resume_token }),
+            (listing(), any::<Vec<String>>(), instance_slots(), heartbeat(), any::<String>())
+                .prop_map(|(changed, removed, slots, heartbeat, resume_token)| ClientBoundMessage::ChallengeListingDelta { changed, removed, slots, heartbeat, resume_token }),
+            (any::<String>(), instance_state(), any::<Option<String>>(), prop::option::of(timestamp()), instance_slots())
+                .prop_map(|(id, state, details, stop_time, slots)| ClientBoundMessage::ChallengeStateChange { id, state, details, stop_time, slots }),
+            (any::<String>(), any::<Option<String>>()).prop_map(|(id, notice)| ClientBoundMessage::ChallengeNotice { id, notice }),
+            (any::<String>(), prop::collection::btree_map(any::<String>(), any::<String>(), 0..4))
+                .prop_map(|(id, metadata)| ClientBoundMessage::ChallengeMetadata { id, metadata }),
+            (any::<String>(), any::<String>(), any::<usize>(), any::<usize>())
+                .prop_map(|(id, name, index, total)| ClientBoundMessage::ChallengePipelineStep { id, name, index, total }),
+            (any::<String>(), any::<u8>()).prop_map(|(id, progress)| ClientBoundMessage::ChallengeProgress { id, progress }),
+            (any::<String>(), any::<bool>(), prop::option::of(timestamp()))
+                .prop_map(|(id, paused, stop_time)| ClientBoundMessage::ChallengePaused { id, paused, stop_time }),
+            (any::<String>(), any::<String>(), severity)
+                .prop_map(|(id, markup, severity)| ClientBoundMessage::Message { id, contents: MessageContents::from_markup(&markup), severity }),
+            LazyJust::new(|| ClientBoundMessage::SessionExpired),
+            LazyJust::new(|| ClientBoundMessage::Heartbeat)
+        ]
+    }
+
+    fn sent_json(message: ClientBoundMessage) -> Value {
+        match Message::from(message) {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("client-bound messages are sent as text, not {:?}", other)
+        }
+    }
+
+    #[test]
+    fn parses_heartbeats() {
+        let message = ServerBoundMessage::try_from(Message::Text(json!({"type": "heartbeat"}).to_string())).unwrap();
+        assert!(matches!(message, ServerBoundMessage::Heartbeat));
+    }
+
+    proptest! {
+        #[test]
+        fn parses_challenge_actions(id in any::<String>(), action in prop::sample::select(ACTIONS)) {
+            let text = json!({"type": "challenge_action", "id": id, "action": action}).to_string();
+            match ServerBoundMessage::try_from(Message::Text(text)) {
+                Ok(ServerBoundMessage::ChallengeAction { id: parsed_id, action: parsed_action }) => {
+                    prop_assert_eq!(parsed_id, id);
+                    prop_assert_eq!(<&str>::from(&parsed_action), action);
+                }
+                other => prop_assert!(false, "parsed as {:?}", other)
+            }
+        }
+
+        #[test]
+        fn rejects_unknown_actions(action in "[a-z_]{1,10}".prop_filter("known action", |action| !ACTIONS.contains(&action.as_str()))) {
+            let text = json!({"type": "challenge_action", "id": "challenge", "action": action}).to_string();
+            prop_assert!(ServerBoundMessage::try_from(Message::Text(text)).is_err());
+        }
+
+        #[test]
+        fn survives_arbitrary_text(text in any::<String>()) {
+            let _ = ServerBoundMessage::try_from(Message::Text(text));
+        }
+
+        #[test]
+        fn survives_arbitrary_json(value in json_value()) {
+            let _ = ServerBoundMessage::try_from(Message::Text(value.to_string()));
+        }
+
+        #[test]
+        fn rejects_binary_messages(data in any::<Vec<u8>>()) {
+            prop_assert!(ServerBoundMessage::try_from(Message::Binary(data)).is_err());
+        }
+
+        #[test]
+        fn sends_handled_message_types(message in client_bound_message()) {
+            let sent = sent_json(message);
+            let kind = sent["type"].as_str().unwrap_or_default();
+            prop_assert!(HANDLED_TYPES.contains(&kind), "the dashboard doesn't handle {:?}", kind);
+        }
+
+        #[test]
+        fn sends_state_changes_as_parsed(id in any::<String>(), state in instance_state(), details in any::<Option<String>>(), stop_time in prop::option::of(timestamp()), slots in instance_slots()) {
+            let message = ClientBoundMessage::ChallengeStateChange { id: id.clone(), state: state.clone(), details: details.clone(), stop_time: stop_time.clone(), slots };
+            let sent = sent_json(message);
+
+            prop_assert_eq!(&sent["id"], &json!(id));
+            prop_assert_eq!(serde_json::from_value::<ChallengeInstanceState>(sent["state"].clone())?, state);
+            prop_assert_eq!(&sent["details"], &json!(details));
+            prop_assert_eq!(serde_json::from_value::<Option<TimeSinceEpoch>>(sent["stop_time"].clone())?, stop_time);
+            prop_assert_eq!(&sent["slots"], &json!({"used": slots.used, "max": slots.max}));
+        }
+
+        #[test]
+        fn sends_listings_keyed_by_challenge(challenges in prop::collection::btree_map(any::<String>(), player_state(), 0..4)) {
+            let heartbeat = HeartbeatConfig { interval: 30, allowed_misses: 2 };
+            let slots = InstanceSlots { used: 0, max: 3 };
+            let sent = sent_json(ClientBoundMessage::ChallengeListing { challenges: challenges.clone(), slots, heartbeat, resume_token: String::new() });
+            let sent_challenges = sent["challenges"].as_object().unwrap();
+
+            prop_assert_eq!(sent_challenges.len(), challenges.len());
+            for (key, challenge) in &challenges {
+                let sent_challenge = &sent_challenges[key];
+                prop_assert_eq!(&sent_challenge["id"], &json!(challenge.id));
+                prop_assert_eq!(serde_json::from_value::<ChallengeInstanceState>(sent_challenge["state"].clone())?, challenge.state.clone());
+                prop_assert_eq!(&sent_challenge["progress"], &json!(challenge.progress));
+                prop_assert_eq!(&sent_challenge["metadata"], &json!(challenge.metadata));
+                prop_assert_eq!(&sent_challenge["cancellable"], &json!(challenge.cancellable));
+            }
+        }
+
+        #[test]
+        fn sends_message_spans_the_dashboard_renders(markup in any::<String>()) {
+            let sent = sent_json(ClientBoundMessage::Message { id: String::new(), contents: MessageContents::from_markup(&markup), severity: MessageSeverity::Info });
+            for span in sent["contents"].as_array().unwrap() {
+                match span["style"].as_str() {
+                    Some("text" | "strong") => prop_assert!(span["text"].is_string()),
+                    Some("link") => prop_assert!(span["text"].is_string() && span["href"].as_str().is_some_and(|href| href.starts_with('/'))),
+                    Some("line_break") => {}
+                    other => prop_assert!(false, "unknown span style {:?}", other)
+                }
+            }
+        }
     }
 }
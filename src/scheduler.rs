@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::deployment_worker::DeploymentRequest;
+
+/// Interleaves pending deployment requests round-robin across users,
+/// limiting how many requests of a single user can be handled concurrently.
+///
+/// Requests of priority users (staff) are served before any player request. The requests of a user are handed out in
+/// order, a user waiting until their oldest request is accepted by a worker.
+pub struct FairScheduler {
+    queues: HashMap<String, VecDeque<DeploymentRequest>>,
+    priority_order: VecDeque<String>,
+    order: VecDeque<String>,
+    priority_users: HashSet<String>,
+    in_flight: HashMap<String, u32>,
+    max_in_flight_per_user: u32,
+    len: usize
+}
+
+impl FairScheduler {
+    pub fn new(max_in_flight_per_user: u32, priority_users: HashSet<String>) -> Self {
+        FairScheduler {
+            queues: HashMap::new(),
+            priority_order: VecDeque::new(),
+            order: VecDeque::new(),
+            priority_users,
+            in_flight: HashMap::new(),
+            max_in_flight_per_user,
+            len: 0
+        }
+    }
+
+    pub fn push(&mut self, request: DeploymentRequest) {
+        let queue = self.queues.entry(request.user_id.clone()).or_default();
+        if queue.is_empty() {
+            let order = if self.priority_users.contains(&request.user_id) { &mut self.priority_order } else { &mut self.order };
+            order.push_back(request.user_id.clone());
+        }
+        queue.push_back(request);
+        self.len += 1;
+    }
+
+    /// Returns the next request accepted by the worker, of the first user in line that isn't at its in-flight limit,
+    /// priority lane first.
+    pub fn next(&mut self, accepts: impl Fn(&DeploymentRequest) -> bool) -> Option<DeploymentRequest> {
+        let request = Self::next_in_lane(&mut self.priority_order, &mut self.queues, &mut self.in_flight, self.max_in_flight_per_user, &accepts)
+            .or_else(|| Self::next_in_lane(&mut self.order, &mut self.queues, &mut self.in_flight, self.max_in_flight_per_user, &accepts))?;
+
+        self.len -= 1;
+        Some(request)
+    }
+
+    fn next_in_lane(
+        order: &mut VecDeque<String>,
+        queues: &mut HashMap<String, VecDeque<DeploymentRequest>>,
+        in_flight: &mut HashMap<String, u32>,
+        max_in_flight_per_user: u32,
+        accepts: &impl Fn(&DeploymentRequest) -> bool
+    ) -> Option<DeploymentRequest> {
+        for _ in 0..order.len() {
+            let user_id = order.pop_front()?;
+
+            let at_limit = in_flight.get(&user_id).copied().unwrap_or(0) >= max_in_flight_per_user;
+            if at_limit || !queues.get(&user_id).and_then(|queue| queue.front()).is_some_and(accepts) {
+                order.push_back(user_id);
+                continue;
+            }
+            *in_flight.entry(user_id.clone()).or_default() += 1;
+
+            let queue = queues.get_mut(&user_id)?;
+            let request = queue.pop_front();
+            if queue.is_empty() {
+                queues.remove(&user_id);
+            } else {
+                order.push_back(user_id);
+            }
+
+            return request;
+        }
+
+        None
+    }
+
+    /// Marks a request returned by `next` as handled.
+    pub fn complete(&mut self, user_id: &str) {
+        if let Some(in_flight) = self.in_flight.get_mut(user_id) {
+            *in_flight -= 1;
+            if *in_flight == 0 {
+                self.in_flight.remove(user_id);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::deployment_worker::DeploymentRequestCommand;
+
+    /// Challenges prefixed with `gpu-` are deployed by the `gpu` pool, the others by the shared workers.
+    fn pool_of(request: &DeploymentRequest) -> Option<&str> {
+        request.challenge_id.starts_with("gpu-").then_some("gpu")
+    }
+
+    fn request(user_id: &str, challenge_id: &str) -> DeploymentRequest {
+        DeploymentRequest {
+            user_id: user_id.to_string(),
+            challenge_id: challenge_id.to_string(),
+            command: DeploymentRequestCommand::Start { retry: false },
+            queued_at: Instant::now()
+        }
+    }
+
+    #[test]
+    fn pooled_requests_are_skipped_by_other_workers() {
+        let mut scheduler = FairScheduler::new(1, HashSet::new());
+        scheduler.push(request("alice", "gpu-heavy"));
+
+        assert!(scheduler.next(|request| pool_of(request).is_none()).is_none());
+        assert_eq!(scheduler.len(), 1);
+
+        let request = scheduler.next(|request| pool_of(request) == Some("gpu")).unwrap();
+        assert_eq!(request.challenge_id, "gpu-heavy");
+        assert_eq!(scheduler.len(), 0);
+    }
+
+    #[test]
+    fn users_waiting_on_another_pool_dont_block_others() {
+        let mut scheduler = FairScheduler::new(1, HashSet::new());
+        scheduler.push(request("alice", "gpu-heavy"));
+        scheduler.push(request("alice", "web"));
+        scheduler.push(request("bob", "web"));
+
+        let request = scheduler.next(|request| pool_of(request).is_none()).unwrap();
+        assert_eq!((request.user_id.as_str(), request.challenge_id.as_str()), ("bob", "web"));
+        assert!(scheduler.next(|request| pool_of(request).is_none()).is_none());
+
+        let request = scheduler.next(|request| pool_of(request) == Some("gpu")).unwrap();
+        assert_eq!((request.user_id.as_str(), request.challenge_id.as_str()), ("alice", "gpu-heavy"));
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::task::JoinSet;
+use tokio::time;
+
+use crate::config::InstancerConfig;
+use crate::deployment_worker::{sha256_hex, Challenge, DeploymentStep, DeploymentWorker, NAMESPACE_VAR};
+
+/// Lines of output kept from a failed self-test, the last ones being the most telling.
+const OUTPUT_LINES: usize = 10;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTestStatus {
+    Passed,
+    Failed,
+    /// The deployer can be run but has no `selftest` action, so whether its dependencies are available is only known
+    /// once it deploys.
+    Untested
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeployerSelfTest {
+    pub status: SelfTestStatus,
+    pub duration_ms: u64,
+    /// Why the deployer failed, along with the last lines it printed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub output: Vec<String>
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChallengeReadiness {
+    pub ready: bool,
+    /// The deployers of the challenge that failed their self-test.
+    pub failed_deployers: Vec<String>
+}
+
+#[derive(Serialize, Debug)]
+pub struct SelfTestReport {
+    pub deployers: BTreeMap<String, DeployerSelfTest>,
+    pub challenges: BTreeMap<String, ChallengeReadiness>
+}
+
+impl SelfTestReport {
+    /// Logs the deployers that failed and the challenges they leave unready.
+    pub fn log(&self) {
+        for (id, test) in self.deployers.iter().filter(|(_, test)| test.status == SelfTestStatus::Failed) {
+            tracing::error!("deployer {} failed its self-test: {}", id, test.output.join(" / "));
+        }
+        for (id, readiness) in self.challenges.iter().filter(|(_, readiness)| !readiness.ready) {
+            tracing::warn!("challenge {} isn't ready, deployers {:?} failed their self-test", id, readiness.failed_deployers);
+        }
+
+        let count = |status| self.deployers.values().filter(|test| test.status == status).count();
+        tracing::info!("deployer self-test: {} passed, {} failed, {} untested",
+            count(SelfTestStatus::Passed), count(SelfTestStatus::Failed), count(SelfTestStatus::Untested));
+    }
+}
+
+/// Self-tests every configured deployer at once, then reports which of the enabled challenges can be deployed.
+pub async fn run(config: &InstancerConfig, deployer: &DeploymentWorker) -> SelfTestReport {
+    let timeout = Duration::from_secs(config.settings.selftest_timeout as u64);
+    let env: Vec<(String, String)> = config.settings.namespace.iter()
+        .map(|namespace| (NAMESPACE_VAR.to_string(), namespace.clone()))
+        .collect();
+
+    let mut tests = JoinSet::new();
+    for (id, cfg) in &config.deployers {
+        let (id, step, env) = (id.clone(), DeploymentStep::new(id, cfg), env.clone());
+        tests.spawn(async move { (id, test_deployer(&step, &env, timeout).await) });
+    }
+    let deployers: BTreeMap<String, DeployerSelfTest> = tests.join_all().await.into_iter().collect();
+
+    let challenges = deployer.challenges.snapshot().iter()
+        .map(|(id, challenge)| (id.clone(), readiness(challenge, &deployers)))
+        .collect();
+    SelfTestReport { deployers, challenges }
+}
+
+async fn test_deployer(step: &DeploymentStep, env: &[(String, String)], timeout: Duration) -> DeployerSelfTest {
+    let started_at = Instant::now();
+    let (status, output) = match check(step, env, timeout).await {
+        Ok(true) => (SelfTestStatus::Passed, Vec::new()),
+        Ok(false) => (SelfTestStatus::Untested, Vec::new()),
+        Err(output) => (SelfTestStatus::Failed, output)
+    };
+    DeployerSelfTest { status, duration_ms: started_at.elapsed().as_millis() as u64, output }
+}
+
+/// Checks that the deployer can be run, then runs its `selftest` action if it has one. Returns whether it was tested,
+/// or why it failed.
+async fn check(step: &DeploymentStep, env: &[(String, String)], timeout: Duration) -> Result<bool, Vec<String>> {
+    let contents = tokio::fs::read(&step.deployer.path).await
+        .map_err(|err| vec![format!("couldn't read the deployer at \"{}\": {}", step.deployer.path.display(), err)])?;
+    if let Some(pinned) = step.deployer.sha256.as_ref().filter(|pinned| !pinned.eq_ignore_ascii_case(&sha256_hex(&contents))) {
+        return Err(vec![format!("the deployer doesn't match its pinned checksum {}", pinned)]);
+    }
+    if let Some(cwd) = step.deployer.cwd.as_ref().filter(|cwd| !cwd.is_dir()) {
+        return Err(vec![format!("the working directory \"{}\" does not exist", cwd.display())]);
+    }
+    if !step.deployer.selftest { return Ok(false); }
+
+    let mut command = step.command(env);
+    command
+        .arg("selftest")
+        .envs(env.iter().cloned())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let output = match time::timeout(timeout, command.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(err)) => return Err(vec![format!("couldn't spawn the deployer: {}", err)]),
+        Err(_) => return Err(vec![format!("the self-test timed out after {}s", timeout.as_secs())])
+    };
+    if output.status.success() { return Ok(true); }
+
+    let mut lines: Vec<String> = String::from_utf8_lossy(&output.stdout).lines()
+        .chain(String::from_utf8_lossy(&output.stderr).lines())
+        .map(str::to_string)
+        .collect();
+    lines.drain(..lines.len().saturating_sub(OUTPUT_LINES));
+    lines.insert(0, match output.status.code() {
+        Some(code) => format!("the self-test exited with status {}", code),
+        None => String::from("the self-test was killed by a signal")
+    });
+    Err(lines)
+}
+
+/// A challenge is ready once every deployer of its pipeline passed, only one of its targets needing to when it has
+/// some. Simulated challenges don't run their deployers and are always ready.
+fn readiness(challenge: &Challenge, deployers: &BTreeMap<String, DeployerSelfTest>) -> ChallengeReadiness {
+    if challenge.simulation.is_some() {
+        return ChallengeReadiness { ready: true, failed_deployers: Vec::new() };
+    }
+
+    let failed = |step: &DeploymentStep| deployers.get(&step.name).is_none_or(|test| test.status == SelfTestStatus::Failed);
+    let placements: Vec<&DeploymentStep> = match challenge.targets.is_empty() {
+        true => challenge.pipeline.iter().take(1).collect(),
+        false => challenge.targets.iter().collect()
+    };
+
+    let ready = placements.iter().any(|&step| !failed(step)) && challenge.pipeline.iter().skip(1).all(|step| !failed(step));
+    let mut failed_deployers: Vec<String> = placements.into_iter()
+        .chain(challenge.pipeline.iter().skip(1))
+        .filter(|&step| failed(step))
+        .map(|step| step.name.clone())
+        .collect();
+    failed_deployers.sort();
+    failed_deployers.dedup();
+    ChallengeReadiness { ready, failed_deployers }
+}
@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde_json::Value;
+use tower_sessions::Session;
+
+use crate::config::SettingsConfig;
+use crate::error::RouterError;
+use crate::models::TimeSinceEpoch;
+use crate::InstancerState;
+
+/// Session key holding the time of the login, in milliseconds since the epoch.
+pub const LOGIN_TIME_KEY: &str = "login_time";
+
+/// Whether a session logged in at `login_time` outlived the configured maximum age.
+/// Sessions predating the policy have no login time and are considered expired.
+pub fn is_past_max_age(settings: &SettingsConfig, login_time: Option<i64>) -> bool {
+    let Some(max_age) = settings.session_max_age else { return false };
+    login_time.is_none_or(|login_time| i64::from(&TimeSinceEpoch::now()) - login_time > i64::from(max_age) * 1000)
+}
+
+/// Like [`is_past_max_age`], for the raw data of a session loaded from the store.
+pub fn is_record_past_max_age(settings: &SettingsConfig, data: &HashMap<String, Value>) -> bool {
+    is_past_max_age(settings, data.get(LOGIN_TIME_KEY).and_then(Value::as_i64))
+}
+
+/// Logs out sessions older than the maximum age before handling the request, whatever their activity.
+pub async fn enforce_max_age(
+    State(state): State<Arc<InstancerState>>,
+    session: Session,
+    request: Request,
+    next: Next
+) -> Result<Response, RouterError> {
+    if session.get::<String>("uid").await?.is_some()
+        && is_past_max_age(&state.config.settings, session.get::<i64>(LOGIN_TIME_KEY).await?) {
+        session.flush().await?;
+    }
+
+    Ok(next.run(request).await)
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use tokio::sync::Mutex;
+
+use crate::database::Database;
+use crate::deployment_worker::{Challenge, DeploymentRequestCommand, DeploymentTarget};
+use crate::live_deployments::LiveDeployments;
+use crate::models::ChallengeInstance;
+use crate::update_hub::UpdateHub;
+
+/// The user id passed to the deployers of shared services.
+const SERVICE_USER: &str = "shared";
+
+struct SharedService {
+    service: Challenge,
+    state: Mutex<ServiceState>
+}
+
+#[derive(Default)]
+struct ServiceState {
+    references: usize,
+    nonce: String
+}
+
+/// Services started when the first instance depending on them starts and stopped when the last one stops.
+pub struct SharedServices {
+    services: HashMap<String, SharedService>,
+    database: Database
+}
+
+impl SharedServices {
+    pub fn new(services: HashMap<String, Challenge>, database: Database) -> Self {
+        let services = services.into_iter()
+            .map(|(id, service)| (id, SharedService { service, state: Mutex::new(ServiceState::default()) }))
+            .collect();
+        SharedServices { services, database }
+    }
+
+    pub fn contains(&self, service_id: &str) -> bool {
+        self.services.contains_key(service_id)
+    }
+
+    /// Takes a reference on every service, starting those that weren't running. On failure, no reference is held.
+    pub async fn acquire(&self, live: &LiveDeployments, updates: &UpdateHub, service_ids: &[String]) -> Result<(), ()> {
+        for (index, service_id) in service_ids.iter().enumerate() {
+            if self.acquire_one(live, updates, service_id).await.is_err() {
+                tracing::error!("couldn't start shared service {}", service_id);
+                self.release(live, updates, &service_ids[..index]).await;
+                return Err(());
+            }
+        }
+        Ok(())
+    }
+
+    async fn acquire_one(&self, live: &LiveDeployments, updates: &UpdateHub, service_id: &str) -> Result<(), ()> {
+        let Some(shared) = self.services.get(service_id) else { return Err(()) };
+        let mut state = shared.state.lock().await;
+
+        if state.references == 0 {
+            tracing::info!("starting shared service {}", service_id);
+
+            let nonce = ChallengeInstance::generate_nonce();
+            if let Err(err) = self.database.insert_shared_service(service_id, &nonce).await {
+                tracing::error!("couldn't record shared service {}: {:?}", service_id, err);
+                return Err(());
+            }
+
+            let target = DeploymentTarget { user_id: SERVICE_USER, nonce: &nonce, labels: &shared.service.labels, deployer: None };
+            if shared.service.deploy(live, updates, &target, DeploymentRequestCommand::Start { retry: false }, None).await.is_err() {
+                let _ = shared.service.deploy(live, updates, &target, DeploymentRequestCommand::Cleanup, None).await;
+                let _ = self.database.delete_shared_service(service_id).await;
+                return Err(());
+            }
+            state.nonce = nonce;
+        }
+
+        state.references += 1;
+        Ok(())
+    }
+
+    /// Drops a reference on every service, stopping those that no instance depends on anymore.
+    pub async fn release(&self, live: &LiveDeployments, updates: &UpdateHub, service_ids: &[String]) {
+        for service_id in service_ids {
+            let Some(shared) = self.services.get(service_id) else { continue };
+            let mut state = shared.state.lock().await;
+
+            state.references = state.references.saturating_sub(1);
+            if state.references > 0 { continue }
+
+            tracing::info!("stopping shared service {}, no instance depends on it anymore", service_id);
+            let target = DeploymentTarget { user_id: SERVICE_USER, nonce: &state.nonce, labels: &shared.service.labels, deployer: None };
+            if shared.service.deploy(live, updates, &target, DeploymentRequestCommand::Stop, None).await.is_err() {
+                tracing::error!("couldn't stop shared service {}, cleaning it up", service_id);
+                let _ = shared.service.deploy(live, updates, &target, DeploymentRequestCommand::Cleanup, None).await;
+            }
+
+            if let Err(err) = self.database.delete_shared_service(service_id).await {
+                tracing::warn!("couldn't forget shared service {}: {:?}", service_id, err);
+            }
+        }
+    }
+
+    /// Restores a reference held by an instance that was deployed before the instancer started.
+    pub async fn restore(&self, service_ids: &[String]) -> anyhow::Result<()> {
+        for service_id in service_ids {
+            let Some(shared) = self.services.get(service_id) else { continue };
+            let mut state = shared.state.lock().await;
+
+            if state.references == 0 {
+                state.nonce = self.database.get_shared_service_nonce(service_id).await?.unwrap_or_default();
+            }
+            state.references += 1;
+        }
+        Ok(())
+    }
+}
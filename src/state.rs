@@ -1,12 +1,28 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
 use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
 use oauth2::basic::BasicClient;
 use oauth2::{AuthUrl, ClientId, ClientSecret, RedirectUrl, RevocationUrl, TokenUrl};
+use tokio::sync::broadcast;
 use tokio_util::sync::CancellationToken;
 use tower_sessions_sqlx_store::SqliteStore;
 
-use crate::config::InstancerConfig;
+use crate::abuse::AbuseDetector;
+use crate::avatars::AvatarCache;
+use crate::bulk_operations::BulkOperation;
+use crate::config::{AuthProvider, InstancerConfig};
 use crate::database::Database;
 use crate::deployment_worker::DeploymentWorker;
+use crate::discord::{DiscordBot, MembershipCache};
+#[cfg(feature = "geoip")]
+use crate::geo::GeoFilter;
+use crate::janitor::JanitorReport;
+use crate::listing::ListingCache;
+use crate::local_auth;
+use crate::models::ChallengeNotice;
+use crate::rctf::Rctf;
 
 pub struct InstancerState {
     pub config: InstancerConfig,
@@ -15,22 +31,60 @@ pub struct InstancerState {
     pub session_store: SqliteStore,
     pub shutdown_token: CancellationToken,
     pub rate_limiter: DefaultKeyedRateLimiter<String>,
-    pub oauth2: BasicClient,
+    /// Limits how many demo users each IP creates, if the demo is enabled.
+    pub demo_limiter: Option<DefaultKeyedRateLimiter<IpAddr>>,
+    /// Limits the password attempts of each IP, if local or LDAP accounts are enabled.
+    pub password_login_limiter: Option<DefaultKeyedRateLimiter<IpAddr>>,
+    pub abuse: Option<AbuseDetector>,
+    /// The Discord OAuth client, if Discord logins are enabled.
+    pub oauth2: Option<BasicClient>,
+    /// Looks up the members of the Discord server, if a bot token is configured.
+    pub discord_bot: Option<DiscordBot>,
+    pub discord_membership: MembershipCache,
+    pub rctf: Option<Rctf>,
+    pub bulk_operations: Mutex<HashMap<String, Arc<BulkOperation>>>,
+    pub notice_tx: broadcast::Sender<ChallengeNotice>,
+    pub listings: ListingCache,
+    pub avatars: AvatarCache,
+    pub janitor: Mutex<JanitorReport>,
+    #[cfg(feature = "geoip")]
+    pub geo: Option<GeoFilter>,
 }
 
 impl InstancerState {
     pub fn new(config: InstancerConfig, database: Database, deployer: DeploymentWorker, session_store: SqliteStore, shutdown_token: CancellationToken) -> InstancerState {
-        let oauth2 = BasicClient::new(
-            ClientId::new(config.discord.client_id.clone()),
-            Some(ClientSecret::new(config.discord.client_secret.clone())),
-            AuthUrl::new("https://discord.com/oauth2/authorize".to_string()).unwrap(),
-            Some(TokenUrl::new("https://discord.com/api/oauth2/token".to_string()).unwrap())
-        )
-            .set_revocation_uri(RevocationUrl::new("https://discord.com/api/oauth2/token/revoke".to_string()).unwrap())
-            .set_redirect_uri(RedirectUrl::new(config.discord.redirect_url.clone()).unwrap());
+        let oauth2 = config.discord.as_ref()
+            .filter(|_| config.auth.enabled(AuthProvider::Discord))
+            .map(|discord| BasicClient::new(
+                ClientId::new(discord.client_id.clone()),
+                Some(ClientSecret::new(discord.client_secret.clone())),
+                AuthUrl::new("https://discord.com/oauth2/authorize".to_string()).unwrap(),
+                Some(TokenUrl::new("https://discord.com/api/oauth2/token".to_string()).unwrap())
+            )
+                .set_revocation_uri(RevocationUrl::new("https://discord.com/api/oauth2/token/revoke".to_string()).unwrap())
+                .set_redirect_uri(RedirectUrl::new(discord.redirect_url.clone()).unwrap()));
+
+        let discord_bot = config.discord.as_ref()
+            .and_then(|discord| discord.bot_token.clone())
+            .map(DiscordBot::new);
+
+        let rctf = config.rctf.as_ref().map(|rctf| Rctf::new(rctf.url.clone()));
+
+        let (notice_tx, _) = broadcast::channel(16);
 
         let rate_limiter = RateLimiter::keyed(Quota::per_minute(config.settings.max_actions_per_minute.try_into().unwrap()));
 
+        let demo_limiter = config.demo.as_ref().map(|demo| RateLimiter::keyed(Quota::per_hour(demo.logins_per_hour.try_into().unwrap())));
+
+        #[cfg(feature = "ldap")]
+        let password_logins = config.auth.enabled(AuthProvider::Local) || config.auth.enabled(AuthProvider::Ldap);
+        #[cfg(not(feature = "ldap"))]
+        let password_logins = config.auth.enabled(AuthProvider::Local);
+        let password_login_limiter = password_logins
+            .then(|| RateLimiter::keyed(Quota::per_minute(local_auth::LOGIN_ATTEMPTS_PER_MINUTE.try_into().unwrap())));
+
+        let abuse = config.abuse.clone().map(AbuseDetector::new);
+
         InstancerState {
             config,
             database,
@@ -38,7 +92,20 @@ impl InstancerState {
             session_store,
             shutdown_token,
             rate_limiter,
+            demo_limiter,
+            password_login_limiter,
+            abuse,
             oauth2,
+            discord_bot,
+            discord_membership: MembershipCache::default(),
+            rctf,
+            bulk_operations: Mutex::new(HashMap::new()),
+            notice_tx,
+            listings: ListingCache::default(),
+            avatars: AvatarCache::default(),
+            janitor: Mutex::new(JanitorReport::default()),
+            #[cfg(feature = "geoip")]
+            geo: None,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tower_sessions::cookie::time::OffsetDateTime;
+
+use crate::models::{ChallengeOutcomes, HourlyStarts, InstanceUsage};
+
+/// How far back instances over time are charted, one bar per hour.
+const TIMELINE_HOURS: i64 = 7 * 24;
+const TOP_USERS: usize = 10;
+
+/// A bar of a chart, sized relative to the largest bar of the chart.
+#[derive(Serialize, Debug)]
+pub struct Bar {
+    pub label: String,
+    pub value: String,
+    pub percent: u32
+}
+
+/// The charts of the admin stats page. Hours are in UTC.
+#[derive(Serialize, Debug)]
+pub struct StatsReport {
+    pub instances_over_time: Vec<Bar>,
+    pub failures_per_challenge: Vec<Bar>,
+    pub busiest_hours: Vec<Bar>,
+    pub top_users: Vec<Bar>
+}
+
+impl StatsReport {
+    pub fn new(starts: &[HourlyStarts], outcomes: &[ChallengeOutcomes], usage: &[InstanceUsage]) -> Self {
+        StatsReport {
+            instances_over_time: instances_over_time(starts),
+            failures_per_challenge: failures_per_challenge(outcomes),
+            busiest_hours: busiest_hours(starts),
+            top_users: top_users(usage)
+        }
+    }
+}
+
+fn instances_over_time(starts: &[HourlyStarts]) -> Vec<Bar> {
+    let counts: HashMap<i64, i64> = starts.iter().map(|starts| (starts.hour, starts.count)).collect();
+    let current_hour = OffsetDateTime::now_utc().unix_timestamp() / 3600;
+    let hours = (current_hour - TIMELINE_HOURS + 1)..=current_hour;
+    let max = hours.clone().filter_map(|hour| counts.get(&hour)).copied().max().unwrap_or(0);
+
+    hours.map(|hour| {
+        let count = counts.get(&hour).copied().unwrap_or(0);
+        Bar { label: hour_label(hour), value: count.to_string(), percent: percent(count, max) }
+    }).collect()
+}
+
+fn failures_per_challenge(outcomes: &[ChallengeOutcomes]) -> Vec<Bar> {
+    let max = outcomes.iter().map(|outcome| outcome.failures).max().unwrap_or(0);
+
+    outcomes.iter()
+        .filter(|outcome| outcome.failures > 0)
+        .map(|outcome| Bar {
+            label: outcome.challenge_id.clone(),
+            value: format!("{} / {}", outcome.failures, outcome.total),
+            percent: percent(outcome.failures, max)
+        })
+        .collect()
+}
+
+fn busiest_hours(starts: &[HourlyStarts]) -> Vec<Bar> {
+    let mut counts = [0; 24];
+    for starts in starts {
+        counts[starts.hour.rem_euclid(24) as usize] += starts.count;
+    }
+    let max = counts.iter().copied().max().unwrap_or(0);
+
+    counts.iter().enumerate()
+        .map(|(hour, count)| Bar { label: format!("{:02}h", hour), value: count.to_string(), percent: percent(*count, max) })
+        .collect()
+}
+
+fn top_users(usage: &[InstanceUsage]) -> Vec<Bar> {
+    let mut users: HashMap<&str, i64> = HashMap::new();
+    for entry in usage {
+        *users.entry(&entry.user_id).or_insert(0) += entry.runtime;
+    }
+    let mut users: Vec<_> = users.into_iter().collect();
+    users.sort_by(|(a_id, a_runtime), (b_id, b_runtime)| b_runtime.cmp(a_runtime).then(a_id.cmp(b_id)));
+    users.truncate(TOP_USERS);
+    let max = users.first().map(|(_, runtime)| *runtime).unwrap_or(0);
+
+    users.into_iter()
+        .map(|(user_id, runtime)| Bar {
+            label: user_id.to_string(),
+            value: format!("{:.1} h", runtime as f64 / 3_600_000.0),
+            percent: percent(runtime, max)
+        })
+        .collect()
+}
+
+fn hour_label(hour: i64) -> String {
+    match OffsetDateTime::from_unix_timestamp(hour * 3600) {
+        Ok(time) => format!("{:02}/{:02} {:02}h", time.day(), time.month() as u8, time.hour()),
+        Err(_) => hour.to_string()
+    }
+}
+
+fn percent(value: i64, max: i64) -> u32 {
+    if max <= 0 { 0 } else { (value * 100 / max) as u32 }
+}
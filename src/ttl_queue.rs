@@ -0,0 +1,184 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::time::{Duration, SystemTime};
+
+use tokio::time::Instant;
+
+use crate::models::TimeSinceEpoch;
+
+/// Drift between the wall clock and the monotonic clock past which stop times are shifted to follow the wall clock.
+const CLOCK_SKEW_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Min-heap of instance expiries indexed by (user_id, challenge_id).
+///
+/// Stop times are wall-clock times, since that's what gets persisted and shown, but the queue is ordered by
+/// deadlines on the monotonic clock so that a clock step doesn't fire or hold back expiries on its own. When the
+/// wall clock drifts away from the monotonic one, [`TtlQueue::resync`] measures by how much and [`TtlQueue::shift`]
+/// moves the stop times by the same amount so that they keep matching the deadlines.
+///
+/// Removals and reschedules only touch the index, outdated heap entries are
+/// discarded lazily when they reach the top or when the heap gets too sparse.
+pub struct TtlQueue {
+    heap: BinaryHeap<Reverse<TtlEntry>>,
+    stop_times: HashMap<(String, String), (TimeSinceEpoch, Instant)>,
+    /// The same moment on both clocks, as of the last resync.
+    anchor: (SystemTime, Instant)
+}
+
+#[derive(Eq)]
+struct TtlEntry {
+    pub user_id: String,
+    pub challenge_id: String,
+    pub deadline: Instant
+}
+
+impl Ord for TtlEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+impl PartialOrd for TtlEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TtlEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Default for TtlQueue {
+    fn default() -> Self {
+        TtlQueue {
+            heap: BinaryHeap::new(),
+            stop_times: HashMap::new(),
+            anchor: (SystemTime::now(), Instant::now())
+        }
+    }
+}
+
+impl TtlQueue {
+    pub fn new() -> Self {
+        TtlQueue::default()
+    }
+
+    /// Schedules the expiry of an instance, replacing any previously scheduled expiry.
+    pub fn push(&mut self, user_id: String, challenge_id: String, stop_time: TimeSinceEpoch) {
+        let deadline = deadline_from((SystemTime::now(), Instant::now()), &stop_time);
+        self.stop_times.insert((user_id.clone(), challenge_id.clone()), (stop_time, deadline));
+        self.heap.push(Reverse(TtlEntry { user_id, challenge_id, deadline }));
+        self.compact_if_sparse();
+    }
+
+    pub fn remove(&mut self, user_id: &str, challenge_id: &str) {
+        self.stop_times.remove(&(user_id.to_string(), challenge_id.to_string()));
+        self.compact_if_sparse();
+    }
+
+    /// Returns the earliest scheduled deadline.
+    pub fn peek(&mut self) -> Option<Instant> {
+        self.discard_stale();
+        self.heap.peek().map(|entry| entry.0.deadline)
+    }
+
+    /// Removes and returns the (user_id, challenge_id) of the earliest scheduled expiry.
+    pub fn pop(&mut self) -> Option<(String, String)> {
+        self.discard_stale();
+        let entry = self.heap.pop()?.0;
+        self.stop_times.remove(&(entry.user_id.clone(), entry.challenge_id.clone()));
+        Some((entry.user_id, entry.challenge_id))
+    }
+
+    /// Returns by how much the wall clock stepped ahead (positive) or behind (negative) of the monotonic clock since
+    /// the last resync, in milliseconds. Nothing is shifted until [`TtlQueue::shift`] is called with it, so a skew
+    /// that couldn't be persisted yet is measured again on the next resync.
+    pub fn resync(&mut self) -> Option<i64> {
+        self.resync_at(SystemTime::now(), Instant::now())
+    }
+
+    /// Shifts every stop time by a skew returned by [`TtlQueue::resync`]. Deadlines are left as they are.
+    pub fn shift(&mut self, skew: i64) {
+        let offset = Duration::from_millis(skew.unsigned_abs());
+        self.anchor.0 = if skew >= 0 { self.anchor.0 + offset } else { self.anchor.0 - offset };
+
+        for (stop_time, _) in self.stop_times.values_mut() {
+            *stop_time = TimeSinceEpoch::from(i64::from(&*stop_time) + skew);
+        }
+    }
+
+    fn resync_at(&mut self, wall: SystemTime, monotonic: Instant) -> Option<i64> {
+        let wall_elapsed = match wall.duration_since(self.anchor.0) {
+            Ok(elapsed) => elapsed.as_millis() as i128,
+            Err(err) => -(err.duration().as_millis() as i128)
+        };
+        let skew = (wall_elapsed - monotonic.duration_since(self.anchor.1).as_millis() as i128) as i64;
+
+        if skew.unsigned_abs() > CLOCK_SKEW_TOLERANCE.as_millis() as u64 { return Some(skew); }
+
+        self.anchor = (wall, monotonic);
+        None
+    }
+
+    fn is_live(&self, entry: &TtlEntry) -> bool {
+        self.stop_times.get(&(entry.user_id.clone(), entry.challenge_id.clone())).is_some_and(|(_, deadline)| *deadline == entry.deadline)
+    }
+
+    fn discard_stale(&mut self) {
+        while let Some(entry) = self.heap.peek() {
+            if self.is_live(&entry.0) { break; }
+            self.heap.pop();
+        }
+    }
+
+    fn compact_if_sparse(&mut self) {
+        if self.heap.len() <= 64 || self.heap.len() <= self.stop_times.len() * 2 { return; }
+
+        let heap = std::mem::take(&mut self.heap);
+        self.heap = heap.into_iter().filter(|entry| self.is_live(&entry.0)).collect();
+    }
+}
+
+/// Maps a stop time to the monotonic clock given the same moment on both clocks. Past stop times are due right away.
+fn deadline_from((wall, monotonic): (SystemTime, Instant), stop_time: &TimeSinceEpoch) -> Instant {
+    monotonic + stop_time.0.duration_since(wall).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_clock_jumps_shift_stop_times() {
+        let mut queue = TtlQueue::new();
+        let (wall, monotonic) = queue.anchor;
+        let stop_time = TimeSinceEpoch(wall + Duration::from_secs(600));
+        queue.push(String::from("user"), String::from("challenge"), stop_time.clone());
+        let deadline = queue.peek().unwrap();
+
+        let jump = Duration::from_secs(3600);
+        let elapsed = Duration::from_secs(10);
+        let skew = queue.resync_at(wall + jump + elapsed, monotonic + elapsed).unwrap();
+        assert_eq!(skew, jump.as_millis() as i64);
+
+        /* unapplied skews are measured again */
+        assert_eq!(queue.resync_at(wall + jump + elapsed * 2, monotonic + elapsed * 2), Some(skew));
+        queue.shift(skew);
+        assert_eq!(queue.resync_at(wall + jump + elapsed * 3, monotonic + elapsed * 3), None);
+
+        assert_eq!(queue.peek(), Some(deadline));
+        let (shifted, _) = &queue.stop_times[&(String::from("user"), String::from("challenge"))];
+        assert_eq!(i64::from(shifted) - i64::from(&stop_time), jump.as_millis() as i64);
+        assert_eq!(queue.pop(), Some((String::from("user"), String::from("challenge"))));
+    }
+
+    #[test]
+    fn drift_within_tolerance_is_ignored() {
+        let mut queue = TtlQueue::new();
+        let (wall, monotonic) = queue.anchor;
+        let elapsed = Duration::from_secs(10);
+        assert_eq!(queue.resync_at(wall + elapsed + Duration::from_millis(500), monotonic + elapsed), None);
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::database::Database;
+use crate::deployment_worker::{DeploymentUpdate, DeploymentUpdateDetails, MessageSeverity};
+use crate::messages::MessageContents;
+use crate::models::{MissedMessage, TimeSinceEpoch};
+
+/// Routes deployment updates to the sockets of the user they concern, so that a socket isn't woken up
+/// for the updates of every other user. Consumers interested in every update, like the event bus,
+/// subscribe to all of them instead.
+///
+/// Messages sent while none of a user's sockets are open are stored for `replay_window`, so that they're shown
+/// once the user reconnects.
+pub struct UpdateHub {
+    users: Mutex<HashMap<String, broadcast::Sender<DeploymentUpdate>>>,
+    all: broadcast::Sender<DeploymentUpdate>,
+    capacity: usize,
+    database: Database,
+    replay_window: Duration
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredMessage {
+    contents: MessageContents,
+    severity: MessageSeverity
+}
+
+impl UpdateHub {
+    pub fn new(capacity: usize, database: Database, replay_window: u32) -> Self {
+        let (all, _) = broadcast::channel(capacity);
+        UpdateHub {
+            users: Mutex::new(HashMap::new()),
+            all,
+            capacity,
+            database,
+            replay_window: Duration::from_secs(replay_window.into())
+        }
+    }
+
+    pub fn send(&self, update: DeploymentUpdate) {
+        if self.all.receiver_count() > 0 {
+            let _ = self.all.send(update.clone());
+        }
+
+        let mut users = self.users.lock().unwrap();
+        let undelivered = match users.get(&update.user_id) {
+            Some(sender) => match sender.send(update) {
+                Ok(_) => None,
+                Err(broadcast::error::SendError(update)) => {
+                    /* every socket of the user has closed since */
+                    users.remove(&update.user_id);
+                    Some(update)
+                }
+            },
+            None => Some(update)
+        };
+        drop(users);
+
+        if let Some(update) = undelivered {
+            self.store_missed(update);
+        }
+    }
+
+    /// Sends an update to every connected user, `user_id` being filled in for each of them. Users that aren't connected
+    /// don't get it replayed.
+    pub fn broadcast(&self, challenge_id: &str, details: DeploymentUpdateDetails) {
+        self.users.lock().unwrap().retain(|user_id, sender| sender.send(DeploymentUpdate {
+            user_id: user_id.clone(),
+            challenge_id: challenge_id.to_string(),
+            details: details.clone()
+        }).is_ok());
+    }
+
+    /// Receives the updates of a single user, shared by all of their sockets.
+    pub fn subscribe(&self, user_id: &str) -> broadcast::Receiver<DeploymentUpdate> {
+        let mut users = self.users.lock().unwrap();
+        users.retain(|_, sender| sender.receiver_count() > 0);
+        users.entry(user_id.to_string())
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .subscribe()
+    }
+
+    #[cfg(feature = "event-bus")]
+    pub fn subscribe_all(&self) -> broadcast::Receiver<DeploymentUpdate> {
+        self.all.subscribe()
+    }
+
+    /// Returns the messages a user missed within the replay window, which won't be replayed again.
+    pub async fn take_missed(&self, user_id: &str) -> Result<Vec<DeploymentUpdate>, sqlx::Error> {
+        if self.replay_window.is_zero() {
+            return Ok(Vec::new());
+        }
+
+        let oldest = TimeSinceEpoch(std::time::SystemTime::now() - self.replay_window);
+        let missed = self.database.take_missed_messages(user_id, &oldest).await?;
+
+        Ok(missed.into_iter()
+            .filter_map(|missed| match serde_json::from_str::<StoredMessage>(&missed.message) {
+                Ok(StoredMessage { contents, severity }) => Some(DeploymentUpdate {
+                    user_id: missed.user_id,
+                    challenge_id: missed.challenge_id,
+                    details: DeploymentUpdateDetails::Message { contents, severity }
+                }),
+                Err(err) => {
+                    tracing::warn!("dropping unreadable missed message of user {}: {:?}", missed.user_id, err);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    fn store_missed(&self, update: DeploymentUpdate) {
+        let DeploymentUpdateDetails::Message { contents, severity } = update.details else { return; };
+        if self.replay_window.is_zero() { return; }
+
+        let message = MissedMessage {
+            time: TimeSinceEpoch::now(),
+            user_id: update.user_id,
+            challenge_id: update.challenge_id,
+            message: serde_json::to_string(&StoredMessage { contents, severity }).unwrap()
+        };
+        let oldest = TimeSinceEpoch(message.time.0 - self.replay_window);
+
+        let database = self.database.clone();
+        tokio::spawn(async move {
+            if let Err(err) = database.insert_missed_message(&message, &oldest).await {
+                tracing::error!("couldn't store missed message of user {}: {:?}", message.user_id, err);
+            }
+        });
+    }
+}
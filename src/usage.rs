@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time;
+
+use crate::models::InstanceUsage;
+use crate::InstancerState;
+
+/// Accounts for the runtime of running instances at a regular interval, so that usage survives crashes.
+pub async fn accrue_periodically(state: Arc<InstancerState>) -> anyhow::Result<()> {
+    let interval = Duration::from_secs(state.config.settings.usage_accounting_interval as u64);
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => break,
+            _ = time::sleep(interval) => {}
+        }
+
+        if let Err(err) = state.database.accrue_instance_usage().await {
+            tracing::warn!("couldn't account for instance usage: {:?}", err);
+        }
+    }
+
+    state.database.accrue_instance_usage().await?;
+    Ok(())
+}
+
+/// Instance-minutes accrued per challenge and per user.
+#[derive(Serialize, Debug)]
+pub struct UsageReport {
+    total_minutes: f64,
+    challenges: BTreeMap<String, f64>,
+    users: BTreeMap<String, f64>
+}
+
+impl UsageReport {
+    pub fn new(usage: &[InstanceUsage]) -> Self {
+        let mut challenges = BTreeMap::new();
+        let mut users = BTreeMap::new();
+
+        for entry in usage {
+            *challenges.entry(entry.challenge_id.clone()).or_insert(0) += entry.runtime;
+            *users.entry(entry.user_id.clone()).or_insert(0) += entry.runtime;
+        }
+
+        UsageReport {
+            total_minutes: to_minutes(usage.iter().map(|entry| entry.runtime).sum()),
+            challenges: challenges.into_iter().map(|(id, runtime)| (id, to_minutes(runtime))).collect(),
+            users: users.into_iter().map(|(id, runtime)| (id, to_minutes(runtime))).collect()
+        }
+    }
+}
+
+fn to_minutes(runtime: i64) -> f64 {
+    (runtime as f64 / 6_000.0).round() / 10.0
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::config::WebhookConfig;
+use crate::models::TimeSinceEpoch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Started,
+    Stopped,
+    Failed,
+    Expired,
+    Throttled,
+    /// A notification routed to webhooks, its text in the details.
+    Notification
+}
+
+impl From<WebhookEvent> for &str {
+    fn from(value: WebhookEvent) -> Self {
+        match value {
+            WebhookEvent::Started => "started",
+            WebhookEvent::Stopped => "stopped",
+            WebhookEvent::Failed => "failed",
+            WebhookEvent::Expired => "expired",
+            WebhookEvent::Throttled => "throttled",
+            WebhookEvent::Notification => "notification"
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct WebhookPayload<'a> {
+    event: WebhookEvent,
+    user_id: &'a str,
+    challenge_id: &'a str,
+    time: TimeSinceEpoch,
+    details: Option<&'a str>
+}
+
+/// Notifies external systems of instance lifecycle events.
+///
+/// Payloads are POSTed as JSON, signed with HMAC-SHA256 in the `X-Instancer-Signature` header when a secret is configured.
+pub struct Webhooks {
+    client: reqwest::Client,
+    webhooks: Vec<WebhookConfig>
+}
+
+impl Webhooks {
+    pub fn new(webhooks: Vec<WebhookConfig>) -> Self {
+        Webhooks {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("failed to build webhook client"),
+            webhooks
+        }
+    }
+
+    /// Sends the event to every subscribed webhook in the background.
+    pub fn fire(&self, event: WebhookEvent, user_id: &str, challenge_id: &str, details: Option<&str>) {
+        let mut subscribed = self.webhooks.iter()
+            .filter(|webhook| webhook.events.as_ref().is_none_or(|events| events.contains(&event)))
+            .peekable();
+        if subscribed.peek().is_none() { return; }
+
+        let payload = WebhookPayload { event, user_id, challenge_id, time: TimeSinceEpoch::now(), details };
+        let body = serde_json::to_vec(&payload).unwrap();
+
+        for webhook in subscribed {
+            let mut request = self.client.post(&webhook.url)
+                .header(CONTENT_TYPE, "application/json")
+                .header("X-Instancer-Event", <WebhookEvent as Into<&str>>::into(event))
+                .body(body.clone());
+
+            if let Some(secret) = &webhook.secret {
+                request = request.header("X-Instancer-Signature", format!("sha256={}", sign(secret, &body)));
+            }
+
+            let url = webhook.url.clone();
+            tokio::spawn(async move {
+                if let Err(err) = request.send().await.and_then(|response| response.error_for_status()) {
+                    tracing::warn!("couldn't deliver {:?} webhook to {}: {:?}", event, url, err);
+                }
+            });
+        }
+    }
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
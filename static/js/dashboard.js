@@ -20,44 +20,110 @@ function formatRemainingTime(stop_time) {
     return '⏱️ ' + formatSeconds(Math.ceil((stop_time - Date.now()) / 1000));
 }
 
+function formatTtl(challenge) {
+    if(challenge.paused) return '⏸️ Temps suspendu';
+    return formatRemainingTime(challenge.stop_time);
+}
+
 const challengesContainer = document.getElementById('challenges-ctn');
+const instanceSlots = document.getElementById('instance-slots');
 const challenges = {};
 
 let ws;
+let sessionExpired = false;
+let heartbeatTimer;
+let lastServerMessage;
+let resumeToken;
+
+function startHeartbeat(heartbeat) {
+    clearInterval(heartbeatTimer);
+    heartbeatTimer = setInterval(() => {
+        if(ws.readyState !== WebSocket.OPEN) return;
+        if(Date.now() - lastServerMessage > heartbeat.interval * (heartbeat.allowed_misses + 1) * 1000) {
+            ws.close();
+            return;
+        }
+        ws.send(JSON.stringify({'type': 'heartbeat'}));
+    }, heartbeat.interval * 1000);
+}
 
 function connectWS() {
-    ws = new WebSocket(`${window.location.origin.replace('http', 'ws')}/ws?sid=${getCookie('id')}`);
+    const resume = resumeToken ? `&resume=${resumeToken}` : '';
+    ws = new WebSocket(`${window.location.origin.replace('http', 'ws')}/ws?sid=${getCookie('id')}${resume}`);
 
     ws.onmessage = e => {
         const msg = JSON.parse(e.data);
+        lastServerMessage = Date.now();
 
         switch(msg.type) {
             case 'challenge_listing':
+                startHeartbeat(msg.heartbeat);
+                resumeToken = msg.resume_token;
+                renderSlots(msg.slots);
+                for(let key of Object.keys(challenges)) delete challenges[key];
+                challengesContainer.replaceChildren();
                 for(let id of Object.keys(msg.challenges).toSorted()) {
                     challenges[id] = msg.challenges[id];
                     loadChallengeDOM(challenges[id]);
                 }
                 break;
+            case 'challenge_listing_delta':
+                startHeartbeat(msg.heartbeat);
+                resumeToken = msg.resume_token;
+                renderSlots(msg.slots);
+                for(let id of msg.removed) {
+                    challenges[id]?.dom.remove();
+                    delete challenges[id];
+                }
+                for(let id of Object.keys(msg.changed)) {
+                    challenges[id]?.dom.remove();
+                    challenges[id] = msg.changed[id];
+                    loadChallengeDOM(challenges[id]);
+                }
+                for(let id of Object.keys(challenges).toSorted()) challengesContainer.appendChild(challenges[id].dom);
+                for(let button of challengesContainer.querySelectorAll('button')) button.removeAttribute('disabled');
+                break;
             case 'challenge_state_change':
+                renderSlots(msg.slots);
                 const challenge = challenges[msg.id];
                 challenge.state = msg.state;
                 challenge.dom.setAttribute('data-state', msg.state);
+                if(msg.state === 'stopped') challenge.paused = false;
                 for(let button of challenge.dom.querySelectorAll('button')) button.removeAttribute('disabled');
+                challenge.dom.querySelector('.pipeline-step').textContent = '';
                 if(msg.details) {
                     challenge.details = msg.details;
                     challenge.dom.querySelector('.instance-details').textContent = msg.details;
                 }
                 if(msg.stop_time) {
                     challenge.stop_time = msg.stop_time;
-                    challenge.dom.querySelector('.ttl').textContent = formatRemainingTime(msg.stop_time);
+                    challenge.dom.querySelector('.ttl').textContent = formatTtl(challenge);
                 }
                 break;
+            case 'challenge_paused':
+                challenges[msg.id].paused = msg.paused;
+                if(msg.stop_time) challenges[msg.id].stop_time = msg.stop_time;
+                challenges[msg.id].dom.querySelector('.ttl').textContent = formatTtl(challenges[msg.id]);
+                break;
+            case 'challenge_metadata':
+                challenges[msg.id].metadata = msg.metadata;
+                renderMetadata(challenges[msg.id].dom.querySelector('.instance-metadata'), msg.metadata);
+                break;
+            case 'challenge_progress':
+                challenges[msg.id].progress = msg.progress;
+                challenges[msg.id].dom.querySelector('.actions-deploying progress').value = msg.progress;
+                break;
+            case 'challenge_pipeline_step':
+                challenges[msg.id].dom.querySelector('.pipeline-step').textContent = `Étape ${msg.index + 1}/${msg.total} : ${msg.name}`;
+                break;
+            case 'challenge_notice':
+                challenges[msg.id].notice = msg.notice;
+                challenges[msg.id].dom.querySelector('.notice').textContent = msg.notice ?? '';
+                break;
             case 'message':
-                for(let button of challenges[msg.id].dom.querySelectorAll('button')) button.removeAttribute('disabled');
-                const text = document.createElement('span');
-                text.innerHTML = msg.contents;
+                for(let button of challenges[msg.id]?.dom.querySelectorAll('button') ?? []) button.removeAttribute('disabled');
                 Toastify({
-                    node: text,
+                    node: renderMessage(msg.contents),
                     className: msg.severity,
                     close: msg.severity === 'error',
                     duration: msg.severity === 'error' ? -1 : 2500,
@@ -65,12 +131,18 @@ function connectWS() {
                     gravity: 'bottom'
                 }).showToast();
                 break;
+            case 'session_expired':
+                sessionExpired = true;
+                window.location.href = '/login';
+                break;
         }
     };
 
     ws.onclose = _ => {
-        for(let key of Object.keys(challenges)) delete challenges[key];
-        challengesContainer.innerHTML = '';
+        clearInterval(heartbeatTimer);
+        if(sessionExpired) return;
+        /* keep the dashboard displayed, the server only sends what changed once reconnected */
+        for(let button of challengesContainer.querySelectorAll('button')) button.setAttribute('disabled', 'disabled');
         Toastify({
             text: 'La connexion avec le serveur a été perdue.\nReconnexion dans 5 secondes...',
             className: 'warning',
@@ -84,6 +156,50 @@ function connectWS() {
 
 connectWS();
 
+function renderMessage(spans) {
+    const text = document.createElement('span');
+    for(let span of spans) {
+        switch(span.style) {
+            case 'strong':
+                const strong = document.createElement('strong');
+                text.appendChild(strong);
+                strong.textContent = span.text;
+                break;
+            case 'link':
+                const link = document.createElement('a');
+                text.appendChild(link);
+                link.href = span.href;
+                link.textContent = span.text;
+                break;
+            case 'line_break':
+                text.appendChild(document.createElement('br'));
+                break;
+            default:
+                text.appendChild(document.createTextNode(span.text));
+        }
+    }
+    return text;
+}
+
+function renderSlots(slots) {
+    const plural = slots.max > 1 ? 's' : '';
+    instanceSlots.textContent = `${slots.used}/${slots.max} instance${plural} utilisée${plural}`;
+    instanceSlots.classList.toggle('full', slots.used >= slots.max);
+}
+
+function renderMetadata(list, metadata) {
+    list.replaceChildren();
+    for(let key of Object.keys(metadata).toSorted()) {
+        const term = document.createElement('dt');
+        list.appendChild(term);
+        term.textContent = key;
+
+        const value = document.createElement('dd');
+        list.appendChild(value);
+        value.textContent = metadata[key];
+    }
+}
+
 function loadChallengeDOM(challenge) {
     const card = document.createElement('div');
     challengesContainer.appendChild(card);
@@ -105,6 +221,11 @@ function loadChallengeDOM(challenge) {
             details.appendChild(description);
             description.innerHTML = challenge.description;
         }
+
+        const notice = document.createElement('p');
+        details.appendChild(notice);
+        notice.classList.add('notice');
+        notice.textContent = challenge.notice ?? '';
     }
 
     const actions = document.createElement('div');
@@ -133,25 +254,56 @@ function loadChallengeDOM(challenge) {
             detailsText.classList.add('instance-details');
             detailsText.textContent = challenge.details;
 
+            const metadataList = document.createElement('dl');
+            actionsRunning.appendChild(metadataList);
+            metadataList.classList.add('instance-metadata');
+            renderMetadata(metadataList, challenge.metadata);
+
             const ttlText = document.createElement('p');
             actionsRunning.appendChild(ttlText);
             ttlText.classList.add('ttl');
-            ttlText.textContent = formatRemainingTime(challenge.stop_time);
+            ttlText.textContent = formatTtl(challenge);
 
             const stopButton = document.createElement('button');
             actionsRunning.appendChild(stopButton);
             stopButton.textContent = 'Arrêter';
             stopButton.setAttribute('data-action', 'stop');
 
-            const restartButton = document.createElement('button');
-            actionsRunning.appendChild(restartButton);
-            restartButton.textContent = 'Redémarrer';
-            restartButton.setAttribute('data-action', 'restart');
-
-            const extendButton = document.createElement('button');
-            actionsRunning.appendChild(extendButton);
-            extendButton.textContent = 'Étendre';
-            extendButton.setAttribute('data-action', 'extend');
+            if (challenge.restartable) {
+                const restartButton = document.createElement('button');
+                actionsRunning.appendChild(restartButton);
+                restartButton.textContent = 'Redémarrer';
+                restartButton.setAttribute('data-action', 'restart');
+            }
+
+            if (challenge.extendable) {
+                const extendButton = document.createElement('button');
+                actionsRunning.appendChild(extendButton);
+                extendButton.textContent = 'Étendre';
+                extendButton.setAttribute('data-action', 'extend');
+            }
+
+            if (challenge.flag_submission) {
+                const flagForm = document.createElement('form');
+                actionsRunning.appendChild(flagForm);
+                flagForm.classList.add('flag-form');
+
+                const flagInput = document.createElement('input');
+                flagForm.appendChild(flagInput);
+                flagInput.setAttribute('name', 'flag');
+                flagInput.setAttribute('placeholder', 'Flag');
+                flagInput.required = true;
+
+                const submitButton = document.createElement('button');
+                flagForm.appendChild(submitButton);
+                submitButton.textContent = 'Soumettre';
+                submitButton.setAttribute('type', 'submit');
+
+                flagForm.onsubmit = e => {
+                    e.preventDefault();
+                    submitFlag(challenge, flagInput.value).then(() => flagInput.value = '');
+                };
+            }
         }
 
         const actionsQueuedStart = document.createElement('div');
@@ -159,19 +311,48 @@ function loadChallengeDOM(challenge) {
         actionsQueuedStart.classList.add('actions-queued-start');
         actionsQueuedStart.textContent = 'En attente du démarrage...';
 
+        const actionsDeploying = document.createElement('div');
+        actions.appendChild(actionsDeploying);
+        actionsDeploying.classList.add('actions-deploying');
+
+        {
+            const deployingText = document.createElement('span');
+            actionsDeploying.appendChild(deployingText);
+            deployingText.textContent = 'Démarrage en cours...';
+
+            const progressBar = document.createElement('progress');
+            actionsDeploying.appendChild(progressBar);
+            progressBar.max = 100;
+            progressBar.value = challenge.progress ?? 0;
+        }
+
         const actionsQueuedRestart = document.createElement('div');
         actions.appendChild(actionsQueuedRestart);
         actionsQueuedRestart.classList.add('actions-queued-restart');
         actionsQueuedRestart.textContent = 'En attente du redémarrage...';
 
+        if (challenge.cancellable) {
+            for(let container of [actionsQueuedStart, actionsDeploying, actionsQueuedRestart]) {
+                const cancelButton = document.createElement('button');
+                container.appendChild(cancelButton);
+                cancelButton.textContent = 'Annuler';
+                cancelButton.setAttribute('data-action', 'cancel');
+            }
+        }
+
         const actionsQueuedStop = document.createElement('div');
         actions.appendChild(actionsQueuedStop);
         actionsQueuedStop.classList.add('actions-queued-stop');
         actionsQueuedStop.textContent = 'En attente de l\'arrêt...';
+
+        const pipelineStep = document.createElement('p');
+        actions.appendChild(pipelineStep);
+        pipelineStep.classList.add('pipeline-step');
     }
 
     card.onclick = e => {
         if(e.target.nodeName !== 'BUTTON') return;
+        if(e.target.getAttribute('type') === 'submit') return;
 
         const action = e.target.getAttribute('data-action');
         if(action == null) return;
@@ -181,6 +362,7 @@ function loadChallengeDOM(challenge) {
             case 'stop':
             case 'restart':
             case 'extend':
+            case 'cancel':
                 ws.send(JSON.stringify({'type': 'challenge_action', 'id': challenge.id, 'action': action}));
                 for(let button of card.querySelectorAll('button')) button.setAttribute('disabled', 'disabled');
                 break;
@@ -192,17 +374,41 @@ function loadChallengeDOM(challenge) {
     challenge.dom = card;
 }
 
+const submissionMessages = {
+    'correct': ['success', 'Flag correct! L\'instance sera arrêtée.'],
+    'incorrect': ['error', 'Flag incorrect.'],
+    'already_solved': ['info', 'Ce défi a déjà été résolu, l\'instance sera arrêtée.'],
+    'rate_limited': ['warning', 'Veuillez attendre avant votre prochaine soumission.']
+};
+
+async function submitFlag(challenge, flag) {
+    const response = await fetch('/api/submit', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+            'X-CSRF-Token': document.querySelector('meta[name="csrf-token"]').content
+        },
+        body: JSON.stringify({'challenge_id': challenge.id, 'flag': flag})
+    });
+
+    const [severity, text] = response.ok
+        ? submissionMessages[(await response.json()).result]
+        : ['error', 'La soumission du flag a échoué.'];
+
+    Toastify({
+        text,
+        className: severity,
+        duration: 2500,
+        position: 'right',
+        gravity: 'bottom'
+    }).showToast();
+}
+
 setInterval(() => {
     for(let id of Object.keys(challenges)) {
         const challenge = challenges[id];
-        if(challenge.state === 'running') {
-            challenge.dom.querySelector('.ttl').textContent = formatRemainingTime(challenge.stop_time);
+        if(challenge.state === 'running' || challenge.state === 'expiring') {
+            challenge.dom.querySelector('.ttl').textContent = formatTtl(challenge);
         }
     }
-}, 1000);
-
-setInterval(() => {
-    if(ws && ws.readyState === WebSocket.OPEN) {
-        ws.send(JSON.stringify({'type': 'heartbeat'}))
-    }
-}, 30000);
\ No newline at end of file
+}, 1000);
\ No newline at end of file
@@ -0,0 +1,12 @@
+self.addEventListener('push', event => {
+    const notification = event.data ? event.data.json() : {title: 'UnitedCTF', body: ''};
+    event.waitUntil(self.registration.showNotification(notification.title, {
+        body: notification.body,
+        icon: '/img/logo.png'
+    }));
+});
+
+self.addEventListener('notificationclick', event => {
+    event.notification.close();
+    event.waitUntil(clients.openWindow('/'));
+});
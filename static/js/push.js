@@ -0,0 +1,61 @@
+const pushToggle = document.getElementById('push-toggle');
+const pushStatus = document.getElementById('push-status');
+
+function base64UrlToBytes(base64Url) {
+    const base64 = (base64Url + '='.repeat((4 - base64Url.length % 4) % 4)).replace(/-/g, '+').replace(/_/g, '/');
+    return Uint8Array.from(atob(base64), c => c.charCodeAt(0));
+}
+
+async function sendSubscription(method, subscription) {
+    const response = await fetch('/api/push/subscriptions', {
+        method,
+        headers: {
+            'Content-Type': 'application/json',
+            'X-CSRF-Token': document.querySelector('meta[name="csrf-token"]').content
+        },
+        body: JSON.stringify(subscription)
+    });
+    if(!response.ok) {
+        throw new Error(`push subscription request failed with ${response.status}`);
+    }
+}
+
+async function renderPushToggle(registration) {
+    const subscription = await registration.pushManager.getSubscription();
+    pushToggle.textContent = subscription
+        ? 'Désactiver les notifications push / Disable push notifications'
+        : 'Activer les notifications push / Enable push notifications';
+    pushToggle.hidden = false;
+}
+
+async function togglePush(registration) {
+    pushToggle.disabled = true;
+    pushStatus.textContent = '';
+    try {
+        const subscription = await registration.pushManager.getSubscription();
+        if(subscription) {
+            await sendSubscription('DELETE', subscription.toJSON());
+            await subscription.unsubscribe();
+        } else {
+            const created = await registration.pushManager.subscribe({
+                userVisibleOnly: true,
+                applicationServerKey: base64UrlToBytes(document.querySelector('meta[name="web-push-key"]').content)
+            });
+            await sendSubscription('POST', created.toJSON());
+        }
+    } catch(err) {
+        console.error(err);
+        pushStatus.textContent = 'Les notifications push n\'ont pas pu être modifiées. / Push notifications couldn\'t be changed.';
+    }
+    pushToggle.disabled = false;
+    await renderPushToggle(registration);
+}
+
+if('serviceWorker' in navigator && 'PushManager' in window) {
+    navigator.serviceWorker.register('/js/push-worker.js').then(async registration => {
+        pushToggle.onclick = _ => togglePush(registration);
+        await renderPushToggle(registration);
+    });
+} else {
+    pushStatus.textContent = 'Votre navigateur ne prend pas en charge les notifications push. / Your browser doesn\'t support push notifications.';
+}